@@ -0,0 +1,94 @@
+//! Flow-structure metrics computed on the CPU from the read-back velocity.
+//!
+//! `integral_length`: the spatial autocorrelation of the velocity fluctuation `u' = u - <u>`,
+//! `R(r) = <u'(p) . u'(p + r)> / <|u'|^2>`, along x and along y. Pairs are taken only where both
+//! cells are on the grid (the domain is walled, not periodic), and the reported length is the
+//! first lag at which `R` falls below `1/e`, interpolated linearly between whole-cell lags.
+
+use serde::Serialize;
+
+/// `integral_length`: per-axis correlation lengths in cells, `null` where the correlation
+/// stays above `1/e` out to half the axis.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub(crate) struct IntegralLength {
+    pub(crate) x: Option<f64>,
+    pub(crate) y: Option<f64>,
+}
+
+impl IntegralLength {
+    pub(crate) fn new(vel: &[[f32; 2]], w: usize, h: usize) -> Self {
+        let n = (w * h) as f64;
+        let mean = vel.iter().fold([0.0f64; 2], |m, v| {
+            [m[0] + v[0] as f64 / n, m[1] + v[1] as f64 / n]
+        });
+        let fluct: Vec<[f64; 2]> = vel
+            .iter()
+            .map(|v| [v[0] as f64 - mean[0], v[1] as f64 - mean[1]])
+            .collect();
+        let variance = fluct.iter().map(|u| u[0] * u[0] + u[1] * u[1]).sum::<f64>() / n;
+        if variance <= 0.0 {
+            return IntegralLength { x: None, y: None };
+        }
+        IntegralLength {
+            x: crossing(&fluct, w, h, variance, 1, 0),
+            y: crossing(&fluct, w, h, variance, 0, 1),
+        }
+    }
+}
+
+/// The first lag of `(dx, dy)` steps at which the autocorrelation of `fluct` drops below `1/e`.
+fn crossing(
+    fluct: &[[f64; 2]],
+    w: usize,
+    h: usize,
+    variance: f64,
+    dx: usize,
+    dy: usize,
+) -> Option<f64> {
+    let threshold = (-1.0f64).exp();
+    let max_lag = (w * dx + h * dy) / 2;
+    let mut prev = 1.0;
+    for r in 1..=max_lag {
+        let (ox, oy) = (r * dx, r * dy);
+        let mut sum = 0.0;
+        for y in 0..h - oy {
+            for x in 0..w - ox {
+                let [a, b] = [fluct[y * w + x], fluct[(y + oy) * w + x + ox]];
+                sum += a[0] * b[0] + a[1] * b[1];
+            }
+        }
+        let corr = sum / ((w - ox) * (h - oy)) as f64 / variance;
+        if corr < threshold {
+            return Some((r - 1) as f64 + (prev - threshold) / (prev - corr));
+        }
+        prev = corr;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integral_length_of_a_single_wavelength() {
+        // u = (cos(2 pi x / L), 0): R(r) = cos(2 pi r / L) along x (up to the pairs the walls
+        // cut off, small over 16 wavelengths), which first reaches 1/e at r = L acos(1/e) / (2 pi);
+        // every row is the same, so R stays 1 along y
+        let (w, h, wavelength) = (512usize, 4usize, 32.0f64);
+        let vel: Vec<[f32; 2]> = (0..w * h)
+            .map(|i| {
+                let x = (i % w) as f64 + 0.5;
+                [(std::f64::consts::TAU * x / wavelength).cos() as f32, 0.0]
+            })
+            .collect();
+        let length = IntegralLength::new(&vel, w, h);
+        let expected = wavelength * (-1.0f64).exp().acos() / std::f64::consts::TAU;
+        let x = length.x.expect("cosine decorrelates along x");
+        assert!(
+            (x - expected).abs() < 0.2,
+            "x length {x}, expected {expected}"
+        );
+        assert_eq!(length.y, None);
+    }
+}
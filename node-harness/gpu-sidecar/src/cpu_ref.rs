@@ -0,0 +1,1705 @@
+//! Single-threaded CPU mirror of the WGSL fluid kernels.
+//!
+//! Each function below follows its kernel line for line (same edge handling, clamps and
+//! buffer ping-pong), so results track the GPU path up to float rounding. It backs
+//! `backend: "cpu"` for machines where wgpu cannot initialize.
+
+use anyhow::Result;
+
+use crate::{
+    AdvectionScheme, CHECKER_TILES, COUNTER_CFL_CLAMP, COUNTER_CLAMP_EVENTS,
+    COUNTER_NEIGHBOR_CLAMP_DYE, COUNTER_NEIGHBOR_CLAMP_VEL, COUNTER_SLOTS, DT_STATS_LEN,
+    DyeAdvectVelocity, FluidField, FluidFields, FluidStepRequest, ForceKey, InitLayerParams,
+    Params, PressureSolver, ProjectionTally, Roi, Slot, dye_color_slot, fluid_params,
+    force_path_center, luminance,
+};
+
+type Vec2 = [f32; 2];
+
+/// CPU counterpart of `FluidSim`: same buffers, same step sequence.
+pub(crate) struct CpuFluid {
+    p: Params,
+    projection_passes: u32,
+    diffuse_iters: u32,
+    /// `FluidStepRequest::target_divergence` and its bookkeeping (`None` when off; see
+    /// `FluidSim::read_projection_tally`).
+    target_divergence: f32,
+    pub(crate) projection_tally: Option<ProjectionTally>,
+    pressure_solver: PressureSolver,
+    adaptive_dt: bool,
+    streamfunction_iters: u32,
+    /// `FluidStepRequest::init_layer_params`.
+    init_layers: Vec<InitLayerParams>,
+    /// `init_vel_b64` / `init_dye_b64` (see `FluidSim::init_vel`).
+    skip_init: bool,
+    init_vel: Option<Vec<Vec2>>,
+    init_dye: Option<Vec<f32>>,
+    /// `adaptive_dt` statistics (see `FluidSim::read_dt_stats`).
+    pub(crate) dt_stats: [f32; DT_STATS_LEN],
+    vel_a: Vec<Vec2>,
+    vel_b: Vec<Vec2>,
+    /// `channels` values per cell, `[r, g, b]` with three (unpadded, unlike the GPU's
+    /// `vec3<f32>` cells).
+    channels: usize,
+    dye_a: Vec<f32>,
+    dye_b: Vec<f32>,
+    dye_advect_velocity: DyeAdvectVelocity,
+    /// `FluidSim::dye_vel` (empty with `DyeAdvectVelocity::Projected`).
+    dye_vel: Vec<Vec2>,
+    div: Vec<f32>,
+    /// `FluidSim::diffuse_rhs` (empty without `viscosity`).
+    diffuse_rhs: Vec<Vec2>,
+    /// `vorticity` scratch (empty when off; see `FluidSim::new`).
+    curl: Vec<f32>,
+    /// MacCormack predictor outputs (empty with semi-Lagrangian advection).
+    vel_pred: Vec<Vec2>,
+    dye_pred: Vec<f32>,
+    /// `FluidStepRequest::ghost_ring` (empty without `ghost_b64`).
+    ghost: Vec<Vec2>,
+    /// `FluidStepRequest::div_target` (empty without `div_sources`).
+    div_target: Vec<f32>,
+    /// `FluidStepRequest::obstacle_table` (empty without `obstacles`), and with
+    /// `obstacle_velocity` the starting labels `move_obstacles` shifts each step (else empty).
+    solid: Vec<u32>,
+    obstacle_start: Vec<u32>,
+    force_path: Vec<ForceKey>,
+    /// `FluidSim::dye_colors`: `inject_color`, then the `dye_color_schedule`.
+    dye_colors: Vec<[f32; 3]>,
+    dye_color_every: u32,
+    /// `FluidSim::vel_prev` / `dye_prev` (empty without `delta_every`).
+    delta_every: u32,
+    vel_prev: Vec<Vec2>,
+    dye_prev: Vec<f32>,
+    pressure_a: Vec<f32>,
+    pressure_b: Vec<f32>,
+    counters: [u32; COUNTER_SLOTS],
+    /// `FluidSim::clamp_history` (stays empty without `clamp_history`).
+    history_steps: u32,
+    pub(crate) clamp_history: Vec<u32>,
+    /// Buffers written last (see `FluidSim::vel_slot`).
+    vel_slot: Slot,
+    dye_slot: Slot,
+    pub(crate) steps_done: u32,
+    residual_points: Vec<u32>,
+    residual_step: Option<u32>,
+    /// `residual_history` curve of the recorded step (see `FluidSim::read_residuals`).
+    pub(crate) residuals: Vec<f32>,
+    pressure_frame_points: Vec<u32>,
+    pressure_frames_step: Option<u32>,
+    /// `dump_pressure_every` frames of the recorded step (see `FluidSim::pressure_frames`).
+    pub(crate) pressure_frames: Vec<Vec<f32>>,
+    /// Per-cell `dye_budget` triples (see `FluidSim::read_dye_budget`).
+    pub(crate) budget: Vec<f32>,
+    /// Per-cell `forcing_budget` pairs (see `FluidSim::read_forcing_budget`).
+    pub(crate) forcing: Vec<Vec2>,
+    /// First step added to the `time_average` sums (`None` when off), the sums (empty when
+    /// off) and the steps in them.
+    average_from: Option<u32>,
+    dye_sum: Vec<f32>,
+    vel_sum: Vec<Vec2>,
+    pub(crate) mean_steps: u32,
+}
+
+impl CpuFluid {
+    /// Fails on a malformed `obstacles` mask, which `prepare` reports first.
+    pub(crate) fn new(cfg: &FluidStepRequest) -> Result<Self> {
+        let cells = (cfg.width as usize) * (cfg.height as usize);
+        let dye_cells = cells * cfg.channels as usize;
+        let maccormack = cfg.advection == AdvectionScheme::MacCormack;
+        let solid = cfg.obstacle_table()?.unwrap_or_default();
+        Ok(Self {
+            p: fluid_params(cfg),
+            projection_passes: cfg.projection_passes,
+            diffuse_iters: cfg.diffuse_iters,
+            target_divergence: cfg.target_divergence,
+            projection_tally: (cfg.target_divergence > 0.0).then(ProjectionTally::default),
+            pressure_solver: cfg.pressure_solver,
+            adaptive_dt: cfg.adaptive_dt,
+            streamfunction_iters: cfg.streamfunction_iters,
+            init_layers: cfg.init_layer_params(),
+            skip_init: cfg.skip_init,
+            init_vel: cfg.init_vel().ok().flatten(),
+            init_dye: cfg.init_dye().ok().flatten(),
+            dt_stats: [0.0; DT_STATS_LEN],
+            vel_a: vec![[0.0; 2]; cells],
+            vel_b: vec![[0.0; 2]; cells],
+            channels: cfg.channels as usize,
+            dye_a: vec![0.0; dye_cells],
+            dye_b: vec![0.0; dye_cells],
+            dye_advect_velocity: cfg.dye_advect_velocity,
+            dye_vel: match cfg.dye_advect_velocity {
+                DyeAdvectVelocity::Projected => Vec::new(),
+                DyeAdvectVelocity::Raw => vec![[0.0; 2]; cells],
+                DyeAdvectVelocity::Uploaded => cfg.advect_vel().ok().flatten().unwrap_or_default(),
+            },
+            div: vec![0.0; cells],
+            diffuse_rhs: vec![[0.0; 2]; if cfg.viscosity > 0.0 { cells } else { 0 }],
+            curl: vec![0.0; if cfg.vorticity > 0.0 { cells } else { 0 }],
+            vel_pred: vec![[0.0; 2]; if maccormack { cells } else { 0 }],
+            dye_pred: vec![0.0; if maccormack { dye_cells } else { 0 }],
+            ghost: cfg.ghost_ring().ok().flatten().unwrap_or_default(),
+            div_target: cfg.div_target().unwrap_or_default(),
+            obstacle_start: if cfg.obstacle_velocity.is_empty() {
+                Vec::new()
+            } else {
+                solid[..cells].to_vec()
+            },
+            solid,
+            force_path: cfg.force_path.clone(),
+            dye_colors: [cfg.inject_color]
+                .into_iter()
+                .chain(cfg.dye_color_schedule.iter().copied())
+                .collect(),
+            dye_color_every: cfg.dye_color_every,
+            delta_every: cfg.delta_every,
+            // zero until the first snapshot, like the GPU's fresh buffers
+            vel_prev: vec![[0.0; 2]; if cfg.delta_every > 0 { cells } else { 0 }],
+            dye_prev: vec![0.0; if cfg.delta_every > 0 { cells } else { 0 }],
+            pressure_a: vec![0.0; cells],
+            pressure_b: vec![0.0; cells],
+            counters: [0; COUNTER_SLOTS],
+            history_steps: if cfg.clamp_history { cfg.steps } else { 0 },
+            clamp_history: Vec::new(),
+            vel_slot: Slot::A,
+            dye_slot: Slot::A,
+            steps_done: 0,
+            residual_points: cfg.residual_points(),
+            residual_step: cfg.residual_step(),
+            residuals: Vec::new(),
+            pressure_frame_points: cfg.pressure_frame_points(),
+            pressure_frames_step: cfg.pressure_frames_step(),
+            pressure_frames: Vec::new(),
+            budget: vec![0.0; 3 * if cfg.dye_budget { cells } else { 1 }],
+            forcing: vec![[0.0; 2]; if cfg.forcing_budget { cells } else { 1 }],
+            average_from: cfg.averages().then_some(cfg.time_average_start),
+            dye_sum: vec![0.0; if cfg.time_average { cells } else { 0 }],
+            vel_sum: vec![[0.0; 2]; if cfg.time_average_vel { cells } else { 0 }],
+            mean_steps: 0,
+        })
+    }
+
+    /// (Re)seed initial velocity + dye (`FLUID_INIT_WGSL`).
+    pub(crate) fn seed(&mut self) {
+        [self.p.inject_r, self.p.inject_g, self.p.inject_b] = self.dye_colors[0];
+        if !self.skip_init {
+            let (vel, dye) = (&mut self.vel_a, &mut self.dye_a);
+            seed_fields(
+                &self.p,
+                &self.init_layers,
+                &self.solid,
+                self.channels,
+                vel,
+                dye,
+            );
+        }
+        if let Some(vel) = &self.init_vel {
+            self.vel_a.copy_from_slice(vel);
+        }
+        if let Some(dye) = &self.init_dye {
+            self.dye_a.copy_from_slice(dye);
+        }
+        self.budget.fill(0.0);
+        self.forcing.fill([0.0; 2]);
+        self.dye_sum.fill(0.0);
+        self.vel_sum.fill([0.0; 2]);
+        self.mean_steps = 0;
+        self.dt_stats = [0.0; DT_STATS_LEN];
+        if let Some(tally) = &mut self.projection_tally {
+            *tally = ProjectionTally::default();
+        }
+        self.vel_slot = Slot::A;
+        self.dye_slot = Slot::A;
+    }
+
+    /// Replace the per-step parameters, keeping the fields (see `FluidRun::set_params`).
+    pub(crate) fn set_params(&mut self, cfg: &FluidStepRequest) {
+        self.p = fluid_params(cfg);
+        self.projection_passes = cfg.projection_passes;
+        self.diffuse_iters = cfg.diffuse_iters;
+        self.adaptive_dt = cfg.adaptive_dt;
+        self.streamfunction_iters = cfg.streamfunction_iters;
+    }
+
+    pub(crate) fn step(&mut self, steps: u32) {
+        for _ in 0..steps {
+            self.p.step_index = self.steps_done;
+            if !self.obstacle_start.is_empty() {
+                move_obstacles(&self.p, &self.obstacle_start, &mut self.solid);
+            }
+            let schedule_len = self.dye_colors.len() - 1;
+            if let Some(slot) = dye_color_slot(schedule_len, self.dye_color_every, self.steps_done)
+            {
+                [self.p.inject_r, self.p.inject_g, self.p.inject_b] = self.dye_colors[1 + slot];
+            }
+            if self.delta_every > 0 && self.steps_done.is_multiple_of(self.delta_every) {
+                self.vel_prev = self.vel().to_vec();
+                self.dye_prev = self.dye().to_vec();
+            }
+            if self.adaptive_dt {
+                self.p.dt = pick_dt(&self.p, &self.vel_a, &mut self.dt_stats);
+            }
+            self.step_once(
+                self.residual_step == Some(self.steps_done),
+                self.pressure_frames_step == Some(self.steps_done),
+            );
+            if self
+                .average_from
+                .is_some_and(|start| self.steps_done >= start)
+            {
+                self.accumulate();
+            }
+            if self.steps_done < self.history_steps {
+                self.clamp_history.push(self.counters[COUNTER_CLAMP_EVENTS]);
+            }
+            self.steps_done += 1;
+        }
+    }
+
+    fn step_once(&mut self, record_residuals: bool, record_pressure: bool) {
+        let p = self.p;
+        let jacobi_even = p.jacobi_iters.is_multiple_of(2);
+
+        self.pressure_a.fill(p.pressure_init);
+        if p.advection == 1 {
+            predict_vel(&p, &self.vel_a, &mut self.vel_pred);
+        }
+        advect_vel(
+            &p,
+            &self.vel_a,
+            &self.vel_pred,
+            &self.ghost,
+            &self.force_path,
+            &self.solid,
+            &mut self.vel_b,
+            &mut self.counters,
+            &mut self.forcing,
+        );
+
+        if p.viscosity > 0.0 {
+            let rhs = &mut self.diffuse_rhs;
+            rhs.copy_from_slice(&self.vel_b);
+            let n = self.diffuse_iters;
+            for i in 0..n {
+                let into_b = (n - i) % 2 == 1;
+                let (src, dst) = match (i, into_b) {
+                    (0, true) => (&*rhs, &mut self.vel_b),
+                    (0, false) => (&*rhs, &mut self.vel_a),
+                    (_, true) => (&self.vel_a, &mut self.vel_b),
+                    (_, false) => (&self.vel_b, &mut self.vel_a),
+                };
+                diffuse_vel(&p, src, rhs, &self.ghost, &self.solid, dst);
+            }
+        }
+        if p.vorticity > 0.0 {
+            curl(&p, &self.vel_b, &self.solid, &mut self.curl);
+            vorticity_force(&p, &self.curl, &self.solid, &mut self.vel_b);
+        }
+
+        if self.dye_advect_velocity == DyeAdvectVelocity::Raw {
+            self.dye_vel.copy_from_slice(&self.vel_b);
+        }
+        divergence(
+            &p,
+            &self.vel_b,
+            &self.div_target,
+            &self.solid,
+            &mut self.div,
+        );
+        if record_residuals {
+            self.residuals.clear();
+        }
+        if record_pressure {
+            self.pressure_frames.clear();
+        }
+        self.solve_pressure(record_residuals, record_pressure);
+        if jacobi_even {
+            project(
+                &p,
+                &self.vel_b,
+                &self.pressure_a,
+                &self.ghost,
+                &self.solid,
+                &mut self.vel_a,
+                &mut self.counters,
+            );
+        } else {
+            project(
+                &p,
+                &self.vel_b,
+                &self.pressure_b,
+                &self.ghost,
+                &self.solid,
+                &mut self.vel_a,
+                &mut self.counters,
+            );
+        }
+
+        let mut vel = Slot::A;
+        // `FLUID_DIVERGENCE_GATE_WGSL`: once a pass finds the divergence at the target, the
+        // rest of the step's passes solve nothing and the project kernel only copies
+        let gated = self.projection_tally.is_some();
+        let (mut closed, mut extra) = (false, 0u32);
+        for _ in 1..self.projection_passes {
+            let vel_in_a = vel == Slot::A;
+            if vel_in_a {
+                divergence(
+                    &p,
+                    &self.vel_a,
+                    &self.div_target,
+                    &self.solid,
+                    &mut self.div,
+                );
+            } else {
+                divergence(
+                    &p,
+                    &self.vel_b,
+                    &self.div_target,
+                    &self.solid,
+                    &mut self.div,
+                );
+            }
+            self.pressure_a.fill(p.pressure_init);
+            if gated {
+                closed = closed || max_abs(&self.div) <= self.target_divergence;
+                if closed {
+                    let (from, to) = match vel {
+                        Slot::A => (&self.vel_a, &mut self.vel_b),
+                        Slot::B => (&self.vel_b, &mut self.vel_a),
+                    };
+                    to.copy_from_slice(from);
+                    vel = vel.flipped();
+                    continue;
+                }
+                extra += 1;
+            }
+            self.solve_pressure(false, false);
+            let pressure = if jacobi_even {
+                &self.pressure_a
+            } else {
+                &self.pressure_b
+            };
+            let counters = &mut self.counters;
+            if vel_in_a {
+                project(
+                    &p,
+                    &self.vel_a,
+                    pressure,
+                    &self.ghost,
+                    &self.solid,
+                    &mut self.vel_b,
+                    counters,
+                );
+            } else {
+                project(
+                    &p,
+                    &self.vel_b,
+                    pressure,
+                    &self.ghost,
+                    &self.solid,
+                    &mut self.vel_a,
+                    counters,
+                );
+            }
+            vel = vel.flipped();
+        }
+        if let Some(tally) = &mut self.projection_tally {
+            let last = match vel {
+                Slot::A => &self.vel_a,
+                Slot::B => &self.vel_b,
+            };
+            divergence(&p, last, &self.div_target, &self.solid, &mut self.div);
+            *tally = ProjectionTally {
+                last_extra: extra,
+                last_divergence: max_abs(&self.div),
+                extra_sum: tally.extra_sum + extra,
+                extra_max: tally.extra_max.max(extra),
+                steps: tally.steps + 1,
+            };
+        }
+        self.vel_slot = vel;
+
+        let vel = match (self.dye_advect_velocity, vel) {
+            (DyeAdvectVelocity::Projected, Slot::A) => &self.vel_a,
+            (DyeAdvectVelocity::Projected, Slot::B) => &self.vel_b,
+            _ => &self.dye_vel,
+        };
+        if p.advection == 1 {
+            predict_dye(&p, self.channels, vel, &self.dye_a, &mut self.dye_pred);
+        }
+        advect_dye(
+            &p,
+            self.channels,
+            vel,
+            &self.dye_a,
+            &self.dye_pred,
+            &mut self.dye_b,
+            &mut self.counters,
+            &mut self.budget,
+        );
+        let mut dye = Slot::B;
+        fade(
+            &p,
+            self.channels,
+            &self.dye_b,
+            &mut self.dye_a,
+            &mut self.budget,
+        );
+        dye = dye.flipped();
+        self.dye_slot = dye;
+    }
+
+    /// `FLUID_ACCUMULATE_WGSL`.
+    fn accumulate(&mut self) {
+        let (vel, dye) = (
+            match self.vel_slot {
+                Slot::A => &self.vel_a,
+                Slot::B => &self.vel_b,
+            },
+            match self.dye_slot {
+                Slot::A => &self.dye_a,
+                Slot::B => &self.dye_b,
+            },
+        );
+        for (sum, v) in self.vel_sum.iter_mut().zip(vel) {
+            *sum = [sum[0] + v[0], sum[1] + v[1]];
+        }
+        for (sum, d) in self.dye_sum.iter_mut().zip(dye) {
+            *sum += d;
+        }
+        self.mean_steps += 1;
+    }
+
+    fn solve_pressure(&mut self, record_residuals: bool, record_pressure: bool) {
+        let iterate: PressureIteration = match self.pressure_solver {
+            PressureSolver::Jacobi => jacobi,
+            PressureSolver::RedBlack => red_black,
+        };
+        for i in 0..self.p.jacobi_iters {
+            let latest = if i % 2 == 0 {
+                iterate(
+                    &self.p,
+                    &self.pressure_a,
+                    &self.div,
+                    &self.solid,
+                    &mut self.pressure_b,
+                );
+                &self.pressure_b
+            } else {
+                iterate(
+                    &self.p,
+                    &self.pressure_b,
+                    &self.div,
+                    &self.solid,
+                    &mut self.pressure_a,
+                );
+                &self.pressure_a
+            };
+            if record_residuals && self.residual_points.contains(&i) {
+                self.residuals
+                    .push(residual(&self.p, latest, &self.div, &self.solid));
+            }
+            if record_pressure && self.pressure_frame_points.contains(&i) {
+                self.pressure_frames.push(latest.clone());
+            }
+        }
+    }
+
+    /// Mirrors `FLUID_MAX_SPEED_WGSL`.
+    pub(crate) fn max_speed(&self) -> f32 {
+        self.vel().iter().map(|&v| length(v)).fold(0.0, f32::max)
+    }
+
+    fn vel(&self) -> &[Vec2] {
+        match self.vel_slot {
+            Slot::A => &self.vel_a,
+            Slot::B => &self.vel_b,
+        }
+    }
+
+    fn dye(&self) -> &[f32] {
+        match self.dye_slot {
+            Slot::A => &self.dye_a,
+            Slot::B => &self.dye_b,
+        }
+    }
+
+    pub(crate) fn read_fields(&self) -> FluidFields {
+        FluidFields {
+            vel: self.vel().to_vec(),
+            dye: self
+                .dye()
+                .chunks(self.channels)
+                .map(dye_luminance)
+                .collect(),
+            counters: self.counters,
+            pressure: Vec::new(),
+        }
+    }
+
+    /// Streamfunction of the current velocity and its solve residual (the GPU's
+    /// `encode_streamfunction`).
+    pub(crate) fn streamfunction(&self) -> (Vec<f32>, f32) {
+        let mut rhs = vec![0.0; self.vel_a.len()];
+        streamfunction_rhs(&self.p, self.vel(), &mut rhs);
+        let mut psi = vec![0.0; rhs.len()];
+        let mut scratch = psi.clone();
+        for _ in 0..self.streamfunction_iters {
+            jacobi(&self.p, &psi, &rhs, &self.solid, &mut scratch);
+            std::mem::swap(&mut psi, &mut scratch);
+        }
+        let residual = residual(&self.p, &psi, &rhs, &self.solid);
+        (psi, residual)
+    }
+
+    pub(crate) fn read_region(&self, field: FluidField, roi: Roi) -> Vec<f32> {
+        let mut derived = Vec::new();
+        let values: &[f32] = match field {
+            FluidField::Vel => bytemuck::cast_slice(self.vel()),
+            FluidField::Dye => {
+                derived = self
+                    .dye()
+                    .chunks(self.channels)
+                    .map(dye_luminance)
+                    .collect();
+                &derived
+            }
+            FluidField::DyeRgb => self.dye(),
+            FluidField::OkuboWeiss => {
+                derived.resize(self.vel_a.len(), 0.0);
+                okubo_weiss(&self.p, self.vel(), &mut derived);
+                &derived
+            }
+            FluidField::DyeGradient => {
+                derived.resize(self.vel_a.len(), 0.0);
+                dye_gradient(&self.p, self.dye(), &mut derived);
+                &derived
+            }
+            FluidField::Delta => {
+                derived.resize(2 * self.vel_a.len(), 0.0);
+                delta(
+                    self.vel(),
+                    self.dye(),
+                    &self.vel_prev,
+                    &self.dye_prev,
+                    &mut derived,
+                );
+                &derived
+            }
+            FluidField::Streamfunction => {
+                derived = self.streamfunction().0;
+                &derived
+            }
+            FluidField::Pressure if self.p.jacobi_iters % 2 == 1 => &self.pressure_b,
+            FluidField::Pressure => &self.pressure_a,
+            FluidField::DyeMean => &self.dye_sum,
+            FluidField::VelMean => bytemuck::cast_slice(&self.vel_sum),
+        };
+        let comps = field.components();
+        let row_len = self.p.width as usize * comps;
+        let mut out = Vec::with_capacity(roi.w as usize * roi.h as usize * comps);
+        for y in roi.y..roi.y + roi.h {
+            let start = y as usize * row_len + roi.x as usize * comps;
+            out.extend_from_slice(&values[start..start + roi.w as usize * comps]);
+        }
+        out
+    }
+}
+
+/// The starting velocity of `cfg` (its `init_vel_b64` upload, else what `init` seeds), without
+/// building a whole `CpuFluid`.
+pub(crate) fn initial_velocity(cfg: &FluidStepRequest) -> Vec<Vec2> {
+    if let Ok(Some(vel)) = cfg.init_vel() {
+        return vel;
+    }
+    let p = fluid_params(cfg);
+    let cells = (p.width as usize) * (p.height as usize);
+    let mut vel = vec![[0.0; 2]; cells];
+    seed_fields(
+        &p,
+        &cfg.init_layer_params(),
+        &cfg.obstacle_table().ok().flatten().unwrap_or_default(),
+        1,
+        &mut vel,
+        &mut vec![0.0; cells],
+    );
+    vel
+}
+
+/// The seed and then each of `layers` on top (`FluidSim::encode_pending_seed`).
+fn seed_fields(
+    p: &Params,
+    layers: &[InitLayerParams],
+    solid: &[u32],
+    channels: usize,
+    vel: &mut [Vec2],
+    dye: &mut [f32],
+) {
+    init(p, channels, vel, dye);
+    for layer in layers {
+        let p = Params {
+            init_layer: layer.init_layer,
+            layer_center_x: layer.layer_center_x,
+            layer_center_y: layer.layer_center_y,
+            layer_radius: layer.layer_radius,
+            layer_impulse: layer.layer_impulse,
+            layer_tightness: layer.layer_tightness,
+            ..*p
+        };
+        init(&p, channels, vel, dye);
+    }
+    // every init dispatch ends by holding the solid cells at their wall velocity
+    for (id, v) in vel.iter_mut().enumerate() {
+        if is_solid(solid, id) {
+            *v = solid_vel(p, solid, id);
+        }
+    }
+}
+
+fn idx(p: &Params, x: u32, y: u32) -> usize {
+    (y * p.width + x) as usize
+}
+
+/// WGSL `c(x, maxv)`: clamp a signed coordinate into `0..maxv`.
+fn c(x: i32, maxv: u32) -> u32 {
+    x.clamp(0, maxv as i32 - 1) as u32
+}
+
+fn is_edge(p: &Params, x: u32, y: u32) -> bool {
+    x == 0 || y == 0 || x == p.width - 1 || y == p.height - 1
+}
+
+/// Mirrors the WGSL `solid`; `solid` is empty without `obstacles`.
+fn is_solid(solid: &[u32], id: usize) -> bool {
+    solid.get(id).is_some_and(|&s| s != 0)
+}
+
+/// Mirrors the WGSL `solid_vel`.
+fn solid_vel(p: &Params, solid: &[u32], id: usize) -> Vec2 {
+    if !is_solid(solid, id) {
+        return [0.0; 2];
+    }
+    let at = (p.width * p.height) as usize + 2 * (solid[id] as usize - 1);
+    match solid.get(at..at + 2) {
+        Some(v) => [f32::from_bits(v[0]), f32::from_bits(v[1])],
+        None => [0.0; 2],
+    }
+}
+
+/// Mirrors `FLUID_MOVE_OBSTACLES_WGSL`: rewrites the cell labels of `obstacles` (an
+/// `obstacle_table`) from the starting labels `start`, shifted to step `p.step_index`.
+pub(crate) fn move_obstacles(p: &Params, start: &[u32], obstacles: &mut [u32]) {
+    let cells = (p.width * p.height) as usize;
+    let (labels, velocities) = obstacles.split_at_mut(cells);
+    let size = [p.width as i32, p.height as i32];
+    for y in 0..p.height {
+        for x in 0..p.width {
+            let mut label = 0;
+            for (k, v) in velocities.chunks_exact(2).enumerate() {
+                let shift = |i: usize| {
+                    (f32::from_bits(v[i]) * p.step_index as f32 * p.dt + 0.5)
+                        .floor()
+                        .clamp(-1e6, 1e6) as i32
+                };
+                let origin = [x as i32 - shift(0), y as i32 - shift(1)];
+                let inside = (0..2).all(|i| (0..size[i]).contains(&origin[i]));
+                if inside && start[idx(p, origin[0] as u32, origin[1] as u32)] == k as u32 + 1 {
+                    label = k as u32 + 1;
+                    break;
+                }
+            }
+            labels[idx(p, x, y)] = label;
+        }
+    }
+}
+
+/// Mirrors the WGSL `wall_p`.
+fn wall_p(solid: &[u32], id: usize, v: f32, center: f32) -> f32 {
+    if is_solid(solid, id) { center } else { v }
+}
+
+/// Mirrors the WGSL `edge_vel`: the ring entry of edge cell (x, y), or 0 without a ring.
+fn edge_vel(p: &Params, ghost: &[Vec2], x: u32, y: u32) -> Vec2 {
+    if ghost.is_empty() {
+        return [0.0, 0.0];
+    }
+    let i = if y == 0 {
+        x
+    } else if y == p.height - 1 {
+        ghost.len() as u32 - p.width + x
+    } else {
+        p.width + 2 * (y - 1) + u32::from(x > 0)
+    };
+    ghost[i as usize]
+}
+
+/// Mirrors `FLUID_MAX_DIVERGENCE_WGSL`.
+fn max_abs(values: &[f32]) -> f32 {
+    values.iter().fold(0.0, |m, v| m.max(v.abs()))
+}
+
+fn length(v: Vec2) -> f32 {
+    (v[0] * v[0] + v[1] * v[1]).sqrt()
+}
+
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    a * (1.0 - t) + b * t
+}
+
+/// The four bilinear taps around `pos` (border-clamped) and the fractional weights. Along a
+/// 1-cell axis the position pins to cell 0, both taps land on it and the weight is 0.
+fn bilinear_taps(p: &Params, pos: Vec2) -> ([usize; 4], f32, f32) {
+    let x = pos[0].clamp(0.0, (p.width as f32 - 1.001).max(0.0));
+    let y = pos[1].clamp(0.0, (p.height as f32 - 1.001).max(0.0));
+    let x0 = x.floor() as i32;
+    let y0 = y.floor() as i32;
+    let tap = |tx: i32, ty: i32| idx(p, c(tx, p.width), c(ty, p.height));
+    (
+        [
+            tap(x0, y0),
+            tap(x0 + 1, y0),
+            tap(x0, y0 + 1),
+            tap(x0 + 1, y0 + 1),
+        ],
+        x - x.floor(),
+        y - y.floor(),
+    )
+}
+
+/// Bilinear sample of one scalar channel; counts (and applies) the neighbor clamp.
+fn sample_scalar(
+    p: &Params,
+    taps: [usize; 4],
+    fx: f32,
+    fy: f32,
+    get: impl Fn(usize) -> f32,
+) -> (f32, bool) {
+    let [a, b, c, d] = taps.map(get);
+    let v = mix(mix(a, b, fx), mix(c, d, fx), fy);
+    if p.clamp_to_neighbors != 0 {
+        let vc = v.clamp(a.min(b).min(c.min(d)), a.max(b).max(c.max(d)));
+        return (vc, vc != v);
+    }
+    (v, false)
+}
+
+/// Mirrors the shared `dt_eff` CFL guard of the advect kernels.
+fn dt_eff(p: &Params, v: Vec2) -> f32 {
+    let max_travel = 1.25;
+    p.dt * (max_travel / (length(v) * p.dt + 1e-5)).min(1.0)
+}
+
+/// Mirrors `wgsl_backtrace!`: `trace_vel` is the plain bilinear filter over `vel`.
+fn backtrace(p: &Params, vel: &[Vec2], pos: Vec2, v: Vec2, dt: f32) -> Vec2 {
+    let trace_vel = |at: Vec2| {
+        let (taps, fx, fy) = bilinear_taps(p, at);
+        let [a, b, c, d] = taps.map(|i| vel[i]);
+        std::array::from_fn(|k| mix(mix(a[k], b[k], fx), mix(c[k], d[k], fx), fy))
+    };
+    let step = |from: Vec2, h: f32, k: Vec2| [from[0] - h * k[0], from[1] - h * k[1]];
+    match p.advect_integrator {
+        1 => step(pos, dt, trace_vel(step(pos, 0.5 * dt, v))),
+        2 => {
+            let k2 = trace_vel(step(pos, 0.5 * dt, v));
+            let k3 = trace_vel(step(pos, 0.5 * dt, k2));
+            let k4 = trace_vel(step(pos, dt, k3));
+            let sum = std::array::from_fn(|k| v[k] + 2.0 * k2[k] + 2.0 * k3[k] + k4[k]);
+            step(pos, dt / 6.0, sum)
+        }
+        _ => step(pos, dt, v),
+    }
+}
+
+/// Plain bilinear sample of one channel at `pos`.
+fn bilinear(p: &Params, pos: Vec2, get: impl Fn(usize) -> f32) -> f32 {
+    let (taps, fx, fy) = bilinear_taps(p, pos);
+    let [a, b, c, d] = taps.map(get);
+    mix(mix(a, b, fx), mix(c, d, fx), fy)
+}
+
+/// `maccormack_vel` / `maccormack_dye` for one channel: `hat`, the sample at `back`, plus half
+/// of `here` minus the predictor field `pred` at `fwd`, clamped to the `src` taps around `back`.
+fn maccormack(
+    p: &Params,
+    back: Vec2,
+    fwd: Vec2,
+    (hat, here): (f32, f32),
+    src: impl Fn(usize) -> f32,
+    pred: impl Fn(usize) -> f32,
+) -> f32 {
+    let corrected = hat + 0.5 * (here - bilinear(p, fwd, pred));
+    let [a, b, c, d] = bilinear_taps(p, back).0.map(src);
+    corrected
+        .max(a.min(b).min(c.min(d)))
+        .min(a.max(b).max(c.max(d)))
+}
+
+/// `FLUID_PREDICT_VEL_WGSL`.
+fn predict_vel(p: &Params, src: &[Vec2], pred: &mut [Vec2]) {
+    for y in 0..p.height {
+        for x in 0..p.width {
+            let id = idx(p, x, y);
+            let v = src[id];
+            let back = backtrace(p, src, [x as f32, y as f32], v, dt_eff(p, v));
+            pred[id] = std::array::from_fn(|k| bilinear(p, back, |i| src[i][k]));
+        }
+    }
+}
+
+/// `FLUID_PREDICT_DYE_WGSL`.
+fn predict_dye(p: &Params, channels: usize, vel: &[Vec2], dye_src: &[f32], pred: &mut [f32]) {
+    for y in 0..p.height {
+        for x in 0..p.width {
+            let id = idx(p, x, y);
+            let v = vel[id];
+            let back = backtrace(p, vel, [x as f32, y as f32], v, dt_eff(p, v));
+            for k in 0..channels {
+                pred[channels * id + k] = bilinear(p, back, |i| dye_src[channels * i + k]);
+            }
+        }
+    }
+}
+
+/// `wgsl_dye!`'s `tint`; with one channel only the first entry counts.
+fn tint(p: &Params, channels: usize, a: f32) -> [f32; 3] {
+    if channels == 3 {
+        [a * p.inject_r, a * p.inject_g, a * p.inject_b]
+    } else {
+        [a; 3]
+    }
+}
+
+/// `wgsl_dye!`'s `luminance` of one dye cell.
+fn dye_luminance(cell: &[f32]) -> f32 {
+    match *cell {
+        [r, g, b] => luminance([r, g, b]),
+        _ => cell[0],
+    }
+}
+
+fn init(p: &Params, channels: usize, vel: &mut [Vec2], dye: &mut [f32]) {
+    for y in 0..p.height {
+        for x in 0..p.width {
+            let u = (x as f32 + 0.5) / p.width as f32;
+            let v = (y as f32 + 0.5) / p.height as f32;
+            let (cx, cy) = (u - 0.5, v - 0.5);
+            let r = length([cx, cy]);
+            let id = idx(p, x, y);
+            let cell = &mut dye[channels * id..channels * (id + 1)];
+            if p.init_layer != 0 {
+                let (lx, ly) = (u - p.layer_center_x, v - p.layer_center_y);
+                let lr = length([lx, ly]);
+                let s = p.layer_impulse * (-p.layer_tightness * lr * lr).exp();
+                vel[id] = [vel[id][0] - ly * s, vel[id][1] + lx * s];
+                if lr <= p.layer_radius {
+                    let add = tint(p, channels, 1.0 - lr / p.layer_radius.max(0.01));
+                    cell.iter_mut().zip(add).for_each(|(d, a)| *d += a);
+                }
+                continue;
+            }
+            vel[id] = if p.init_kind == 1 {
+                let k = std::f32::consts::TAU * p.init_mode as f32;
+                [
+                    (k * u).sin() * (k * v).cos(),
+                    -(k * u).cos() * (k * v).sin(),
+                ]
+            } else {
+                let s = p.impulse * (-30.0 * r * r).exp();
+                [-cy * s, cx * s]
+            };
+            let seeded = match p.init_dye {
+                1 => 1.0,
+                2 => u,
+                3 => {
+                    let tile = (u * CHECKER_TILES) as u32 + (v * CHECKER_TILES) as u32;
+                    (tile % 2) as f32
+                }
+                _ if r <= p.dye_radius => 1.0 - r / p.dye_radius.max(0.01),
+                _ => 0.0,
+            };
+            cell.copy_from_slice(&tint(p, channels, seeded)[..channels]);
+        }
+    }
+}
+
+/// `FLUID_DT_PEAKS_WGSL` followed by `FLUID_PICK_DT_WGSL`.
+fn pick_dt(p: &Params, vel: &[Vec2], stats: &mut [f32; DT_STATS_LEN]) -> f32 {
+    let mut speed = 0.0f32;
+    let mut curl = 0.0f32;
+    for y in 0..p.height {
+        for x in 0..p.width {
+            let id = idx(p, x, y);
+            speed = speed.max(length(vel[id]));
+            if x > 0 && y > 0 && x + 1 < p.width && y + 1 < p.height {
+                let w = p.width as usize;
+                let dvy_dx = vel[id + 1][1] - vel[id - 1][1];
+                let dvx_dy = vel[id + w][0] - vel[id - w][0];
+                curl = curl.max((0.5 * (dvy_dx - dvx_dy)).abs());
+            }
+        }
+    }
+    let mut dt = p.dt_max;
+    if speed > 0.0 {
+        dt = dt.min(p.cfl_limit / speed);
+    }
+    if curl > 0.0 {
+        dt = dt.min(p.vorticity_cfl_limit / curl);
+    }
+    stats[0] = dt;
+    if stats[4] == 0.0 || dt < stats[1] {
+        stats[1] = dt;
+    }
+    stats[2] = stats[2].max(dt);
+    stats[3] += dt;
+    stats[4] += 1.0;
+    dt
+}
+
+#[allow(clippy::too_many_arguments)]
+fn advect_vel(
+    p: &Params,
+    src: &[Vec2],
+    pred: &[Vec2],
+    ghost: &[Vec2],
+    force_path: &[ForceKey],
+    solid: &[u32],
+    dst: &mut [Vec2],
+    counters: &mut [u32; COUNTER_SLOTS],
+    forcing: &mut [Vec2],
+) {
+    let [cx, cy] = force_path_center(force_path, p.step_index)
+        .unwrap_or([p.source_center_x, p.source_center_y]);
+    let center = [cx * p.width as f32, cy * p.height as f32];
+    for y in 0..p.height {
+        for x in 0..p.width {
+            let id = idx(p, x, y);
+            if is_edge(p, x, y) {
+                dst[id] = edge_vel(p, ghost, x, y);
+                continue;
+            }
+            if is_solid(solid, id) {
+                dst[id] = solid_vel(p, solid, id);
+                continue;
+            }
+            let pos = [x as f32, y as f32];
+            let v = src[id];
+            let dt_eff = dt_eff(p, v);
+            let back = backtrace(p, src, pos, v, dt_eff);
+            let domain_max = [p.width as f32 - 1.0, p.height as f32 - 1.0];
+            if (0..2).any(|k| back[k] != back[k].clamp(0.0, domain_max[k].max(0.0))) {
+                counters[COUNTER_CLAMP_EVENTS] += 1;
+            }
+
+            let visc_decay = 1.0 / (1.0 + 4.0 * p.viscosity * dt_eff);
+            let (taps, fx, fy) = bilinear_taps(p, back);
+            let (sx, cx) = sample_scalar(p, taps, fx, fy, |i| src[i][0]);
+            let (sy, cy) = sample_scalar(p, taps, fx, fy, |i| src[i][1]);
+            if cx || cy {
+                counters[COUNTER_NEIGHBOR_CLAMP_VEL] += 1;
+            }
+            let mut v_hat = [sx, sy];
+            if p.advection == 1 {
+                let fwd = backtrace(p, src, pos, v, -dt_eff);
+                v_hat = std::array::from_fn(|k| {
+                    let channel = (v_hat[k], v[k]);
+                    maccormack(p, back, fwd, channel, |i| src[i][k], |i| pred[i][k])
+                });
+            }
+            let mut v_next = [v_hat[0] * visc_decay, v_hat[1] * visc_decay];
+
+            let rel = [pos[0] - center[0], pos[1] - center[1]];
+            let r = length(rel) / (p.width.min(p.height) as f32).max(1.0);
+            let mut power = 0.0;
+            if r <= p.dye_radius {
+                let t = [-rel[1] + 1e-4, rel[0]];
+                let tl = length(t);
+                let falloff = 1.0 - r / p.dye_radius.max(1e-3);
+                let ramp = if p.impulse_ramp_steps > 0 {
+                    ((p.step_index + 1) as f32 / p.impulse_ramp_steps as f32).min(1.0)
+                } else {
+                    1.0
+                };
+                let k = p.impulse * ramp * dt_eff * falloff;
+                let kick = [t[0] / tl * k, t[1] / tl * k];
+                let f = p.impulse * ramp * falloff;
+                power = t[0] / tl * f * (v_next[0] + 0.5 * kick[0])
+                    + t[1] / tl * f * (v_next[1] + 0.5 * kick[1]);
+                v_next[0] += kick[0];
+                v_next[1] += kick[1];
+            }
+            if forcing.len() == (p.width * p.height) as usize {
+                forcing[id] = [power, forcing[id][1] + power * dt_eff];
+            }
+            dst[id] = v_next;
+        }
+    }
+}
+
+fn diffuse_vel(
+    p: &Params,
+    src: &[Vec2],
+    rhs: &[Vec2],
+    ghost: &[Vec2],
+    solid: &[u32],
+    dst: &mut [Vec2],
+) {
+    let a = (p.viscosity * p.dt).max(0.0);
+    let wall_vel = |i: usize| {
+        if is_solid(solid, i) {
+            solid_vel(p, solid, i)
+        } else {
+            src[i]
+        }
+    };
+    for y in 0..p.height {
+        for x in 0..p.width {
+            let id = idx(p, x, y);
+            if is_edge(p, x, y) {
+                dst[id] = edge_vel(p, ghost, x, y);
+                continue;
+            }
+            if is_solid(solid, id) {
+                dst[id] = solid_vel(p, solid, id);
+                continue;
+            }
+            let (xi, yi) = (x as i32, y as i32);
+            let center = rhs[id];
+            let vl = wall_vel(idx(p, c(xi - 1, p.width), y));
+            let vr = wall_vel(idx(p, c(xi + 1, p.width), y));
+            let vb = wall_vel(idx(p, x, c(yi - 1, p.height)));
+            let vt = wall_vel(idx(p, x, c(yi + 1, p.height)));
+            let blend =
+                |k: usize| (center[k] + a * (vl[k] + vr[k] + vb[k] + vt[k])) / (1.0 + 4.0 * a);
+            dst[id] = [blend(0), blend(1)];
+        }
+    }
+}
+
+fn curl(p: &Params, vel: &[Vec2], solid: &[u32], curl: &mut [f32]) {
+    let wall_vel = |i: usize| {
+        if is_solid(solid, i) {
+            solid_vel(p, solid, i)
+        } else {
+            vel[i]
+        }
+    };
+    for y in 0..p.height {
+        for x in 0..p.width {
+            let id = idx(p, x, y);
+            if is_edge(p, x, y) || is_solid(solid, id) {
+                curl[id] = 0.0;
+                continue;
+            }
+            let vl = wall_vel(idx(p, x - 1, y));
+            let vr = wall_vel(idx(p, x + 1, y));
+            let vb = wall_vel(idx(p, x, y - 1));
+            let vt = wall_vel(idx(p, x, y + 1));
+            curl[id] = 0.5 * ((vr[1] - vl[1]) - (vt[0] - vb[0]));
+        }
+    }
+}
+
+fn vorticity_force(p: &Params, curl: &[f32], solid: &[u32], vel: &mut [Vec2]) {
+    for y in 0..p.height {
+        for x in 0..p.width {
+            let id = idx(p, x, y);
+            if is_edge(p, x, y) || is_solid(solid, id) {
+                continue;
+            }
+            let gx = 0.5 * (curl[idx(p, x + 1, y)].abs() - curl[idx(p, x - 1, y)].abs());
+            let gy = 0.5 * (curl[idx(p, x, y + 1)].abs() - curl[idx(p, x, y - 1)].abs());
+            let len = (gx * gx + gy * gy).sqrt() + 1e-5;
+            let (nx, ny) = (gx / len, gy / len);
+            let w = curl[id];
+            let k = p.dt * p.vorticity;
+            vel[id] = [vel[id][0] + k * (ny * w), vel[id][1] + k * (-nx * w)];
+        }
+    }
+}
+
+fn divergence(p: &Params, vel: &[Vec2], target: &[f32], solid: &[u32], div: &mut [f32]) {
+    let wall_vel = |i: usize| {
+        if is_solid(solid, i) {
+            solid_vel(p, solid, i)
+        } else {
+            vel[i]
+        }
+    };
+    for y in 0..p.height {
+        for x in 0..p.width {
+            let id = idx(p, x, y);
+            if is_edge(p, x, y) || is_solid(solid, id) {
+                div[id] = 0.0;
+                continue;
+            }
+            let (xi, yi) = (x as i32, y as i32);
+            let vl = wall_vel(idx(p, c(xi - 1, p.width), y))[0];
+            let vr = wall_vel(idx(p, c(xi + 1, p.width), y))[0];
+            let vb = wall_vel(idx(p, x, c(yi - 1, p.height)))[1];
+            let vt = wall_vel(idx(p, x, c(yi + 1, p.height)))[1];
+            div[id] = 0.5 * ((vr - vl) * p.inv_dx + (vt - vb) * p.inv_dy)
+                - target.get(id).copied().unwrap_or(0.0);
+        }
+    }
+}
+
+/// One `pressure_solver` iteration: `(p, p_in, div, solid, p_out)`.
+type PressureIteration = fn(&Params, &[f32], &[f32], &[u32], &mut [f32]);
+
+fn jacobi(p: &Params, p_in: &[f32], div: &[f32], solid: &[u32], p_out: &mut [f32]) {
+    let idx2 = p.inv_dx * p.inv_dx;
+    let idy2 = p.inv_dy * p.inv_dy;
+    let denom = (2.0 * (idx2 + idy2)).max(1e-6);
+    for y in 0..p.height {
+        for x in 0..p.width {
+            let id = idx(p, x, y);
+            if is_edge(p, x, y) || is_solid(solid, id) {
+                p_out[id] = 0.0;
+                continue;
+            }
+            let (xi, yi) = (x as i32, y as i32);
+            let p_at = |i: usize| wall_p(solid, i, p_in[i], p_in[id]);
+            let pl = p_at(idx(p, c(xi - 1, p.width), y));
+            let pr = p_at(idx(p, c(xi + 1, p.width), y));
+            let pb = p_at(idx(p, x, c(yi - 1, p.height)));
+            let pt = p_at(idx(p, x, c(yi + 1, p.height)));
+            p_out[id] = ((pl + pr) * idx2 + (pb + pt) * idy2 - div[id]) / denom;
+        }
+    }
+}
+
+/// Both phases of one `FLUID_RED_BLACK_WGSL` iteration.
+fn red_black(p: &Params, p_in: &[f32], div: &[f32], solid: &[u32], p_out: &mut [f32]) {
+    let idx2 = p.inv_dx * p.inv_dx;
+    let idy2 = p.inv_dy * p.inv_dy;
+    let denom = (2.0 * (idx2 + idy2)).max(1e-6);
+    let w = p.width as usize;
+    let update = |src: &[f32], id: usize, d: f32| {
+        let p_at = |i: usize| wall_p(solid, i, src[i], src[id]);
+        ((p_at(id - 1) + p_at(id + 1)) * idx2 + (p_at(id - w) + p_at(id + w)) * idy2 - d) / denom
+    };
+    for y in 0..p.height {
+        for x in 0..p.width {
+            let id = idx(p, x, y);
+            p_out[id] = if is_edge(p, x, y) || is_solid(solid, id) {
+                0.0
+            } else if (x + y) % 2 == 0 {
+                update(p_in, id, div[id])
+            } else {
+                p_in[id]
+            };
+        }
+    }
+    for y in 1..p.height.saturating_sub(1) {
+        for x in 1..p.width.saturating_sub(1) {
+            let id = idx(p, x, y);
+            if (x + y) % 2 == 1 && !is_solid(solid, id) {
+                p_out[id] = update(p_out, id, div[id]);
+            }
+        }
+    }
+}
+
+/// `FLUID_DYE_GRADIENT_WGSL`.
+fn dye_gradient(p: &Params, dye: &[f32], grad: &mut [f32]) {
+    for y in 0..p.height {
+        for x in 0..p.width {
+            let (xm, xp) = (x.saturating_sub(1), (x + 1).min(p.width - 1));
+            let (ym, yp) = (y.saturating_sub(1), (y + 1).min(p.height - 1));
+            let ddx = (dye[idx(p, xp, y)] - dye[idx(p, xm, y)]) * 0.5 * p.inv_dx;
+            let ddy = (dye[idx(p, x, yp)] - dye[idx(p, x, ym)]) * 0.5 * p.inv_dy;
+            grad[idx(p, x, y)] = (ddx * ddx + ddy * ddy).sqrt();
+        }
+    }
+}
+
+/// `FLUID_DELTA_WGSL`, interleaved `[|v - v_prev|, |dye - dye_prev|]`.
+fn delta(vel: &[Vec2], dye: &[f32], vel_prev: &[Vec2], dye_prev: &[f32], out: &mut [f32]) {
+    for (id, cell) in out.chunks_exact_mut(2).enumerate() {
+        let dv = [vel[id][0] - vel_prev[id][0], vel[id][1] - vel_prev[id][1]];
+        cell[0] = length(dv);
+        cell[1] = (dye[id] - dye_prev[id]).abs();
+    }
+}
+
+/// `FLUID_STREAMFUNCTION_RHS_WGSL`.
+fn streamfunction_rhs(p: &Params, vel: &[Vec2], rhs: &mut [f32]) {
+    for y in 0..p.height {
+        for x in 0..p.width {
+            let id = idx(p, x, y);
+            if is_edge(p, x, y) {
+                rhs[id] = 0.0;
+                continue;
+            }
+            let vl = vel[idx(p, x - 1, y)];
+            let vr = vel[idx(p, x + 1, y)];
+            let vb = vel[idx(p, x, y - 1)];
+            let vt = vel[idx(p, x, y + 1)];
+            let vorticity = (vr[1] - vl[1]) * 0.5 * p.inv_dx - (vt[0] - vb[0]) * 0.5 * p.inv_dy;
+            rhs[id] = -vorticity;
+        }
+    }
+}
+
+/// Okubo-Weiss parameter per cell (`FLUID_OKUBO_WEISS_WGSL`).
+fn okubo_weiss(p: &Params, vel: &[Vec2], ow: &mut [f32]) {
+    for y in 0..p.height {
+        for x in 0..p.width {
+            let id = idx(p, x, y);
+            if is_edge(p, x, y) {
+                ow[id] = 0.0;
+                continue;
+            }
+            let vl = vel[idx(p, x - 1, y)];
+            let vr = vel[idx(p, x + 1, y)];
+            let vb = vel[idx(p, x, y - 1)];
+            let vt = vel[idx(p, x, y + 1)];
+            let ddx = [
+                (vr[0] - vl[0]) * 0.5 * p.inv_dx,
+                (vr[1] - vl[1]) * 0.5 * p.inv_dx,
+            ];
+            let ddy = [
+                (vt[0] - vb[0]) * 0.5 * p.inv_dy,
+                (vt[1] - vb[1]) * 0.5 * p.inv_dy,
+            ];
+            let normal_strain = ddx[0] - ddy[1];
+            let shear_strain = ddx[1] + ddy[0];
+            let vorticity = ddx[1] - ddy[0];
+            ow[id] =
+                normal_strain * normal_strain + shear_strain * shear_strain - vorticity * vorticity;
+        }
+    }
+}
+
+/// Max |Lp - div| over interior fluid cells (`FLUID_RESIDUAL_WGSL`).
+fn residual(p: &Params, pressure: &[f32], div: &[f32], solid: &[u32]) -> f32 {
+    let idx2 = p.inv_dx * p.inv_dx;
+    let idy2 = p.inv_dy * p.inv_dy;
+    let mut max_r = 0.0f32;
+    for y in 1..p.height - 1 {
+        for x in 1..p.width - 1 {
+            let id = idx(p, x, y);
+            if is_solid(solid, id) {
+                continue;
+            }
+            let pc = pressure[id];
+            let p_at = |i: usize| wall_p(solid, i, pressure[i], pc);
+            let pl = p_at(idx(p, x - 1, y));
+            let pr = p_at(idx(p, x + 1, y));
+            let pb = p_at(idx(p, x, y - 1));
+            let pt = p_at(idx(p, x, y + 1));
+            let lap = (pl + pr - 2.0 * pc) * idx2 + (pb + pt - 2.0 * pc) * idy2;
+            max_r = max_r.max((lap - div[idx(p, x, y)]).abs());
+        }
+    }
+    max_r
+}
+
+fn project(
+    p: &Params,
+    vel: &[Vec2],
+    pressure: &[f32],
+    ghost: &[Vec2],
+    solid: &[u32],
+    out_vel: &mut [Vec2],
+    counters: &mut [u32; COUNTER_SLOTS],
+) {
+    for y in 0..p.height {
+        for x in 0..p.width {
+            let id = idx(p, x, y);
+            if !is_edge(p, x, y) && is_solid(solid, id) {
+                out_vel[id] = solid_vel(p, solid, id);
+                continue;
+            }
+            let (xi, yi) = (x as i32, y as i32);
+            let p_at = |i: usize| wall_p(solid, i, pressure[i], pressure[id]);
+            let pl = p_at(idx(p, c(xi - 1, p.width), c(yi, p.height)));
+            let pr = p_at(idx(p, c(xi + 1, p.width), c(yi, p.height)));
+            let pb = p_at(idx(p, c(xi, p.width), c(yi - 1, p.height)));
+            let pt = p_at(idx(p, c(xi, p.width), c(yi + 1, p.height)));
+            let grad = [(pr - pl) * 0.5 * p.inv_dx, (pt - pb) * 0.5 * p.inv_dy];
+
+            let mut v = [vel[id][0] - grad[0], vel[id][1] - grad[1]];
+            let speed = length(v);
+            if p.max_velocity > 0.0 && speed > p.max_velocity {
+                let k = p.max_velocity / speed;
+                v = [v[0] * k, v[1] * k];
+                if !is_edge(p, x, y) {
+                    counters[COUNTER_CLAMP_EVENTS] += 1;
+                }
+            }
+            if p.cfl_max > 0.0 {
+                let cap = p.cfl_max / p.dt;
+                let s = length(v);
+                if s > cap {
+                    v = [v[0] * (cap / s), v[1] * (cap / s)];
+                    if !is_edge(p, x, y) {
+                        counters[COUNTER_CFL_CLAMP] += 1;
+                    }
+                }
+            }
+            out_vel[id] = if is_edge(p, x, y) {
+                edge_vel(p, ghost, x, y)
+            } else {
+                v
+            };
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn advect_dye(
+    p: &Params,
+    channels: usize,
+    vel: &[Vec2],
+    dye_src: &[f32],
+    pred: &[f32],
+    dye_dst: &mut [f32],
+    counters: &mut [u32; COUNTER_SLOTS],
+    budget: &mut [f32],
+) {
+    for y in 0..p.height {
+        for x in 0..p.width {
+            let id = idx(p, x, y);
+            let v = vel[id];
+            let dt_eff = dt_eff(p, v);
+            let pos = [x as f32, y as f32];
+            let back = backtrace(p, vel, pos, v, dt_eff);
+            let fwd = (p.advection == 1).then(|| backtrace(p, vel, pos, v, -dt_eff));
+            let (taps, fx, fy) = bilinear_taps(p, back);
+            let mut any_clamped = false;
+            let mut lost = [0.0; 3];
+            for k in 0..channels {
+                let src = |i: usize| dye_src[channels * i + k];
+                let (mut d, clamped) = sample_scalar(p, taps, fx, fy, src);
+                any_clamped |= clamped;
+                if let Some(fwd) = fwd {
+                    let pred = |i: usize| pred[channels * i + k];
+                    d = maccormack(p, back, fwd, (d, src(id)), src, pred);
+                }
+                dye_dst[channels * id + k] = d;
+                lost[k] = src(id) - d;
+            }
+            if any_clamped {
+                counters[COUNTER_NEIGHBOR_CLAMP_DYE] += 1;
+            }
+            if p.dye_budget != 0 {
+                budget[3 * id + 2] += dye_luminance(&lost[..channels]);
+            }
+        }
+    }
+}
+
+/// Fade factor at unit height `v`, `r` away from the source (the shader's `local_fade`).
+pub(crate) fn local_fade(p: &Params, v: f32, r: f32) -> f32 {
+    match p.fade_gradient {
+        1 => mix(p.fade, p.fade_edge, (2.0 * r).min(1.0)),
+        2 => mix(p.fade, p.fade_edge, v),
+        _ => p.fade,
+    }
+}
+
+fn fade(p: &Params, channels: usize, src: &[f32], dst: &mut [f32], budget: &mut [f32]) {
+    let active = p.source_period == 0 || p.step_index % p.source_period < p.source_on_steps;
+    for y in 0..p.height {
+        for x in 0..p.width {
+            let cx = (x as f32 + 0.5) / p.width as f32 - p.source_center_x;
+            let cy = (y as f32 + 0.5) / p.height as f32 - p.source_center_y;
+            let r = length([cx, cy]);
+            let id = idx(p, x, y);
+            let source = if active && r <= p.dye_radius * 0.4 {
+                0.02
+            } else {
+                0.0
+            };
+            let fade = local_fade(p, (y as f32 + 0.5) / p.height as f32, r);
+            let (cell, added) = (
+                channels * id..channels * (id + 1),
+                tint(p, channels, source),
+            );
+            for (k, i) in cell.clone().enumerate() {
+                dst[i] = src[i] * fade + added[k];
+            }
+            if p.dye_budget != 0 {
+                budget[3 * id] += dye_luminance(&added[..channels]);
+                budget[3 * id + 1] += dye_luminance(&src[cell]) * (1.0 - fade);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytemuck::Zeroable;
+
+    #[test]
+    fn readback_follows_the_last_written_buffer() {
+        for projection_passes in 1..=4 {
+            for jacobi_iters in [5, 6] {
+                for viscosity in [0.0, 0.05] {
+                    let cfg: FluidStepRequest = serde_json::from_value(serde_json::json!({
+                        "width": 16, "height": 16, "backend": "cpu",
+                        "projection_passes": projection_passes,
+                        "jacobi_iters": jacobi_iters, "viscosity": viscosity,
+                    }))
+                    .expect("request");
+                    let mut sim = CpuFluid::new(&cfg).unwrap();
+                    sim.seed();
+                    sim.step(2);
+                    // the first projection writes vel_a and every further pass flips it
+                    let (latest, stale) = if projection_passes % 2 == 1 {
+                        (Slot::A, &sim.vel_b)
+                    } else {
+                        (Slot::B, &sim.vel_a)
+                    };
+                    let case = format!("{projection_passes} passes, {jacobi_iters} iters");
+                    assert_eq!(sim.vel_slot, latest, "{case}");
+                    assert_eq!(sim.dye_slot, Slot::A, "{case}");
+                    let fields = sim.read_fields();
+                    assert_eq!(fields.vel, sim.vel(), "{case}");
+                    assert_eq!(fields.dye, sim.dye_a, "{case}");
+                    if projection_passes > 1 {
+                        assert_ne!(&fields.vel, stale, "{case}");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Residual after `iters` iterations of `iterate` from zero pressure on a `w`x`h` grid with a
+    /// smooth divergence bump, relative to the bump's peak: the max over the whole interior and
+    /// over each of the four rings' sides next to the boundary (bottom, top, left, right).
+    fn relaxed(iterate: PressureIteration, w: u32, h: u32, iters: u32) -> (f32, [f32; 4]) {
+        let p = Params {
+            width: w,
+            height: h,
+            inv_dx: w as f32,
+            inv_dy: h as f32,
+            ..Params::zeroed()
+        };
+        let pi = std::f32::consts::PI;
+        let div: Vec<f32> = (0..w * h)
+            .map(|i| {
+                let (x, y) = (i % w, i / w);
+                if is_edge(&p, x, y) {
+                    return 0.0;
+                }
+                let bump =
+                    (pi * x as f32 / (w - 1) as f32).sin() * (pi * y as f32 / (h - 1) as f32).sin();
+                bump * (w * h) as f32
+            })
+            .collect();
+        let peak = div.iter().fold(0.0f32, |m, d| m.max(d.abs()));
+        let (mut a, mut b) = (vec![0.0; div.len()], vec![0.0; div.len()]);
+        for i in 0..iters {
+            if i % 2 == 0 {
+                iterate(&p, &a, &div, &[], &mut b);
+            } else {
+                iterate(&p, &b, &div, &[], &mut a);
+            }
+        }
+        let pressure = if iters % 2 == 1 { &b } else { &a };
+        let (idx2, idy2) = (p.inv_dx * p.inv_dx, p.inv_dy * p.inv_dy);
+        let (mut all, mut sides) = (0.0f32, [0.0f32; 4]);
+        for y in 1..h - 1 {
+            for x in 1..w - 1 {
+                let at = |x: u32, y: u32| pressure[idx(&p, x, y)];
+                let lap = (at(x - 1, y) + at(x + 1, y) - 2.0 * at(x, y)) * idx2
+                    + (at(x, y - 1) + at(x, y + 1) - 2.0 * at(x, y)) * idy2;
+                let r = (lap - div[idx(&p, x, y)]).abs() / peak;
+                all = all.max(r);
+                for (side, on) in [y == 1, y == h - 2, x == 1, x == w - 2]
+                    .into_iter()
+                    .enumerate()
+                {
+                    if on {
+                        sides[side] = sides[side].max(r);
+                    }
+                }
+            }
+        }
+        (all, sides)
+    }
+
+    #[test]
+    fn red_black_relaxes_every_grid_parity_alike() {
+        const ITERS: u32 = 40;
+        let mut residuals = Vec::new();
+        for (w, h) in [(15, 15), (16, 16), (16, 15)] {
+            let (all, [bottom, top, left, right]) = relaxed(red_black, w, h, ITERS);
+            let (jacobi_all, _) = relaxed(jacobi, w, h, ITERS);
+            assert!(
+                all < 0.9 * jacobi_all,
+                "{w}x{h}: red-black {all} vs jacobi {jacobi_all}"
+            );
+            // the colouring starts from the origin, so on an odd axis the far wall's cells are the
+            // other colour from the near wall's; both sides must still be relaxed alike
+            for (near, far) in [(bottom, top), (left, right)] {
+                assert!(
+                    near < all && far < all,
+                    "{w}x{h}: boundary rows lag the interior"
+                );
+                assert!(
+                    near.max(far) < 1.25 * near.min(far),
+                    "{w}x{h}: opposite sides {near} vs {far}"
+                );
+            }
+            residuals.push(all);
+        }
+        let (lo, hi) = residuals
+            .iter()
+            .fold((f32::MAX, 0.0f32), |(lo, hi), &r| (lo.min(r), hi.max(r)));
+        assert!(
+            hi < 1.5 * lo,
+            "residuals differ across parities: {residuals:?}"
+        );
+    }
+
+    #[test]
+    fn forcing_work_is_the_kinetic_energy_the_kick_adds() {
+        let cfg: FluidStepRequest = serde_json::from_value(serde_json::json!({
+            "width": 32, "height": 32, "backend": "cpu", "forcing_budget": true,
+        }))
+        .expect("request");
+        let mut sim = CpuFluid::new(&cfg).unwrap();
+        sim.seed();
+        let p = sim.p;
+        let advect = |p: &Params| {
+            let (mut dst, mut forcing) = (sim.vel_b.clone(), vec![[0.0; 2]; sim.vel_a.len()]);
+            let mut counters = [0; COUNTER_SLOTS];
+            advect_vel(
+                p,
+                &sim.vel_a,
+                &sim.vel_pred,
+                &sim.ghost,
+                &sim.force_path,
+                &sim.solid,
+                &mut dst,
+                &mut counters,
+                &mut forcing,
+            );
+            (dst, forcing)
+        };
+        let (forced, forcing) = advect(&p);
+        let (unforced, _) = advect(&Params { impulse: 0.0, ..p });
+        // summed over the grid, f . v dt is the |v|^2 / 2 the kick adds to the advected field
+        let energy = |vel: &[Vec2]| {
+            vel.iter()
+                .map(|v| 0.5 * (v[0] as f64 * v[0] as f64 + v[1] as f64 * v[1] as f64))
+                .sum::<f64>()
+        };
+        let added = energy(&forced) - energy(&unforced);
+        let work: f64 = forcing.iter().map(|c| c[1] as f64).sum();
+        assert!(added > 0.0);
+        assert!((work - added).abs() < 1e-4 * added, "{work} vs {added}");
+        let forced_cells = forcing.iter().filter(|c| c[0] != 0.0).count();
+        assert!(0 < forced_cells && forced_cells < forcing.len());
+    }
+
+    #[test]
+    fn viscosity_iterations_converge_on_the_implicit_step() {
+        let cfg: FluidStepRequest = serde_json::from_value(serde_json::json!({
+            "width": 32, "height": 32, "backend": "cpu", "viscosity": 0.5,
+        }))
+        .expect("request");
+        let mut sim = CpuFluid::new(&cfg).unwrap();
+        sim.seed();
+        let (p, rhs) = (sim.p, sim.vel_a.clone());
+        let solve = |iters: u32| {
+            let mut x = rhs.clone();
+            let mut next = rhs.clone();
+            for _ in 0..iters {
+                diffuse_vel(&p, &x, &rhs, &sim.ghost, &sim.solid, &mut next);
+                std::mem::swap(&mut x, &mut next);
+            }
+            x
+        };
+        let (few, many) = (solve(20), solve(200));
+        // more iterations refine the same solution of (1 - a lap) v = rhs instead of
+        // smoothing further
+        let a = p.viscosity * p.dt;
+        let mut residual = 0.0f32;
+        for y in 1..p.height - 1 {
+            for x in 1..p.width - 1 {
+                let v = |x: u32, y: u32| many[idx(&p, x, y)];
+                for (k, target) in rhs[idx(&p, x, y)].into_iter().enumerate() {
+                    let lap = v(x - 1, y)[k] + v(x + 1, y)[k] + v(x, y - 1)[k] + v(x, y + 1)[k]
+                        - 4.0 * v(x, y)[k];
+                    let r = v(x, y)[k] - a * lap - target;
+                    residual = residual.max(r.abs());
+                }
+            }
+        }
+        assert!(residual < 1e-5, "residual {residual}");
+        let diff = few
+            .iter()
+            .zip(&many)
+            .map(|(f, m)| (f[0] - m[0]).abs().max((f[1] - m[1]).abs()))
+            .fold(0.0, f32::max);
+        assert!(diff < 1e-4, "20 vs 200 iterations differ by {diff}");
+        assert!(rhs.iter().zip(&many).any(|(r, m)| r != m));
+    }
+
+    #[test]
+    fn vorticity_confinement_keeps_the_swirl_at_the_default_dt() {
+        let run = |vorticity: f32| {
+            let cfg: FluidStepRequest = serde_json::from_value(serde_json::json!({
+                "width": 48, "height": 48, "steps": 150, "backend": "cpu", "vorticity": vorticity,
+            }))
+            .expect("request");
+            let mut sim = CpuFluid::new(&cfg.clamped()).unwrap();
+            sim.seed();
+            sim.step(150);
+            let (p, vel) = (sim.p, sim.vel().to_vec());
+            let mut w = vec![0.0; vel.len()];
+            curl(&p, &vel, &[], &mut w);
+            let enstrophy: f64 = w.iter().map(|w| (*w as f64).powi(2)).sum();
+            (p, vel, enstrophy)
+        };
+        let (_, _, plain) = run(0.0);
+        let (p, vel, confined) = run(1.0);
+        assert!(
+            confined.is_finite() && confined > 1.05 * plain,
+            "{confined} vs {plain}"
+        );
+        assert!(
+            vel.iter()
+                .flatten()
+                .all(|v| v.is_finite() && v.abs() < 50.0)
+        );
+        for y in 0..p.height {
+            for x in 0..p.width {
+                if is_edge(&p, x, y) {
+                    assert_eq!(vel[idx(&p, x, y)], [0.0, 0.0], "edge cell ({x}, {y})");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bilinear_sampling_collapses_one_cell_axes() {
+        // a linear field is reproduced exactly wherever it can vary
+        let sample = |width: u32, height: u32, pos: Vec2| {
+            let p = Params {
+                width,
+                height,
+                ..Params::zeroed()
+            };
+            let (taps, fx, fy) = bilinear_taps(&p, pos);
+            let field = |i: usize| (i as u32 % width) as f32 + 10.0 * (i as u32 / width) as f32;
+            (sample_scalar(&p, taps, fx, fy, field).0, taps, fx, fy)
+        };
+        assert_eq!(sample(16, 16, [3.25, 4.5]).0, 48.25);
+
+        let (v, taps, fx, _) = sample(1, 16, [0.7, 4.5]);
+        assert_eq!((v, fx), (45.0, 0.0));
+        assert_eq!(taps[1], taps[0]);
+        assert_eq!(sample(1, 16, [-3.0, 4.5]).0, 45.0);
+
+        let (v, taps, _, fy) = sample(16, 1, [3.25, 2.0]);
+        assert_eq!((v, fy), (3.25, 0.0));
+        assert_eq!((taps[0], taps[1]), (taps[2], taps[3]));
+
+        assert_eq!(sample(1, 1, [5.0, -2.0]).0, 0.0);
+    }
+}
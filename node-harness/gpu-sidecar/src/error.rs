@@ -0,0 +1,78 @@
+//! Stable machine-readable `error_code`s for failed requests.
+//!
+//! Failures stay plain `anyhow` errors; the sites whose class matters tag them with
+//! `.code(ErrorCode::...)`, and `error_json` reports the first tag found in the chain
+//! (untagged errors are request validation failures, `invalid_request`).
+
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ErrorCode {
+    /// The request is not valid JSON or doesn't match the protocol.
+    InvalidJson,
+    /// The request parsed but its values are rejected (the default for untagged errors).
+    InvalidRequest,
+    /// No GPU adapter, or none matching `adapter_index` / `adapter_name`.
+    NoAdapter,
+    /// The grid exceeds a device buffer limit or an output's size cap.
+    GridTooLarge,
+    /// The GPU didn't finish submitted work within `GPU_WAIT_TIMEOUT`.
+    GpuTimeout,
+    /// Device creation, a buffer mapping or a wgpu validation/out-of-memory error failed.
+    DeviceError,
+    /// The option isn't available for this command.
+    UnsupportedFeature,
+    /// Reading or writing a file failed.
+    IoError,
+    /// `on_blowup: "error"` found a non-finite state.
+    Blowup,
+}
+
+/// An error tagged with its `ErrorCode`; displays as the wrapped error.
+#[derive(Debug)]
+struct CodedError {
+    code: ErrorCode,
+    error: anyhow::Error,
+}
+
+impl fmt::Display for CodedError {
+    // no `source()`: the full wrapped chain prints here instead
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#}", self.error)
+    }
+}
+
+impl std::error::Error for CodedError {}
+
+pub(crate) trait ResultExt<T> {
+    /// Tag the error (if any) with `code`.
+    fn code(self, code: ErrorCode) -> anyhow::Result<T>;
+}
+
+impl<T, E: Into<anyhow::Error>> ResultExt<T> for Result<T, E> {
+    fn code(self, code: ErrorCode) -> anyhow::Result<T> {
+        self.map_err(|error| coded(code, error.into()))
+    }
+}
+
+/// `error` tagged with `code`.
+pub(crate) fn coded(code: ErrorCode, error: anyhow::Error) -> anyhow::Error {
+    CodedError { code, error }.into()
+}
+
+pub(crate) fn error_code(err: &anyhow::Error) -> ErrorCode {
+    err.chain()
+        .find_map(|e| e.downcast_ref::<CodedError>())
+        .map_or(ErrorCode::InvalidRequest, |coded| coded.code)
+}
+
+/// `{"ok": false, "error", "error_code"}` for a failed request.
+pub(crate) fn error_json(err: &anyhow::Error) -> serde_json::Value {
+    serde_json::json!({
+        "ok": false,
+        "error": format!("{err:#}"),
+        "error_code": error_code(err),
+    })
+}
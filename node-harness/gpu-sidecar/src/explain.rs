@@ -0,0 +1,717 @@
+//! `explain: true`: describe what a `fluid_step` / `fluid_render` request would do instead of
+//! running it.
+//!
+//! The request is parsed and validated exactly as for a real run (so a bad request still
+//! fails), then the step sequence of `FluidSim::encode_step` is listed kernel by kernel with
+//! its dispatch counts, alongside a rough memory estimate and the parameters that were filled
+//! in or changed on the way.
+//!
+//! `pipeline` walks the same step sequence and draws it as a Graphviz graph of the kernels and
+//! the buffers each one reads and writes.
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    AdvectionScheme, DyeAdvectVelocity, FadeGradient, FieldStorage, FluidBackend, FluidStepRequest,
+    PressureSolver, ring_cells,
+};
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ExplainResponse {
+    ok: bool,
+    explain: bool,
+    /// The same as the fields below, in prose.
+    explanation: Vec<String>,
+    /// Kernels of one step, in dispatch order.
+    kernels: Vec<KernelStep>,
+    /// Dispatches outside the regular step: the seed, some steps only, readback kernels.
+    occasional: Vec<OccasionalDispatch>,
+    dispatches_per_step: u64,
+    dispatches_total: u64,
+    /// Per-cell field, scratch and staging buffers; small fixed buffers are left out.
+    estimated_memory_bytes: u64,
+    /// Parameters the request left out, as `name = value`.
+    defaulted: Vec<String>,
+    /// Parameters the request gave but the run changes (clamps, unit conversion).
+    adjusted: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct KernelStep {
+    kernel: &'static str,
+    per_step: u64,
+    note: String,
+}
+
+#[derive(Debug, Serialize)]
+struct OccasionalDispatch {
+    kernel: &'static str,
+    dispatches: u64,
+    note: String,
+}
+
+/// Explain `cfg` (already through `prepare`); `raw` is the request JSON it was parsed from,
+/// after the `preset` merge. `render` describes the image a `fluid_render` would write.
+pub(crate) fn explain(
+    raw: &Value,
+    cfg: &FluidStepRequest,
+    render: Option<String>,
+) -> ExplainResponse {
+    let kernels = kernel_steps(cfg);
+    let dispatches_per_step: u64 = kernels.iter().map(|k| k.per_step).sum();
+    let occasional = occasional_dispatches(cfg);
+    let once: u64 = occasional.iter().map(|o| o.dispatches).sum();
+    let dispatches_total = dispatches_per_step * cfg.steps as u64 + once;
+    let estimated_memory_bytes = estimated_memory_bytes(cfg);
+    let (defaulted, adjusted) = parameter_changes(raw, cfg);
+
+    let backend = match cfg.backend {
+        FluidBackend::Cpu => "the single-threaded CPU mirror",
+        _ => "the GPU",
+    };
+    let mut explanation = vec![
+        format!(
+            "Would run {} steps of a {}x{} grid on {backend}; nothing was run.",
+            cfg.steps, cfg.width, cfg.height
+        ),
+        "Every step runs:".to_owned(),
+    ];
+    for k in &kernels {
+        explanation.push(format!("  {} x{}: {}", k.kernel, k.per_step, k.note));
+    }
+    explanation.push("Outside the regular step:".to_owned());
+    for o in &occasional {
+        explanation.push(format!("  {} x{}: {}", o.kernel, o.dispatches, o.note));
+    }
+    explanation.push(format!(
+        "That is {dispatches_per_step} dispatches per step and {dispatches_total} in total \
+         ({once} outside the step loop)."
+    ));
+    explanation.push(format!(
+        "The field buffers take about {:.1} MiB.",
+        estimated_memory_bytes as f64 / (1024.0 * 1024.0)
+    ));
+    if let Some(render) = render {
+        explanation.push(render);
+    }
+    explanation.push(format!(
+        "{} parameters took their defaults (listed in `defaulted`).",
+        defaulted.len()
+    ));
+    if !adjusted.is_empty() {
+        explanation.push(format!("Adjusted: {}.", adjusted.join(", ")));
+    }
+
+    ExplainResponse {
+        ok: true,
+        explain: true,
+        explanation,
+        kernels,
+        occasional,
+        dispatches_per_step,
+        dispatches_total,
+        estimated_memory_bytes,
+        defaulted,
+        adjusted,
+    }
+}
+
+/// One step's dispatches, in the order `FluidSim::encode_step` encodes them.
+fn kernel_steps(cfg: &FluidStepRequest) -> Vec<KernelStep> {
+    let passes = cfg.projection_passes as u64;
+    let mut steps = Vec::new();
+    let mut push = |kernel, per_step, note: String| {
+        steps.push(KernelStep {
+            kernel,
+            per_step,
+            note,
+        })
+    };
+    if !cfg.obstacle_velocity.is_empty() {
+        push(
+            "move_obstacles",
+            1,
+            format!(
+                "re-rasterize the {} moving obstacles at this step's positions",
+                cfg.obstacle_velocity.len()
+            ),
+        );
+    }
+    if cfg.adaptive_dt {
+        push(
+            "dt_peaks",
+            1,
+            "max |v| and |curl v| of the velocity about to move".into(),
+        );
+        push(
+            "pick_dt",
+            1,
+            "one thread picks this step's dt from the CFL limits".into(),
+        );
+    }
+    if cfg.pressure_init != 0.0 {
+        push(
+            "fill",
+            passes,
+            format!("start each pressure solve from {}", cfg.pressure_init),
+        );
+    }
+    let maccormack = cfg.advection == AdvectionScheme::MacCormack;
+    if maccormack {
+        push(
+            "predict_vel",
+            1,
+            "semi-Lagrangian velocity the MacCormack correction traces forward".into(),
+        );
+    }
+    if cfg.storage == FieldStorage::Texture {
+        push(
+            "pack",
+            1,
+            "copy velocity and dye into the sampled texture (if the adapter has a format)".into(),
+        );
+    }
+    push(
+        "advect_vel",
+        1,
+        if maccormack {
+            "MacCormack velocity advection plus the tangential forcing"
+        } else {
+            "semi-Lagrangian velocity advection plus the tangential forcing"
+        }
+        .into(),
+    );
+    if cfg.viscosity > 0.0 {
+        push(
+            "diffuse_vel",
+            cfg.diffuse_iters as u64,
+            format!("viscosity {} Jacobi iterations", cfg.viscosity),
+        );
+    }
+    if cfg.vorticity > 0.0 {
+        push("curl", 1, "curl of the diffused velocity".into());
+        push(
+            "vorticity_force",
+            1,
+            format!("vorticity confinement of strength {}", cfg.vorticity),
+        );
+    }
+    let gated = cfg.target_divergence > 0.0;
+    if gated {
+        push(
+            "divergence",
+            passes + 1,
+            "once per projection pass, and after the last for target_divergence".into(),
+        );
+        push(
+            "max_divergence",
+            passes,
+            "max |div| ahead of each extra pass and after the last".into(),
+        );
+        push(
+            "divergence_gate",
+            passes - 1,
+            format!(
+                "one thread stops the extra passes once max |div| <= {}",
+                cfg.target_divergence
+            ),
+        );
+    } else {
+        push("divergence", passes, "once per projection pass".into());
+    }
+    let up_to = if gated { "up to " } else { "" };
+    let iterations = format!(
+        "{} pressure iterations for each of {up_to}{passes} projection passes",
+        cfg.jacobi_iters
+    );
+    match cfg.pressure_solver {
+        PressureSolver::Jacobi => push("jacobi", cfg.jacobi_iters as u64 * passes, iterations),
+        PressureSolver::RedBlack => push(
+            "red_black",
+            2 * cfg.jacobi_iters as u64 * passes,
+            format!("{iterations}, a red and a black half each"),
+        ),
+    }
+    push("project", passes, "subtract the pressure gradient".into());
+    if maccormack {
+        push(
+            "predict_dye",
+            1,
+            "semi-Lagrangian dye the MacCormack correction traces forward".into(),
+        );
+    }
+    push(
+        "advect_dye",
+        1,
+        match cfg.dye_advect_velocity {
+            DyeAdvectVelocity::Projected => "advect the dye through the projected velocity",
+            DyeAdvectVelocity::Raw => {
+                "advect the dye through the velocity as it was before the first projection"
+            }
+            DyeAdvectVelocity::Uploaded => "advect the dye through the uploaded advect_vel_b64",
+        }
+        .into(),
+    );
+    push(
+        "fade",
+        1,
+        match cfg.fade_gradient {
+            FadeGradient::None => {
+                format!("multiply the dye by {} and re-inject the source", cfg.fade)
+            }
+            gradient => format!(
+                "multiply the dye by a {gradient:?} blend of {} and {} and re-inject the source",
+                cfg.fade, cfg.fade_edge
+            ),
+        },
+    );
+    steps
+}
+
+/// Everything `kernel_steps` leaves out, with its total dispatch count over the run.
+fn occasional_dispatches(cfg: &FluidStepRequest) -> Vec<OccasionalDispatch> {
+    let mut out = Vec::new();
+    let mut push = |kernel, dispatches, note: String| {
+        out.push(OccasionalDispatch {
+            kernel,
+            dispatches,
+            note,
+        })
+    };
+    push(
+        "init",
+        1 + cfg.init_layers.len() as u64,
+        format!(
+            "seed the {:?} velocity and {:?} dye before the first step, then add {} init_layers",
+            cfg.init,
+            cfg.init_dye,
+            cfg.init_layers.len()
+        ),
+    );
+    if cfg.averages() {
+        push(
+            "accumulate",
+            cfg.steps.saturating_sub(cfg.time_average_start) as u64,
+            format!(
+                "after every step from step {}: add to the time_average sums",
+                cfg.time_average_start
+            ),
+        );
+    }
+    if cfg.residual_step().is_some() {
+        push(
+            "residual",
+            cfg.residual_points().len() as u64,
+            "inside the final step's first solve, for residual_history".into(),
+        );
+    }
+    if cfg.dump_okubo_weiss {
+        push("okubo_weiss", 1, "at readback, for dump_okubo_weiss".into());
+    }
+    if cfg.dump_dye_gradient {
+        push(
+            "dye_gradient",
+            1,
+            "at readback, for dump_dye_gradient".into(),
+        );
+    }
+    if cfg.delta_every > 0 {
+        push("delta", 1, "at readback, for delta_every".into());
+    }
+    if cfg.dump_streamfunction {
+        push(
+            "streamfunction_rhs",
+            1,
+            "at readback: -curl v for dump_streamfunction".into(),
+        );
+        push(
+            "jacobi",
+            cfg.streamfunction_iters as u64,
+            "at readback: the streamfunction solve".into(),
+        );
+        push(
+            "residual",
+            1,
+            "at readback: the streamfunction solve's residual".into(),
+        );
+    }
+    if let Some(lines) = cfg.steps.checked_div(cfg.progress_every) {
+        push(
+            "max_speed",
+            lines as u64,
+            format!("every {} steps, for the progress line", cfg.progress_every),
+        );
+    }
+    out
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct PipelineResponse {
+    ok: bool,
+    /// The command drawn (`fluid_step`, the only one with a pipeline).
+    #[serde(rename = "for")]
+    command: &'static str,
+    /// A Graphviz digraph: kernels (boxes) in dispatch order along the bold chain, buffer
+    /// operations as notes, buffers as ellipses with dashed read and solid write edges.
+    dot: String,
+}
+
+/// `pipeline`: the seed and one step of `cfg` (already through `prepare`) as a Graphviz graph,
+/// following the buffer choices of `FluidSim::encode_step`.
+pub(crate) fn pipeline(cfg: &FluidStepRequest) -> PipelineResponse {
+    let mut g = Graph::default();
+    let passes = cfg.projection_passes.max(1);
+    let iters = cfg.jacobi_iters;
+    // every pressure solve ping-pongs a -> b, b -> a, ...; an odd count ends in pressure_b
+    let (solved, other) = if iters % 2 == 1 {
+        ("pressure_b", "pressure_a")
+    } else {
+        ("pressure_a", "pressure_b")
+    };
+    let solve = match cfg.pressure_solver {
+        PressureSolver::Jacobi => format!("jacobi x{iters}"),
+        PressureSolver::RedBlack => format!("red_black x{iters} (red, then black)"),
+    };
+    let reset = |g: &mut Graph| {
+        if cfg.pressure_init == 0.0 {
+            g.op("clear", &[], &["pressure_a"]);
+        } else {
+            g.kernel("fill".into(), &[], &["pressure_a"]);
+        }
+    };
+
+    g.kernel(
+        "init (seed, once before the first step)".into(),
+        &[],
+        &["vel_a", "dye_a"],
+    );
+    if !cfg.obstacle_velocity.is_empty() {
+        g.kernel(
+            "move_obstacles".into(),
+            &["obstacle_start", "obstacles"],
+            &["obstacles"],
+        );
+    }
+    if cfg.adaptive_dt {
+        g.op("clear", &[], &["dt_peaks"]);
+        g.kernel("dt_peaks".into(), &["vel_a"], &["dt_peaks"]);
+        g.kernel("pick_dt".into(), &["dt_peaks"], &["dt_stats"]);
+        g.op("copy dt", &["dt_stats"], &["params"]);
+    }
+    reset(&mut g);
+    let maccormack = cfg.advection == AdvectionScheme::MacCormack;
+    if maccormack {
+        g.kernel("predict_vel".into(), &["vel_a"], &["vel_pred"]);
+    }
+    // the texture path samples the packed copy, the correction reads the predictor's output
+    let texture = cfg.storage == FieldStorage::Texture;
+    let sampled = |field| {
+        let mut reads = vec![if texture { "field_tex" } else { field }];
+        if maccormack {
+            reads.push(if field == "vel_a" {
+                "vel_pred"
+            } else {
+                "dye_pred"
+            });
+        }
+        reads
+    };
+    if texture {
+        g.kernel("pack".into(), &["vel_a", "dye_a"], &["field_tex"]);
+    }
+    g.kernel("advect_vel".into(), &sampled("vel_a"), &["vel_b"]);
+    if cfg.viscosity > 0.0 {
+        // iterations alternate between vel_a and vel_b, ending back in vel_b
+        g.op("copy", &["vel_b"], &["diffuse_rhs"]);
+        g.kernel(
+            format!("diffuse_vel x{}", cfg.diffuse_iters),
+            &["diffuse_rhs", "vel_b", "vel_a"],
+            &["vel_a", "vel_b"],
+        );
+    }
+    if cfg.vorticity > 0.0 {
+        g.kernel("curl".into(), &["vel_b"], &["curl"]);
+        g.kernel("vorticity_force".into(), &["curl", "vel_b"], &["vel_b"]);
+    }
+    if cfg.dye_advect_velocity == DyeAdvectVelocity::Raw {
+        g.op("copy", &["vel_b"], &["dye_vel"]);
+    }
+    let mut vel = "vel_b";
+    for pass in 0..passes {
+        let suffix = if passes > 1 {
+            format!(" [pass {}]", pass + 1)
+        } else {
+            String::new()
+        };
+        if pass > 0 {
+            reset(&mut g);
+        }
+        let out = if vel == "vel_b" { "vel_a" } else { "vel_b" };
+        g.kernel(format!("divergence{suffix}"), &[vel], &["div"]);
+        if pass > 0 && cfg.target_divergence > 0.0 {
+            g.kernel(format!("max_divergence{suffix}"), &["div"], &["gate"]);
+            g.kernel(format!("divergence_gate{suffix}"), &["gate"], &["gate"]);
+        }
+        let reads: &[&str] = if iters > 1 {
+            &["pressure_a", "pressure_b", "div"]
+        } else {
+            &["pressure_a", "div"]
+        };
+        let writes: &[&str] = if iters > 1 {
+            &[solved, other]
+        } else {
+            &[solved]
+        };
+        g.kernel(format!("{solve}{suffix}"), reads, writes);
+        g.kernel(format!("project{suffix}"), &[vel, solved], &[out]);
+        vel = out;
+    }
+    if cfg.target_divergence > 0.0 {
+        g.kernel("divergence (after the last pass)".into(), &[vel], &["div"]);
+        g.kernel("max_divergence".into(), &["div"], &["gate"]);
+    }
+    let advect_vel = match cfg.dye_advect_velocity {
+        DyeAdvectVelocity::Projected => vel,
+        DyeAdvectVelocity::Raw | DyeAdvectVelocity::Uploaded => "dye_vel",
+    };
+    if maccormack {
+        g.kernel("predict_dye".into(), &[advect_vel, "dye_a"], &["dye_pred"]);
+    }
+    g.kernel(
+        "advect_dye".into(),
+        &[&[advect_vel][..], &sampled("dye_a")].concat(),
+        &["dye_b"],
+    );
+    g.kernel("fade".into(), &["dye_b"], &["dye_a"]);
+
+    PipelineResponse {
+        ok: true,
+        command: "fluid_step",
+        dot: g.finish(&format!(
+            "fluid_step {}x{}: one step, velocity ends in {vel}, dye in dye_a",
+            cfg.width, cfg.height
+        )),
+    }
+}
+
+/// The nodes and edges of `pipeline`'s graph, built up in dispatch order.
+#[derive(Default)]
+struct Graph {
+    lines: Vec<String>,
+    buffers: Vec<&'static str>,
+    steps: usize,
+}
+
+impl Graph {
+    fn kernel(&mut self, label: String, reads: &[&'static str], writes: &[&'static str]) {
+        self.node(label, "shape=box", reads, writes);
+    }
+
+    /// A buffer operation recorded between dispatches (clear, copy).
+    fn op(&mut self, label: &str, reads: &[&'static str], writes: &[&'static str]) {
+        self.node(label.into(), "shape=note, style=dashed", reads, writes);
+    }
+
+    fn node(
+        &mut self,
+        label: String,
+        attrs: &str,
+        reads: &[&'static str],
+        writes: &[&'static str],
+    ) {
+        let id = self.steps;
+        self.lines
+            .push(format!("  s{id} [label=\"{}. {label}\", {attrs}];", id + 1));
+        if id > 0 {
+            self.lines
+                .push(format!("  s{} -> s{id} [style=bold, weight=10];", id - 1));
+        }
+        for &buffer in reads.iter().chain(writes) {
+            if !self.buffers.contains(&buffer) {
+                self.buffers.push(buffer);
+            }
+        }
+        for buffer in reads {
+            self.lines.push(format!(
+                "  {buffer} -> s{id} [style=dashed, constraint=false];"
+            ));
+        }
+        for buffer in writes {
+            self.lines
+                .push(format!("  s{id} -> {buffer} [constraint=false];"));
+        }
+        self.steps += 1;
+    }
+
+    fn finish(self, title: &str) -> String {
+        let mut dot =
+            format!("digraph fluid_step {{\n  label=\"{title}\";\n  labelloc=t;\n  rankdir=TB;\n");
+        for buffer in &self.buffers {
+            dot += &format!("  {buffer} [shape=ellipse, style=filled, fillcolor=lightgray];\n");
+        }
+        for line in &self.lines {
+            dot += line;
+            dot.push('\n');
+        }
+        dot += "}\n";
+        dot
+    }
+}
+
+/// Bytes of every buffer that scales with the grid (see `FluidSim::new`).
+fn estimated_memory_bytes(cfg: &FluidStepRequest) -> u64 {
+    let cells = cfg.width as u64 * cfg.height as u64;
+    // vel a/b, dye a/b, div, pressure a/b, diag, plus the velocity and dye staging buffers
+    let mut per_cell = 2 * 8 + 2 * 4 + 4 + 2 * 4 + 4 + 8 + 4;
+    if cfg.dye_budget {
+        per_cell += 3 * 4;
+    }
+    if cfg.forcing_budget {
+        per_cell += 8;
+    }
+    if cfg.viscosity > 0.0 {
+        per_cell += 8;
+    }
+    if cfg.vorticity > 0.0 {
+        per_cell += 4;
+    }
+    if cfg.advection == AdvectionScheme::MacCormack {
+        per_cell += 8 + 4;
+    }
+    if cfg.time_average {
+        per_cell += 4;
+    }
+    if cfg.time_average_vel {
+        per_cell += 8;
+    }
+    if !cfg.div_sources.is_empty() {
+        per_cell += 4;
+    }
+    if cfg.obstacles.is_some() {
+        per_cell += 4;
+    }
+    if cfg.channels == 3 {
+        // dye a/b, its staging buffer and any predictor hold a 16-byte `vec3<f32>` a cell
+        let dye_buffers = if cfg.advection == AdvectionScheme::MacCormack {
+            4
+        } else {
+            3
+        };
+        per_cell += dye_buffers * 12;
+    }
+    if cfg.dye_advect_velocity != DyeAdvectVelocity::Projected {
+        per_cell += 8;
+    }
+    if cfg.storage == FieldStorage::Texture {
+        // rgba32float, the larger of the two texture formats
+        per_cell += 16;
+    }
+    let ghost = if cfg.ghost_b64.is_some() {
+        ring_cells(cfg.width, cfg.height) as u64 * 8
+    } else {
+        0
+    };
+    cells * per_cell + ghost
+}
+
+/// (`defaulted`, `adjusted`): every resolved parameter missing from `raw`, and every given one
+/// whose resolved value differs from it.
+fn parameter_changes(raw: &Value, cfg: &FluidStepRequest) -> (Vec<String>, Vec<String>) {
+    let (mut defaulted, mut adjusted) = (Vec::new(), Vec::new());
+    let Ok(Value::Object(resolved)) = serde_json::to_value(cfg) else {
+        return (defaulted, adjusted);
+    };
+    for (key, value) in &resolved {
+        match raw.get(key) {
+            None => defaulted.push(format!("{key} = {}", show(value))),
+            Some(given) if !same_value(given, value) => {
+                adjusted.push(format!("{key} {} -> {}", show(given), show(value)))
+            }
+            Some(_) => {}
+        }
+    }
+    (defaulted, adjusted)
+}
+
+/// JSON equality that compares floats at `f32` precision (the request fields are `f32`).
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => match (x.as_u64(), y.as_u64()) {
+            (Some(x), Some(y)) => x == y,
+            _ => x.as_f64().map(|v| v as f32) == y.as_f64().map(|v| v as f32),
+        },
+        (Value::Array(x), Value::Array(y)) => {
+            x.len() == y.len() && x.iter().zip(y).all(|(x, y)| same_value(x, y))
+        }
+        (Value::Object(x), Value::Object(y)) => {
+            x.len() == y.len()
+                && x.iter()
+                    .all(|(k, v)| y.get(k).is_some_and(|w| same_value(v, w)))
+        }
+        _ => a == b,
+    }
+}
+
+/// `value` as JSON with floats at `f32` precision (`0.8`, not `0.800000011920929`).
+fn show(value: &Value) -> String {
+    match value {
+        Value::Number(n) if n.as_u64().is_none() && n.as_i64().is_none() => n
+            .as_f64()
+            .map_or_else(|| n.to_string(), |v| (v as f32).to_string()),
+        Value::Array(items) => format!(
+            "[{}]",
+            items.iter().map(show).collect::<Vec<_>>().join(", ")
+        ),
+        _ => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_dispatches_and_reports_clamps() {
+        let raw = serde_json::json!({
+            "width": 32, "height": 32, "steps": 3, "jacobi_iters": 500,
+            "projection_passes": 1, "viscosity": 0.0, "explain": true,
+        });
+        let cfg: FluidStepRequest = serde_json::from_value(raw.clone()).expect("request");
+        let cfg = cfg.prepare().expect("valid config").0;
+        let resp = explain(&raw, &cfg, None);
+        // advect_vel, divergence, 120 jacobi, project, advect_dye, fade; plus the seed
+        assert_eq!(resp.dispatches_per_step, 125);
+        assert_eq!(resp.dispatches_total, 3 * 125 + 1);
+        assert_eq!(resp.adjusted, ["jacobi_iters 500 -> 120"]);
+        assert!(resp.defaulted.iter().any(|d| d == "fade = 0.995"));
+        assert!(!resp.defaulted.iter().any(|d| d.starts_with("width")));
+    }
+
+    #[test]
+    fn pipeline_follows_the_ping_pong() {
+        for (passes, iters, vel, (read, unread)) in [
+            (1, 6, "vel_a", ("pressure_a", "pressure_b")),
+            (2, 5, "vel_b", ("pressure_b", "pressure_a")),
+            (3, 6, "vel_a", ("pressure_a", "pressure_b")),
+        ] {
+            let cfg: FluidStepRequest = serde_json::from_value(serde_json::json!({
+                "width": 16, "height": 16, "projection_passes": passes, "jacobi_iters": iters,
+            }))
+            .expect("request");
+            let dot = pipeline(&cfg.prepare().expect("valid config").0).dot;
+            assert!(dot.contains(&format!("velocity ends in {vel}")), "{dot}");
+            // every projection subtracts the gradient of the buffer its solve ended in
+            let projects: Vec<&str> = dot
+                .lines()
+                .filter(|l| l.contains(". project"))
+                .filter_map(|l| l.trim().split(' ').next())
+                .collect();
+            assert_eq!(projects.len(), passes);
+            for id in projects {
+                assert!(dot.contains(&format!("{read} -> {id} ")), "{dot}");
+                assert!(!dot.contains(&format!("{unread} -> {id} ")), "{dot}");
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Process-level reuse of GPU devices and the pipelines compiled on them.
+//!
+//! Fluid simulations, smoke, `fluid_init` and `device_info` get their device from `device` and every kernel
+//! goes through `pipeline`, so the runs of one process (sweeps, `--interactive`, `--serve`)
+//! share a device per adapter and feature set and compile each kernel on it once. Pipelines for
+//! devices created elsewhere (kernel bench, `shader_check`) are compiled fresh and not kept.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::thread::ThreadId;
+
+pub(crate) struct CachedDevice {
+    pub(crate) adapter: wgpu::AdapterInfo,
+    pub(crate) features: wgpu::Features,
+    pub(crate) device: wgpu::Device,
+    queue: wgpu::Queue,
+    /// Keyed by WGSL source.
+    pipelines: HashMap<String, wgpu::ComputePipeline>,
+    /// Pipelines compiled on this device (cache misses), by the thread that compiled them: a
+    /// setup runs on one thread, so one compiling elsewhere at the same time can't turn its
+    /// hit into a miss.
+    compiled: HashMap<ThreadId, u32>,
+}
+
+static DEVICES: Mutex<Vec<CachedDevice>> = Mutex::new(Vec::new());
+
+fn devices() -> MutexGuard<'static, Vec<CachedDevice>> {
+    // a panic mid-insert leaves the list itself intact
+    DEVICES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// The cached device for `adapter` with the `wanted` features it supports, created on first use.
+pub(crate) async fn device(
+    adapter: &wgpu::Adapter,
+    wanted: wgpu::Features,
+) -> Result<(wgpu::Device, wgpu::Queue)> {
+    let info = adapter.get_info();
+    let features = wanted & adapter.features();
+    if let Some(cached) = devices()
+        .iter()
+        .find(|d| d.adapter == info && d.features == features)
+    {
+        return Ok((cached.device.clone(), cached.queue.clone()));
+    }
+    let (device, queue) = crate::request_device(adapter, features).await?;
+    devices().push(CachedDevice {
+        adapter: info,
+        features,
+        device: device.clone(),
+        queue: queue.clone(),
+        pipelines: HashMap::new(),
+        compiled: HashMap::new(),
+    });
+    Ok((device, queue))
+}
+
+/// Drop the cached devices `failed` picks after a `device_error`, so later requests create a
+/// fresh device instead of reusing one in an unknown (possibly lost) state. Handles already
+/// out keep working; their pipelines are no longer cached.
+pub(crate) fn evict(failed: impl Fn(&CachedDevice) -> bool) {
+    devices().retain(|d| !failed(d));
+}
+
+/// `wgsl`'s pipeline on `device`: reused when `device` is cached and compiled it before.
+pub(crate) fn pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    wgsl: &str,
+    compile: impl FnOnce(&wgpu::Device, &str, &str) -> wgpu::ComputePipeline,
+) -> wgpu::ComputePipeline {
+    let mut devices = devices();
+    let cached = devices.iter_mut().find(|d| d.device == *device);
+    if let Some(pipeline) = cached
+        .as_ref()
+        .and_then(|cached| cached.pipelines.get(wgsl))
+    {
+        return pipeline.clone();
+    }
+    let pipeline = compile(device, label, wgsl);
+    if let Some(cached) = cached {
+        *cached
+            .compiled
+            .entry(std::thread::current().id())
+            .or_default() += 1;
+        cached.pipelines.insert(wgsl.to_owned(), pipeline.clone());
+    }
+    pipeline
+}
+
+/// Pipelines this thread compiled on the cached `device` so far; unchanged across a setup that
+/// hit the cache. `None` when `device` isn't cached (every pipeline on it compiles).
+pub(crate) fn compiled(device: &wgpu::Device) -> Option<u32> {
+    let devices = devices();
+    let cached = devices.iter().find(|d| d.device == *device)?;
+    Some(
+        cached
+            .compiled
+            .get(&std::thread::current().id())
+            .copied()
+            .unwrap_or(0),
+    )
+}
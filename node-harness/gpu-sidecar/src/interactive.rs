@@ -0,0 +1,267 @@
+//! `--interactive`: a line-oriented REPL over one persistent simulation.
+//!
+//! The device and the sim are created once (from the optional `fluid_step` JSON after the flag,
+//! e.g. `--interactive '{"width":256,"height":256}'`) and every command works on that state.
+//! Each command prints one JSON response; errors print `{"ok": false, "error", "error_code"}`
+//! and the session carries on.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::io::{BufRead, Write};
+
+use crate::error::{ErrorCode, ResultExt, error_json};
+use crate::live::{self, LiveRenderRequest};
+use crate::presets;
+use crate::stream::{self, FrameFormat, StreamTcpRequest};
+use crate::{FluidRenderRequest, FluidRun, FluidStats, FluidStepRequest, render, summarize};
+
+const HELP: &str = "commands:
+  step [n]                       advance n steps (default 1)
+  dt <value>                     change the time step, keeping the fields
+  stats                          read back and summarize the current fields
+  render <path> [field] [map]    write a PNG (field: dye|speed, map: viridis|magma|gray|flow_hsv)
+  live <dir> <fps> <steps>       step and write a numbered dye PNG per step into dir at fps
+  stream <addr> <fps> <frames>   step and send a dye PNG per step to a TCP listener at fps
+  reset                          reseed and rewind to step 0
+  help                           this text
+  quit                           leave (also end of input)";
+
+const DEFAULT_CONFIG: &str = r#"{"width":128,"height":128}"#;
+
+#[derive(Serialize)]
+struct StepResponse {
+    ok: bool,
+    steps_done: u32,
+    elapsed_ms: f64,
+    sps: f64,
+}
+
+#[derive(Serialize)]
+struct StatsResponse {
+    ok: bool,
+    steps_done: u32,
+    #[serde(flatten)]
+    stats: FluidStats,
+}
+
+#[derive(Serialize)]
+struct RenderResponse {
+    ok: bool,
+    steps_done: u32,
+    image_path: String,
+    image_width: u32,
+    image_height: u32,
+    value_range: [f32; 2],
+}
+
+#[derive(Serialize)]
+struct ParamsResponse {
+    ok: bool,
+    steps_done: u32,
+    dt: f32,
+}
+
+struct Session {
+    cfg: FluidStepRequest,
+    sim: FluidRun,
+}
+
+pub(crate) fn run(config: Option<&str>) -> Result<()> {
+    let cfg = session_config(config.unwrap_or(DEFAULT_CONFIG))?;
+    let (mut sim, adapter) = pollster::block_on(FluidRun::new(&cfg))?;
+    sim.seed();
+    let mut session = Session { cfg, sim };
+    eprintln!(
+        "{}x{} on {}; type `help` for commands",
+        session.cfg.width,
+        session.cfg.height,
+        adapter.as_deref().unwrap_or("cpu")
+    );
+
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        eprint!("> ");
+        std::io::stderr().flush()?;
+        let Some(line) = lines.next().transpose()? else {
+            break;
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => continue,
+            ["quit" | "exit"] => break,
+            ["help"] => println!("{HELP}"),
+            words => {
+                let out = session
+                    .command(words)
+                    .unwrap_or_else(|err| error_json(&err));
+                println!("{}", serde_json::to_string_pretty(&out)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parse the `--interactive` config as a `fluid_step` request: its `preset` and then the
+/// `FLUID_*` environment defaults fill in what it leaves out, as in `dispatch`.
+fn session_config(config: &str) -> Result<FluidStepRequest> {
+    let mut value: serde_json::Value = serde_json::from_str(config)
+        .context("invalid --interactive config")
+        .code(ErrorCode::InvalidJson)?;
+    if let Some(obj) = value.as_object_mut() {
+        obj.insert("cmd".into(), "fluid_step".into());
+    }
+    let filled = presets::fill(&mut value)?;
+    let cfg: FluidStepRequest = serde_json::from_value(value)
+        .with_context(|| match filled.env_keys() {
+            [] => "invalid --interactive config".to_owned(),
+            env => format!(
+                "invalid --interactive config (with FLUID_* environment defaults for {})",
+                env.join(", ")
+            ),
+        })
+        .code(ErrorCode::InvalidJson)?;
+    Ok(cfg.prepare()?.0)
+}
+
+impl Session {
+    fn command(&mut self, words: &[&str]) -> Result<serde_json::Value> {
+        let steps_done = self.sim.steps_done();
+        let value = match words {
+            ["step"] => self.step(1)?,
+            ["step", n] => {
+                self.step(n.parse().with_context(|| format!("bad step count {n:?}"))?)?
+            }
+            ["dt", dt] => {
+                let dt: f32 = dt.parse().with_context(|| format!("bad dt {dt:?}"))?;
+                self.cfg = FluidStepRequest {
+                    dt,
+                    ..self.cfg.clone()
+                }
+                .clamped();
+                self.sim.set_params(&self.cfg);
+                serde_json::to_value(ParamsResponse {
+                    ok: true,
+                    steps_done,
+                    dt: self.cfg.dt,
+                })?
+            }
+            ["stats"] => {
+                let fields = self.sim.read_fields()?;
+                serde_json::to_value(StatsResponse {
+                    ok: true,
+                    steps_done,
+                    stats: summarize(&self.cfg, steps_done, &fields.vel, &fields.dye),
+                })?
+            }
+            ["render", path, rest @ ..] if rest.len() <= 2 => self.render(path, rest)?,
+            ["live", dir, fps, steps] => {
+                let req = LiveRenderRequest {
+                    render: self.render_request(None, &[])?,
+                    fps: fps.parse().with_context(|| format!("bad fps {fps:?}"))?,
+                    out_dir: dir.to_string(),
+                    steps_per_frame: 1,
+                    max_steps: Some(
+                        steps_done
+                            + steps
+                                .parse::<u32>()
+                                .with_context(|| format!("bad step count {steps:?}"))?,
+                    ),
+                };
+                serde_json::to_value(live::run_loop(&mut self.sim, &req)?)?
+            }
+            ["stream", addr, fps, frames] => {
+                let req = StreamTcpRequest {
+                    render: self.render_request(None, &[])?,
+                    addr: addr.to_string(),
+                    fps: fps.parse().with_context(|| format!("bad fps {fps:?}"))?,
+                    frames: frames
+                        .parse()
+                        .with_context(|| format!("bad frame count {frames:?}"))?,
+                    steps_per_frame: 1,
+                    format: FrameFormat::Png,
+                };
+                serde_json::to_value(stream::run_loop(&mut self.sim, &req)?)?
+            }
+            ["reset"] => {
+                self.sim.restart();
+                serde_json::json!({"ok": true, "steps_done": 0})
+            }
+            _ => anyhow::bail!("unknown command {:?}; type `help`", words.join(" ")),
+        };
+        Ok(value)
+    }
+
+    fn step(&mut self, steps: u32) -> Result<serde_json::Value> {
+        let t0 = std::time::Instant::now();
+        self.sim.step(steps);
+        self.sim.wait();
+        let elapsed = t0.elapsed().as_secs_f64();
+        Ok(serde_json::to_value(StepResponse {
+            ok: true,
+            steps_done: self.sim.steps_done(),
+            elapsed_ms: elapsed * 1000.0,
+            sps: steps as f64 / elapsed.max(1e-6),
+        })?)
+    }
+
+    /// Render at grid size; `rest` is an optional field and colormap (by their JSON names).
+    fn render(&mut self, path: &str, rest: &[&str]) -> Result<serde_json::Value> {
+        let req = self.render_request(Some(path), rest)?;
+        let (width, height) = (self.cfg.width, self.cfg.height);
+        let fields = self.sim.read_fields()?;
+        let palette = render::Palette::new(req.colormap, &req.colormap_stops)?;
+        let (rgba, value_range) = render::render_rgba(&req, &palette, &fields, width, height);
+        crate::write_png_rgba(path, width, height, &rgba)?;
+        Ok(serde_json::to_value(RenderResponse {
+            ok: true,
+            steps_done: self.sim.steps_done(),
+            image_path: path.to_owned(),
+            image_width: width,
+            image_height: height,
+            value_range,
+        })?)
+    }
+
+    /// A grid-size render of the session's sim; `rest` as for `render`.
+    fn render_request(&self, path: Option<&str>, rest: &[&str]) -> Result<FluidRenderRequest> {
+        let name = |word: &str| serde_json::Value::String(word.to_owned());
+        Ok(FluidRenderRequest {
+            sim: self.cfg.clone(),
+            out_path: path.map(str::to_owned),
+            data_url: false,
+            field: match rest.first() {
+                Some(&field) => serde_json::from_value(name(field))
+                    .with_context(|| format!("bad field {field:?}"))?,
+                None => Default::default(),
+            },
+            colormap: match rest.get(1) {
+                Some(&map) => serde_json::from_value(name(map))
+                    .with_context(|| format!("bad colormap {map:?}"))?,
+                None => Default::default(),
+            },
+            colormap_stops: Vec::new(),
+            square_pixels: true,
+            out_width: None,
+            out_height: None,
+            resample: Default::default(),
+            overlay: None,
+            overlay_alpha: 0.0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_session_config_loads_its_preset() {
+        let cfg = session_config(r#"{"preset":"tight_vortex","width":64,"backend":"cpu"}"#)
+            .expect("shipped preset");
+        assert_eq!((cfg.width, cfg.height, cfg.jacobi_iters), (64, 256, 40));
+        let err = session_config(r#"{"preset":"does_not_exist","width":16,"height":16}"#)
+            .expect_err("missing preset");
+        assert!(format!("{err:#}").contains("does_not_exist"), "{err:#}");
+    }
+}
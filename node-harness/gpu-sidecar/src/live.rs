@@ -0,0 +1,173 @@
+//! `live_render`: step one simulation and write a numbered PNG per frame at a target frame rate,
+//! as a simple animation server.
+//!
+//! Frames are paced against absolute deadlines (`start + k / fps`), so the time spent stepping,
+//! reading back and encoding counts against the frame it belongs to and only the rest is slept
+//! off. A frame that misses its deadline is counted as late and the schedule restarts from
+//! now rather than bursting to catch up. Without `max_steps` the loop runs until the process
+//! is interrupted; the frames already written stay on disk.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::error::{ErrorCode, ResultExt};
+use crate::{FluidRenderRequest, FluidRun, render, write_png_rgba};
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct LiveRenderRequest {
+    /// The simulation and how each frame is drawn (`out_path` / `data_url` are unused).
+    #[serde(flatten)]
+    pub(crate) render: FluidRenderRequest,
+    pub(crate) fps: f64,
+    pub(crate) out_dir: String,
+    #[serde(default = "default_steps_per_frame")]
+    pub(crate) steps_per_frame: u32,
+    /// Stop once this many steps have run (`None`: until interrupted).
+    #[serde(default)]
+    pub(crate) max_steps: Option<u32>,
+}
+
+fn default_steps_per_frame() -> u32 {
+    1
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct LiveRenderResponse {
+    ok: bool,
+    out_dir: String,
+    frames_written: u32,
+    /// Frames that finished after their deadline.
+    late_frames: u32,
+    target_fps: f64,
+    achieved_fps: f64,
+    steps_done: u32,
+    image_width: u32,
+    image_height: u32,
+}
+
+/// Set up and seed the request's simulation, then run the frame loop on it.
+pub(crate) async fn run(req: &LiveRenderRequest) -> Result<LiveRenderResponse> {
+    let (mut sim, _adapter) = FluidRun::new(&req.render.sim).await?;
+    sim.seed();
+    run_loop(&mut sim, req)
+}
+
+/// The frame loop on an existing simulation (also `--interactive`'s `live`): step
+/// `steps_per_frame`, render, write `frame_NNNNNN.png` to `out_dir`, sleep to the next deadline.
+pub(crate) fn run_loop(sim: &mut FluidRun, req: &LiveRenderRequest) -> Result<LiveRenderResponse> {
+    let frame = Frame::new(&req.render, "live_render")?;
+    let mut pacer = Pacer::new(req.fps)?;
+    std::fs::create_dir_all(&req.out_dir)
+        .with_context(|| format!("create {}", req.out_dir))
+        .code(ErrorCode::IoError)?;
+    let steps_per_frame = req.steps_per_frame.max(1);
+
+    let mut frames = 0u32;
+    loop {
+        let steps = match req.max_steps {
+            Some(max) => steps_per_frame.min(max.saturating_sub(sim.steps_done())),
+            None => steps_per_frame,
+        };
+        if steps == 0 {
+            break;
+        }
+        let rgba = frame.render(sim, steps)?;
+        let path = format!("{}/frame_{frames:06}.png", req.out_dir);
+        write_png_rgba(&path, frame.width, frame.height, &rgba)?;
+        frames += 1;
+        pacer.wait();
+    }
+    Ok(LiveRenderResponse {
+        ok: true,
+        out_dir: req.out_dir.clone(),
+        frames_written: frames,
+        late_frames: pacer.late,
+        target_fps: req.fps,
+        achieved_fps: pacer.achieved_fps(frames),
+        steps_done: sim.steps_done(),
+        image_width: frame.width,
+        image_height: frame.height,
+    })
+}
+
+/// How each frame of a paced loop is drawn: the render request's image size and palette.
+pub(crate) struct Frame<'a> {
+    render: &'a FluidRenderRequest,
+    palette: render::Palette,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+}
+
+impl<'a> Frame<'a> {
+    /// Validate `render` for `command`, which draws no overlays.
+    pub(crate) fn new(render: &'a FluidRenderRequest, command: &str) -> Result<Self> {
+        anyhow::ensure!(
+            render.overlay.is_none(),
+            "{command} does not draw overlays; use fluid_render"
+        );
+        let (width, height) = render::output_size(
+            render.sim.width,
+            render.sim.height,
+            render.square_pixels,
+            render.out_width,
+            render.out_height,
+        )?;
+        let palette = render::Palette::new(render.colormap, &render.colormap_stops)?;
+        Ok(Frame {
+            render,
+            palette,
+            width,
+            height,
+        })
+    }
+
+    /// Step `sim` by `steps`, read it back and draw it as RGBA8.
+    pub(crate) fn render(&self, sim: &mut FluidRun, steps: u32) -> Result<Vec<u8>> {
+        sim.step(steps);
+        let fields = sim.read_fields()?;
+        let (rgba, _) =
+            render::render_rgba(self.render, &self.palette, &fields, self.width, self.height);
+        Ok(rgba)
+    }
+}
+
+/// Absolute frame deadlines at `fps`: `wait` sleeps off what is left of the current frame's
+/// period, or counts the frame late and restarts the schedule from now.
+pub(crate) struct Pacer {
+    period: Duration,
+    start: Instant,
+    deadline: Instant,
+    pub(crate) late: u32,
+}
+
+impl Pacer {
+    pub(crate) fn new(fps: f64) -> Result<Self> {
+        anyhow::ensure!(
+            fps.is_finite() && fps > 0.0,
+            "fps must be positive (got {fps})"
+        );
+        let start = Instant::now();
+        Ok(Pacer {
+            period: Duration::from_secs_f64(1.0 / fps),
+            start,
+            deadline: start,
+            late: 0,
+        })
+    }
+
+    pub(crate) fn wait(&mut self) {
+        self.deadline += self.period;
+        let now = Instant::now();
+        if self.deadline > now {
+            std::thread::sleep(self.deadline - now);
+        } else {
+            self.late += 1;
+            self.deadline = now;
+        }
+    }
+
+    pub(crate) fn achieved_fps(&self, frames: u32) -> f64 {
+        frames as f64 / self.start.elapsed().as_secs_f64().max(1e-6)
+    }
+}
@@ -1,16 +1,38 @@
 use anyhow::{Context, Result};
 use bytemuck::{Pod, Zeroable};
 use serde::{Deserialize, Serialize};
-use std::io::{self, Read};
+use std::io::{self, BufRead, Read, Write};
+
+use error::{ErrorCode, ResultExt};
+
+mod analysis;
+mod cpu_ref;
+mod error;
+mod explain;
+mod gpu_cache;
+mod interactive;
+mod live;
+mod presets;
+mod render;
+mod shader_check;
+mod stream;
+mod vtk;
 
 #[derive(Debug, Deserialize)]
 #[serde(tag = "cmd", rename_all = "snake_case")]
 enum Request {
     Smoke {
         n: u32,
+        /// Invocations per workgroup of the 1D smoke kernel, for occupancy sweeps.
+        #[serde(default = "default_smoke_workgroup")]
+        workgroup: u32,
+        #[serde(default)]
+        backend: WgpuBackend,
     },
     SmokeSweep {
         sizes: Vec<u32>,
+        #[serde(default)]
+        backend: WgpuBackend,
     },
     FluidInit {
         width: u32,
@@ -19,965 +41,7221 @@ enum Request {
         dye_radius: f32,
         #[serde(default = "default_impulse")]
         impulse: f32,
+        /// Checked against the grid (after the 16x16 minimum) and counted in `obstacle_cells`;
+        /// the seed holds its cells at zero velocity.
+        #[serde(default)]
+        obstacles: Option<ObstacleMask>,
+        #[serde(default)]
+        backend: WgpuBackend,
     },
-    FluidStep {
-        width: u32,
-        height: u32,
+    FluidStep(FluidStepRequest),
+    FluidRender(FluidRenderRequest),
+    LiveRender(live::LiveRenderRequest),
+    StreamTcp(stream::StreamTcpRequest),
+    SelfTest {
+        #[serde(default)]
+        backend: WgpuBackend,
+    },
+    Formats {
+        #[serde(default)]
+        backend: WgpuBackend,
+        #[serde(flatten)]
+        adapter: AdapterChoice,
+    },
+    DeviceInfo {
+        #[serde(default)]
+        backend: WgpuBackend,
+        #[serde(flatten)]
+        adapter: AdapterChoice,
+    },
+    StorageSweep {
+        #[serde(default)]
+        sizes: Vec<u32>,
         #[serde(default = "default_steps")]
         steps: u32,
-        #[serde(default = "default_dt")]
-        dt: f32,
-        #[serde(default = "default_viscosity")]
-        viscosity: f32,
-        #[serde(default = "default_fade")]
-        fade: f32,
-        #[serde(default = "default_jacobi")]
-        jacobi_iters: u32,
-        #[serde(default = "default_projection_passes")]
-        projection_passes: u32,
-        #[serde(default = "default_dye_radius")]
-        dye_radius: f32,
-        #[serde(default = "default_impulse")]
-        impulse: f32,
+        /// Runs per size and storage; each reports the spread and a smoothed throughput.
+        #[serde(default = "default_sweep_repeats")]
+        repeats: u32,
+        /// Weight of each new run in `sps_ema`, clamped to (0, 1].
+        #[serde(default = "default_sps_ema_alpha")]
+        sps_ema_alpha: f64,
+        #[serde(default)]
+        backend: WgpuBackend,
+    },
+    KernelBench {
+        kernel: String,
+        width: u32,
+        height: u32,
+        #[serde(default = "default_bench_iters")]
+        iters: u32,
+        #[serde(default)]
+        backend: WgpuBackend,
+        #[serde(flatten)]
+        adapter: AdapterChoice,
+    },
+    ReadbackBench {
+        bytes: u64,
+        #[serde(default = "default_readback_iters")]
+        iters: u32,
+        #[serde(default)]
+        backend: WgpuBackend,
+        #[serde(flatten)]
+        adapter: AdapterChoice,
+    },
+    ShaderCheck {
+        #[serde(default)]
+        backend: WgpuBackend,
+    },
+    /// Draw a command's GPU pipeline as Graphviz DOT; the rest of the request configures it.
+    Pipeline {
+        #[serde(rename = "for", default)]
+        command: PipelineCommand,
+        #[serde(flatten)]
+        sim: FluidStepRequest,
     },
 }
 
-fn default_steps() -> u32 {
-    1
-}
-fn default_dt() -> f32 {
-    0.1
-}
-fn default_viscosity() -> f32 {
-    0.0002
-}
-fn default_fade() -> f32 {
-    0.995
-}
-fn default_jacobi() -> u32 {
-    30
+/// Commands `pipeline` can draw.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum PipelineCommand {
+    #[default]
+    FluidStep,
 }
-fn default_projection_passes() -> u32 {
-    2
-}
-fn default_dye_radius() -> f32 {
-    0.15
-}
-fn default_impulse() -> f32 {
-    25.0
+
+/// The wgpu backend a command creates its device on; `auto` takes the platform's primary one
+/// (Vulkan, Metal or DX12), falling back to GL when it has no adapter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum WgpuBackend {
+    #[default]
+    Auto,
+    Metal,
+    Vulkan,
+    Dx12,
+    Gl,
 }
 
-#[derive(Debug, Serialize)]
-struct SmokeResponse {
-    ok: bool,
-    backend: &'static str,
-    n: u32,
-    elapsed_ms: f64,
-    sample: [f32; 4],
-    mismatch_count: u32,
-    max_abs_error: f32,
+impl WgpuBackend {
+    fn backends(self) -> wgpu::Backends {
+        match self {
+            WgpuBackend::Auto => wgpu::Backends::PRIMARY,
+            WgpuBackend::Metal => wgpu::Backends::METAL,
+            WgpuBackend::Vulkan => wgpu::Backends::VULKAN,
+            WgpuBackend::Dx12 => wgpu::Backends::DX12,
+            WgpuBackend::Gl => wgpu::Backends::GL,
+        }
+    }
 }
 
-#[derive(Debug, Serialize)]
-struct SmokeSweepResponse {
-    ok: bool,
-    backend: &'static str,
-    runs: Vec<SmokeResponse>,
+/// The `backend` responses report for a device on `backend`.
+fn backend_label(backend: wgpu::Backend) -> &'static str {
+    match backend {
+        wgpu::Backend::Vulkan => "vulkan/wgpu",
+        wgpu::Backend::Metal => "metal/wgpu",
+        wgpu::Backend::Dx12 => "dx12/wgpu",
+        wgpu::Backend::Gl => "gl/wgpu",
+        wgpu::Backend::BrowserWebGpu => "webgpu/wgpu",
+        wgpu::Backend::Noop => "noop/wgpu",
+    }
 }
 
-#[derive(Debug, Serialize)]
-struct FluidInitResponse {
-    ok: bool,
-    backend: &'static str,
-    width: u32,
-    height: u32,
-    initialized_cells: u32,
-    elapsed_ms: f64,
+/// Pins the GPU adapter instead of taking the default one: its position in the adapter list,
+/// or its name (case-insensitive; an exact match, else a unique substring).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct AdapterChoice {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    adapter_index: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    adapter_name: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
-struct FluidStepResponse {
-    ok: bool,
-    backend: &'static str,
-    width: u32,
-    height: u32,
-    steps: u32,
-    elapsed_ms: f64,
-    sps: f64,
-    avg_speed: f32,
-    max_speed: f32,
-    avg_divergence: f32,
-    max_divergence: f32,
-    dye_footprint: f32,
-    dye_total: f32,
+impl std::fmt::Display for AdapterChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.adapter_index, &self.adapter_name) {
+            (Some(index), _) => write!(f, "adapter_index {index}"),
+            (None, Some(name)) => write!(f, "adapter_name {name:?}"),
+            (None, None) => f.write_str("the default adapter"),
+        }
+    }
 }
 
-#[repr(C)]
-#[derive(Clone, Copy, Pod, Zeroable)]
-struct Params {
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FluidStepRequest {
     width: u32,
     height: u32,
-    jacobi_iters: u32,
-    _pad0: u32,
+    #[serde(default = "default_steps")]
+    steps: u32,
+    #[serde(default = "default_dt")]
     dt: f32,
+    #[serde(default = "default_viscosity")]
     viscosity: f32,
+    /// Jacobi iterations of the implicit viscosity solve (run only when `viscosity` > 0).
+    #[serde(default = "default_diffuse_iters")]
+    diffuse_iters: u32,
+    /// Vorticity confinement strength (0 = off): after advection and diffusion, pushes the
+    /// velocity along `N x curl` to put back the small-scale rotation the advection smears.
+    #[serde(default)]
+    vorticity: f32,
+    #[serde(default = "default_fade")]
     fade: f32,
+    /// Vary the fade across the grid, from `fade` (at `source_center` / the bottom row) to
+    /// `fade_edge` (half a domain away / the top row).
+    #[serde(default)]
+    fade_gradient: FadeGradient,
+    #[serde(default = "default_fade_edge")]
+    fade_edge: f32,
+    #[serde(default = "default_jacobi")]
+    jacobi_iters: u32,
+    /// Iteration the pressure solve repeats `jacobi_iters` times.
+    #[serde(default)]
+    pressure_solver: PressureSolver,
+    /// Raise `jacobi_iters`' upper clamp from `JACOBI_ITERS_MAX` (for convergence studies),
+    /// up to `JACOBI_ITERS_OVERRIDE_MAX`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_jacobi_override: Option<u32>,
+    #[serde(default = "default_projection_passes")]
+    projection_passes: u32,
+    /// Stop the extra projection passes of a step once the max |div| the solve sees (interior
+    /// cells, less any `div_sources`) is at most this; `projection_passes` is then the cap
+    /// (0 = off, every pass runs).
+    #[serde(default)]
+    target_divergence: f32,
+    #[serde(default = "default_dye_radius")]
     dye_radius: f32,
+    /// Dye channels: 1 (scalar) or 3 (RGB, each injection tinted by `inject_color`). The stats
+    /// and renders of RGB dye see its luminance (see `DYE_LUMINANCE`).
+    #[serde(default = "default_dye_channels")]
+    channels: u32,
+    #[serde(default = "default_inject_color")]
+    inject_color: [f32; 3],
+    /// Colors the source injects in turn, each for `dye_color_every` steps from step 0 and
+    /// cycling when shorter than the run (`channels: 3` only); the seed keeps `inject_color`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    dye_color_schedule: Vec<[f32; 3]>,
+    #[serde(default = "default_dye_color_every")]
+    dye_color_every: u32,
+    #[serde(default = "default_impulse")]
     impulse: f32,
-    inv_dx: f32,
-    inv_dy: f32,
-    _pad3: f32,
+    /// Turning direction of the central swirl seed and the sustained forcing.
+    #[serde(default)]
+    rotation: Rotation,
+    /// Velocity magnitude cap applied in the projection kernel (0 = no cap).
+    #[serde(default)]
+    max_velocity: f32,
+    /// Write the final velocity field as an RGBA8 PNG (vx/vy in R/G, normalized by max speed).
+    #[serde(default)]
+    velocity_texture_path: Option<String>,
+    /// Write the final `csv_field` as a `height`-row, `width`-column CSV (row 0 first).
+    #[serde(default)]
+    csv_out_path: Option<String>,
+    #[serde(default)]
+    csv_field: CsvField,
+    /// Write the final velocity (vectors) and dye (scalars) as a legacy VTK file.
+    #[serde(default)]
+    vtk_out_path: Option<String>,
+    #[serde(default)]
+    vtk_format: vtk::VtkFormat,
+    /// Linearly ramp the sustained forcing from 0 to `impulse` over this many steps (0 = off).
+    #[serde(default)]
+    impulse_ramp_steps: u32,
+    /// Physical domain extent in meters; with `dt_seconds` this derives the grid-cell `dt`.
+    #[serde(default)]
+    domain_size_m: Option<[f32; 2]>,
+    #[serde(default)]
+    dt_seconds: Option<f32>,
+    /// How the advection kernels trace each cell back along the velocity.
+    #[serde(default)]
+    advect_integrator: AdvectIntegrator,
+    /// Advection scheme of both the velocity and the dye.
+    #[serde(default)]
+    advection: AdvectionScheme,
+    /// Velocity the dye is advected with; `uploaded` takes it from `advect_vel_b64` (same
+    /// layout as `init_vel_b64`).
+    #[serde(default)]
+    dye_advect_velocity: DyeAdvectVelocity,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    advect_vel_b64: Option<String>,
+    /// Clamp advected values to the min/max of the contributing source cells (scheme-agnostic
+    /// stabilizer for higher-order advection).
+    #[serde(default)]
+    clamp_to_neighbors: bool,
+    /// Also report `gpu_clamp_events` step by step (`gpu_clamp_events_per_step`).
+    #[serde(default)]
+    clamp_history: bool,
+    /// Report the kernels the first step dispatches, in order, by bind group
+    /// (`dispatch_trace`).
+    #[serde(default)]
+    trace_dispatches: bool,
+    /// What to do when the state goes non-finite mid-run.
+    #[serde(default)]
+    on_blowup: OnBlowup,
+    /// Steps between non-finite scans when `on_blowup` is `error` or `reset`.
+    #[serde(default = "default_blowup_check_every")]
+    blowup_check_every: u32,
+    /// Return the final velocity / dye fields as base64 (see `FieldDump`).
+    #[serde(default)]
+    dump_vel: bool,
+    #[serde(default)]
+    dump_dye: bool,
+    /// Compression applied to every dump's bytes before base64 encoding.
+    #[serde(default)]
+    compress: DumpCompression,
+    /// Dump the Okubo-Weiss parameter (strain^2 - vorticity^2) of the final velocity and
+    /// report its min/max over the dumped window.
+    #[serde(default)]
+    dump_okubo_weiss: bool,
+    /// Dump `|grad dye|` of the final dye, the edge-strength field behind `avg_dye_gradient`.
+    #[serde(default)]
+    dump_dye_gradient: bool,
+    /// Snapshot velocity and dye ahead of every Kth step and report how far the final fields
+    /// moved from the latest snapshot (`delta`); 0 = off.
+    #[serde(default)]
+    delta_every: u32,
+    /// Dump that change per cell, `[|v - v_prev|, |dye - dye_prev|]` (needs `delta_every`).
+    #[serde(default)]
+    dump_delta: bool,
+    /// Dump the streamfunction of the final velocity: `lap(psi) = -omega` solved with the
+    /// pressure Jacobi kernel (psi = 0 on the boundary ring) over `streamfunction_iters` sweeps.
+    #[serde(default)]
+    dump_streamfunction: bool,
+    #[serde(default = "default_streamfunction_iters")]
+    streamfunction_iters: u32,
+    /// Restrict dumps to this window instead of the whole grid.
+    #[serde(default)]
+    roi: Option<Roi>,
+    /// Record the pressure-solve residual (max |Lp - div|) across the Jacobi iterations of the
+    /// final step's first projection.
+    #[serde(default)]
+    residual_history: bool,
+    /// Record every Kth iteration; the last iteration is always recorded.
+    #[serde(default = "default_residual_every")]
+    residual_every: u32,
+    /// Capture the pressure after every Kth iteration of the final step's first solve (the last
+    /// iteration always included) and dump the frames in order; 0 = off.
+    #[serde(default)]
+    dump_pressure_every: u32,
+    /// Exclude this many cells at every edge from the aggregate stats (the boundary ring is
+    /// force-zeroed, which biases averages down).
+    #[serde(default)]
+    stats_margin: u32,
+    /// Seed for randomized options; drawn from system entropy when omitted and echoed back as
+    /// `seed_used` either way.
+    #[serde(default)]
+    seed: Option<u64>,
+    /// Initial velocity field, and the Taylor-Green wavenumber (vortex pairs per unit length).
+    #[serde(default)]
+    init: InitKind,
+    #[serde(default = "default_mode")]
+    mode: u32,
+    /// Initial dye pattern.
+    #[serde(default)]
+    init_dye: InitDye,
+    /// Extra swirls added on top of the seed, one `init` dispatch each (see `InitLayer`).
+    #[serde(default)]
+    init_layers: Vec<InitLayer>,
+    /// Solver to run on: `cpu` needs no GPU at all, anything else is a wgpu backend.
+    #[serde(default)]
+    backend: FluidBackend,
+    /// Where the advection kernels read their source fields from.
+    #[serde(default)]
+    storage: FieldStorage,
+    /// Account for dye added by the source, removed by fade and lost in advection.
+    #[serde(default)]
+    dye_budget: bool,
+    /// Account for the kinetic energy the central forcing injects (`forcing_power`,
+    /// `forcing_work`).
+    #[serde(default)]
+    forcing_budget: bool,
+    /// Return the final fields as nested JSON arrays (`vel_grid`/`dye_grid`) for eyeballing
+    /// tiny grids; limited to `DUMP_JSON_MAX_CELLS`.
+    #[serde(default)]
+    dump_json: bool,
+    /// Wall-clock budget for the whole request; once exceeded, stepping stops and the response
+    /// describes the state reached so far.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deadline_ms: Option<u64>,
+    /// Print a progress line to stderr every N steps (0 = off).
+    #[serde(default)]
+    progress_every: u32,
+    /// Initial pressure of every solve (default 0, a plain buffer clear).
+    #[serde(default)]
+    pressure_init: f32,
+    /// Size the fluid kernels' dispatches from a GPU-written indirect args buffer.
+    #[serde(default)]
+    indirect_dispatch: bool,
+    /// Named parameter file merged under the explicit fields (see `presets`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    preset: Option<String>,
+    /// Pulse the dye source: with a nonzero period it injects only on the first `source_duty`
+    /// fraction of every `source_period`-step cycle.
+    #[serde(default)]
+    source_period: u32,
+    #[serde(default = "default_source_duty")]
+    source_duty: f32,
+    /// Where the dye source and the forcing sit, in unit-domain coordinates (clamped into it).
+    #[serde(default = "default_source_center")]
+    source_center: [f32; 2],
+    /// Keyframes the forcing follows instead of `source_center` (the dye source stays put):
+    /// linearly interpolated between keys by step, held before the first and after the last.
+    #[serde(default)]
+    force_path: Vec<ForceKey>,
+    /// Pick each step's `dt` from the current velocity so that `max|v| dt <= cfl_limit` and
+    /// `max|curl| dt <= vorticity_cfl_limit` (both per cell); `dt` becomes the upper bound.
+    #[serde(default)]
+    adaptive_dt: bool,
+    #[serde(default = "default_cfl_limit")]
+    cfl_limit: f32,
+    #[serde(default = "default_vorticity_cfl_limit")]
+    vorticity_cfl_limit: f32,
+    /// Cap every cell's projected velocity at `cfl_max / dt`, so the next backtrace moves at
+    /// most `cfl_max` cells (see `COUNTER_CFL_CLAMP`).
+    #[serde(default)]
+    cfl_clamp: bool,
+    #[serde(default = "default_cfl_max")]
+    cfl_max: f32,
+    /// Average the dye (and with `time_average_vel`, velocity) over every step from
+    /// `time_average_start` on, dumped as `dye_mean` / `vel_mean`.
+    #[serde(default)]
+    time_average: bool,
+    #[serde(default)]
+    time_average_vel: bool,
+    #[serde(default)]
+    time_average_start: u32,
+    /// Fixed velocities for the edge ring (see `ghost_ring`) instead of the no-slip 0, for
+    /// driving the grid as a subdomain of a larger field.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ghost_b64: Option<String>,
+    /// Starting velocity / dye uploaded over the seed (see `init_vel`); with `skip_init` the
+    /// init kernel never runs and both are required.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    init_vel_b64: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    init_dye_b64: Option<String>,
+    #[serde(default)]
+    skip_init: bool,
+    /// Prescribed volume sources (`rate > 0`) and sinks the projection enforces instead of
+    /// a divergence-free field.
+    #[serde(default)]
+    div_sources: Vec<DivSource>,
+    /// Solid cells the kernels treat like the edge ring (see `obstacle_mask`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    obstacles: Option<ObstacleMask>,
+    /// Velocities (cells per unit time) moving each separate obstacle of `obstacles`, in the
+    /// order of `obstacle_labels` (see `obstacle_table`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    obstacle_velocity: Vec<[f32; 2]>,
+    /// Measure how much gradient (curl-free) energy the final velocity still carries.
+    #[serde(default)]
+    helmholtz_check: bool,
+    /// Report `Re = U L / nu` of the final state (see `Reynolds`).
+    #[serde(default)]
+    report_reynolds: bool,
+    /// Report where each parameter came from: JSON, preset, `FLUID_*` environment or default.
+    #[serde(default)]
+    report_param_sources: bool,
+    /// Report the velocity's autocorrelation length along x and y (see `analysis`).
+    #[serde(default)]
+    integral_length: bool,
+    /// Read the last pressure solve back with the fields (`FluidFields::pressure`); set by
+    /// `fluid_render`'s `overlay: "pressure"`, not by requests.
+    #[serde(skip)]
+    read_pressure: bool,
+    /// Validate the request and describe what it would run (see `explain`) instead of running.
+    #[serde(default, skip_serializing)]
+    explain: bool,
+    /// `false`: step and return only `{ok, steps, compute_ms}`, with no field readback or
+    /// stats (see `run_fluid_advance`).
+    #[serde(default = "default_readback", skip_serializing)]
+    readback: bool,
+    /// GPU adapter to run on (ignored by the CPU backend).
+    #[serde(flatten)]
+    adapter: AdapterChoice,
 }
 
-fn main() {
-    if let Err(err) = run() {
-        let out = serde_json::json!({"ok": false, "error": format!("{err:#}")});
-        println!(
-            "{}",
-            serde_json::to_string_pretty(&out).unwrap_or_else(|_| "{\"ok\":false}".into())
-        );
-        std::process::exit(1);
+/// `cpu`, or the kernels on the GPU through the `WgpuBackend` of the same name (`gpu` is
+/// `auto`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FluidBackend {
+    #[default]
+    #[serde(alias = "gpu")]
+    Auto,
+    Metal,
+    Vulkan,
+    Dx12,
+    Gl,
+    /// Single-threaded mirror of the kernels in `cpu_ref` (deterministic, slow).
+    Cpu,
+}
+
+impl FluidBackend {
+    /// The wgpu backend to run on; `None` for the CPU mirror.
+    fn wgpu(self) -> Option<WgpuBackend> {
+        match self {
+            FluidBackend::Auto => Some(WgpuBackend::Auto),
+            FluidBackend::Metal => Some(WgpuBackend::Metal),
+            FluidBackend::Vulkan => Some(WgpuBackend::Vulkan),
+            FluidBackend::Dx12 => Some(WgpuBackend::Dx12),
+            FluidBackend::Gl => Some(WgpuBackend::Gl),
+            FluidBackend::Cpu => None,
+        }
     }
 }
 
-fn run() -> Result<()> {
-    let mut input = String::new();
-    io::stdin().read_to_string(&mut input)?;
-    let req: Request = if input.trim().is_empty() {
-        Request::Smoke { n: 1024 }
-    } else {
-        serde_json::from_str(&input).context("invalid JSON request")?
-    };
+/// Turning direction of the central swirl and forcing (in grid coordinates, y down the rows).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Rotation {
+    /// The kernels' own `(-c.y, c.x)` direction.
+    #[default]
+    Ccw,
+    Cw,
+}
 
-    match req {
-        Request::Smoke { n } => {
-            let resp = pollster::block_on(run_smoke(n.max(64)))?;
-            println!("{}", serde_json::to_string_pretty(&resp)?);
-        }
-        Request::SmokeSweep { sizes } => {
-            let fallback = vec![1024, 4096, 16384, 65536];
-            let mut runs = Vec::new();
-            for n in if sizes.is_empty() { &fallback } else { &sizes } {
-                runs.push(pollster::block_on(run_smoke((*n).max(64)))?);
-            }
-            let ok = runs.iter().all(|r| r.ok);
-            let resp = SmokeSweepResponse {
-                ok,
-                backend: "metal/wgpu",
-                runs,
-            };
-            println!("{}", serde_json::to_string_pretty(&resp)?);
-        }
-        Request::FluidInit {
-            width,
-            height,
-            dye_radius,
-            impulse,
-        } => {
-            let resp = pollster::block_on(run_fluid_init(
-                width.max(16),
-                height.max(16),
-                dye_radius,
-                impulse,
-            ))?;
-            println!("{}", serde_json::to_string_pretty(&resp)?);
-        }
-        Request::FluidStep {
-            width,
-            height,
-            steps,
-            dt,
-            viscosity,
-            fade,
-            jacobi_iters,
-            projection_passes,
-            dye_radius,
-            impulse,
-        } => {
-            let resp = pollster::block_on(run_fluid_step(
-                width.max(16),
-                height.max(16),
-                steps.max(1),
-                dt.max(1e-4),
-                viscosity.max(0.0),
-                fade.clamp(0.8, 1.0),
-                jacobi_iters.clamp(5, 120),
-                projection_passes.clamp(1, 6),
-                dye_radius,
-                impulse,
-            ))?;
-            println!("{}", serde_json::to_string_pretty(&resp)?);
+impl Rotation {
+    /// Factor on `Params.impulse`, which both the swirl seed and the forcing scale by.
+    fn sign(self) -> f32 {
+        match self {
+            Self::Ccw => 1.0,
+            Self::Cw => -1.0,
         }
     }
+}
 
-    Ok(())
+/// Pressure iteration of the projection solve (the streamfunction solve always uses Jacobi).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum PressureSolver {
+    /// `FLUID_JACOBI_WGSL`, one dispatch per iteration.
+    #[default]
+    Jacobi,
+    /// `FLUID_RED_BLACK_WGSL`: Gauss-Seidel over a checkerboard, two dispatches per iteration.
+    RedBlack,
 }
 
-async fn create_device() -> Result<(wgpu::Device, wgpu::Queue)> {
-    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
-        backends: wgpu::Backends::METAL,
-        ..Default::default()
-    });
-    let adapter = instance
-        .request_adapter(&wgpu::RequestAdapterOptions::default())
-        .await
-        .context("no GPU adapter")?;
+/// Velocity field `FLUID_ADVECT_DYE_WGSL` moves the dye with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum DyeAdvectVelocity {
+    /// The step's final, projected velocity.
+    #[default]
+    Projected,
+    /// The advected (diffused and confined) velocity before the first projection.
+    Raw,
+    /// A fixed field from `advect_vel_b64`.
+    Uploaded,
+}
 
-    let (device, queue) = adapter
-        .request_device(&wgpu::DeviceDescriptor::default())
-        .await
-        .context("request_device failed")?;
-    Ok((device, queue))
+/// Scalar field `csv_out_path` writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum CsvField {
+    #[default]
+    Dye,
+    /// `dvy/dx - dvx/dy`, as for `enstrophy`.
+    Curl,
+    Speed,
 }
 
-async fn run_fluid_init(
-    width: u32,
-    height: u32,
-    dye_radius: f32,
-    impulse: f32,
-) -> Result<FluidInitResponse> {
-    let t0 = std::time::Instant::now();
-    let (device, queue) = create_device().await?;
-    let cells = (width as usize) * (height as usize);
-    let wg_x = width.div_ceil(8);
-    let wg_y = height.div_ceil(8);
+/// Initial condition the init kernel seeds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum InitKind {
+    /// Gaussian swirl of strength `impulse` around the center.
+    #[default]
+    Swirl,
+    /// `u = sin(kx) cos(ky)`, `v = -cos(kx) sin(ky)` with `k = 2 pi mode` over the unit domain.
+    TaylorGreen,
+}
 
-    let params = Params {
-        width,
-        height,
-        jacobi_iters: 0,
-        _pad0: 0,
-        dt: default_dt(),
-        viscosity: default_viscosity(),
-        fade: default_fade(),
-        dye_radius,
-        impulse,
-        inv_dx: width as f32,
-        inv_dy: height as f32,
-        _pad3: 0.0,
-    };
+impl InitKind {
+    /// `Params.init_kind` value.
+    fn as_u32(self) -> u32 {
+        match self {
+            Self::Swirl => 0,
+            Self::TaylorGreen => 1,
+        }
+    }
+}
 
-    let params_buf = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("fluid-params"),
-        size: std::mem::size_of::<Params>() as u64,
-        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
-    queue.write_buffer(&params_buf, 0, bytemuck::bytes_of(&params));
+/// Initial dye the init kernel seeds; the non-blob patterns cover the whole domain, so
+/// advection visibly deforms them everywhere.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum InitDye {
+    /// Cone of height 1 and radius `dye_radius` at the center.
+    #[default]
+    Blob,
+    /// 1 everywhere.
+    Uniform,
+    /// Linear ramp from 0 at the left edge to 1 at the right.
+    Gradient,
+    /// `CHECKER_TILES` x `CHECKER_TILES` tiles alternating 0 and 1.
+    Checker,
+}
 
-    let vel_buf = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("vel"),
-        size: (cells * std::mem::size_of::<[f32; 2]>()) as u64,
-        usage: wgpu::BufferUsages::STORAGE,
-        mapped_at_creation: false,
-    });
-    let dye_buf = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("dye"),
-        size: (cells * std::mem::size_of::<f32>()) as u64,
-        usage: wgpu::BufferUsages::STORAGE,
-        mapped_at_creation: false,
-    });
+/// Shape of the spatially varying fade (`FLUID_FADE_WGSL`'s `local_fade`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FadeGradient {
+    /// `fade` everywhere.
+    #[default]
+    None,
+    /// `fade` at `source_center`, linear in the distance to `fade_edge` half a domain away
+    /// (held beyond it).
+    Radial,
+    /// `fade` at the bottom edge, linear in `y` to `fade_edge` at the top.
+    Vertical,
+}
 
-    let init_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: Some("fluid-init"),
-        source: wgpu::ShaderSource::Wgsl(FLUID_INIT_WGSL.into()),
-    });
-    let init_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-        label: Some("fluid-init-pipeline"),
-        layout: None,
-        module: &init_shader,
-        entry_point: Some("main"),
-        compilation_options: wgpu::PipelineCompilationOptions::default(),
-        cache: None,
-    });
-    let init_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("fluid-init-bg"),
-        layout: &init_pipeline.get_bind_group_layout(0),
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: params_buf.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: vel_buf.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: dye_buf.as_entire_binding(),
-            },
-        ],
-    });
+impl FadeGradient {
+    /// `Params.fade_gradient` value.
+    fn as_u32(self) -> u32 {
+        self as u32
+    }
+}
 
-    let mut encoder = device.create_command_encoder(&Default::default());
-    {
-        let mut pass = encoder.begin_compute_pass(&Default::default());
-        pass.set_pipeline(&init_pipeline);
-        pass.set_bind_group(0, &init_bg, &[]);
-        pass.dispatch_workgroups(wg_x, wg_y, 1);
+/// (min, max) of the fade factor `FLUID_FADE_WGSL` applies over the grid.
+fn fade_range(cfg: &FluidStepRequest) -> (f32, f32) {
+    let p = fluid_params(cfg);
+    let (w, h) = (cfg.width as f32, cfg.height as f32);
+    let mut range = (f32::INFINITY, f32::NEG_INFINITY);
+    for y in 0..cfg.height {
+        for x in 0..cfg.width {
+            let (u, v) = ((x as f32 + 0.5) / w, (y as f32 + 0.5) / h);
+            let r = ((u - p.source_center_x).powi(2) + (v - p.source_center_y).powi(2)).sqrt();
+            let fade = cpu_ref::local_fade(&p, v, r);
+            range = (range.0.min(fade), range.1.max(fade));
+        }
     }
-    queue.submit(Some(encoder.finish()));
-    let _ = device.poll(wgpu::PollType::wait_indefinitely());
+    range
+}
 
-    Ok(FluidInitResponse {
-        ok: true,
-        backend: "metal/wgpu",
-        width,
-        height,
-        initialized_cells: cells as u32,
-        elapsed_ms: t0.elapsed().as_secs_f64() * 1000.0,
-    })
+/// Back-trace integrator of the advection kernels (`wgsl_backtrace!`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum AdvectIntegrator {
+    /// `pos - dt v`: one velocity sample, first order.
+    #[default]
+    Euler,
+    /// Midpoint rule: one extra velocity sample halfway back.
+    Rk2,
+    /// Classic fourth-order Runge-Kutta: three extra samples along the path.
+    Rk4,
 }
 
-async fn run_fluid_step(
-    width: u32,
-    height: u32,
-    steps: u32,
-    dt: f32,
-    viscosity: f32,
-    fade: f32,
-    jacobi_iters: u32,
-    projection_passes: u32,
-    dye_radius: f32,
-    impulse: f32,
-) -> Result<FluidStepResponse> {
-    let t0 = std::time::Instant::now();
-    let (device, queue) = create_device().await?;
-    let cells = (width as usize) * (height as usize);
+impl AdvectIntegrator {
+    /// `Params.advect_integrator` value.
+    fn as_u32(self) -> u32 {
+        self as u32
+    }
+}
 
-    let params = Params {
-        width,
-        height,
-        jacobi_iters,
-        _pad0: 0,
-        dt,
-        viscosity,
-        fade,
-        dye_radius,
-        impulse,
-        inv_dx: width as f32,
-        inv_dy: height as f32,
-        _pad3: 0.0,
-    };
+/// How the advection kernels turn the back-traced sample into the advected value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum AdvectionScheme {
+    /// The bilinear sample at the back-traced position.
+    #[default]
+    SemiLagrangian,
+    /// That sample corrected by half the error of tracing it forward again, and clamped to the
+    /// source cells it came from. A predictor kernel per field runs ahead of each advection.
+    #[serde(rename = "maccormack")]
+    MacCormack,
+}
 
-    let params_buf = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("fluid-params"),
-        size: std::mem::size_of::<Params>() as u64,
-        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
-    queue.write_buffer(&params_buf, 0, bytemuck::bytes_of(&params));
+impl AdvectionScheme {
+    /// `Params.advection` value.
+    fn as_u32(self) -> u32 {
+        self as u32
+    }
+}
 
-    let vel_a = mk_storage_vec2(&device, "vel-a", cells);
-    let vel_b = mk_storage_vec2(&device, "vel-b", cells);
-    let dye_a = mk_storage_f32(&device, "dye-a", cells);
-    let dye_b = mk_storage_f32(&device, "dye-b", cells);
-    let div = mk_storage_f32(&device, "div", cells);
-    let pressure_a = mk_storage_f32(&device, "pressure-a", cells);
-    let pressure_b = mk_storage_f32(&device, "pressure-b", cells);
+impl InitDye {
+    /// `Params.init_dye` value.
+    fn as_u32(self) -> u32 {
+        match self {
+            Self::Blob => 0,
+            Self::Uniform => 1,
+            Self::Gradient => 2,
+            Self::Checker => 3,
+        }
+    }
+}
 
-    let vel_read = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("vel-read"),
-        size: (cells * std::mem::size_of::<[f32; 2]>()) as u64,
-        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-        mapped_at_creation: false,
-    });
-    let dye_read = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("dye-read"),
-        size: (cells * std::mem::size_of::<f32>()) as u64,
-        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-        mapped_at_creation: false,
-    });
+/// Tiles per side of `init_dye: "checker"` (mirrored in `FLUID_INIT_WGSL`).
+const CHECKER_TILES: f32 = 8.0;
 
-    let init_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: Some("fluid-init"),
-        source: wgpu::ShaderSource::Wgsl(FLUID_INIT_WGSL.into()),
-    });
-    let init_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-        label: Some("fluid-init-pipeline"),
-        layout: None,
-        module: &init_shader,
-        entry_point: Some("main"),
-        compilation_options: wgpu::PipelineCompilationOptions::default(),
-        cache: None,
-    });
+/// Rec. 709 luma weights: the scalar `channels: 3` dye reduces to for the stats, the renders
+/// and the `dye_budget` terms (mirrored in `wgsl_dye!`'s RGB variant).
+const DYE_LUMINANCE: [f32; 3] = [0.2126, 0.7152, 0.0722];
 
-    let advect_vel_pipeline = mk_pipeline(&device, "advect-vel", FLUID_ADVECT_VEL_WGSL);
-    let diffuse_vel_pipeline = mk_pipeline(&device, "diffuse-vel", FLUID_DIFFUSE_VEL_WGSL);
-    let divergence_pipeline = mk_pipeline(&device, "divergence", FLUID_DIVERGENCE_WGSL);
-    let jacobi_pipeline = mk_pipeline(&device, "jacobi", FLUID_JACOBI_WGSL);
-    let project_pipeline = mk_pipeline(&device, "project", FLUID_PROJECT_WGSL);
-    let advect_dye_pipeline = mk_pipeline(&device, "advect-dye", FLUID_ADVECT_DYE_WGSL);
-    let fade_pipeline = mk_pipeline(&device, "fade", FLUID_FADE_WGSL);
-
-    // pre-build bind groups so per-step work stays focused on GPU kernels (less CPU descriptor churn)
-    let bg_init = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("bg-init"),
-        layout: &init_pipeline.get_bind_group_layout(0),
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: params_buf.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: vel_a.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: dye_a.as_entire_binding(),
-            },
-        ],
-    });
-    let bg_advect_vel = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("bg-advect-vel"),
-        layout: &advect_vel_pipeline.get_bind_group_layout(0),
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: params_buf.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: vel_a.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: vel_b.as_entire_binding(),
-            },
-        ],
-    });
-    let bg_diffuse_ba = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("bg-diffuse-ba"),
-        layout: &diffuse_vel_pipeline.get_bind_group_layout(0),
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: params_buf.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: vel_b.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: vel_a.as_entire_binding(),
-            },
-        ],
-    });
-    let bg_diffuse_ab = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("bg-diffuse-ab"),
-        layout: &diffuse_vel_pipeline.get_bind_group_layout(0),
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: params_buf.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: vel_a.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: vel_b.as_entire_binding(),
-            },
-        ],
-    });
-    let bg_div = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("bg-div"),
-        layout: &divergence_pipeline.get_bind_group_layout(0),
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: params_buf.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: vel_b.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: div.as_entire_binding(),
-            },
-        ],
-    });
-    let bg_div_from_a = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("bg-div-from-a"),
-        layout: &divergence_pipeline.get_bind_group_layout(0),
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: params_buf.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: vel_a.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: div.as_entire_binding(),
-            },
-        ],
-    });
-    let bg_jacobi_ab = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("bg-jacobi-ab"),
-        layout: &jacobi_pipeline.get_bind_group_layout(0),
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: params_buf.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: pressure_a.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: div.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 3,
-                resource: pressure_b.as_entire_binding(),
-            },
-        ],
-    });
-    let bg_jacobi_ba = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("bg-jacobi-ba"),
-        layout: &jacobi_pipeline.get_bind_group_layout(0),
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: params_buf.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: pressure_b.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: div.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 3,
-                resource: pressure_a.as_entire_binding(),
-            },
-        ],
-    });
-    let bg_project_from_a = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("bg-project-from-a"),
-        layout: &project_pipeline.get_bind_group_layout(0),
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: params_buf.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: vel_b.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: pressure_a.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 3,
-                resource: vel_a.as_entire_binding(),
-            },
-        ],
-    });
-    let bg_project_from_b = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("bg-project-from-b"),
-        layout: &project_pipeline.get_bind_group_layout(0),
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: params_buf.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: vel_b.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: pressure_b.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 3,
-                resource: vel_a.as_entire_binding(),
-            },
-        ],
-    });
-    let bg_project_cleanup_from_a = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("bg-project-cleanup-from-a"),
-        layout: &project_pipeline.get_bind_group_layout(0),
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: params_buf.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: vel_a.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: pressure_a.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 3,
-                resource: vel_b.as_entire_binding(),
-            },
-        ],
-    });
-    let bg_project_cleanup_from_b = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("bg-project-cleanup-from-b"),
-        layout: &project_pipeline.get_bind_group_layout(0),
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: params_buf.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: vel_a.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: pressure_b.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 3,
-                resource: vel_b.as_entire_binding(),
-            },
-        ],
-    });
-    let bg_advect_dye_from_a = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("bg-advect-dye-from-a"),
-        layout: &advect_dye_pipeline.get_bind_group_layout(0),
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: params_buf.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: vel_a.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: dye_a.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 3,
-                resource: dye_b.as_entire_binding(),
-            },
-        ],
-    });
-    let bg_advect_dye_from_b = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("bg-advect-dye-from-b"),
-        layout: &advect_dye_pipeline.get_bind_group_layout(0),
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: params_buf.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: vel_b.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: dye_a.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 3,
-                resource: dye_b.as_entire_binding(),
-            },
-        ],
-    });
-    let bg_fade = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("bg-fade"),
-        layout: &fade_pipeline.get_bind_group_layout(0),
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: params_buf.as_entire_binding(),
-            },
+fn luminance(rgb: [f32; 3]) -> f32 {
+    rgb.iter().zip(DYE_LUMINANCE).map(|(c, w)| c * w).sum()
+}
+
+/// Entry of a `len`-entry `dye_color_schedule` that absolute step `step` injects.
+fn dye_color_slot(len: usize, every: u32, step: u32) -> Option<usize> {
+    (len > 0).then(|| (step / every.max(1)) as usize % len)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FieldStorage {
+    #[default]
+    Buffer,
+    /// Advection samples velocity/dye from a texture mirror of the field buffers with
+    /// hardware bilinear filtering (`textureSampleLevel`) instead of four manual taps.
+    Texture,
+}
+
+/// Grid-cell rectangle; `x`/`y` is the top-left cell (row 0 = grid row y = 0).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Roi {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+impl Roi {
+    fn full(width: u32, height: u32) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            w: width,
+            h: height,
+        }
+    }
+
+    fn check(&self, width: u32, height: u32) -> Result<()> {
+        anyhow::ensure!(
+            self.w > 0
+                && self.h > 0
+                && self.x.saturating_add(self.w) <= width
+                && self.y.saturating_add(self.h) <= height,
+            "roi {}x{} at ({}, {}) must be non-empty and inside the {width}x{height} grid",
+            self.w,
+            self.h,
+            self.x,
+            self.y
+        );
+        Ok(())
+    }
+}
+
+/// `fluid_render`: run a `fluid_step` simulation, then write one field as a colormapped PNG.
+#[derive(Debug, Deserialize)]
+struct FluidRenderRequest {
+    #[serde(flatten)]
+    sim: FluidStepRequest,
+    #[serde(default)]
+    out_path: Option<String>,
+    /// Also (or instead) return the PNG inline as a `data:image/png;base64,` URL.
+    #[serde(default)]
+    data_url: bool,
+    #[serde(default)]
+    field: render::RenderField,
+    #[serde(default)]
+    colormap: render::Colormap,
+    /// Stops of `colormap: "custom"`, sorted by `t`.
+    #[serde(default)]
+    colormap_stops: Vec<render::ColormapStop>,
+    /// Keep grid cells square: the image follows the grid's aspect ratio.
+    #[serde(default = "default_square_pixels")]
+    square_pixels: bool,
+    #[serde(default)]
+    out_width: Option<u32>,
+    #[serde(default)]
+    out_height: Option<u32>,
+    #[serde(default)]
+    resample: render::Resample,
+    /// Blend a signed heatmap of this diagnostic over the image (see `render::apply_overlay`).
+    #[serde(default)]
+    overlay: Option<render::Overlay>,
+    /// Opacity of the overlay where `|value|` peaks, in 0..=1.
+    #[serde(default = "default_overlay_alpha")]
+    overlay_alpha: f32,
+}
+
+/// One `force_path` keyframe: the forcing center on absolute step `step`, in unit-domain
+/// coordinates (clamped into it). Uploaded as is for the advection kernels.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Pod, Zeroable)]
+struct ForceKey {
+    step: u32,
+    x: f32,
+    y: f32,
+}
+
+/// Most keyframes one `force_path` may hold.
+const FORCE_PATH_MAX: usize = 256;
+/// Most `dye_color_schedule` entries a request may give.
+const DYE_COLOR_SCHEDULE_MAX: usize = 4096;
+/// Most `obstacle_velocity` entries (so separate moving obstacles) a request may give.
+const OBSTACLE_VELOCITY_MAX: usize = 64;
+
+/// Where `force_path` puts the forcing on `step`, as `FLUID_ADVECT_VEL_WGSL`'s `force_center`
+/// does (`None` without a path).
+fn force_path_center(keys: &[ForceKey], step: u32) -> Option<[f32; 2]> {
+    let (first, last) = (keys.first()?, keys.last()?);
+    if step <= first.step {
+        return Some([first.x, first.y]);
+    }
+    if step >= last.step {
+        return Some([last.x, last.y]);
+    }
+    let [a, b] = keys
+        .windows(2)
+        .map(|w| [w[0], w[1]])
+        .find(|[_, b]| step < b.step)?;
+    let t = (step - a.step) as f32 / (b.step - a.step) as f32;
+    Some([a.x + (b.x - a.x) * t, a.y + (b.y - a.y) * t])
+}
+
+/// A disk of prescribed divergence `rate` (1/s), in unit-domain coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct DivSource {
+    x: f32,
+    y: f32,
+    radius: f32,
+    rate: f32,
+}
+
+/// `obstacles`: the solid cells of the grid, row-major from row 0.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ObstacleMask {
+    /// One byte per cell; nonzero is solid.
+    Flat(Vec<u8>),
+    /// Alternating run lengths of open and solid cells, starting with open (so a leading 0
+    /// makes the first cell solid).
+    Runs(Vec<u32>),
+}
+
+impl ObstacleMask {
+    /// One `u32` per cell of a `width`x`height` grid, 1 where solid.
+    fn cells(&self, width: u32, height: u32) -> Result<Vec<u32>> {
+        let cells = width as usize * height as usize;
+        let solid: Vec<u32> = match self {
+            Self::Flat(bytes) => bytes.iter().map(|&b| (b != 0) as u32).collect(),
+            Self::Runs(runs) => {
+                let mut solid = Vec::with_capacity(cells);
+                for (i, &run) in runs.iter().enumerate() {
+                    anyhow::ensure!(
+                        solid.len() + run as usize <= cells,
+                        "obstacles runs cover more than the {cells} cells of a {width}x{height} \
+                         grid"
+                    );
+                    solid.extend(std::iter::repeat_n((i % 2) as u32, run as usize));
+                }
+                solid
+            }
+        };
+        anyhow::ensure!(
+            solid.len() == cells,
+            "obstacles covers {} cells; a {width}x{height} grid has {cells}",
+            solid.len()
+        );
+        Ok(solid)
+    }
+}
+
+/// The separate obstacles of `mask` (one `u32` per cell of a `width`-wide grid, nonzero =
+/// solid): each 4-connected solid region labeled 1, 2, ... in row-major order of its first
+/// cell, open cells 0, and the region count.
+fn obstacle_labels(mask: &[u32], width: u32) -> (Vec<u32>, u32) {
+    let w = width as usize;
+    let mut labels = vec![0u32; mask.len()];
+    let mut count = 0;
+    let mut stack = Vec::new();
+    for first in 0..mask.len() {
+        if mask[first] == 0 || labels[first] != 0 {
+            continue;
+        }
+        count += 1;
+        labels[first] = count;
+        stack.push(first);
+        while let Some(i) = stack.pop() {
+            let neighbors = [
+                (i % w > 0).then(|| i - 1),
+                (i % w + 1 < w).then(|| i + 1),
+                i.checked_sub(w),
+                Some(i + w).filter(|&j| j < mask.len()),
+            ];
+            for j in neighbors.into_iter().flatten() {
+                if mask[j] != 0 && labels[j] == 0 {
+                    labels[j] = count;
+                    stack.push(j);
+                }
+            }
+        }
+    }
+    (labels, count)
+}
+
+/// One `init_layers` entry: a swirl `impulse * exp(-tightness * r^2)` and a dye disk of
+/// `radius` around `center` (unit-domain coordinates), added to what the seed left.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct InitLayer {
+    #[serde(default = "default_layer_center")]
+    center: [f32; 2],
+    #[serde(default = "default_dye_radius")]
+    radius: f32,
+    #[serde(default = "default_impulse")]
+    impulse: f32,
+    #[serde(default = "default_layer_tightness")]
+    tightness: f32,
+}
+
+/// Most `init_layers` one request may add.
+const INIT_LAYERS_MAX: usize = 64;
+
+/// Most frames one `dump_pressure_every` capture may take.
+const PRESSURE_FRAMES_MAX: usize = 256;
+
+/// The `Params` block an `init` dispatch reads, from `init_layer` on: all zero for the seed
+/// itself, else one `init_layers` entry (see `FluidStepRequest::init_layer_params`).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, Pod, Zeroable)]
+struct InitLayerParams {
+    init_layer: u32,
+    layer_center_x: f32,
+    layer_center_y: f32,
+    layer_radius: f32,
+    layer_impulse: f32,
+    layer_tightness: f32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum DumpCompression {
+    #[default]
+    None,
+    /// A gzip member (RFC 1952) of the little-endian f32 bytes.
+    Gzip,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum OnBlowup {
+    /// Fail the request.
+    Error,
+    /// Re-run the init seed and keep stepping (counted in `resets`).
+    Reset,
+    /// Keep stepping and report whatever the fields hold (no mid-run scans).
+    #[default]
+    Continue,
+}
+
+impl FluidStepRequest {
+    /// Clamp request values into the ranges the kernels are known to handle.
+    fn clamped(self) -> Self {
+        Self {
+            width: self.width.max(16),
+            height: self.height.max(16),
+            dt: self.dt.max(1e-4),
+            viscosity: self.viscosity.max(0.0),
+            diffuse_iters: self.diffuse_iters.clamp(1, DIFFUSE_ITERS_MAX),
+            vorticity: self.vorticity.max(0.0),
+            fade: self.fade.clamp(0.8, 1.0),
+            fade_edge: self.fade_edge.clamp(0.8, 1.0),
+            jacobi_iters: self.jacobi_iters.clamp(5, self.jacobi_iters_cap()),
+            streamfunction_iters: self.streamfunction_iters.clamp(1, STREAMFUNCTION_ITERS_MAX),
+            projection_passes: self.projection_passes.clamp(1, 6),
+            max_velocity: self.max_velocity.max(0.0),
+            residual_every: self.residual_every.max(1),
+            mode: self.mode.max(1),
+            source_duty: self.source_duty.clamp(0.0, 1.0),
+            source_center: self.source_center.map(|c| c.clamp(0.0, 1.0)),
+            force_path: self
+                .force_path
+                .iter()
+                .map(|k| ForceKey {
+                    x: k.x.clamp(0.0, 1.0),
+                    y: k.y.clamp(0.0, 1.0),
+                    ..*k
+                })
+                .collect(),
+            cfl_limit: self.cfl_limit.max(1e-3),
+            vorticity_cfl_limit: self.vorticity_cfl_limit.max(1e-3),
+            cfl_max: self.cfl_max.max(1e-3),
+            dye_color_every: self.dye_color_every.max(1),
+            ..self
+        }
+    }
+
+    /// Clamp, derive physical-unit `dt` and pin the seed: everything a run needs before it
+    /// starts. Returns the request plus the cell size from `apply_physical_units`.
+    fn prepare(self) -> Result<(Self, Option<[f32; 2]>)> {
+        let mut cfg = self.clamped();
+        anyhow::ensure!(
+            cfg.stats_margin.saturating_mul(2) < cfg.width.min(cfg.height),
+            "stats_margin {} leaves no interior on a {}x{} grid",
+            cfg.stats_margin,
+            cfg.width,
+            cfg.height
+        );
+        anyhow::ensure!(
+            !cfg.averages() || cfg.time_average_start < cfg.steps,
+            "time_average_start {} leaves no steps to average (steps = {})",
+            cfg.time_average_start,
+            cfg.steps
+        );
+        cfg.ghost_ring()?;
+        if let Some(mask) = cfg.obstacle_mask()? {
+            let moving = cfg.obstacle_velocity.len();
+            if moving > 0 {
+                anyhow::ensure!(
+                    !cfg.adaptive_dt,
+                    "obstacle_velocity moves obstacles by the fixed dt; drop adaptive_dt"
+                );
+                anyhow::ensure!(
+                    moving <= OBSTACLE_VELOCITY_MAX,
+                    "{moving} obstacle_velocity entries exceed the limit of \
+                     {OBSTACLE_VELOCITY_MAX}"
+                );
+                anyhow::ensure!(
+                    cfg.obstacle_velocity
+                        .iter()
+                        .flatten()
+                        .all(|v| v.is_finite()),
+                    "obstacle_velocity needs finite values"
+                );
+                let (_, count) = obstacle_labels(&mask, cfg.width);
+                anyhow::ensure!(
+                    moving == count as usize,
+                    "obstacle_velocity has {moving} entries; obstacles holds {count} separate \
+                     obstacles"
+                );
+            }
+        } else {
+            anyhow::ensure!(
+                cfg.obstacle_velocity.is_empty(),
+                "obstacle_velocity needs obstacles"
+            );
+        }
+        let (init_vel, init_dye) = (cfg.init_vel()?, cfg.init_dye()?);
+        anyhow::ensure!(
+            (cfg.dye_advect_velocity == DyeAdvectVelocity::Uploaded) == cfg.advect_vel()?.is_some(),
+            "advect_vel_b64 goes with dye_advect_velocity: \"uploaded\", and only with it"
+        );
+        if cfg.skip_init {
+            anyhow::ensure!(
+                init_vel.is_some() && init_dye.is_some(),
+                "skip_init needs both init_vel_b64 and init_dye_b64"
+            );
+            anyhow::ensure!(
+                cfg.init_layers.is_empty(),
+                "skip_init cannot apply init_layers (they are init kernel dispatches)"
+            );
+        }
+        for s in &cfg.div_sources {
+            anyhow::ensure!(
+                [s.x, s.y, s.rate].iter().all(|v| v.is_finite()) && s.radius > 0.0,
+                "div_sources entry {s:?} needs finite values and radius > 0"
+            );
+        }
+        anyhow::ensure!(
+            cfg.target_divergence.is_finite() && cfg.target_divergence >= 0.0,
+            "target_divergence {} must be finite and >= 0",
+            cfg.target_divergence
+        );
+        anyhow::ensure!(
+            !cfg.dump_delta || cfg.delta_every > 0,
+            "dump_delta needs delta_every > 0"
+        );
+        anyhow::ensure!(
+            matches!(cfg.channels, 1 | 3),
+            "channels {} must be 1 or 3",
+            cfg.channels
+        );
+        anyhow::ensure!(
+            cfg.inject_color.iter().all(|c| c.is_finite()),
+            "inject_color {:?} needs finite values",
+            cfg.inject_color
+        );
+        anyhow::ensure!(
+            cfg.dye_color_schedule.is_empty() || cfg.channels == 3,
+            "dye_color_schedule needs RGB dye (channels: 3)"
+        );
+        anyhow::ensure!(
+            cfg.dye_color_schedule.len() <= DYE_COLOR_SCHEDULE_MAX,
+            "{} dye_color_schedule entries exceed the limit of {DYE_COLOR_SCHEDULE_MAX}",
+            cfg.dye_color_schedule.len()
+        );
+        anyhow::ensure!(
+            cfg.dye_color_schedule
+                .iter()
+                .flatten()
+                .all(|c| c.is_finite()),
+            "dye_color_schedule needs finite values"
+        );
+        if cfg.channels == 3 {
+            // these read or write the dye buffers as one f32 per cell
+            let scalar_only = [
+                ("storage: \"texture\"", cfg.storage == FieldStorage::Texture),
+                ("time_average", cfg.time_average),
+                ("dump_dye_gradient", cfg.dump_dye_gradient),
+                ("delta_every", cfg.delta_every > 0),
+                ("init_dye_b64", cfg.init_dye_b64.is_some()),
+            ];
+            if let Some((name, _)) = scalar_only.iter().find(|(_, on)| *on) {
+                anyhow::bail!("{name} needs scalar dye (channels: 1)");
+            }
+        }
+        anyhow::ensure!(
+            cfg.force_path.len() <= FORCE_PATH_MAX,
+            "{} force_path keys exceed the limit of {FORCE_PATH_MAX}",
+            cfg.force_path.len()
+        );
+        anyhow::ensure!(
+            cfg.force_path
+                .iter()
+                .all(|k| k.x.is_finite() && k.y.is_finite())
+                && cfg.force_path.windows(2).all(|w| w[0].step < w[1].step),
+            "force_path needs finite positions and strictly increasing steps"
+        );
+        anyhow::ensure!(
+            cfg.init_layers.len() <= INIT_LAYERS_MAX,
+            "{} init_layers exceed the limit of {INIT_LAYERS_MAX}",
+            cfg.init_layers.len()
+        );
+        for l in &cfg.init_layers {
+            anyhow::ensure!(
+                [l.center[0], l.center[1], l.impulse]
+                    .iter()
+                    .all(|v| v.is_finite())
+                    && l.radius > 0.0
+                    && l.tightness >= 0.0,
+                "init_layers entry {l:?} needs finite values, radius > 0 and tightness >= 0"
+            );
+        }
+        anyhow::ensure!(
+            cfg.pressure_frame_points().len() <= PRESSURE_FRAMES_MAX,
+            "dump_pressure_every {} takes {} frames of the {}-iteration solve; the limit is \
+             {PRESSURE_FRAMES_MAX}",
+            cfg.dump_pressure_every,
+            cfg.pressure_frame_points().len(),
+            cfg.jacobi_iters
+        );
+        let readback_options = cfg.readback_options();
+        anyhow::ensure!(
+            cfg.readback || readback_options.is_empty(),
+            "readback: false reads nothing back and replies with only the step count, so {} \
+             need readback",
+            readback_options.join(", ")
+        );
+        let cell_size_m = cfg.apply_physical_units()?;
+        cfg.seed.get_or_insert_with(entropy_seed);
+        Ok((cfg, cell_size_m))
+    }
+
+    /// The `Params` block of each `init_layers` dispatch, in order.
+    fn init_layer_params(&self) -> Vec<InitLayerParams> {
+        self.init_layers
+            .iter()
+            .map(|l| InitLayerParams {
+                init_layer: 1,
+                layer_center_x: l.center[0],
+                layer_center_y: l.center[1],
+                layer_radius: l.radius,
+                layer_impulse: l.impulse,
+                layer_tightness: l.tightness,
+            })
+            .collect()
+    }
+
+    /// Upper clamp of `jacobi_iters`.
+    fn jacobi_iters_cap(&self) -> u32 {
+        self.max_jacobi_override.map_or(JACOBI_ITERS_MAX, |cap| {
+            cap.clamp(JACOBI_ITERS_MAX, JACOBI_ITERS_OVERRIDE_MAX)
+        })
+    }
+
+    /// Jacobi iterations (0-based) after which `residual_history` records the residual.
+    fn residual_points(&self) -> Vec<u32> {
+        self.solve_points(self.residual_every)
+    }
+
+    /// Jacobi iterations (0-based) after which `dump_pressure_every` captures the pressure
+    /// (none when off).
+    fn pressure_frame_points(&self) -> Vec<u32> {
+        match self.dump_pressure_every {
+            0 => Vec::new(),
+            every => self.solve_points(every),
+        }
+    }
+
+    /// Every `every`th iteration of a solve, and its last.
+    fn solve_points(&self, every: u32) -> Vec<u32> {
+        (0..self.jacobi_iters)
+            .filter(|&i| (i + 1).is_multiple_of(every) || i + 1 == self.jacobi_iters)
+            .collect()
+    }
+
+    /// Whether steps accumulate into the `time_average` sums.
+    fn averages(&self) -> bool {
+        self.time_average || self.time_average_vel
+    }
+
+    /// Whether absolute step `step` (0-based) is added to the `time_average` sums.
+    fn averages_step(&self, step: u32) -> bool {
+        self.averages() && step >= self.time_average_start
+    }
+
+    /// Decoded `ghost_b64`: one little-endian `[vx, vy]` f32 pair per edge-ring cell, in
+    /// row-major grid order (bottom row, then the left/right pair of every inner row, then the
+    /// top row).
+    fn ghost_ring(&self) -> Result<Option<Vec<[f32; 2]>>> {
+        use base64::Engine as _;
+        let Some(b64) = &self.ghost_b64 else {
+            return Ok(None);
+        };
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(b64)
+            .context("ghost_b64 is not valid base64")?;
+        let cells = ring_cells(self.width, self.height);
+        anyhow::ensure!(
+            bytes.len() == cells * std::mem::size_of::<[f32; 2]>(),
+            "ghost_b64 holds {} bytes; a {}x{} grid's edge ring needs {cells} [vx, vy] f32 pairs \
+             ({} bytes)",
+            bytes.len(),
+            self.width,
+            self.height,
+            cells * std::mem::size_of::<[f32; 2]>()
+        );
+        Ok(Some(bytemuck::pod_collect_to_vec(&bytes)))
+    }
+
+    /// Decoded `obstacles`, one `u32` per cell (1 = solid). Solid cells hold their wall
+    /// velocity (zero unless moving), zero divergence and pressure like the edge ring; a solid
+    /// neighbor of a fluid cell enters its divergence and viscous stencil with that velocity and
+    /// its pressure stencils mirroring the cell's own pressure, so no gradient pushes flow
+    /// through the wall.
+    fn obstacle_mask(&self) -> Result<Option<Vec<u32>>> {
+        let Some(mask) = &self.obstacles else {
+            return Ok(None);
+        };
+        mask.cells(self.width, self.height).map(Some)
+    }
+
+    /// `obstacles` as the kernels bind it: `obstacle_mask`, or with `obstacle_velocity` the
+    /// starting `obstacle_labels` followed by the velocities as `f32` bits. Each step
+    /// `FLUID_MOVE_OBSTACLES_WGSL` rewrites the labels from the starting ones, and the
+    /// kernels' `solid_vel` looks a solid cell's velocity up by its label.
+    fn obstacle_table(&self) -> Result<Option<Vec<u32>>> {
+        let Some(mask) = self.obstacle_mask()? else {
+            return Ok(None);
+        };
+        if self.obstacle_velocity.is_empty() {
+            return Ok(Some(mask));
+        }
+        let (mut table, _) = obstacle_labels(&mask, self.width);
+        table.extend(self.obstacle_velocity.iter().flatten().map(|v| v.to_bits()));
+        Ok(Some(table))
+    }
+
+    /// `obstacle_table` as absolute step `step` (0-based) sees it.
+    fn obstacles_at(&self, step: u32) -> Result<Option<Vec<u32>>> {
+        let Some(mut table) = self.obstacle_table()? else {
+            return Ok(None);
+        };
+        if !self.obstacle_velocity.is_empty() {
+            let cells = self.width as usize * self.height as usize;
+            let start = table[..cells].to_vec();
+            let p = Params {
+                step_index: step,
+                ..fluid_params(self)
+            };
+            cpu_ref::move_obstacles(&p, &start, &mut table);
+        }
+        Ok(Some(table))
+    }
+
+    /// Where each `obstacle_velocity` obstacle is on absolute step `step`: the centroid of its
+    /// starting cell centers moved by `velocity * step * dt`, in unit-domain coordinates (`None`
+    /// without `obstacle_velocity`).
+    fn obstacle_positions_at(&self, step: u32) -> Option<Vec<[f32; 2]>> {
+        if self.obstacle_velocity.is_empty() {
+            return None;
+        }
+        let mask = self.obstacle_mask().ok().flatten()?;
+        let (labels, _) = obstacle_labels(&mask, self.width);
+        let mut sums = vec![[0.0f64; 3]; self.obstacle_velocity.len()];
+        for (i, &label) in labels.iter().enumerate().filter(|&(_, &l)| l != 0) {
+            let sum = &mut sums[label as usize - 1];
+            sum[0] += (i % self.width as usize) as f64 + 0.5;
+            sum[1] += (i / self.width as usize) as f64 + 0.5;
+            sum[2] += 1.0;
+        }
+        let travel = step as f32 * self.dt;
+        let positions = sums.iter().zip(&self.obstacle_velocity).map(|(sum, v)| {
+            [
+                (sum[0] / sum[2]) as f32 / self.width as f32 + v[0] * travel / self.width as f32,
+                (sum[1] / sum[2]) as f32 / self.height as f32 + v[1] * travel / self.height as f32,
+            ]
+        });
+        Some(positions.collect())
+    }
+
+    /// Decoded `init_vel_b64`: one little-endian `[vx, vy]` f32 pair per cell, row-major.
+    fn init_vel(&self) -> Result<Option<Vec<[f32; 2]>>> {
+        self.init_upload("init_vel_b64", self.init_vel_b64.as_deref())
+    }
+
+    /// Decoded `advect_vel_b64`, in the `init_vel` layout.
+    fn advect_vel(&self) -> Result<Option<Vec<[f32; 2]>>> {
+        self.init_upload("advect_vel_b64", self.advect_vel_b64.as_deref())
+    }
+
+    /// Decoded `init_dye_b64`: one little-endian f32 per cell, row-major.
+    fn init_dye(&self) -> Result<Option<Vec<f32>>> {
+        self.init_upload("init_dye_b64", self.init_dye_b64.as_deref())
+    }
+
+    fn init_upload<T: bytemuck::Pod>(
+        &self,
+        name: &str,
+        b64: Option<&str>,
+    ) -> Result<Option<Vec<T>>> {
+        use base64::Engine as _;
+        let Some(b64) = b64 else {
+            return Ok(None);
+        };
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(b64)
+            .with_context(|| format!("{name} is not valid base64"))?;
+        let size = self.width as usize * self.height as usize * std::mem::size_of::<T>();
+        anyhow::ensure!(
+            bytes.len() == size,
+            "{name} holds {} bytes; a {}x{} grid needs {size}",
+            bytes.len(),
+            self.width,
+            self.height
+        );
+        Ok(Some(bytemuck::pod_collect_to_vec(&bytes)))
+    }
+
+    /// Per-cell prescribed divergence of `div_sources` (`None` without any): the sum of the
+    /// rates of every disk holding the cell center, 0 on the edge ring where divergence is
+    /// pinned to 0.
+    fn div_target(&self) -> Option<Vec<f32>> {
+        if self.div_sources.is_empty() {
+            return None;
+        }
+        let (w, h) = (self.width, self.height);
+        let mut target = vec![0.0f32; (w * h) as usize];
+        for y in 1..h.saturating_sub(1) {
+            for x in 1..w.saturating_sub(1) {
+                let u = (x as f32 + 0.5) / w as f32;
+                let v = (y as f32 + 0.5) / h as f32;
+                target[(y * w + x) as usize] = self
+                    .div_sources
+                    .iter()
+                    .filter(|s| (u - s.x).hypot(v - s.y) <= s.radius)
+                    .map(|s| s.rate)
+                    .sum();
+            }
+        }
+        Some(target)
+    }
+
+    /// The color the source injects on absolute step `step` (`None` without
+    /// `dye_color_schedule`).
+    fn dye_color_at(&self, step: u32) -> Option<[f32; 3]> {
+        let slot = dye_color_slot(self.dye_color_schedule.len(), self.dye_color_every, step)?;
+        Some(self.dye_color_schedule[slot])
+    }
+
+    /// `f32`s per cell of the dye buffers: 1, or 4 with `channels: 3` (a WGSL `vec3<f32>`
+    /// array element takes 16 bytes).
+    fn dye_stride(&self) -> usize {
+        if self.channels == 3 { 4 } else { 1 }
+    }
+
+    /// The options set on this request that read the fields back or add to the full response,
+    /// all of which `readback: false` would drop.
+    fn readback_options(&self) -> Vec<&'static str> {
+        [
+            ("on_blowup", self.on_blowup != OnBlowup::Continue),
+            ("progress_every", self.progress_every > 0),
+            ("dump_vel", self.dump_vel),
+            ("dump_dye", self.dump_dye),
+            ("dump_okubo_weiss", self.dump_okubo_weiss),
+            ("dump_dye_gradient", self.dump_dye_gradient),
+            ("delta_every", self.delta_every > 0),
+            ("dump_streamfunction", self.dump_streamfunction),
+            ("dump_pressure_every", self.dump_pressure_every > 0),
+            ("dump_json", self.dump_json),
+            ("time_average", self.time_average),
+            (
+                "velocity_texture_path",
+                self.velocity_texture_path.is_some(),
+            ),
+            ("csv_out_path", self.csv_out_path.is_some()),
+            ("vtk_out_path", self.vtk_out_path.is_some()),
+            ("residual_history", self.residual_history),
+            ("clamp_history", self.clamp_history),
+            ("trace_dispatches", self.trace_dispatches),
+            ("dye_budget", self.dye_budget),
+            ("forcing_budget", self.forcing_budget),
+            ("helmholtz_check", self.helmholtz_check),
+            ("report_reynolds", self.report_reynolds),
+            ("report_param_sources", self.report_param_sources),
+            ("integral_length", self.integral_length),
+        ]
+        .into_iter()
+        .filter_map(|(option, set)| set.then_some(option))
+        .collect()
+    }
+
+    /// `COPY_SRC` when the run reads `field` back, else nothing. Velocity and dye always feed
+    /// the stats; the derived and averaged fields only when dumped.
+    fn readback_usage(&self, field: FluidField) -> wgpu::BufferUsages {
+        let read = match field {
+            FluidField::Vel | FluidField::Dye | FluidField::DyeRgb => true,
+            FluidField::OkuboWeiss => self.dump_okubo_weiss,
+            FluidField::DyeGradient => self.dump_dye_gradient,
+            FluidField::Delta => self.delta_every > 0,
+            FluidField::Streamfunction => self.dump_streamfunction,
+            FluidField::Pressure => self.read_pressure,
+            FluidField::DyeMean => self.time_average,
+            FluidField::VelMean => self.time_average_vel,
+        };
+        if read {
+            wgpu::BufferUsages::COPY_SRC
+        } else {
+            wgpu::BufferUsages::empty()
+        }
+    }
+
+    /// Whether `delta_every` snapshots the fields ahead of absolute step `step`.
+    fn delta_snapshot_step(&self, step: u32) -> bool {
+        self.delta_every > 0 && step.is_multiple_of(self.delta_every)
+    }
+
+    /// Steps since the snapshot `delta` compares against, after `steps` steps (`None` when
+    /// off or before the first step).
+    fn delta_steps(&self, steps: u32) -> Option<u32> {
+        let last = steps.checked_sub(1).filter(|_| self.delta_every > 0)?;
+        Some(steps - last / self.delta_every * self.delta_every)
+    }
+
+    /// Steps per `source_period` cycle during which the dye source injects.
+    fn source_on_steps(&self) -> u32 {
+        (self.source_duty * self.source_period as f32).round() as u32
+    }
+
+    /// CPU mirror of the fade kernel's source gate for step `step` (0-based).
+    fn source_active(&self, step: u32) -> bool {
+        self.source_period == 0 || step % self.source_period < self.source_on_steps()
+    }
+
+    /// Absolute step whose solve is recorded for `residual_history` (none for `steps: 0`).
+    fn residual_step(&self) -> Option<u32> {
+        self.residual_history
+            .then(|| self.steps.checked_sub(1))
+            .flatten()
+    }
+
+    /// Absolute step whose first solve `dump_pressure_every` captures, like `residual_step`.
+    fn pressure_frames_step(&self) -> Option<u32> {
+        (self.dump_pressure_every > 0)
+            .then(|| self.steps.checked_sub(1))
+            .flatten()
+    }
+
+    /// Convert `dt_seconds` into the grid-cell `dt` the kernels use (velocity is read as m/s, so a
+    /// step moves `v * dt_seconds / cell_size` cells). Anisotropic cells use the smaller spacing,
+    /// which keeps the reported CFL conservative. Returns the cell size in meters.
+    fn apply_physical_units(&mut self) -> Result<Option<[f32; 2]>> {
+        match (self.domain_size_m, self.dt_seconds) {
+            (None, None) => Ok(None),
+            (Some([lx, ly]), Some(dt_seconds)) => {
+                anyhow::ensure!(
+                    lx > 0.0 && ly > 0.0 && dt_seconds > 0.0,
+                    "domain_size_m and dt_seconds must be positive"
+                );
+                let cell = [lx / self.width as f32, ly / self.height as f32];
+                self.dt = (dt_seconds / cell[0].min(cell[1])).max(1e-4);
+                Ok(Some(cell))
+            }
+            _ => anyhow::bail!("domain_size_m and dt_seconds must be given together"),
+        }
+    }
+}
+
+/// Fresh seed from the OS-keyed std hasher, kept below 2^53 so JS clients can echo it back
+/// exactly.
+fn entropy_seed() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    hasher.write_u128(nanos);
+    hasher.finish() & ((1 << 53) - 1)
+}
+
+fn default_steps() -> u32 {
+    1
+}
+fn default_dt() -> f32 {
+    0.1
+}
+fn default_viscosity() -> f32 {
+    0.0002
+}
+fn default_fade() -> f32 {
+    0.995
+}
+fn default_dye_channels() -> u32 {
+    1
+}
+fn default_inject_color() -> [f32; 3] {
+    [1.0; 3]
+}
+fn default_dye_color_every() -> u32 {
+    1
+}
+/// `jacobi_iters` cap for callers without `max_jacobi_override`.
+const JACOBI_ITERS_MAX: u32 = 120;
+/// Ceiling of `max_jacobi_override`: every iteration is a pass encoded per step.
+const JACOBI_ITERS_OVERRIDE_MAX: u32 = 100_000;
+
+fn default_fade_edge() -> f32 {
+    0.95
+}
+/// Upper clamp of `streamfunction_iters`.
+const STREAMFUNCTION_ITERS_MAX: u32 = 10_000;
+/// Upper clamp of `diffuse_iters`: every iteration is a pass encoded per step.
+const DIFFUSE_ITERS_MAX: u32 = 200;
+
+fn default_diffuse_iters() -> u32 {
+    20
+}
+
+fn default_streamfunction_iters() -> u32 {
+    1000
+}
+fn default_jacobi() -> u32 {
+    30
+}
+fn default_projection_passes() -> u32 {
+    2
+}
+fn default_blowup_check_every() -> u32 {
+    50
+}
+fn default_square_pixels() -> bool {
+    true
+}
+fn default_readback() -> bool {
+    true
+}
+fn default_residual_every() -> u32 {
+    1
+}
+fn default_bench_iters() -> u32 {
+    100
+}
+fn default_readback_iters() -> u32 {
+    20
+}
+fn default_overlay_alpha() -> f32 {
+    0.6
+}
+fn default_sweep_repeats() -> u32 {
+    1
+}
+fn default_sps_ema_alpha() -> f64 {
+    0.3
+}
+fn default_mode() -> u32 {
+    1
+}
+fn default_source_center() -> [f32; 2] {
+    [0.5, 0.5]
+}
+fn default_cfl_limit() -> f32 {
+    0.8
+}
+fn default_vorticity_cfl_limit() -> f32 {
+    0.5
+}
+fn default_cfl_max() -> f32 {
+    1.0
+}
+fn default_source_duty() -> f32 {
+    1.0
+}
+fn default_dye_radius() -> f32 {
+    0.15
+}
+fn default_impulse() -> f32 {
+    25.0
+}
+fn default_layer_center() -> [f32; 2] {
+    [0.5, 0.5]
+}
+/// The seed's own swirl falloff (`FLUID_INIT_WGSL`).
+fn default_layer_tightness() -> f32 {
+    30.0
+}
+
+fn default_smoke_workgroup() -> u32 {
+    64
+}
+
+#[derive(Debug, Serialize)]
+struct SmokeResponse {
+    ok: bool,
+    backend: &'static str,
+    n: u32,
+    workgroup: u32,
+    elapsed_ms: f64,
+    /// Wall time from submitting the dispatch to the GPU going idle (no readback).
+    kernel_ms: f64,
+    /// `n / kernel_ms`: achieved throughput at this workgroup size.
+    elements_per_sec: f64,
+    sample: [f32; 4],
+    mismatch_count: u32,
+    max_abs_error: f32,
+}
+
+#[derive(Debug, Serialize)]
+struct SmokeSweepResponse {
+    ok: bool,
+    backend: &'static str,
+    runs: Vec<SmokeResponse>,
+}
+
+#[derive(Debug, Serialize)]
+struct SelfTestResponse {
+    ok: bool,
+    /// Absent when no case got a device.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backend: Option<&'static str>,
+    cases: Vec<SelfTestCase>,
+}
+
+#[derive(Debug, Serialize)]
+struct SelfTestCase {
+    name: &'static str,
+    passed: bool,
+    detail: String,
+}
+
+/// `report_reynolds`: `Re = U L / nu` in grid units, with the final `max_speed` (cells per unit
+/// time) as `U`, the shorter grid edge (cells) as `L` and `viscosity` (cells^2 per unit time,
+/// as the diffusion kernel applies it) as `nu`.
+#[derive(Debug, Serialize)]
+struct Reynolds {
+    /// `null` when `viscosity` is 0.
+    re: Option<f64>,
+    velocity: f32,
+    length: u32,
+    viscosity: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    note: Option<&'static str>,
+}
+
+impl Reynolds {
+    fn new(cfg: &FluidStepRequest, max_speed: f32) -> Self {
+        let length = cfg.width.min(cfg.height);
+        let inviscid = cfg.viscosity <= 0.0;
+        Reynolds {
+            re: (!inviscid).then(|| max_speed as f64 * length as f64 / cfg.viscosity as f64),
+            velocity: max_speed,
+            length,
+            viscosity: cfg.viscosity,
+            note: inviscid.then_some(
+                "viscosity 0 is inviscid: Re is unbounded, short of the advection's own \
+                 numerical diffusion",
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct StorageSweepResponse {
+    ok: bool,
+    backend: &'static str,
+    /// Texture format behind `texture_sps` (absent when the texture path fell back to buffers).
+    texture_format: Option<&'static str>,
+    runs: Vec<StorageSweepRun>,
+}
+
+#[derive(Debug, Serialize)]
+struct StorageSweepRun {
+    size: u32,
+    /// `buffer.sps_median` / `texture.sps_median`.
+    buffer_sps: f64,
+    texture_sps: f64,
+    buffer: SpsStats,
+    texture: SpsStats,
+}
+
+/// Steps per second over a sweep's `repeats` runs of one configuration.
+#[derive(Debug, Serialize)]
+struct SpsStats {
+    sps_min: f64,
+    sps_median: f64,
+    sps_max: f64,
+    /// Exponential moving average in run order: the first run, then
+    /// `ema += sps_ema_alpha * (sps - ema)` for each later one.
+    sps_ema: f64,
+}
+
+impl SpsStats {
+    fn new(runs: &[f64], alpha: f64) -> SpsStats {
+        let mut sorted = runs.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let mid = sorted.len() / 2;
+        let sps_median = if sorted.len().is_multiple_of(2) {
+            (sorted[mid - 1] + sorted[mid]) / 2.0
+        } else {
+            sorted[mid]
+        };
+        let sps_ema = runs[1..]
+            .iter()
+            .fold(runs[0], |ema, &sps| ema + alpha * (sps - ema));
+        SpsStats {
+            sps_min: sorted[0],
+            sps_median,
+            sps_max: sorted[sorted.len() - 1],
+            sps_ema,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct FormatsResponse {
+    ok: bool,
+    backend: &'static str,
+    adapter: String,
+    formats: Vec<FormatSupport>,
+}
+
+#[derive(Debug, Serialize)]
+struct FormatSupport {
+    format: &'static str,
+    /// Usable as a storage texture (`STORAGE_BINDING`) in some access mode.
+    storage: bool,
+    /// Usable as a `read_write` storage texture, as an in-place field update would need.
+    storage_read_write: bool,
+    /// Sampleable with a linear filter.
+    filterable: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct DeviceInfoResponse {
+    ok: bool,
+    backend: &'static str,
+    adapter: String,
+    device_type: &'static str,
+    driver: String,
+    driver_info: String,
+    /// Of the device the sidecar creates on the adapter, which `grid_too_large` checks against.
+    limits: DeviceLimits,
+}
+
+#[derive(Debug, Serialize)]
+struct DeviceLimits {
+    max_buffer_size: u64,
+    max_storage_buffer_binding_size: u32,
+    max_compute_workgroups_per_dimension: u32,
+    max_compute_invocations_per_workgroup: u32,
+    max_compute_workgroup_size_x: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct KernelBenchResponse {
+    ok: bool,
+    backend: &'static str,
+    adapter: String,
+    kernel: &'static str,
+    width: u32,
+    height: u32,
+    iters: u32,
+    /// Submit-to-idle wall time for the timed batch.
+    wall_ms: f64,
+    /// How `gpu_ms` was measured (see `TimestampGranularity`).
+    timestamp_granularity: TimestampGranularity,
+    /// Timestamp-query span of the timed pass (absent with granularity `none`).
+    gpu_ms: Option<f64>,
+    /// Spread of the single-dispatch times (granularity `pass` only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dispatch_gpu_ms: Option<DispatchSpread>,
+    /// Cells processed per second, from `gpu_ms` when available, else `wall_ms`.
+    cells_per_sec: f64,
+}
+
+/// The finest timing `kernel_bench` gets from the device's timestamp support.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum TimestampGranularity {
+    /// `TIMESTAMP_QUERY_INSIDE_PASSES`: a timestamp between every dispatch of the pass.
+    Pass,
+    /// `TIMESTAMP_QUERY` alone: timestamps at the start and end of the submitted pass.
+    Submission,
+    /// No timestamp queries; wall-clock only.
+    None,
+}
+
+impl TimestampGranularity {
+    /// For timing `dispatches` dispatches on a device with `features`. A pass needing more
+    /// queries than one query set holds falls back to the pass boundaries.
+    fn new(features: wgpu::Features, dispatches: u32) -> Self {
+        if features.contains(wgpu::Features::TIMESTAMP_QUERY_INSIDE_PASSES)
+            && dispatches < wgpu::QUERY_SET_MAX_QUERIES
+        {
+            Self::Pass
+        } else if features.contains(wgpu::Features::TIMESTAMP_QUERY) {
+            Self::Submission
+        } else {
+            Self::None
+        }
+    }
+
+    /// Timestamps written for `dispatches` dispatches.
+    fn queries(self, dispatches: u32) -> u32 {
+        match self {
+            Self::Pass => dispatches + 1,
+            Self::Submission => 2,
+            Self::None => 0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DispatchSpread {
+    min: f64,
+    mean: f64,
+    max: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct ReadbackBenchResponse {
+    ok: bool,
+    backend: &'static str,
+    adapter: String,
+    /// Bytes per readback (`bytes` rounded up to the copy alignment).
+    bytes: u64,
+    iters: u32,
+    /// Per-iteration copy + submit + map + host read, mean and fastest.
+    mean_ms: f64,
+    min_ms: f64,
+    /// Download bandwidth from `mean_ms` and from `min_ms`.
+    gb_per_s: f64,
+    peak_gb_per_s: f64,
+    /// The last readback matched the uploaded pattern.
+    verified: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct FluidInitResponse {
+    ok: bool,
+    backend: &'static str,
+    width: u32,
+    height: u32,
+    initialized_cells: u32,
+    /// Solid cells of `obstacles` (only with `obstacles`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    obstacle_cells: Option<u32>,
+    elapsed_ms: f64,
+}
+
+/// `fluid_step` with `readback: false`.
+#[derive(Debug, Serialize)]
+struct FluidAdvanceResponse {
+    ok: bool,
+    /// Steps run; below the requested `steps` when `deadline_ms` hit.
+    steps: u32,
+    /// From the seed until the GPU drained the last step.
+    compute_ms: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct FluidStepResponse {
+    ok: bool,
+    backend: &'static str,
+    width: u32,
+    height: u32,
+    steps: u32,
+    elapsed_ms: f64,
+    sps: f64,
+    /// Steps per second of the stepping alone, from the seed until the GPU drains the last
+    /// step: device setup, pipeline builds and field readbacks excluded (`progress_every`
+    /// readbacks still count).
+    steady_sps: f64,
+    avg_speed: f32,
+    max_speed: f32,
+    avg_divergence: f32,
+    max_divergence: f32,
+    dye_footprint: f32,
+    dye_total: f32,
+    avg_dye_gradient: f64,
+    dye_centroid: Option<[f32; 2]>,
+    dye_spread: Option<[f32; 2]>,
+    velocity_clamped_cells: u32,
+    /// Total `(r x v)_z` about the grid center (unit density, cell units), accumulated in f64.
+    angular_momentum: f64,
+    /// `integral of omega^2 dA` over the unit domain for the final and the seeded velocity.
+    enstrophy: f64,
+    enstrophy_initial: f64,
+    /// `integral of |grad omega|^2 dA` over the unit domain for the final velocity.
+    palinstrophy: f64,
+    /// `integral of |v|^2 / 2 dA` over the unit domain for the final and the seeded velocity.
+    kinetic_energy: f64,
+    kinetic_energy_initial: f64,
+    init_dye: InitDye,
+    /// `init_layers` dispatched on top of the seed (only with `init_layers`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    init_layers_applied: Option<usize>,
+    /// Cells behind the aggregate stats: the full grid minus `stats_margin` on every edge.
+    stats_cells: u32,
+    /// `obstacles` cells among `stats_cells`, left out of the divergence stats (only with
+    /// `obstacles`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    obstacle_cells: Option<u32>,
+    /// Steps behind the `dye_mean` / `vel_mean` dumps (since the last (re)seed).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time_average_steps: Option<u32>,
+    /// Edge-ring cells held at the `ghost_b64` velocities (only with `ghost_b64`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ghost_cells_applied: Option<usize>,
+    /// `integral of div_target dA`: what `div_sources` add in net (only with `div_sources`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    net_prescribed_divergence: Option<f64>,
+    /// `helmholtz_check`: energy of the final velocity's gradient component as a fraction of
+    /// its total energy (0 = perfectly solenoidal), and the CG iterations the estimate took.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    helmholtz_gradient_fraction: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    helmholtz_iters: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reynolds: Option<Reynolds>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    integral_length: Option<analysis::IntegralLength>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    velocity_texture_path: Option<String>,
+    /// Decode with `v = (byte / 255 * 2 - 1) * velocity_texture_scale`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    velocity_texture_scale: Option<f32>,
+    /// `csv_out_path` as written, with its row (`height`) and column (`width`) counts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    csv_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    csv_rows: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    csv_cols: Option<u32>,
+    /// `vtk_out_path` as written, with its point count (`width * height`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vtk_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vtk_points: Option<u32>,
+    /// Forcing strength applied on the last step (after `impulse_ramp_steps`; 0 without steps).
+    effective_impulse: f32,
+    rotation: Rotation,
+    /// Upper clamp applied to `jacobi_iters`, and whether `max_jacobi_override` let the
+    /// iterations past the default `JACOBI_ITERS_MAX`.
+    jacobi_iters_cap: u32,
+    jacobi_override_used: bool,
+    /// `skip_init`: the fields started from the uploads alone.
+    init_skipped: bool,
+    /// Whether the dye source injected on the last step (always true without `source_period`).
+    source_active: bool,
+    /// `source_center` after clamping into the domain.
+    source_center: [f32; 2],
+    /// Where `force_path` put the forcing on the final step (only with `force_path`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    force_position: Option<[f32; 2]>,
+    advect_integrator: AdvectIntegrator,
+    advection: AdvectionScheme,
+    pressure_solver: PressureSolver,
+    dye_advect_velocity: DyeAdvectVelocity,
+    /// Extremes of the per-cell fade factor (only with `fade_gradient`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fade_min: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fade_max: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cell_size_m: Option<[f32; 2]>,
+    /// Grid-cell dt derived from `dt_seconds`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dt_grid: Option<f32>,
+    /// `max_speed * dt_grid`: cells travelled per step by the fastest cell.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cfl: Option<f32>,
+    /// Range and mean of the `dt`s `adaptive_dt` picked since the last (re)seed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dt_min: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dt_max: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dt_mean: Option<f32>,
+    /// `target_divergence`: projection passes (the first included) of the last step, their
+    /// mean and most over the steps since the last (re)seed, and the max |div| the last step's
+    /// final pass left, over the cells the solve sees (`max_divergence` also counts the edges).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    projection_passes_used: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    projection_passes_mean: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    projection_passes_max: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    projection_divergence: Option<f32>,
+    /// Advected samples pulled back into their neighborhood bounds, summed over all steps for
+    /// velocity and dye (only with `clamp_to_neighbors`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    neighbor_clamped_samples: Option<u32>,
+    /// Velocity caps and out-of-domain backtraces counted on the GPU over all steps (see
+    /// `COUNTER_CLAMP_EVENTS`).
+    gpu_clamp_events: u32,
+    /// Cell caps by `cfl_clamp` over all steps (see `COUNTER_CFL_CLAMP`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cfl_clamped_cells: Option<u32>,
+    /// The same per step (only with `clamp_history`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gpu_clamp_events_per_step: Option<Vec<u32>>,
+    /// `trace_dispatches`: the first step's dispatches, each by the bind group it ran with
+    /// (`jacobi_ab`, `project_from_b`, ...; absent on the CPU backend).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dispatch_trace: Option<Vec<String>>,
+    /// Reseeds triggered by non-finite state (only with `on_blowup: "reset"`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resets: Option<u32>,
+    /// Window the dumps cover (only when `roi` was requested).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    roi: Option<Roi>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    dumps: Vec<FieldDump>,
+    /// Fields behind `dumps`, read back together in one submission.
+    dump_fields_read: usize,
+    /// Extremes of the dumped Okubo-Weiss window: negative = rotation-dominated (vortex
+    /// cores), positive = strain-dominated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    okubo_weiss_min: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    okubo_weiss_max: Option<f32>,
+    /// Range of the dumped streamfunction, and the max `|lap(psi) + omega|` left after its
+    /// solve (`dump_streamfunction`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    streamfunction_min: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    streamfunction_max: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    streamfunction_residual: Option<f32>,
+    /// Max residual after each recorded Jacobi iteration of the final step (`residual_history`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    residual_curve: Option<Vec<f32>>,
+    /// `dump_pressure_every`: the final step's first solve, frame by frame.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pressure_frames: Option<PressureFrames>,
+    /// `delta_every`: change of the final fields against the latest snapshot.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delta: Option<DeltaStats>,
+    seed_used: u64,
+    /// Name of the GPU adapter the run used (absent on the CPU backend).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    adapter: Option<String>,
+    /// Field storage the advection kernels actually used (`texture` falls back to `buffer`
+    /// when the adapter lacks a suitable format).
+    storage: FieldStorage,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    texture_format: Option<&'static str>,
+    /// Whether every pipeline was reused from the process-level cache rather than compiled
+    /// for this request (GPU only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pipeline_cache_hit: Option<bool>,
+    /// `dye_budget` terms since the last (re)seed: `dye_total` ~= seeded dye + `dye_added` -
+    /// `dye_faded` - `dye_boundary_loss`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dye_added: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dye_faded: Option<f32>,
+    /// Dye removed by advection: outflow at the clamped boundary plus semi-Lagrangian
+    /// non-conservation (negative when resampling gains mass).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dye_boundary_loss: Option<f32>,
+    /// `dye_color_schedule`'s color on the last step run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dye_color_final: Option<[f32; 3]>,
+    /// Each `obstacle_velocity` obstacle's position on the last step run (see
+    /// `FluidStepRequest::obstacle_positions_at`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    obstacle_positions: Option<Vec<[f32; 2]>>,
+    /// `forcing_budget`: `integral of f . v dA` over the forced cells on the last step, with
+    /// `f` the forcing acceleration and `v` the mean of the velocity before and after it (in
+    /// `kinetic_energy` per unit time), and `f . v dt` summed over the steps since the last
+    /// (re)seed: the kinetic energy the forcing added ahead of projection.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    forcing_power: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    forcing_work: Option<f64>,
+    /// Full parameter set after merging `preset` and applying defaults/clamps (only with
+    /// `preset`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolved_params: Option<FluidStepRequest>,
+    /// `report_param_sources`: `json`, `preset`, `env` or `default` per parameter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    param_sources: Option<std::collections::BTreeMap<String, presets::ParamSource>>,
+    /// `dump_json` rows (`[y][x]`) of the `roi` window.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vel_grid: Option<Vec<Vec<[f32; 2]>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dye_grid: Option<Vec<Vec<f32>>>,
+    /// Whether `deadline_ms` cut the run short (only with `deadline_ms`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deadline_hit: Option<bool>,
+    /// Steps actually run; below `steps` when the deadline hit (only with `deadline_ms`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    steps_completed: Option<u32>,
+    /// Whether the fluid kernels were sized by `dispatch_workgroups_indirect`.
+    indirect_dispatch: bool,
+    /// Pressure every solve started from.
+    pressure_init: f32,
+}
+
+#[derive(Debug, Serialize)]
+struct FluidRenderResponse {
+    ok: bool,
+    backend: &'static str,
+    width: u32,
+    height: u32,
+    steps: u32,
+    elapsed_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data_url: Option<String>,
+    /// Length of `data_url` in bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data_url_bytes: Option<usize>,
+    image_width: u32,
+    image_height: u32,
+    field: render::RenderField,
+    colormap: render::Colormap,
+    /// Field values mapped to the ends of the colormap.
+    value_range: [f32; 2],
+    /// Speed drawn at full value by `colormap: "flow_hsv"` (only with it).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_speed: Option<f32>,
+    /// `overlay` drawn, and the symmetric range its heatmap was normalized to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    overlay: Option<render::Overlay>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    overlay_range: Option<[f32; 2]>,
+    seed_used: u64,
+    /// Simulation parameters after merging `preset` (only with `preset`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolved_params: Option<FluidStepRequest>,
+    /// `report_param_sources`: `json`, `preset`, `env` or `default` per parameter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    param_sources: Option<std::collections::BTreeMap<String, presets::ParamSource>>,
+}
+
+/// `|field - field_prev|` over the stats cells against the `delta_every` snapshot.
+#[derive(Debug, Serialize)]
+struct DeltaStats {
+    /// Steps between the snapshot and the final fields (1..=`delta_every`).
+    steps: u32,
+    vel_max: f32,
+    vel_mean: f32,
+    dye_max: f32,
+    dye_mean: f32,
+}
+
+impl DeltaStats {
+    /// From the full-grid `FluidField::Delta` readback.
+    fn new(cfg: &FluidStepRequest, steps: u32, delta: &[f32]) -> Self {
+        let (w, h, m) = (
+            cfg.width as usize,
+            cfg.height as usize,
+            cfg.stats_margin as usize,
+        );
+        let mut max = [0.0f32; 2];
+        let mut sum = [0.0f64; 2];
+        for y in m..h - m {
+            for x in m..w - m {
+                let id = 2 * (y * w + x);
+                for c in 0..2 {
+                    max[c] = max[c].max(delta[id + c]);
+                    sum[c] += delta[id + c] as f64;
+                }
+            }
+        }
+        let cells = ((w - 2 * m) * (h - 2 * m)) as f64;
+        DeltaStats {
+            steps,
+            vel_max: max[0],
+            vel_mean: (sum[0] / cells) as f32,
+            dye_max: max[1],
+            dye_mean: (sum[1] / cells) as f32,
+        }
+    }
+}
+
+/// Pressure snapshots of one solve, oldest first.
+#[derive(Debug, Serialize)]
+struct PressureFrames {
+    /// Iterations done (1-based) when each frame was taken.
+    iterations: Vec<u32>,
+    /// The pressure over the `roi` window, as `dumps` entries of field `pressure`.
+    frames: Vec<FieldDump>,
+}
+
+#[derive(Debug, Serialize)]
+struct FieldDump {
+    field: &'static str,
+    /// `[w, h, components]`; values are row-major with components interleaved.
+    shape: [u32; 3],
+    /// Little-endian f32 bytes, gzipped first when `compressed`.
+    data_b64: String,
+    compressed: bool,
+    /// Byte length of the raw f32 data (only when `compressed`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uncompressed_bytes: Option<usize>,
+}
+
+impl FieldDump {
+    fn new(field: FluidField, roi: Roi, values: &[f32], compress: DumpCompression) -> Result<Self> {
+        use base64::Engine as _;
+        let raw = bytemuck::cast_slice::<f32, u8>(values);
+        let (bytes, uncompressed_bytes) = match compress {
+            DumpCompression::None => (raw.to_vec(), None),
+            DumpCompression::Gzip => {
+                use std::io::Write as _;
+                let mut gz =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                gz.write_all(raw)?;
+                (gz.finish()?, Some(raw.len()))
+            }
+        };
+        Ok(Self {
+            field: field.name(),
+            shape: [roi.w, roi.h, field.components() as u32],
+            data_b64: base64::engine::general_purpose::STANDARD.encode(bytes),
+            compressed: uncompressed_bytes.is_some(),
+            uncompressed_bytes,
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Params {
+    width: u32,
+    height: u32,
+    jacobi_iters: u32,
+    /// Absolute step number; refreshed per step from a small index table (see `FluidSim::step`).
+    step_index: u32,
+    dt: f32,
+    viscosity: f32,
+    fade: f32,
+    dye_radius: f32,
+    impulse: f32,
+    inv_dx: f32,
+    inv_dy: f32,
+    max_velocity: f32,
+    impulse_ramp_steps: u32,
+    clamp_to_neighbors: u32,
+    /// `residuals` slot the residual kernel writes, copied in ahead of each dispatch.
+    residual_slot: u32,
+    /// Nonzero makes advect_dye/fade accumulate the per-cell `dye_budget` terms.
+    dye_budget: u32,
+    /// Value the pressure solve starts from (`FLUID_FILL_WGSL` when nonzero).
+    pressure_init: f32,
+    /// Dye source pulsing: inject while `step_index % source_period < source_on_steps`
+    /// (period 0 = always).
+    source_period: u32,
+    source_on_steps: u32,
+    /// `InitKind::as_u32` and the Taylor-Green `mode`.
+    init_kind: u32,
+    init_mode: u32,
+    /// `InitDye::as_u32`.
+    init_dye: u32,
+    /// `source_center` (scalars: a WGSL `vec2` would be 8-byte aligned here).
+    source_center_x: f32,
+    source_center_y: f32,
+    /// `adaptive_dt` limits; `dt_max` is the requested `dt`, which `dt` never exceeds.
+    cfl_limit: f32,
+    vorticity_cfl_limit: f32,
+    dt_max: f32,
+    /// `AdvectIntegrator::as_u32`.
+    advect_integrator: u32,
+    /// `FadeGradient::as_u32` and its far-end fade.
+    fade_gradient: u32,
+    fade_edge: f32,
+    /// `InitLayerParams`, copied in ahead of each `init_layers` dispatch (zero otherwise).
+    init_layer: u32,
+    layer_center_x: f32,
+    layer_center_y: f32,
+    layer_radius: f32,
+    layer_impulse: f32,
+    layer_tightness: f32,
+    /// `cfl_clamp`'s per-step displacement cap in cells (0 = off).
+    cfl_max: f32,
+    /// Half of the red-black iteration `FLUID_RED_BLACK_WGSL` runs, copied in ahead of each
+    /// dispatch.
+    rb_phase: u32,
+    /// `force_path` keyframes bound to the advection kernels (0 = force at `source_center`).
+    force_keys: u32,
+    /// Vorticity confinement strength; `FLUID_VORTICITY_FORCE_WGSL` only runs when positive.
+    vorticity: f32,
+    /// `AdvectionScheme::as_u32`.
+    advection: u32,
+    /// `inject_color`, the tint of every dye injection with `channels: 3`; replaced per step by
+    /// a `dye_color_schedule`.
+    inject_r: f32,
+    inject_g: f32,
+    inject_b: f32,
+}
+
+const _: () = assert!(
+    std::mem::offset_of!(Params, cfl_max) - std::mem::offset_of!(Params, init_layer)
+        == std::mem::size_of::<InitLayerParams>()
+);
+
+// Side of the square workgroup every 2D fluid kernel runs: declared to the WGSL as `WG` by
+// `wgsl_params!()` and divided into the grid by `workgroups`, so the two cannot disagree.
+macro_rules! workgroup_side {
+    () => {
+        8
+    };
+}
+
+const WORKGROUP_SIDE: u32 = workgroup_side!();
+
+/// Workgroups covering a `width`x`height` grid with `side`x`side` workgroups.
+fn workgroups(width: u32, height: u32, side: u32) -> (u32, u32) {
+    (width.div_ceil(side), height.div_ceil(side))
+}
+
+// Shared WGSL mirror of `Params`, plus the workgroup side `WG`; every kernel binds `Params` at
+// group(0) binding(0).
+macro_rules! wgsl_params {
+    () => {
+        concat!(
+            r#"
+struct Params {
+  width: u32,
+  height: u32,
+  jacobi_iters: u32,
+  step_index: u32,
+  dt: f32,
+  viscosity: f32,
+  fade: f32,
+  dye_radius: f32,
+  impulse: f32,
+  inv_dx: f32,
+  inv_dy: f32,
+  max_velocity: f32,
+  impulse_ramp_steps: u32,
+  clamp_to_neighbors: u32,
+  residual_slot: u32,
+  dye_budget: u32,
+  pressure_init: f32,
+  source_period: u32,
+  source_on_steps: u32,
+  init_kind: u32,
+  init_mode: u32,
+  init_dye: u32,
+  source_center_x: f32,
+  source_center_y: f32,
+  cfl_limit: f32,
+  vorticity_cfl_limit: f32,
+  dt_max: f32,
+  advect_integrator: u32,
+  fade_gradient: u32,
+  fade_edge: f32,
+  init_layer: u32,
+  layer_center_x: f32,
+  layer_center_y: f32,
+  layer_radius: f32,
+  layer_impulse: f32,
+  layer_tightness: f32,
+  cfl_max: f32,
+  rb_phase: u32,
+  force_keys: u32,
+  vorticity: f32,
+  advection: u32,
+  inject_r: f32,
+  inject_g: f32,
+  inject_b: f32,
+};
+
+const WG: u32 = "#,
+            workgroup_side!(),
+            "u;\n"
+        )
+    };
+}
+
+fn main() {
+    if let Err(err) = run() {
+        fail(&err);
+    }
+}
+
+/// Print `err` as the failed-request response (see `error::error_json`) and exit nonzero.
+fn fail(err: &anyhow::Error) -> ! {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&error::error_json(err))
+            .unwrap_or_else(|_| "{\"ok\":false}".into())
+    );
+    std::process::exit(1);
+}
+
+fn run() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(i) = args.iter().position(|a| a == "--interactive") {
+        return interactive::run(args.get(i + 1).map(String::as_str));
+    }
+    if args.iter().any(|a| a == "--serve") {
+        return serve();
+    }
+    let mut input = String::new();
+    io::stdin()
+        .read_to_string(&mut input)
+        .context("read stdin")
+        .code(ErrorCode::IoError)?;
+    let reply = dispatch(&input)?;
+    println!("{}", reply.json);
+    if reply.failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// `--serve`: one request per stdin line, one compact response line each, until end of input.
+/// Devices and pipelines stay in `gpu_cache`, so only the first request that needs the GPU pays
+/// for device creation and each kernel compiles once.
+fn serve() -> Result<()> {
+    serve_lines(io::stdin().lock(), io::stdout().lock())
+}
+
+/// The `--serve` loop over `input`. A line that fails (malformed JSON included) answers with
+/// the one-line error response and the loop reads on; after a `device_error` the next request
+/// gets a fresh device (see `gpu_cache::evict`).
+fn serve_lines(input: impl BufRead, mut out: impl Write) -> Result<()> {
+    for line in input.lines() {
+        let line = line.context("read stdin").code(ErrorCode::IoError)?;
+        // a blank line is not a request (one-shot mode would run the default smoke test)
+        if line.trim().is_empty() {
+            continue;
+        }
+        let json = match dispatch(&line) {
+            Ok(reply) => reply.line,
+            Err(err) => error::error_json(&err).to_string(),
+        };
+        writeln!(out, "{json}")
+            .and_then(|()| out.flush())
+            .context("write stdout")
+            .code(ErrorCode::IoError)?;
+    }
+    Ok(())
+}
+
+/// What one request prints.
+struct Reply {
+    /// The pretty-printed response.
+    json: String,
+    /// The same on one line (`--serve`).
+    line: String,
+    /// Exit nonzero after printing (a failed `self_test`).
+    failed: bool,
+}
+
+impl Reply {
+    fn new(resp: &impl Serialize) -> Result<Self> {
+        Ok(Self {
+            json: serde_json::to_string_pretty(resp)?,
+            line: serde_json::to_string(resp)?,
+            failed: false,
+        })
+    }
+}
+
+/// Parse the request JSON `input` (empty runs the default smoke test), run it and return its
+/// response; an `Err` is reported with `error::error_json`.
+fn dispatch(input: &str) -> Result<Reply> {
+    let reply = dispatch_request(input);
+    // an uncaptured wgpu error outranks whatever the request made of its aftermath
+    check_device_error()?;
+    reply
+}
+
+fn dispatch_request(input: &str) -> Result<Reply> {
+    // `raw` is kept for `explain`, which reports what the request left out
+    let (req, raw, filled): (Request, _, _) = if input.trim().is_empty() {
+        let filled = presets::Filled::default();
+        let smoke = Request::Smoke {
+            n: 1024,
+            workgroup: default_smoke_workgroup(),
+            backend: WgpuBackend::default(),
+        };
+        (smoke, serde_json::Value::Null, filled)
+    } else {
+        let mut value: serde_json::Value = serde_json::from_str(input)
+            .context("invalid JSON request")
+            .code(ErrorCode::InvalidJson)?;
+        let filled = presets::fill(&mut value)?;
+        let req = serde_json::from_value(value.clone())
+            .with_context(|| match filled.env_keys() {
+                [] => "invalid JSON request".to_owned(),
+                env => format!(
+                    "invalid JSON request (with FLUID_* environment defaults for {})",
+                    env.join(", ")
+                ),
+            })
+            .code(ErrorCode::InvalidJson)?;
+        (req, value, filled)
+    };
+
+    match req {
+        Request::Smoke {
+            n,
+            workgroup,
+            backend,
+        } => {
+            let resp = pollster::block_on(run_smoke(n.max(64), workgroup, backend))?;
+            Reply::new(&resp)
+        }
+        Request::SmokeSweep { sizes, backend } => {
+            let fallback = vec![1024, 4096, 16384, 65536];
+            let mut runs = Vec::new();
+            for n in if sizes.is_empty() { &fallback } else { &sizes } {
+                runs.push(pollster::block_on(run_smoke(
+                    (*n).max(64),
+                    default_smoke_workgroup(),
+                    backend,
+                ))?);
+            }
+            let ok = runs.iter().all(|r| r.ok);
+            let resp = SmokeSweepResponse {
+                ok,
+                // every size ran on the same adapter, and there is at least one
+                backend: runs[0].backend,
+                runs,
+            };
+            Reply::new(&resp)
+        }
+        Request::FluidInit {
+            width,
+            height,
+            dye_radius,
+            impulse,
+            obstacles,
+            backend,
+        } => {
+            let (width, height) = (width.max(16), height.max(16));
+            let solid = match &obstacles {
+                Some(mask) => Some(mask.cells(width, height)?),
+                None => None,
+            };
+            let resp = pollster::block_on(run_fluid_init(
+                width,
+                height,
+                dye_radius,
+                impulse,
+                solid.as_deref(),
+                backend,
+            ))?;
+            Reply::new(&resp)
+        }
+        Request::FluidStep(cfg) => {
+            let (cfg, cell_size_m) = cfg.prepare()?;
+            if cfg.explain {
+                let resp = explain::explain(&raw, &cfg, None);
+                return Reply::new(&resp);
+            }
+            if !cfg.readback {
+                let resp = pollster::block_on(run_fluid_advance(&cfg))?;
+                return Reply::new(&resp);
+            }
+            let mut resp = pollster::block_on(run_fluid_step(&cfg))?;
+            if cfg.report_param_sources {
+                resp.param_sources = Some(filled.sources(&raw, &cfg));
+            }
+            if cell_size_m.is_some() {
+                resp.cell_size_m = cell_size_m;
+                resp.dt_grid = Some(cfg.dt);
+                resp.cfl = Some(resp.max_speed * cfg.dt);
+            }
+            Reply::new(&resp)
+        }
+        Request::LiveRender(mut req) => {
+            req.render.sim = req.render.sim.prepare()?.0;
+            let resp = pollster::block_on(live::run(&req))?;
+            Reply::new(&resp)
+        }
+        Request::StreamTcp(mut req) => {
+            req.render.sim = req.render.sim.prepare()?.0;
+            let resp = pollster::block_on(stream::run(&req))?;
+            Reply::new(&resp)
+        }
+        Request::FluidRender(mut req) => {
+            req.sim = req.sim.prepare()?.0;
+            if req.sim.explain {
+                let (w, h) = render::output_size(
+                    req.sim.width,
+                    req.sim.height,
+                    req.square_pixels,
+                    req.out_width,
+                    req.out_height,
+                )?;
+                let image = format!(
+                    "Afterwards the final {:?} field is read back and rendered with {:?} into a \
+                     {w}x{h} PNG.",
+                    req.field, req.colormap
+                );
+                let resp = explain::explain(&raw, &req.sim, Some(image));
+                return Reply::new(&resp);
+            }
+            req.sim.read_pressure = req.overlay == Some(render::Overlay::Pressure);
+            let mut resp = pollster::block_on(run_fluid_render(&req))?;
+            if req.sim.report_param_sources {
+                resp.param_sources = Some(filled.sources(&raw, &req.sim));
+            }
+            Reply::new(&resp)
+        }
+        Request::KernelBench {
+            kernel,
+            width,
+            height,
+            iters,
+            backend,
+            adapter,
+        } => {
+            let resp = pollster::block_on(run_kernel_bench(
+                &kernel,
+                width.max(16),
+                height.max(16),
+                iters.max(1),
+                backend,
+                &adapter,
+            ))?;
+            Reply::new(&resp)
+        }
+        Request::ReadbackBench {
+            bytes,
+            iters,
+            backend,
+            adapter,
+        } => {
+            let resp =
+                pollster::block_on(run_readback_bench(bytes, iters.max(1), backend, &adapter))?;
+            Reply::new(&resp)
+        }
+        Request::StorageSweep {
+            sizes,
+            steps,
+            repeats,
+            sps_ema_alpha,
+            backend,
+        } => {
+            let alpha = if sps_ema_alpha > 0.0 {
+                sps_ema_alpha.min(1.0)
+            } else {
+                default_sps_ema_alpha()
+            };
+            let resp = pollster::block_on(run_storage_sweep(
+                &sizes,
+                steps.max(1),
+                repeats.max(1),
+                alpha,
+                backend,
+            ))?;
+            Reply::new(&resp)
+        }
+        Request::Formats { backend, adapter } => {
+            let resp = pollster::block_on(run_formats(backend, &adapter))?;
+            Reply::new(&resp)
+        }
+        Request::DeviceInfo { backend, adapter } => {
+            let resp = pollster::block_on(run_device_info(backend, &adapter))?;
+            Reply::new(&resp)
+        }
+        Request::ShaderCheck { backend } => {
+            let resp = pollster::block_on(shader_check::run(backend))?;
+            Reply::new(&resp)
+        }
+        Request::Pipeline { command, sim } => {
+            let PipelineCommand::FluidStep = command;
+            let resp = explain::pipeline(&sim.prepare()?.0);
+            Reply::new(&resp)
+        }
+        Request::SelfTest { backend } => {
+            let resp = pollster::block_on(run_self_test(backend));
+            Ok(Reply {
+                failed: !resp.ok,
+                ..Reply::new(&resp)?
+            })
+        }
+    }
+}
+
+/// The first `backend` adapter's device, shared through `gpu_cache` with the fluid runs, and
+/// its `backend_label`.
+async fn create_device(backend: WgpuBackend) -> Result<(wgpu::Device, wgpu::Queue, &'static str)> {
+    let adapter = request_adapter(backend, &AdapterChoice::default()).await?;
+    let (device, queue) = gpu_cache::device(&adapter, wgpu::Features::empty()).await?;
+    Ok((device, queue, backend_label(adapter.get_info().backend)))
+}
+
+/// Create a device on `adapter`, additionally enabling whichever of `wanted` it supports
+/// (callers check `device.features()` for what they actually got).
+async fn request_device(
+    adapter: &wgpu::Adapter,
+    wanted: wgpu::Features,
+) -> Result<(wgpu::Device, wgpu::Queue)> {
+    let features = wanted & adapter.features();
+    let (device, queue) = adapter
+        .request_device(&wgpu::DeviceDescriptor {
+            required_features: features,
+            ..Default::default()
+        })
+        .await
+        .context("request_device failed")
+        .code(ErrorCode::DeviceError)?;
+    // wgpu panics on uncaptured validation / out-of-memory errors by default; keep them for
+    // `check_device_error` to fail the request with instead
+    let adapter = adapter.get_info();
+    device.on_uncaptured_error(std::sync::Arc::new(move |error| {
+        DEVICE_ERROR.with_borrow_mut(|slot| {
+            slot.get_or_insert_with(|| UncapturedError {
+                adapter: adapter.clone(),
+                features,
+                error,
+            });
+        })
+    }));
+    Ok((device, queue))
+}
+
+/// A wgpu error no error scope caught, with the `gpu_cache` key of the device it came from.
+struct UncapturedError {
+    adapter: wgpu::AdapterInfo,
+    features: wgpu::Features,
+    error: wgpu::Error,
+}
+
+thread_local! {
+    /// The first uncaptured wgpu error since the last `check_device_error`. wgpu reports an
+    /// error on the thread of the call that caused it, so each thread's requests (which run
+    /// one at a time) only see their own.
+    static DEVICE_ERROR: std::cell::RefCell<Option<UncapturedError>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+/// Fail with `device_error` on the uncaptured wgpu error this thread recorded, clearing it and
+/// evicting its device from `gpu_cache`.
+fn check_device_error() -> Result<()> {
+    let Some(err) = DEVICE_ERROR.with_borrow_mut(Option::take) else {
+        return Ok(());
+    };
+    gpu_cache::evict(|cached| cached.adapter == err.adapter && cached.features == err.features);
+    Err(error::coded(
+        ErrorCode::DeviceError,
+        anyhow::anyhow!("wgpu error: {}", err.error),
+    ))
+}
+
+/// Fail with `grid_too_large` before allocating when a `width`x`height` grid's largest
+/// per-cell buffer (`bytes_per_cell`) or its workgroup dispatch exceeds the device limits.
+fn check_grid_limits(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    bytes_per_cell: u64,
+) -> Result<()> {
+    let limits = device.limits();
+    let bytes = width as u64 * height as u64 * bytes_per_cell;
+    let max_bytes = limits
+        .max_buffer_size
+        .min(limits.max_storage_buffer_binding_size as u64);
+    let max_groups = limits.max_compute_workgroups_per_dimension;
+    let (wg_x, wg_y) = workgroups(width, height, WORKGROUP_SIDE);
+    if bytes > max_bytes || wg_x.max(wg_y) > max_groups {
+        return Err(anyhow::anyhow!(
+            "{width}x{height} grid needs {bytes}-byte buffers and {wg_x}x{wg_y} workgroups; the \
+             device allows {max_bytes} bytes and {max_groups} workgroups per dimension"
+        ))
+        .code(ErrorCode::GridTooLarge);
+    }
+    Ok(())
+}
+
+/// Texture format for `storage: "texture"`: the most precise one the adapter can both write as
+/// a storage texture and filter (`rgba32float` needs `FLOAT32_FILTERABLE`), or `None` to fall
+/// back to buffers.
+fn field_texture_format(adapter: &wgpu::Adapter) -> Option<(&'static str, wgpu::TextureFormat)> {
+    let usable = |tf| {
+        let features = adapter.get_texture_format_features(tf);
+        features
+            .allowed_usages
+            .contains(wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING)
+            && features
+                .flags
+                .contains(wgpu::TextureFormatFeatureFlags::FILTERABLE)
+    };
+    let float32_filterable = adapter
+        .features()
+        .contains(wgpu::Features::FLOAT32_FILTERABLE);
+    [
+        ("rgba32float", wgpu::TextureFormat::Rgba32Float),
+        ("rgba16float", wgpu::TextureFormat::Rgba16Float),
+    ]
+    .into_iter()
+    .filter(|&(_, tf)| tf != wgpu::TextureFormat::Rgba32Float || float32_filterable)
+    .find(|&(_, tf)| usable(tf))
+}
+
+/// The first adapter on `backend`, or the one `choice` pins.
+async fn request_adapter(backend: WgpuBackend, choice: &AdapterChoice) -> Result<wgpu::Adapter> {
+    // one instance per process: wgpu ids (which `gpu_cache` compares devices by) are only
+    // unique within an instance
+    static INSTANCE: std::sync::OnceLock<wgpu::Instance> = std::sync::OnceLock::new();
+    let instance = INSTANCE.get_or_init(|| {
+        wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        })
+    });
+    let mut adapters = instance.enumerate_adapters(backend.backends());
+    if adapters.is_empty() && backend == WgpuBackend::Auto {
+        adapters = instance.enumerate_adapters(wgpu::Backends::SECONDARY);
+    }
+    if adapters.is_empty() {
+        return Err(match backend {
+            WgpuBackend::Auto => anyhow::anyhow!("no GPU adapter"),
+            _ => anyhow::anyhow!("no {backend:?} adapter"),
+        })
+        .code(ErrorCode::NoAdapter);
+    }
+    if choice.adapter_index.is_none() && choice.adapter_name.is_none() {
+        return Ok(adapters.swap_remove(0));
+    }
+    let names: Vec<String> = adapters.iter().map(|a| a.get_info().name).collect();
+    let index = match (choice.adapter_index, &choice.adapter_name) {
+        (Some(_), Some(_)) => anyhow::bail!("give adapter_index or adapter_name, not both"),
+        (Some(index), None) => Some(index as usize).filter(|&i| i < names.len()),
+        (None, Some(name)) => {
+            // an exact (case-insensitive) name wins; otherwise the substring must be unique
+            let name = name.to_lowercase();
+            let lower: Vec<String> = names.iter().map(|n| n.to_lowercase()).collect();
+            let hits: Vec<usize> = (0..lower.len())
+                .filter(|&i| lower[i].contains(&name))
+                .collect();
+            match lower.iter().position(|n| *n == name) {
+                Some(exact) => Some(exact),
+                None => {
+                    if hits.len() > 1 {
+                        return Err(anyhow::anyhow!(
+                            "{choice} matches several adapters: {:?}",
+                            hits.iter().map(|&i| &names[i]).collect::<Vec<_>>()
+                        ))
+                        .code(ErrorCode::NoAdapter);
+                    }
+                    hits.first().copied()
+                }
+            }
+        }
+        (None, None) => unreachable!(),
+    };
+    let Some(index) = index else {
+        return Err(anyhow::anyhow!(
+            "no adapter matches {choice} (available: {names:?})"
+        ))
+        .code(ErrorCode::NoAdapter);
+    };
+    Ok(adapters.swap_remove(index))
+}
+
+/// Formats probed by `formats`: the candidates for texture-backed scalar/vector fields.
+const PROBED_FORMATS: [(&str, wgpu::TextureFormat); 6] = [
+    ("r32float", wgpu::TextureFormat::R32Float),
+    ("rg32float", wgpu::TextureFormat::Rg32Float),
+    ("rgba32float", wgpu::TextureFormat::Rgba32Float),
+    ("r16float", wgpu::TextureFormat::R16Float),
+    ("rgba16float", wgpu::TextureFormat::Rgba16Float),
+    ("rgba8unorm", wgpu::TextureFormat::Rgba8Unorm),
+];
+
+async fn run_formats(backend: WgpuBackend, choice: &AdapterChoice) -> Result<FormatsResponse> {
+    let adapter = request_adapter(backend, choice).await?;
+    let formats = PROBED_FORMATS
+        .iter()
+        .map(|&(format, tf)| {
+            let features = adapter.get_texture_format_features(tf);
+            let flags = features.flags;
+            FormatSupport {
+                format,
+                storage: features
+                    .allowed_usages
+                    .contains(wgpu::TextureUsages::STORAGE_BINDING),
+                storage_read_write: flags
+                    .contains(wgpu::TextureFormatFeatureFlags::STORAGE_READ_WRITE),
+                filterable: flags.contains(wgpu::TextureFormatFeatureFlags::FILTERABLE),
+            }
+        })
+        .collect();
+    Ok(FormatsResponse {
+        ok: true,
+        backend: backend_label(adapter.get_info().backend),
+        adapter: adapter.get_info().name,
+        formats,
+    })
+}
+
+async fn run_device_info(
+    backend: WgpuBackend,
+    choice: &AdapterChoice,
+) -> Result<DeviceInfoResponse> {
+    let adapter = request_adapter(backend, choice).await?;
+    let (device, _queue) = gpu_cache::device(&adapter, wgpu::Features::empty()).await?;
+    let info = adapter.get_info();
+    let limits = device.limits();
+    Ok(DeviceInfoResponse {
+        ok: true,
+        backend: backend_label(info.backend),
+        adapter: info.name,
+        device_type: match info.device_type {
+            wgpu::DeviceType::DiscreteGpu => "discrete_gpu",
+            wgpu::DeviceType::IntegratedGpu => "integrated_gpu",
+            wgpu::DeviceType::VirtualGpu => "virtual_gpu",
+            wgpu::DeviceType::Cpu => "cpu",
+            wgpu::DeviceType::Other => "other",
+        },
+        driver: info.driver,
+        driver_info: info.driver_info,
+        limits: DeviceLimits {
+            max_buffer_size: limits.max_buffer_size,
+            max_storage_buffer_binding_size: limits.max_storage_buffer_binding_size,
+            max_compute_workgroups_per_dimension: limits.max_compute_workgroups_per_dimension,
+            max_compute_invocations_per_workgroup: limits.max_compute_invocations_per_workgroup,
+            max_compute_workgroup_size_x: limits.max_compute_workgroup_size_x,
+        },
+    })
+}
+
+/// `solid` is the `obstacles` mask, one `0`/`1` per cell; the seed holds its cells at 0.
+async fn run_fluid_init(
+    width: u32,
+    height: u32,
+    dye_radius: f32,
+    impulse: f32,
+    solid: Option<&[u32]>,
+    backend: WgpuBackend,
+) -> Result<FluidInitResponse> {
+    let t0 = std::time::Instant::now();
+    let (device, queue, label) = create_device(backend).await?;
+    let cells = (width as usize) * (height as usize);
+    let (wg_x, wg_y) = workgroups(width, height, WORKGROUP_SIDE);
+
+    let params = Params {
+        width,
+        height,
+        dt: default_dt(),
+        viscosity: default_viscosity(),
+        fade: default_fade(),
+        dye_radius,
+        impulse,
+        inv_dx: width as f32,
+        inv_dy: height as f32,
+        ..Params::zeroed()
+    };
+
+    let params_buf = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("fluid-params"),
+        size: std::mem::size_of::<Params>() as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    queue.write_buffer(&params_buf, 0, bytemuck::bytes_of(&params));
+
+    let vel_buf = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("vel"),
+        size: (cells * std::mem::size_of::<[f32; 2]>()) as u64,
+        usage: wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    });
+    let dye_buf = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("dye"),
+        size: (cells * std::mem::size_of::<f32>()) as u64,
+        usage: wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    });
+    // a 0/1 mask reads as an `obstacle_table` of static walls (1 element = none)
+    let obstacles_buf = {
+        use wgpu::util::DeviceExt as _;
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("obstacles"),
+            contents: bytemuck::cast_slice(solid.unwrap_or(&[0])),
+            usage: wgpu::BufferUsages::STORAGE,
+        })
+    };
+
+    let init_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("fluid-init"),
+        source: wgpu::ShaderSource::Wgsl(FLUID_INIT_WGSL.into()),
+    });
+    let init_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("fluid-init-pipeline"),
+        layout: None,
+        module: &init_shader,
+        entry_point: Some("main"),
+        compilation_options: wgpu::PipelineCompilationOptions::default(),
+        cache: None,
+    });
+    let init_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("fluid-init-bg"),
+        layout: &init_pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params_buf.as_entire_binding(),
+            },
             wgpu::BindGroupEntry {
                 binding: 1,
-                resource: dye_b.as_entire_binding(),
+                resource: vel_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: dye_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: obstacles_buf.as_entire_binding(),
+            },
+        ],
+    });
+
+    let mut encoder = device.create_command_encoder(&Default::default());
+    {
+        let mut pass = encoder.begin_compute_pass(&Default::default());
+        pass.set_pipeline(&init_pipeline);
+        pass.set_bind_group(0, &init_bg, &[]);
+        pass.dispatch_workgroups(wg_x, wg_y, 1);
+    }
+    queue.submit(Some(encoder.finish()));
+    let _ = device.poll(wgpu::PollType::wait_indefinitely());
+
+    Ok(FluidInitResponse {
+        ok: true,
+        backend: label,
+        width,
+        height,
+        initialized_cells: cells as u32,
+        obstacle_cells: solid.map(|solid| solid.iter().sum()),
+        elapsed_ms: t0.elapsed().as_secs_f64() * 1000.0,
+    })
+}
+
+async fn run_fluid_step(cfg: &FluidStepRequest) -> Result<FluidStepResponse> {
+    Ok(run_fluid(cfg).await?.0)
+}
+
+/// `readback: false`: seed and step, then wait for the GPU; nothing is copied back, so the
+/// state is dropped with the sim (`--interactive`'s `step` is the same on a kept sim).
+async fn run_fluid_advance(cfg: &FluidStepRequest) -> Result<FluidAdvanceResponse> {
+    let t0 = std::time::Instant::now();
+    let (mut sim, _adapter) = FluidRun::new(cfg).await?;
+    sim.seed();
+    let window = std::time::Instant::now();
+    match cfg.deadline_ms {
+        Some(ms) => {
+            let deadline = t0 + std::time::Duration::from_millis(ms);
+            while sim.steps_done() < cfg.steps && std::time::Instant::now() < deadline {
+                sim.step((cfg.steps - sim.steps_done()).min(sim.steps_per_submit()));
+                sim.wait();
+            }
+        }
+        None => {
+            sim.step(cfg.steps);
+            sim.wait();
+        }
+    }
+    Ok(FluidAdvanceResponse {
+        ok: true,
+        steps: sim.steps_done(),
+        compute_ms: window.elapsed().as_secs_f64() * 1000.0,
+    })
+}
+
+async fn run_fluid_render(req: &FluidRenderRequest) -> Result<FluidRenderResponse> {
+    let t0 = std::time::Instant::now();
+    let cfg = &req.sim;
+    let (image_width, image_height) = render::output_size(
+        cfg.width,
+        cfg.height,
+        req.square_pixels,
+        req.out_width,
+        req.out_height,
+    )?;
+    anyhow::ensure!(
+        req.out_path.is_some() || req.data_url,
+        "fluid_render needs out_path, data_url: true, or both"
+    );
+    let palette = render::Palette::new(req.colormap, &req.colormap_stops)?;
+    if req.data_url {
+        anyhow::ensure!(
+            image_width * image_height <= DATA_URL_MAX_PIXELS,
+            "data_url images are limited to {DATA_URL_MAX_PIXELS} pixels (got {image_width}x{image_height}); \
+             lower out_width/out_height or write to out_path instead"
+        );
+    }
+    anyhow::ensure!(
+        (0.0..=1.0).contains(&req.overlay_alpha),
+        "overlay_alpha must be within 0..=1 (got {})",
+        req.overlay_alpha
+    );
+    anyhow::ensure!(
+        !(req.overlay == Some(render::Overlay::Pressure) && cfg.dump_streamfunction),
+        "overlay: \"pressure\" cannot be combined with dump_streamfunction (its solve reuses \
+         the pressure buffers)"
+    );
+    let (step, fields) = run_fluid(cfg).await?;
+    let (mut rgba, value_range) =
+        render::render_rgba(req, &palette, &fields, image_width, image_height);
+    let overlay_range = render::apply_overlay(req, &fields, &mut rgba, image_width, image_height);
+    let png = encode_png_rgba(image_width, image_height, &rgba)?;
+    if let Some(path) = &req.out_path {
+        std::fs::write(path, &png)
+            .with_context(|| format!("write {path}"))
+            .code(ErrorCode::IoError)?;
+    }
+    let data_url = req.data_url.then(|| {
+        use base64::Engine as _;
+        format!(
+            "data:image/png;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(&png)
+        )
+    });
+
+    Ok(FluidRenderResponse {
+        ok: true,
+        backend: step.backend,
+        width: cfg.width,
+        height: cfg.height,
+        steps: cfg.steps,
+        elapsed_ms: t0.elapsed().as_secs_f64() * 1000.0,
+        image_path: req.out_path.clone(),
+        data_url_bytes: data_url.as_ref().map(String::len),
+        data_url,
+        image_width,
+        image_height,
+        field: req.field,
+        colormap: req.colormap,
+        value_range,
+        max_speed: matches!(palette, render::Palette::FlowHsv).then_some(value_range[1]),
+        overlay: req.overlay,
+        overlay_range,
+        seed_used: step.seed_used,
+        resolved_params: step.resolved_params,
+        param_sources: None,
+    })
+}
+
+/// Time the same square fluid run with `storage: "buffer"` and `"texture"` at each size,
+/// `repeats` times each (alternating the two, so drift hits both alike).
+async fn run_storage_sweep(
+    sizes: &[u32],
+    steps: u32,
+    repeats: u32,
+    alpha: f64,
+    backend: WgpuBackend,
+) -> Result<StorageSweepResponse> {
+    // every run takes this same first adapter
+    let adapter = request_adapter(backend, &AdapterChoice::default()).await?;
+    let fallback = [128, 256, 512];
+    let mut texture_format = None;
+    let mut runs = Vec::new();
+    for &size in if sizes.is_empty() {
+        &fallback[..]
+    } else {
+        sizes
+    } {
+        let size = size.max(16);
+        let mut sps = [Vec::new(), Vec::new()];
+        for _ in 0..repeats {
+            for (slot, storage) in sps.iter_mut().zip(["buffer", "texture"]) {
+                let cfg = small_fluid_config(serde_json::json!({
+                    "width": size, "height": size, "steps": steps, "storage": storage,
+                    "backend": backend,
+                }));
+                let resp = run_fluid_step(&cfg).await?;
+                slot.push(resp.sps);
+                texture_format = texture_format.or(resp.texture_format);
+            }
+        }
+        let buffer = SpsStats::new(&sps[0], alpha);
+        let texture = SpsStats::new(&sps[1], alpha);
+        runs.push(StorageSweepRun {
+            size,
+            buffer_sps: buffer.sps_median,
+            texture_sps: texture.sps_median,
+            buffer,
+            texture,
+        });
+    }
+    Ok(StorageSweepResponse {
+        ok: true,
+        backend: backend_label(adapter.get_info().backend),
+        texture_format,
+        runs,
+    })
+}
+
+/// Run a `fluid_step` simulation; returns the response plus the final fields it summarizes.
+async fn run_fluid(cfg: &FluidStepRequest) -> Result<(FluidStepResponse, FluidFields)> {
+    let t0 = std::time::Instant::now();
+    if let Some(roi) = cfg.roi {
+        roi.check(cfg.width, cfg.height)?;
+    }
+    if cfg.dump_json {
+        let roi = cfg.roi.unwrap_or(Roi::full(cfg.width, cfg.height));
+        if roi.w * roi.h > DUMP_JSON_MAX_CELLS {
+            return Err(anyhow::anyhow!(
+                "dump_json is limited to {DUMP_JSON_MAX_CELLS} cells (got {}x{}); use \
+                 dump_vel/dump_dye or a smaller roi",
+                roi.w,
+                roi.h
+            ))
+            .code(ErrorCode::GridTooLarge);
+        }
+    }
+    let (mut sim, adapter_name) = FluidRun::new(cfg).await?;
+    let texture_format = sim.texture_format();
+    sim.seed();
+
+    // with a blowup policy, run in chunks of `blowup_check_every` steps and scan the fields
+    // between chunks; `continue` keeps the single end-of-run readback.
+    let chunk = match cfg.on_blowup {
+        OnBlowup::Continue => cfg.steps,
+        OnBlowup::Error | OnBlowup::Reset => cfg.blowup_check_every.max(1),
+    };
+    // with a deadline, step one submission at a time and check the clock once it drains
+    let deadline = cfg
+        .deadline_ms
+        .map(|ms| t0 + std::time::Duration::from_millis(ms));
+    let mut deadline_hit = false;
+    let mut resets = 0u32;
+    let mut stepping = std::time::Duration::ZERO;
+    let fields = loop {
+        let target = (sim.steps_done() + chunk).min(cfg.steps);
+        let window = std::time::Instant::now();
+        while sim.steps_done() < target {
+            let mut remaining = target - sim.steps_done();
+            if cfg.progress_every > 0 {
+                remaining =
+                    remaining.min(cfg.progress_every - sim.steps_done() % cfg.progress_every);
+            }
+            match deadline {
+                Some(deadline) => {
+                    sim.step(remaining.min(sim.steps_per_submit()));
+                    sim.wait();
+                    if std::time::Instant::now() >= deadline && sim.steps_done() < cfg.steps {
+                        deadline_hit = true;
+                        break;
+                    }
+                }
+                None => sim.step(remaining),
+            }
+            if cfg.progress_every > 0 && sim.steps_done().is_multiple_of(cfg.progress_every) {
+                report_progress(&mut sim, cfg.steps, t0)?;
+            }
+        }
+        // the window closes once the GPU has drained the chunk, not when it was submitted
+        sim.wait();
+        stepping += window.elapsed();
+        let mut fields = sim.read_fields()?;
+        if cfg.on_blowup != OnBlowup::Continue && !fields.is_finite() {
+            match cfg.on_blowup {
+                OnBlowup::Error => {
+                    return Err(anyhow::anyhow!(
+                        "fluid state became non-finite by step {}",
+                        sim.steps_done()
+                    ))
+                    .code(ErrorCode::Blowup);
+                }
+                OnBlowup::Continue => unreachable!("continue never scans for a blowup"),
+                OnBlowup::Reset => {
+                    resets += 1;
+                    sim.seed();
+                    if deadline_hit || sim.steps_done() >= cfg.steps {
+                        fields = sim.read_fields()?;
+                    }
+                }
+            }
+        }
+        if deadline_hit || sim.steps_done() >= cfg.steps {
+            break fields;
+        }
+    };
+    let steps = sim.steps_done();
+    let mut fields = fields;
+    if cfg.read_pressure {
+        let full = Roi::full(cfg.width, cfg.height);
+        fields.pressure = sim.read_regions(&[FluidField::Pressure], full)?.remove(0);
+    }
+
+    let stats = summarize(cfg, steps, &fields.vel, &fields.dye);
+    let helmholtz = cfg
+        .helmholtz_check
+        .then(|| helmholtz_gradient_fraction(cfg, &fields.vel));
+    let velocity_texture_scale = match &cfg.velocity_texture_path {
+        Some(path) => {
+            let scale = if stats.max_speed > 0.0 {
+                stats.max_speed
+            } else {
+                1.0
+            };
+            write_velocity_png(path, cfg.width, cfg.height, &fields.vel, scale)?;
+            Some(scale)
+        }
+        None => None,
+    };
+    if let Some(path) = &cfg.csv_out_path {
+        let (w, h) = (cfg.width as usize, cfg.height as usize);
+        let values: Vec<f32> = match cfg.csv_field {
+            CsvField::Dye => fields.dye.clone(),
+            CsvField::Curl => (0..w * h)
+                .map(|i| vorticity(&fields.vel, w, h, i % w, i / w) as f32)
+                .collect(),
+            CsvField::Speed => fields
+                .vel
+                .iter()
+                .map(|v| (v[0] * v[0] + v[1] * v[1]).sqrt())
+                .collect(),
+        };
+        write_csv(path, cfg.width, &values)?;
+    }
+    if let Some(path) = &cfg.vtk_out_path {
+        vtk::write(
+            path,
+            cfg.vtk_format,
+            cfg.width,
+            cfg.height,
+            &fields.vel,
+            &fields.dye,
+        )?;
+    }
+
+    let roi = cfg.roi.unwrap_or(Roi::full(cfg.width, cfg.height));
+    let (vel_grid, dye_grid) = if cfg.dump_json {
+        let rows =
+            |y: u32| (y * cfg.width + roi.x) as usize..(y * cfg.width + roi.x + roi.w) as usize;
+        (
+            Some(
+                (roi.y..roi.y + roi.h)
+                    .map(|y| fields.vel[rows(y)].to_vec())
+                    .collect(),
+            ),
+            Some(
+                (roi.y..roi.y + roi.h)
+                    .map(|y| fields.dye[rows(y)].to_vec())
+                    .collect(),
+            ),
+        )
+    } else {
+        (None, None)
+    };
+    let dump_fields: Vec<FluidField> = [
+        (FluidField::Vel, cfg.dump_vel),
+        (FluidField::Dye, cfg.dump_dye && cfg.channels == 1),
+        (FluidField::DyeRgb, cfg.dump_dye && cfg.channels == 3),
+        (FluidField::OkuboWeiss, cfg.dump_okubo_weiss),
+        (FluidField::DyeGradient, cfg.dump_dye_gradient),
+        (FluidField::Delta, cfg.dump_delta),
+        (FluidField::Streamfunction, cfg.dump_streamfunction),
+        (FluidField::DyeMean, cfg.time_average),
+        (FluidField::VelMean, cfg.time_average_vel),
+    ]
+    .into_iter()
+    .filter_map(|(field, wanted)| wanted.then_some(field))
+    .collect();
+    let mut dumps = Vec::new();
+    let mut okubo_weiss_range = None;
+    let mut streamfunction_range = None;
+    if !dump_fields.is_empty() {
+        let regions = sim.read_regions(&dump_fields, roi)?;
+        for (&field, values) in dump_fields.iter().zip(&regions) {
+            match field {
+                FluidField::OkuboWeiss => okubo_weiss_range = Some(render::value_range(values)),
+                FluidField::Streamfunction => {
+                    streamfunction_range = Some(render::value_range(values))
+                }
+                _ => {}
+            }
+            dumps.push(FieldDump::new(field, roi, values, cfg.compress)?);
+        }
+    }
+
+    let streamfunction_residual = if cfg.dump_streamfunction {
+        Some(sim.streamfunction_residual()?)
+    } else {
+        None
+    };
+
+    // the recorded step is the last one, which a deadline may never reach
+    let residual_curve = if cfg.residual_step().is_some() && !deadline_hit {
+        Some(sim.read_residuals()?)
+    } else {
+        None
+    };
+    let delta = match cfg.delta_steps(steps) {
+        Some(since) => {
+            let full = Roi::full(cfg.width, cfg.height);
+            let values = sim.read_regions(&[FluidField::Delta], full)?.remove(0);
+            Some(DeltaStats::new(cfg, since, &values))
+        }
+        None => None,
+    };
+    let pressure_frames = if cfg.pressure_frames_step().is_some() && !deadline_hit {
+        let width = cfg.width as usize;
+        let rows = |y: u32| {
+            let start = y as usize * width + roi.x as usize;
+            start..start + roi.w as usize
+        };
+        let mut dumps = Vec::new();
+        for field in sim.pressure_frames()? {
+            let values: Vec<f32> = (roi.y..roi.y + roi.h)
+                .flat_map(|y| field[rows(y)].to_vec())
+                .collect();
+            dumps.push(FieldDump::new(
+                FluidField::Pressure,
+                roi,
+                &values,
+                cfg.compress,
+            )?);
+        }
+        Some(PressureFrames {
+            iterations: cfg.pressure_frame_points().iter().map(|i| i + 1).collect(),
+            frames: dumps,
+        })
+    } else {
+        None
+    };
+    let dye_budget = if cfg.dye_budget {
+        Some(sim.read_dye_budget()?)
+    } else {
+        None
+    };
+    let forcing_budget = if cfg.forcing_budget {
+        Some(sim.read_forcing_budget(cfg)?)
+    } else {
+        None
+    };
+    let clamp_history = if cfg.clamp_history {
+        Some(sim.clamp_history()?)
+    } else {
+        None
+    };
+    // no steps leave no dt picked
+    let dt_stats = if cfg.adaptive_dt {
+        Some(sim.dt_stats()?).filter(|s| s[4] > 0.0)
+    } else {
+        None
+    };
+    let projection = sim.projection_tally()?.filter(|t| t.steps > 0);
+
+    let fade_range = (cfg.fade_gradient != FadeGradient::None).then(|| fade_range(cfg));
+
+    // the seed is deterministic, so take it from the CPU mirror instead of a readback
+    let initial_vel = cpu_ref::initial_velocity(cfg);
+    let elapsed = t0.elapsed().as_secs_f64();
+    let resp = FluidStepResponse {
+        ok: true,
+        backend: sim.backend_name(),
+        width: cfg.width,
+        height: cfg.height,
+        steps: cfg.steps,
+        elapsed_ms: elapsed * 1000.0,
+        sps: (steps as f64) / elapsed.max(1e-6),
+        steady_sps: (steps as f64) / stepping.as_secs_f64().max(1e-6),
+        avg_speed: stats.avg_speed,
+        max_speed: stats.max_speed,
+        avg_divergence: stats.avg_divergence,
+        max_divergence: stats.max_divergence,
+        dye_footprint: stats.dye_footprint,
+        dye_total: stats.dye_total,
+        avg_dye_gradient: stats.avg_dye_gradient,
+        dye_centroid: stats.dye_centroid,
+        dye_spread: stats.dye_spread,
+        velocity_clamped_cells: stats.velocity_clamped_cells,
+        angular_momentum: stats.angular_momentum,
+        enstrophy: stats.enstrophy,
+        enstrophy_initial: enstrophy(cfg, &initial_vel),
+        palinstrophy: stats.palinstrophy,
+        kinetic_energy: stats.kinetic_energy,
+        kinetic_energy_initial: kinetic_energy(cfg, &initial_vel),
+        init_dye: cfg.init_dye,
+        init_layers_applied: (!cfg.init_layers.is_empty()).then_some(cfg.init_layers.len()),
+        stats_cells: stats.cells,
+        obstacle_cells: stats.obstacle_cells,
+        time_average_steps: cfg.averages().then(|| sim.mean_steps()),
+        ghost_cells_applied: cfg
+            .ghost_b64
+            .is_some()
+            .then(|| ring_cells(cfg.width, cfg.height)),
+        net_prescribed_divergence: cfg
+            .div_target()
+            .map(|target| target.iter().map(|&d| d as f64).sum::<f64>() / target.len() as f64),
+        helmholtz_gradient_fraction: helmholtz.map(|h| h.0),
+        helmholtz_iters: helmholtz.map(|h| h.1),
+        reynolds: cfg
+            .report_reynolds
+            .then(|| Reynolds::new(cfg, stats.max_speed)),
+        integral_length: cfg.integral_length.then(|| {
+            analysis::IntegralLength::new(&fields.vel, cfg.width as usize, cfg.height as usize)
+        }),
+        velocity_texture_path: cfg.velocity_texture_path.clone(),
+        velocity_texture_scale,
+        csv_path: cfg.csv_out_path.clone(),
+        csv_rows: cfg.csv_out_path.is_some().then_some(cfg.height),
+        csv_cols: cfg.csv_out_path.is_some().then_some(cfg.width),
+        vtk_path: cfg.vtk_out_path.clone(),
+        vtk_points: cfg.vtk_out_path.is_some().then_some(cfg.width * cfg.height),
+        // `steps: 0` (or a deadline before the first step) leaves no last step
+        effective_impulse: steps.checked_sub(1).map_or(0.0, |last| {
+            cfg.impulse * impulse_ramp(last, cfg.impulse_ramp_steps)
+        }),
+        rotation: cfg.rotation,
+        source_active: steps
+            .checked_sub(1)
+            .is_some_and(|last| cfg.source_active(last)),
+        source_center: cfg.source_center,
+        force_position: steps
+            .checked_sub(1)
+            .and_then(|last| force_path_center(&cfg.force_path, last)),
+        advect_integrator: cfg.advect_integrator,
+        advection: cfg.advection,
+        pressure_solver: cfg.pressure_solver,
+        dye_advect_velocity: cfg.dye_advect_velocity,
+        fade_min: fade_range.map(|r| r.0),
+        fade_max: fade_range.map(|r| r.1),
+        jacobi_iters_cap: cfg.jacobi_iters_cap(),
+        jacobi_override_used: cfg.jacobi_iters > JACOBI_ITERS_MAX,
+        init_skipped: cfg.skip_init,
+        cell_size_m: None,
+        dt_grid: None,
+        cfl: None,
+        dt_min: dt_stats.map(|s| s[1]),
+        dt_max: dt_stats.map(|s| s[2]),
+        dt_mean: dt_stats.map(|s| s[3] / s[4]),
+        projection_passes_used: projection.map(|t| 1 + t.last_extra),
+        projection_passes_mean: projection.map(|t| 1.0 + t.extra_sum as f64 / t.steps as f64),
+        projection_passes_max: projection.map(|t| 1 + t.extra_max),
+        projection_divergence: projection.map(|t| t.last_divergence),
+        neighbor_clamped_samples: cfg.clamp_to_neighbors.then(|| {
+            fields.counters[COUNTER_NEIGHBOR_CLAMP_VEL]
+                + fields.counters[COUNTER_NEIGHBOR_CLAMP_DYE]
+        }),
+        gpu_clamp_events: fields.counters[COUNTER_CLAMP_EVENTS],
+        cfl_clamped_cells: cfg.cfl_clamp.then_some(fields.counters[COUNTER_CFL_CLAMP]),
+        gpu_clamp_events_per_step: clamp_history,
+        dispatch_trace: sim.dispatch_trace(),
+        resets: (cfg.on_blowup == OnBlowup::Reset).then_some(resets),
+        roi: cfg.roi,
+        dump_fields_read: dump_fields.len(),
+        dumps,
+        okubo_weiss_min: okubo_weiss_range.map(|r| r[0]),
+        okubo_weiss_max: okubo_weiss_range.map(|r| r[1]),
+        streamfunction_min: streamfunction_range.map(|r| r[0]),
+        streamfunction_max: streamfunction_range.map(|r| r[1]),
+        streamfunction_residual,
+        residual_curve,
+        pressure_frames,
+        delta,
+        // pinned by `FluidStepRequest::prepare`
+        seed_used: cfg.seed.unwrap_or_default(),
+        adapter: adapter_name,
+        storage: if texture_format.is_some() {
+            FieldStorage::Texture
+        } else {
+            FieldStorage::Buffer
+        },
+        texture_format,
+        pipeline_cache_hit: sim.pipeline_cache_hit(),
+        dye_added: dye_budget.map(|b| b[0]),
+        dye_faded: dye_budget.map(|b| b[1]),
+        dye_boundary_loss: dye_budget.map(|b| b[2]),
+        dye_color_final: steps.checked_sub(1).and_then(|last| cfg.dye_color_at(last)),
+        obstacle_positions: steps
+            .checked_sub(1)
+            .and_then(|last| cfg.obstacle_positions_at(last)),
+        forcing_power: forcing_budget.map(|b| b[0]),
+        forcing_work: forcing_budget.map(|b| b[1]),
+        resolved_params: cfg.preset.is_some().then(|| cfg.clone()),
+        param_sources: None,
+        vel_grid,
+        dye_grid,
+        deadline_hit: deadline.map(|_| deadline_hit),
+        steps_completed: deadline.map(|_| steps),
+        indirect_dispatch: sim.indirect_dispatch(),
+        pressure_init: cfg.pressure_init,
+    };
+    Ok((resp, fields))
+}
+
+/// The solver behind a `FluidStep`: GPU kernels or their CPU mirror.
+// one per request, so the variant size gap doesn't matter
+#[allow(clippy::large_enum_variant)]
+enum FluidRun {
+    Gpu(FluidSim),
+    Cpu(cpu_ref::CpuFluid),
+}
+
+impl FluidRun {
+    /// Set up `cfg.backend` (for the GPU: adapter, device and buffers), unseeded. Also returns
+    /// the GPU adapter's name.
+    async fn new(cfg: &FluidStepRequest) -> Result<(Self, Option<String>)> {
+        Ok(match cfg.backend.wgpu() {
+            Some(backend) => {
+                let adapter = request_adapter(backend, &cfg.adapter).await?;
+                let field_texture = match cfg.storage {
+                    FieldStorage::Buffer => None,
+                    FieldStorage::Texture => field_texture_format(&adapter),
+                };
+                let wanted = match field_texture {
+                    Some(_) => wgpu::Features::FLOAT32_FILTERABLE,
+                    None => wgpu::Features::empty(),
+                };
+                let (device, queue) = gpu_cache::device(&adapter, wanted).await?;
+                // velocity (8 bytes per cell) is the largest buffer unless the budget's triples are on
+                let bytes_per_cell = if cfg.dye_budget { 12 } else { 8 };
+                check_grid_limits(&device, cfg.width, cfg.height, bytes_per_cell)?;
+                let info = adapter.get_info();
+                let backend = backend_label(info.backend);
+                let sim = FluidSim::new(&device, &queue, cfg, field_texture, backend);
+                (Self::Gpu(sim), Some(info.name))
+            }
+            None => (Self::Cpu(cpu_ref::CpuFluid::new(cfg)?), None),
+        })
+    }
+
+    fn backend_name(&self) -> &'static str {
+        match self {
+            Self::Gpu(sim) => sim.backend,
+            Self::Cpu(_) => "cpu",
+        }
+    }
+
+    /// Whether setup reused only cached pipelines (GPU only).
+    fn pipeline_cache_hit(&self) -> Option<bool> {
+        match self {
+            Self::Gpu(sim) => Some(sim.pipeline_cache_hit),
+            Self::Cpu(_) => None,
+        }
+    }
+
+    /// Format of the advection source texture, when `storage: "texture"` took effect.
+    fn texture_format(&self) -> Option<&'static str> {
+        match self {
+            Self::Gpu(sim) => sim.field_tex.as_ref().map(|tex| tex.format),
+            Self::Cpu(_) => None,
+        }
+    }
+
+    fn steps_done(&self) -> u32 {
+        match self {
+            Self::Gpu(sim) => sim.steps_done,
+            Self::Cpu(sim) => sim.steps_done,
+        }
+    }
+
+    fn seed(&mut self) {
+        match self {
+            Self::Gpu(sim) => sim.seed(),
+            Self::Cpu(sim) => sim.seed(),
+        }
+    }
+
+    /// Reseed and rewind the step counter to 0, as if freshly created.
+    fn restart(&mut self) {
+        self.seed();
+        match self {
+            Self::Gpu(sim) => sim.steps_done = 0,
+            Self::Cpu(sim) => sim.steps_done = 0,
+        }
+    }
+
+    /// Swap in new per-step parameters (`dt`, `fade`, ...) without touching the fields. Grid
+    /// size and buffer layout must stay as created.
+    fn set_params(&mut self, cfg: &FluidStepRequest) {
+        match self {
+            Self::Gpu(sim) => {
+                sim.queue
+                    .write_buffer(&sim.params_buf, 0, bytemuck::bytes_of(&fluid_params(cfg)));
+                sim.cfg = cfg.clone();
+            }
+            Self::Cpu(sim) => sim.set_params(cfg),
+        }
+    }
+
+    fn indirect_dispatch(&self) -> bool {
+        matches!(self, Self::Gpu(sim) if matches!(sim.grid, GridDispatch::Indirect(_)))
+    }
+
+    /// Steps `step` issues per GPU submission (the granularity of deadline checks).
+    fn steps_per_submit(&self) -> u32 {
+        match self {
+            Self::Gpu(sim) => sim.steps_per_submit,
+            Self::Cpu(_) => 1,
+        }
+    }
+
+    /// Block until submitted steps have finished (CPU steps are synchronous).
+    fn wait(&self) {
+        if let Self::Gpu(sim) = self {
+            let _ = sim.device.poll(wgpu::PollType::wait_indefinitely());
+        }
+    }
+
+    fn step(&mut self, steps: u32) {
+        match self {
+            Self::Gpu(sim) => sim.step(steps),
+            Self::Cpu(sim) => sim.step(steps),
+        }
+    }
+
+    fn read_residuals(&mut self) -> Result<Vec<f32>> {
+        match self {
+            Self::Gpu(sim) => sim.read_residuals(),
+            Self::Cpu(sim) => Ok(sim.residuals.clone()),
+        }
+    }
+
+    fn read_dye_budget(&mut self) -> Result<[f32; 3]> {
+        let cells = match self {
+            Self::Gpu(sim) => sim.read_dye_budget()?,
+            Self::Cpu(sim) => sim.budget.clone(),
+        };
+        Ok(sum_dye_budget(&cells))
+    }
+
+    /// `forcing_budget` (power on the last step, work since the last (re)seed).
+    fn read_forcing_budget(&mut self, cfg: &FluidStepRequest) -> Result<[f64; 2]> {
+        let cells = match self {
+            Self::Gpu(sim) => sim.read_forcing_budget()?,
+            Self::Cpu(sim) => sim.forcing.clone(),
+        };
+        Ok(sum_forcing_budget(cfg, &cells))
+    }
+
+    fn read_fields(&mut self) -> Result<FluidFields> {
+        match self {
+            Self::Gpu(sim) => sim.read_fields(),
+            Self::Cpu(sim) => Ok(sim.read_fields()),
+        }
+    }
+
+    fn read_regions(&mut self, fields: &[FluidField], roi: Roi) -> Result<Vec<Vec<f32>>> {
+        let mut regions = match self {
+            Self::Gpu(sim) => sim.read_regions(fields, roi)?,
+            Self::Cpu(sim) => fields.iter().map(|&f| sim.read_region(f, roi)).collect(),
+        };
+        let n = self.mean_steps().max(1) as f32;
+        for (field, values) in fields.iter().zip(&mut regions) {
+            if let FluidField::DyeMean | FluidField::VelMean = field {
+                values.iter_mut().for_each(|v| *v /= n);
+            }
+        }
+        Ok(regions)
+    }
+
+    /// Largest `|v|` over the whole grid, without a full field readback.
+    fn max_speed(&mut self) -> Result<f32> {
+        match self {
+            Self::Gpu(sim) => sim.read_max_speed(),
+            Self::Cpu(sim) => Ok(sim.max_speed()),
+        }
+    }
+
+    /// Residual of the last `streamfunction` solve (see `FluidSim::read_regions`).
+    fn streamfunction_residual(&mut self) -> Result<f32> {
+        match self {
+            Self::Gpu(sim) => sim.read_streamfunction_residual(),
+            Self::Cpu(sim) => Ok(sim.streamfunction().1),
+        }
+    }
+
+    /// `adaptive_dt` statistics (see `DT_STATS_LEN`).
+    fn dt_stats(&mut self) -> Result<[f32; DT_STATS_LEN]> {
+        match self {
+            Self::Gpu(sim) => sim.read_dt_stats(),
+            Self::Cpu(sim) => Ok(sim.dt_stats),
+        }
+    }
+
+    /// Full-grid pressure frames of the recorded solve (see `dump_pressure_every`).
+    fn pressure_frames(&mut self) -> Result<Vec<Vec<f32>>> {
+        match self {
+            Self::Gpu(sim) => sim.read_pressure_frames(),
+            Self::Cpu(sim) => Ok(sim.pressure_frames.clone()),
+        }
+    }
+
+    /// `gpu_clamp_events_per_step` of the steps run so far (up to `cfg.steps`).
+    fn clamp_history(&mut self) -> Result<Vec<u32>> {
+        let totals = match self {
+            Self::Gpu(sim) => sim.read_clamp_history()?,
+            Self::Cpu(sim) => sim.clamp_history.clone(),
+        };
+        // running totals, one per step
+        Ok(totals
+            .iter()
+            .scan(0, |prev, &total| {
+                Some(total - std::mem::replace(prev, total))
+            })
+            .collect())
+    }
+
+    fn projection_tally(&mut self) -> Result<Option<ProjectionTally>> {
+        match self {
+            Self::Gpu(sim) => sim.read_projection_tally(),
+            Self::Cpu(sim) => Ok(sim.projection_tally),
+        }
+    }
+
+    /// The first step's `trace_dispatches` record (GPU only).
+    fn dispatch_trace(&self) -> Option<Vec<String>> {
+        match self {
+            Self::Gpu(sim) => sim.dispatch_trace.clone(),
+            Self::Cpu(_) => None,
+        }
+    }
+
+    /// Steps accumulated into the `time_average` sums since the last (re)seed.
+    fn mean_steps(&self) -> u32 {
+        match self {
+            Self::Gpu(sim) => sim.mean_steps,
+            Self::Cpu(sim) => sim.mean_steps,
+        }
+    }
+}
+
+/// `adaptive_dt` statistics buffer: the current step's `dt`, then the min, max and sum of every
+/// `dt` picked since the last (re)seed and how many there were.
+const DT_STATS_LEN: usize = 5;
+
+/// `target_divergence` gate buffer, in `u32` words: the gated pressure solve's indirect args,
+/// whether the step's passes have stopped, the step's extra passes and the max |div| last
+/// reduced (`f32` bits), then the extra passes summed over and most in one of the steps since
+/// the last (re)seed, and the target (`f32` bits, written once).
+const PROJECTION_GATE_LEN: usize = 9;
+
+/// `target_divergence` bookkeeping (see `PROJECTION_GATE_LEN`).
+#[derive(Debug, Clone, Copy, Default)]
+struct ProjectionTally {
+    /// Extra passes of the last step, and the max |div| its final pass left.
+    last_extra: u32,
+    last_divergence: f32,
+    /// Extra passes summed over and most in one of the `steps` since the last (re)seed.
+    extra_sum: u32,
+    extra_max: u32,
+    steps: u32,
+}
+
+/// `trace_dispatches`: the bind groups of one step's dispatches, in encode order, when
+/// recording (the default records nothing).
+#[derive(Debug, Default)]
+struct DispatchTrace(Option<Vec<String>>);
+
+impl DispatchTrace {
+    fn recording() -> Self {
+        DispatchTrace(Some(Vec::new()))
+    }
+
+    fn push(&mut self, group: &str) {
+        if let Some(groups) = &mut self.0 {
+            groups.push(group.to_owned());
+        }
+    }
+}
+
+/// Cells in the one-cell border of a `width` x `height` grid.
+fn ring_cells(width: u32, height: u32) -> usize {
+    (2 * (width as usize + height as usize)).saturating_sub(4)
+}
+
+/// `progress_every` line on stderr: `step/total`, elapsed and remaining time extrapolated from
+/// the rate so far, and the current max speed (the one small readback this costs).
+fn report_progress(sim: &mut FluidRun, total: u32, t0: std::time::Instant) -> Result<()> {
+    let done = sim.steps_done();
+    let max_speed = sim.max_speed()?;
+    let elapsed = t0.elapsed().as_secs_f64();
+    let eta = elapsed / done.max(1) as f64 * total.saturating_sub(done) as f64;
+    eprintln!(
+        "progress: step {done}/{total} elapsed {elapsed:.1}s eta {eta:.1}s max_speed {max_speed:.4}"
+    );
+    Ok(())
+}
+
+/// Largest window `dump_json` will emit (32x32): nested JSON arrays get unwieldy fast.
+const DUMP_JSON_MAX_CELLS: u32 = 32 * 32;
+
+/// Totals of the per-cell (added, faded, advection loss) `dye_budget` triples, summed in f64.
+fn sum_dye_budget(cells: &[f32]) -> [f32; 3] {
+    let mut sums = [0.0f64; 3];
+    for cell in cells.chunks_exact(3) {
+        for (sum, &term) in sums.iter_mut().zip(cell) {
+            *sum += term as f64;
+        }
+    }
+    sums.map(|sum| sum as f32)
+}
+
+/// Totals of the per-cell (power, work) `forcing_budget` pairs over the unit domain, summed in
+/// f64 like `kinetic_energy`.
+fn sum_forcing_budget(cfg: &FluidStepRequest, cells: &[[f32; 2]]) -> [f64; 2] {
+    let area = (cfg.width as usize * cfg.height as usize) as f64;
+    let mut sums = [0.0f64; 2];
+    for cell in cells {
+        sums[0] += cell[0] as f64;
+        sums[1] += cell[1] as f64;
+    }
+    sums.map(|sum| sum / area)
+}
+
+/// Which buffer of a ping-pong pair (`vel_a` / `vel_b`, `dye_a` / `dye_b`) holds a field's
+/// latest values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    A,
+    B,
+}
+
+impl Slot {
+    /// The other buffer: where a kernel reading this one writes.
+    fn flipped(self) -> Self {
+        match self {
+            Slot::A => Slot::B,
+            Slot::B => Slot::A,
+        }
+    }
+}
+
+/// Simulation fields that can be dumped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FluidField {
+    Vel,
+    /// With `channels: 3`, the luminance of `DyeRgb`.
+    Dye,
+    /// `channels: 3` dye, `[r, g, b]` per cell.
+    DyeRgb,
+    /// Derived from velocity at readback time.
+    OkuboWeiss,
+    /// Derived from dye at readback time.
+    DyeGradient,
+    /// `[|v - v_prev|, |dye - dye_prev|]` against the `delta_every` snapshot, at readback time.
+    Delta,
+    Streamfunction,
+    /// The last pressure solve of the last step.
+    Pressure,
+    /// `time_average` means: the running sums, divided by `mean_steps` at readback.
+    DyeMean,
+    VelMean,
+}
+
+impl FluidField {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Vel => "vel",
+            Self::Dye => "dye",
+            Self::DyeRgb => "dye_rgb",
+            Self::OkuboWeiss => "okubo_weiss",
+            Self::DyeGradient => "dye_gradient",
+            Self::Delta => "delta",
+            Self::Streamfunction => "streamfunction",
+            Self::Pressure => "pressure",
+            Self::DyeMean => "dye_mean",
+            Self::VelMean => "vel_mean",
+        }
+    }
+
+    fn components(self) -> usize {
+        match self {
+            Self::Vel | Self::VelMean | Self::Delta => 2,
+            Self::DyeRgb => 3,
+            Self::Dye
+            | Self::OkuboWeiss
+            | Self::DyeGradient
+            | Self::Streamfunction
+            | Self::Pressure
+            | Self::DyeMean => 1,
+        }
+    }
+}
+
+/// Fail cleanly, rather than with a wgpu validation error, when `field`'s buffer was created
+/// without `COPY_SRC` (see `FluidStepRequest::readback_usage`).
+fn check_readable(field: FluidField, usage: wgpu::BufferUsages) -> Result<()> {
+    if usage.contains(wgpu::BufferUsages::COPY_SRC) {
+        return Ok(());
+    }
+    Err(anyhow::anyhow!(
+        "field {} can't be read back: it wasn't requested when the simulation was set up",
+        field.name()
+    ))
+    .code(ErrorCode::UnsupportedFeature)
+}
+
+/// Host copy of the simulation state after a readback.
+struct FluidFields {
+    vel: Vec<[f32; 2]>,
+    dye: Vec<f32>,
+    counters: [u32; COUNTER_SLOTS],
+    /// The last pressure solve (empty unless `read_pressure`).
+    pressure: Vec<f32>,
+}
+
+impl FluidFields {
+    /// Speeds are checked squared, so velocities large enough to overflow the stats count too.
+    fn is_finite(&self) -> bool {
+        self.vel
+            .iter()
+            .all(|v| (v[0] * v[0] + v[1] * v[1]).is_finite())
+            && self.dye.iter().all(|d| d.is_finite())
+    }
+}
+
+/// Scalar diagnostics derived from a velocity/dye readback.
+#[derive(Serialize)]
+struct FluidStats {
+    avg_speed: f32,
+    max_speed: f32,
+    /// Mean and max `|div v|` over the stats cells outside `obstacles`.
+    avg_divergence: f32,
+    max_divergence: f32,
+    dye_footprint: f32,
+    dye_total: f32,
+    /// Mean `|grad dye|` over the stats cells (unit domain): how sharp the dye's edges are.
+    avg_dye_gradient: f64,
+    /// Dye-weighted mean and standard deviation of the cell-center position over the stats
+    /// cells, in unit-domain coordinates; `None` without dye.
+    dye_centroid: Option<[f32; 2]>,
+    dye_spread: Option<[f32; 2]>,
+    velocity_clamped_cells: u32,
+    /// Sum of `(r x v)_z` about the forcing center, in cells * velocity (unit density).
+    angular_momentum: f64,
+    enstrophy: f64,
+    palinstrophy: f64,
+    kinetic_energy: f64,
+    /// Cells the aggregates cover (after `stats_margin`).
+    cells: u32,
+    /// Solid cells of `obstacles` among them (only with `obstacles`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    obstacle_cells: Option<u32>,
+}
+
+/// Stats of the fields after `steps` steps (which place `obstacle_velocity` walls).
+fn summarize(cfg: &FluidStepRequest, steps: u32, vel: &[[f32; 2]], dye: &[f32]) -> FluidStats {
+    let w = cfg.width as usize;
+    let h = cfg.height as usize;
+    // `stats_margin` cells are dropped from every edge (`prepare` keeps the interior non-empty)
+    let m = cfg.stats_margin as usize;
+    let (xs, ys) = (m..w - m, m..h - m);
+    let cells = xs.len() * ys.len();
+    let interior = || {
+        let xs = xs.clone();
+        ys.clone()
+            .flat_map(move |y| xs.clone().map(move |x| (x, y)))
+    };
+
+    // cells sitting at the cap in the returned field (the project kernel rescales them onto it)
+    let clamp_floor = cfg.max_velocity * (1.0 - 1e-4);
+    let mut sum_speed = 0.0f32;
+    let mut max_speed = 0.0f32;
+    let mut clamped = 0u32;
+    // same center as the advect kernel's forcing
+    let (cx, cy) = (w as f64 * 0.5, h as f64 * 0.5);
+    let mut angular_momentum = 0.0f64;
+    for (x, y) in interior() {
+        let v = vel[y * w + x];
+        let s = (v[0] * v[0] + v[1] * v[1]).sqrt();
+        sum_speed += s;
+        max_speed = max_speed.max(s);
+        if cfg.max_velocity > 0.0 && s >= clamp_floor {
+            clamped += 1;
+        }
+        angular_momentum += (x as f64 - cx) * v[1] as f64 - (y as f64 - cy) * v[0] as f64;
+    }
+
+    // a solid cell holds no fluid to compress; its fluid neighbors already see it as a wall
+    let solid = cfg.obstacles_at(steps.saturating_sub(1)).ok().flatten();
+    let is_solid = |x: usize, y: usize| solid.as_ref().is_some_and(|s| s[y * w + x] != 0);
+    let mut sum_div = 0.0f32;
+    let mut max_div = 0.0f32;
+    let mut fluid_cells = 0usize;
+    for (x, y) in interior().filter(|&(x, y)| !is_solid(x, y)) {
+        let ad = divergence(vel, w, h, x, y).abs();
+        sum_div += ad;
+        max_div = max_div.max(ad);
+        fluid_cells += 1;
+    }
+    let enstrophy = enstrophy(cfg, vel);
+    let palinstrophy = palinstrophy(cfg, vel);
+    let kinetic_energy = kinetic_energy(cfg, vel);
+
+    let mut dye_total = 0.0f32;
+    let mut nonzero = 0usize;
+    // weight, first and second moments of position; negative dye (undershoot) weighs nothing
+    let (mut weight, mut first, mut second) = (0.0f64, [0.0f64; 2], [0.0f64; 2]);
+    for (x, y) in interior() {
+        let d = dye[y * w + x];
+        dye_total += d;
+        if d > 0.01 {
+            nonzero += 1;
+        }
+        let d = d.max(0.0) as f64;
+        let pos = [(x as f64 + 0.5) / w as f64, (y as f64 + 0.5) / h as f64];
+        weight += d;
+        for axis in 0..2 {
+            first[axis] += d * pos[axis];
+            second[axis] += d * pos[axis] * pos[axis];
+        }
+    }
+    let (dye_centroid, dye_spread) = if weight > 0.0 {
+        let mean = first.map(|m| m / weight);
+        let spread = [0, 1].map(|axis| {
+            (second[axis] / weight - mean[axis] * mean[axis])
+                .max(0.0)
+                .sqrt() as f32
+        });
+        (Some(mean.map(|m| m as f32)), Some(spread))
+    } else {
+        (None, None)
+    };
+
+    FluidStats {
+        avg_speed: sum_speed / (cells as f32),
+        max_speed,
+        avg_divergence: sum_div / (fluid_cells.max(1) as f32),
+        max_divergence: max_div,
+        dye_footprint: (nonzero as f32) / (cells as f32),
+        dye_total,
+        avg_dye_gradient: avg_dye_gradient(cfg, dye),
+        dye_centroid,
+        dye_spread,
+        velocity_clamped_cells: clamped,
+        angular_momentum,
+        enstrophy,
+        palinstrophy,
+        kinetic_energy,
+        cells: cells as u32,
+        obstacle_cells: solid.as_ref().map(|_| (cells - fluid_cells) as u32),
+    }
+}
+
+/// `integral of |v|^2 / 2 dA` over the stats cells of the unit domain, accumulated in f64.
+fn kinetic_energy(cfg: &FluidStepRequest, vel: &[[f32; 2]]) -> f64 {
+    let w = cfg.width as usize;
+    let h = cfg.height as usize;
+    let m = cfg.stats_margin as usize;
+    let mut sum = 0.0f64;
+    for y in m..h - m {
+        for v in &vel[y * w + m..y * w + w - m] {
+            sum += 0.5 * (v[0] as f64 * v[0] as f64 + v[1] as f64 * v[1] as f64);
+        }
+    }
+    sum / (w * h) as f64
+}
+
+/// `integral of omega^2 dA` over the stats cells of the unit domain, with f64 accumulation.
+fn enstrophy(cfg: &FluidStepRequest, vel: &[[f32; 2]]) -> f64 {
+    let w = cfg.width as usize;
+    let h = cfg.height as usize;
+    let m = cfg.stats_margin as usize;
+    let mut sum = 0.0f64;
+    for y in m..h - m {
+        for x in m..w - m {
+            let omega = vorticity(vel, w, h, x, y);
+            sum += omega * omega;
+        }
+    }
+    sum / (w * h) as f64
+}
+
+/// `integral of |grad omega|^2 dA` over the stats cells of the unit domain: the `vorticity` of
+/// every cell, differentiated again with the same stencil, accumulated in f64.
+fn palinstrophy(cfg: &FluidStepRequest, vel: &[[f32; 2]]) -> f64 {
+    let w = cfg.width as usize;
+    let h = cfg.height as usize;
+    let m = cfg.stats_margin as usize;
+    let omega: Vec<f64> = (0..w * h)
+        .map(|i| vorticity(vel, w, h, i % w, i / w))
+        .collect();
+    let mut sum = 0.0f64;
+    for y in m..h - m {
+        for x in m..w - m {
+            let (ym, yp) = (y.saturating_sub(1), (y + 1).min(h - 1));
+            let (xm, xp) = (x.saturating_sub(1), (x + 1).min(w - 1));
+            let dx = (omega[y * w + xp] - omega[y * w + xm]) * 0.5 * w as f64;
+            let dy = (omega[yp * w + x] - omega[ym * w + x]) * 0.5 * h as f64;
+            sum += dx * dx + dy * dy;
+        }
+    }
+    sum / (w * h) as f64
+}
+
+/// Mean `|grad dye|` over the stats cells of the unit domain, from `dye_gradient`.
+fn avg_dye_gradient(cfg: &FluidStepRequest, dye: &[f32]) -> f64 {
+    let w = cfg.width as usize;
+    let h = cfg.height as usize;
+    let m = cfg.stats_margin as usize;
+    let mut sum = 0.0f64;
+    for y in m..h - m {
+        for x in m..w - m {
+            sum += dye_gradient(dye, w, h, x, y) as f64;
+        }
+    }
+    sum / ((w - 2 * m) * (h - 2 * m)) as f64
+}
+
+/// `|grad dye|` of a `w`x`h` `dye` at cell (x, y), from central differences (one-sided at the
+/// grid edge) like the dye gradient kernel's.
+fn dye_gradient(dye: &[f32], w: usize, h: usize, x: usize, y: usize) -> f32 {
+    let (ym, yp) = (y.saturating_sub(1), (y + 1).min(h - 1));
+    let (xm, xp) = (x.saturating_sub(1), (x + 1).min(w - 1));
+    let ddx = (dye[y * w + xp] - dye[y * w + xm]) * 0.5 * w as f32;
+    let ddy = (dye[yp * w + x] - dye[ym * w + x]) * 0.5 * h as f32;
+    (ddx * ddx + ddy * ddy).sqrt()
+}
+
+/// The divergence of a `w`x`h` `vel` at cell (x, y), from central differences (one-sided at the
+/// grid edge) like the divergence kernel's.
+fn divergence(vel: &[[f32; 2]], w: usize, h: usize, x: usize, y: usize) -> f32 {
+    let (ym, yp) = (y.saturating_sub(1), (y + 1).min(h - 1));
+    let (xm, xp) = (x.saturating_sub(1), (x + 1).min(w - 1));
+    let (vl, vr) = (vel[y * w + xm][0], vel[y * w + xp][0]);
+    let (vb, vt) = (vel[ym * w + x][1], vel[yp * w + x][1]);
+    0.5 * ((vr - vl) * w as f32 + (vt - vb) * h as f32)
+}
+
+/// The vorticity `omega = dvy/dx - dvx/dy` of a `w`x`h` `vel` at cell (x, y), from central
+/// differences (one-sided at the grid edge, as for divergence).
+fn vorticity(vel: &[[f32; 2]], w: usize, h: usize, x: usize, y: usize) -> f64 {
+    let (ym, yp) = (y.saturating_sub(1), (y + 1).min(h - 1));
+    let (xm, xp) = (x.saturating_sub(1), (x + 1).min(w - 1));
+    let dvy_dx = (vel[y * w + xp][1] - vel[y * w + xm][1]) as f64 * 0.5 * w as f64;
+    let dvx_dy = (vel[yp * w + x][0] - vel[ym * w + x][0]) as f64 * 0.5 * h as f64;
+    dvy_dx - dvx_dy
+}
+
+/// CG iteration cap and relative residual target for `helmholtz_gradient_fraction`.
+const HELMHOLTZ_MAX_ITERS: u32 = 4000;
+const HELMHOLTZ_TOLERANCE: f64 = 1e-10;
+
+/// Fraction of `vel`'s energy in its gradient component, plus the CG iterations used.
+///
+/// The gradient component is the least-squares fit `G phi ~= vel`, with `G` the projection
+/// kernel's central-difference gradient (clamped at the edges) and `phi` pinned to 0 on the
+/// boundary ring, exactly like pressure. Solving the normal equations `G^T G phi = G^T vel`
+/// with conjugate gradients (f64) makes the remainder `vel - G phi` orthogonal to every
+/// gradient, so `|G phi|^2 / |vel|^2` is the curl-free share the projection left behind.
+fn helmholtz_gradient_fraction(cfg: &FluidStepRequest, vel: &[[f32; 2]]) -> (f64, u32) {
+    let w = cfg.width as usize;
+    let h = cfg.height as usize;
+    let (hx, hy) = (0.5 * w as f64, 0.5 * h as f64);
+    let interior = |x: usize, y: usize| x > 0 && y > 0 && x + 1 < w && y + 1 < h;
+    let neighbors = |x: usize, y: usize| {
+        let (xm, xp) = (x.saturating_sub(1), (x + 1).min(w - 1));
+        let (ym, yp) = (y.saturating_sub(1), (y + 1).min(h - 1));
+        (y * w + xm, y * w + xp, ym * w + x, yp * w + x)
+    };
+    let grad = |phi: &[f64], out: &mut [[f64; 2]]| {
+        for y in 0..h {
+            for x in 0..w {
+                let (l, r, b, t) = neighbors(x, y);
+                out[y * w + x] = [(phi[r] - phi[l]) * hx, (phi[t] - phi[b]) * hy];
+            }
+        }
+    };
+    // adjoint of `grad`, restricted to the free (interior) cells
+    let grad_t = |g: &[[f64; 2]], out: &mut [f64]| {
+        out.fill(0.0);
+        for y in 0..h {
+            for x in 0..w {
+                let (l, r, b, t) = neighbors(x, y);
+                let [gx, gy] = g[y * w + x];
+                out[r] += gx * hx;
+                out[l] -= gx * hx;
+                out[t] += gy * hy;
+                out[b] -= gy * hy;
+            }
+        }
+        for y in 0..h {
+            for x in 0..w {
+                if !interior(x, y) {
+                    out[y * w + x] = 0.0;
+                }
+            }
+        }
+    };
+    let dot = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(a, b)| a * b).sum::<f64>();
+
+    let v: Vec<[f64; 2]> = vel.iter().map(|v| [v[0] as f64, v[1] as f64]).collect();
+    let energy: f64 = v.iter().map(|v| v[0] * v[0] + v[1] * v[1]).sum();
+    if !(energy.is_finite() && energy > 0.0) {
+        return (0.0, 0);
+    }
+
+    let n = w * h;
+    let mut phi = vec![0.0f64; n];
+    let mut r = vec![0.0f64; n];
+    grad_t(&v, &mut r);
+    let mut d = r.clone();
+    let mut gd = vec![[0.0f64; 2]; n];
+    let mut ad = vec![0.0f64; n];
+    let mut rr = dot(&r, &r);
+    let target = rr * HELMHOLTZ_TOLERANCE * HELMHOLTZ_TOLERANCE;
+    let mut iters = 0;
+    while iters < HELMHOLTZ_MAX_ITERS && rr > target {
+        grad(&d, &mut gd);
+        grad_t(&gd, &mut ad);
+        let dad = dot(&d, &ad);
+        if dad <= 0.0 {
+            break;
+        }
+        let alpha = rr / dad;
+        for i in 0..n {
+            phi[i] += alpha * d[i];
+            r[i] -= alpha * ad[i];
+        }
+        let rr_next = dot(&r, &r);
+        let beta = rr_next / rr;
+        for i in 0..n {
+            d[i] = r[i] + beta * d[i];
+        }
+        rr = rr_next;
+        iters += 1;
+    }
+
+    grad(&phi, &mut gd);
+    let gradient: f64 = gd.iter().map(|g| g[0] * g[0] + g[1] * g[1]).sum();
+    (gradient / energy, iters)
+}
+
+struct FluidPipelines {
+    init: wgpu::ComputePipeline,
+    advect_vel: wgpu::ComputePipeline,
+    predict_vel: wgpu::ComputePipeline,
+    predict_dye: wgpu::ComputePipeline,
+    diffuse_vel: wgpu::ComputePipeline,
+    curl: wgpu::ComputePipeline,
+    vorticity_force: wgpu::ComputePipeline,
+    divergence: wgpu::ComputePipeline,
+    jacobi: wgpu::ComputePipeline,
+    red_black: wgpu::ComputePipeline,
+    project: wgpu::ComputePipeline,
+    advect_dye: wgpu::ComputePipeline,
+    fade: wgpu::ComputePipeline,
+    residual: wgpu::ComputePipeline,
+    okubo_weiss: wgpu::ComputePipeline,
+    dye_gradient: wgpu::ComputePipeline,
+    delta: wgpu::ComputePipeline,
+    streamfunction_rhs: wgpu::ComputePipeline,
+    fill: wgpu::ComputePipeline,
+    accumulate: wgpu::ComputePipeline,
+    max_speed: wgpu::ComputePipeline,
+    dt_peaks: wgpu::ComputePipeline,
+    pick_dt: wgpu::ComputePipeline,
+    max_divergence: wgpu::ComputePipeline,
+    divergence_gate: wgpu::ComputePipeline,
+    move_obstacles: wgpu::ComputePipeline,
+}
+
+struct FluidBindGroups {
+    init: wgpu::BindGroup,
+    advect_vel: wgpu::BindGroup,
+    /// MacCormack predictors; the dye's read the velocity `advect_dye_from_*` does, and serve
+    /// the texture path too.
+    predict_vel: wgpu::BindGroup,
+    predict_dye_from_a: wgpu::BindGroup,
+    predict_dye_from_b: wgpu::BindGroup,
+    predict_dye_from_dye_vel: wgpu::BindGroup,
+    /// The viscosity solve's first iteration, from `FluidSim::diffuse_rhs` into vel_a / vel_b.
+    diffuse_rhs_a: wgpu::BindGroup,
+    diffuse_rhs_b: wgpu::BindGroup,
+    diffuse_ba: wgpu::BindGroup,
+    diffuse_ab: wgpu::BindGroup,
+    /// Both read and write `vel_b`, where the velocity is between diffusion and divergence.
+    curl: wgpu::BindGroup,
+    vorticity_force: wgpu::BindGroup,
+    div: wgpu::BindGroup,
+    div_from_a: wgpu::BindGroup,
+    jacobi_ab: wgpu::BindGroup,
+    jacobi_ba: wgpu::BindGroup,
+    red_black_ab: wgpu::BindGroup,
+    red_black_ba: wgpu::BindGroup,
+    project_from_a: wgpu::BindGroup,
+    project_from_b: wgpu::BindGroup,
+    project_cleanup_from_a: wgpu::BindGroup,
+    project_cleanup_from_b: wgpu::BindGroup,
+    advect_dye_from_a: wgpu::BindGroup,
+    advect_dye_from_b: wgpu::BindGroup,
+    /// Reads `FluidSim::dye_vel` (see `DyeAdvectVelocity`).
+    advect_dye_from_dye_vel: wgpu::BindGroup,
+    fade: wgpu::BindGroup,
+    residual_a: wgpu::BindGroup,
+    residual_b: wgpu::BindGroup,
+    okubo_weiss_from_a: wgpu::BindGroup,
+    okubo_weiss_from_b: wgpu::BindGroup,
+    dye_gradient_from_a: wgpu::BindGroup,
+    dye_gradient_from_b: wgpu::BindGroup,
+    /// Indexed `[vel_slot][dye_slot]`.
+    delta_from: [[wgpu::BindGroup; 2]; 2],
+    streamfunction_rhs_from_a: wgpu::BindGroup,
+    streamfunction_rhs_from_b: wgpu::BindGroup,
+    streamfunction_residual_a: wgpu::BindGroup,
+    streamfunction_residual_b: wgpu::BindGroup,
+    pressure_fill: wgpu::BindGroup,
+    accumulate_from_a: wgpu::BindGroup,
+    accumulate_from_b: wgpu::BindGroup,
+    max_speed_from_a: wgpu::BindGroup,
+    max_speed_from_b: wgpu::BindGroup,
+    dt_peaks: wgpu::BindGroup,
+    pick_dt: wgpu::BindGroup,
+    max_divergence: wgpu::BindGroup,
+    divergence_gate: wgpu::BindGroup,
+    move_obstacles: wgpu::BindGroup,
+}
+
+/// `storage: "texture"` resources: a texture mirror of (vel.x, vel.y, dye) packed at the start
+/// of every step, and advection kernels that sample it instead of the buffers.
+struct FieldTexture {
+    format: &'static str,
+    pack: wgpu::ComputePipeline,
+    advect_vel: wgpu::ComputePipeline,
+    advect_dye: wgpu::ComputePipeline,
+    pack_group: wgpu::BindGroup,
+    advect_vel_group: wgpu::BindGroup,
+    advect_dye_from_a: wgpu::BindGroup,
+    advect_dye_from_b: wgpu::BindGroup,
+    /// Reads `FluidSim::dye_vel` (see `DyeAdvectVelocity`).
+    advect_dye_from_dye_vel: wgpu::BindGroup,
+}
+
+impl FieldTexture {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        device: &wgpu::Device,
+        (format, texture_format): (&'static str, wgpu::TextureFormat),
+        cfg: &FluidStepRequest,
+        params: &wgpu::Buffer,
+        vel_a: &wgpu::Buffer,
+        vel_b: &wgpu::Buffer,
+        dye_a: &wgpu::Buffer,
+        dye_b: &wgpu::Buffer,
+        counters: &wgpu::Buffer,
+        dye_budget: &wgpu::Buffer,
+        ghost: &wgpu::Buffer,
+        force_path: &wgpu::Buffer,
+        forcing: &wgpu::Buffer,
+        dye_vel: &wgpu::Buffer,
+        (vel_pred, dye_pred): (&wgpu::Buffer, &wgpu::Buffer),
+        obstacles: &wgpu::Buffer,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("field-tex"),
+            size: wgpu::Extent3d {
+                width: cfg.width,
+                height: cfg.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: texture_format,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("field-sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let pack = mk_pipeline(
+            device,
+            "pack-field-tex",
+            &FLUID_PACK_TEX_WGSL.replace("FIELD_TEX_FORMAT", format),
+        );
+        let advect_vel = mk_pipeline(device, "advect-vel-tex", FLUID_ADVECT_VEL_TEX_WGSL);
+        let advect_dye = mk_pipeline(device, "advect-dye-tex", FLUID_ADVECT_DYE_TEX_WGSL);
+        let sampled = |label: &str, pipeline: &wgpu::ComputePipeline, buffers: &[&wgpu::Buffer]| {
+            let mut resources: Vec<_> = buffers.iter().map(|b| b.as_entire_binding()).collect();
+            resources.push(wgpu::BindingResource::TextureView(&view));
+            resources.push(wgpu::BindingResource::Sampler(&sampler));
+            mk_bind_group_with(device, label, pipeline, resources)
+        };
+        Self {
+            format,
+            pack_group: mk_bind_group_with(
+                device,
+                "bg-pack-field-tex",
+                &pack,
+                vec![
+                    params.as_entire_binding(),
+                    vel_a.as_entire_binding(),
+                    dye_a.as_entire_binding(),
+                    wgpu::BindingResource::TextureView(&view),
+                ],
+            ),
+            advect_vel_group: sampled(
+                "bg-advect-vel-tex",
+                &advect_vel,
+                &[
+                    params, vel_a, vel_b, counters, ghost, force_path, forcing, vel_pred, obstacles,
+                ],
+            ),
+            advect_dye_from_a: sampled(
+                "bg-advect-dye-tex-from-a",
+                &advect_dye,
+                &[params, vel_a, dye_a, dye_b, counters, dye_budget, dye_pred],
+            ),
+            advect_dye_from_b: sampled(
+                "bg-advect-dye-tex-from-b",
+                &advect_dye,
+                &[params, vel_b, dye_a, dye_b, counters, dye_budget, dye_pred],
+            ),
+            advect_dye_from_dye_vel: sampled(
+                "bg-advect-dye-tex-from-dye-vel",
+                &advect_dye,
+                &[
+                    params, dye_vel, dye_a, dye_b, counters, dye_budget, dye_pred,
+                ],
+            ),
+            pack,
+            advect_vel,
+            advect_dye,
+        }
+    }
+}
+
+/// Workgroup grid for the per-cell fluid kernels (`WORKGROUP_SIDE` square).
+enum GridDispatch {
+    Direct(u32, u32),
+    /// `dispatch_workgroups_indirect` args, written on the GPU from `Params` by
+    /// `FLUID_DISPATCH_ARGS_WGSL`.
+    Indirect(wgpu::Buffer),
+}
+
+impl GridDispatch {
+    fn indirect(device: &wgpu::Device, queue: &wgpu::Queue, params: &wgpu::Buffer) -> Self {
+        let args = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("dispatch-args"),
+            size: std::mem::size_of::<[u32; 3]>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT,
+            mapped_at_creation: false,
+        });
+        let pipeline = mk_pipeline(device, "dispatch-args", FLUID_DISPATCH_ARGS_WGSL);
+        let group = mk_bind_group(device, "bg-dispatch-args", &pipeline, &[params, &args]);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &group, &[]);
+            pass.dispatch_workgroups(1, 1, 1);
+        }
+        queue.submit(Some(encoder.finish()));
+        Self::Indirect(args)
+    }
+
+    fn dispatch(&self, pass: &mut wgpu::ComputePass<'_>) {
+        match self {
+            Self::Direct(x, y) => pass.dispatch_workgroups(*x, *y, 1),
+            Self::Indirect(args) => pass.dispatch_workgroups_indirect(args, 0),
+        }
+    }
+}
+
+/// GPU-resident fluid state plus everything needed to advance it; steps can be issued in
+/// several calls so callers can inspect (or reseed) the fields between them.
+struct FluidSim {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    cfg: FluidStepRequest,
+    params_buf: wgpu::Buffer,
+    vel_a: wgpu::Buffer,
+    vel_b: wgpu::Buffer,
+    dye_a: wgpu::Buffer,
+    dye_b: wgpu::Buffer,
+    /// The velocity dye advection reads unless it is `DyeAdvectVelocity::Projected`.
+    dye_vel: wgpu::Buffer,
+    /// The advected velocity the viscosity solve holds its iterates to (1 element when
+    /// `viscosity` is 0).
+    diffuse_rhs: wgpu::Buffer,
+    pressure_a: wgpu::Buffer,
+    pressure_b: wgpu::Buffer,
+    diag: wgpu::Buffer,
+    /// `dump_dye_gradient`'s output (1 element = not dumped).
+    dye_grad: wgpu::Buffer,
+    /// `delta_every`: the snapshot fields and the change against them (1 element = off).
+    vel_prev: wgpu::Buffer,
+    dye_prev: wgpu::Buffer,
+    delta: wgpu::Buffer,
+    step_table: wgpu::Buffer,
+    /// `inject_color` then the `dye_color_schedule` (see `copy_dye_color`).
+    dye_colors: Option<wgpu::Buffer>,
+    counters: wgpu::Buffer,
+    counters_read: wgpu::Buffer,
+    vel_read: wgpu::Buffer,
+    dye_read: wgpu::Buffer,
+    residuals: wgpu::Buffer,
+    residual_slots: wgpu::Buffer,
+    /// `[0, 1]`, the `rb_phase` values `encode_pressure_iteration` copies into the params.
+    rb_phases: wgpu::Buffer,
+    /// `InitLayerParams` table (see `encode_pending_seed`).
+    init_layers: wgpu::Buffer,
+    /// `init_vel_b64` / `init_dye_b64`, copied over the seed (see `encode_pending_seed`).
+    init_vel: Option<wgpu::Buffer>,
+    init_dye: Option<wgpu::Buffer>,
+    streamfunction_residual: wgpu::Buffer,
+    streamfunction_residual_read: wgpu::Buffer,
+    residual_read: wgpu::Buffer,
+    residual_points: Vec<u32>,
+    /// `f32` bits of the `max_speed` reduction, and its staging buffer.
+    max_speed: wgpu::Buffer,
+    max_speed_read: wgpu::Buffer,
+    /// `adaptive_dt`: the step's (max |v|, max |curl|) as `f32` bits, and the picked `dt`
+    /// with its (min, max, sum, steps) since the last (re)seed plus their staging buffer.
+    dt_peaks: wgpu::Buffer,
+    dt_stats: wgpu::Buffer,
+    dt_stats_read: wgpu::Buffer,
+    /// `target_divergence`: the pass gate (see `PROJECTION_GATE_LEN`; 1 element = off), its
+    /// staging buffer, the gated pressure solve's dispatch through the gate's args, and the
+    /// steps gated since the last (re)seed.
+    projection_gate: wgpu::Buffer,
+    projection_gate_read: wgpu::Buffer,
+    gated_grid: Option<GridDispatch>,
+    projection_steps: u32,
+    /// `trace_dispatches`: the first step's dispatches, once it has run.
+    dispatch_trace: Option<Vec<String>>,
+    /// `clamp_history`: the `COUNTER_CLAMP_EVENTS` total after each of the first `cfg.steps`
+    /// steps, copied out of `counters` in the step loop.
+    clamp_history: Option<wgpu::Buffer>,
+    /// `dump_pressure_every`: one full pressure field per `pressure_frame_points` entry, copied
+    /// out of the pressure pair during the recorded solve.
+    pressure_frames: Option<wgpu::Buffer>,
+    pressure_frame_points: Vec<u32>,
+    dye_budget: wgpu::Buffer,
+    /// `forcing_budget`: per-cell (power on the last step, work since the last (re)seed).
+    forcing: wgpu::Buffer,
+    dye_sum: wgpu::Buffer,
+    vel_sum: wgpu::Buffer,
+    /// Steps in `dye_sum` / `vel_sum` (see `FluidRun::mean_steps`).
+    mean_steps: u32,
+    pipelines: FluidPipelines,
+    groups: FluidBindGroups,
+    field_tex: Option<FieldTexture>,
+    grid: GridDispatch,
+    steps_per_submit: u32,
+    /// Buffers written last by the step (`Slot::A` right after a seed).
+    vel_slot: Slot,
+    dye_slot: Slot,
+    steps_done: u32,
+    /// Seed dispatch to fold into the next submission (set by `seed`).
+    pending_seed: bool,
+    /// Every pipeline came from `gpu_cache` instead of being compiled for this sim.
+    pipeline_cache_hit: bool,
+    /// `backend_label` of the adapter `device` is on.
+    backend: &'static str,
+}
+
+impl FluidSim {
+    /// `field_texture` selects the `storage: "texture"` path (see `field_texture_format`);
+    /// `backend` is what `FluidRun::backend_name` reports.
+    fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        cfg: &FluidStepRequest,
+        field_texture: Option<(&'static str, wgpu::TextureFormat)>,
+        backend: &'static str,
+    ) -> Self {
+        let cells = (cfg.width as usize) * (cfg.height as usize);
+        let compiled_before = gpu_cache::compiled(device);
+
+        let params_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("fluid-params"),
+            size: std::mem::size_of::<Params>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(&params_buf, 0, bytemuck::bytes_of(&fluid_params(cfg)));
+
+        // velocity ends in either buffer; dye always ends in dye_a, so reading a dye_b
+        // `dye_slot` fails `check_readable` instead of returning a stale field
+        let none = wgpu::BufferUsages::empty();
+        let upload_usage = |upload: &Option<String>| match upload {
+            Some(_) => wgpu::BufferUsages::COPY_DST,
+            None => none,
+        };
+        let vel_a = mk_storage_vec2(
+            device,
+            "vel-a",
+            cells,
+            cfg.readback_usage(FluidField::Vel) | upload_usage(&cfg.init_vel_b64),
+        );
+        let vel_b = mk_storage_vec2(device, "vel-b", cells, cfg.readback_usage(FluidField::Vel));
+        let dye_cells = cells * cfg.dye_stride();
+        let dye_a = mk_storage_f32(
+            device,
+            "dye-a",
+            dye_cells,
+            cfg.readback_usage(FluidField::Dye) | upload_usage(&cfg.init_dye_b64),
+        );
+        // the uploads stay on the GPU so a reseed restores them without another write
+        let upload = |label, bytes: &[u8]| {
+            use wgpu::util::DeviceExt as _;
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytes,
+                usage: wgpu::BufferUsages::COPY_SRC,
+            })
+        };
+        let init_vel = (cfg.init_vel().ok().flatten())
+            .map(|vel| upload("init-vel", bytemuck::cast_slice(&vel)));
+        let init_dye = (cfg.init_dye().ok().flatten())
+            .map(|dye| upload("init-dye", bytemuck::cast_slice(&dye)));
+        let dye_b = mk_storage_f32(device, "dye-b", dye_cells, none);
+        let div = mk_storage_f32(device, "div", cells, none);
+        // `vorticity` scratch: the curl the confinement force reads (1 element = off)
+        let curl = mk_storage_f32(
+            device,
+            "curl",
+            if cfg.vorticity > 0.0 { cells } else { 1 },
+            none,
+        );
+        // `advection: "maccormack"` predictor output per field (1 element = off)
+        let maccormack = cfg.advection == AdvectionScheme::MacCormack;
+        let vel_pred =
+            mk_storage_vec2(device, "vel-pred", if maccormack { cells } else { 1 }, none);
+        // a single dye element when off: a whole `vec3<f32>` with `channels: 3`
+        let dye_pred_len = if maccormack {
+            dye_cells
+        } else {
+            cfg.dye_stride()
+        };
+        let dye_pred = mk_storage_f32(device, "dye-pred", dye_pred_len, none);
+        // edge-ring velocities (1 element = none; a ring always has an even cell count)
+        let ghost_ring = cfg.ghost_ring().ok().flatten();
+        let ghost = mk_storage_vec2(
+            device,
+            "ghost",
+            ghost_ring.as_ref().map_or(1, Vec::len),
+            wgpu::BufferUsages::COPY_DST,
+        );
+        if let Some(ring) = &ghost_ring {
+            queue.write_buffer(&ghost, 0, bytemuck::cast_slice(ring));
+        }
+        // `obstacle_table` (1 element = none), and with `obstacle_velocity` the starting labels
+        // it is re-rasterized from every step (1 element = static)
+        let (obstacles, obstacle_start) = {
+            use wgpu::util::DeviceExt as _;
+            let table = cfg.obstacle_table().ok().flatten();
+            let moving = !cfg.obstacle_velocity.is_empty();
+            let start = table.as_deref().filter(|_| moving).map(|t| &t[..cells]);
+            let init = |label, contents: &[u32]| {
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(label),
+                    contents: bytemuck::cast_slice(contents),
+                    usage: wgpu::BufferUsages::STORAGE,
+                })
+            };
+            (
+                init("obstacles", table.as_deref().unwrap_or(&[0])),
+                init("obstacle-start", start.unwrap_or(&[0])),
+            )
+        };
+        // `dye_advect_velocity` other than projected: the upload, or each step's pre-projection
+        // velocity copied out of vel_b (1 element = none)
+        let dye_vel = mk_storage_vec2(
+            device,
+            "dye-vel",
+            match cfg.dye_advect_velocity {
+                DyeAdvectVelocity::Projected => 1,
+                _ => cells,
             },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: dye_a.as_entire_binding(),
+            wgpu::BufferUsages::COPY_DST,
+        );
+        let diffuse_rhs = mk_storage_vec2(
+            device,
+            "diffuse-rhs",
+            if cfg.viscosity > 0.0 { cells } else { 1 },
+            wgpu::BufferUsages::COPY_DST,
+        );
+        if let Some(vel) = cfg.advect_vel().ok().flatten() {
+            queue.write_buffer(&dye_vel, 0, bytemuck::cast_slice(&vel));
+        }
+        // subtracted from the divergence so the projection leaves it behind (1 element = none)
+        let div_target = cfg.div_target();
+        let div_target_buf = mk_storage_f32(
+            device,
+            "div-target",
+            div_target.as_ref().map_or(1, Vec::len),
+            wgpu::BufferUsages::COPY_DST,
+        );
+        if let Some(target) = &div_target {
+            queue.write_buffer(&div_target_buf, 0, bytemuck::cast_slice(target));
+        }
+        // `force_path` keyframes for the advection kernels (one zero key = none)
+        let force_path = {
+            let keys = if cfg.force_path.is_empty() {
+                vec![ForceKey::zeroed()]
+            } else {
+                cfg.force_path.clone()
+            };
+            let buf = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("force-path"),
+                size: std::mem::size_of_val(keys.as_slice()) as u64,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            queue.write_buffer(&buf, 0, bytemuck::cast_slice(&keys));
+            buf
+        };
+        // scratch for diagnostic fields derived at readback time (see `FluidField`)
+        let diag = mk_storage_f32(
+            device,
+            "diag",
+            cells,
+            cfg.readback_usage(FluidField::OkuboWeiss),
+        );
+        let dye_grad = mk_storage_f32(
+            device,
+            "dye-grad",
+            if cfg.dump_dye_gradient { cells } else { 1 },
+            cfg.readback_usage(FluidField::DyeGradient),
+        );
+        let delta_cells = if cfg.delta_every > 0 { cells } else { 1 };
+        let vel_prev = mk_storage_vec2(
+            device,
+            "vel-prev",
+            delta_cells,
+            wgpu::BufferUsages::COPY_DST,
+        );
+        let dye_prev = mk_storage_f32(
+            device,
+            "dye-prev",
+            delta_cells,
+            wgpu::BufferUsages::COPY_DST,
+        );
+        let delta = mk_storage_vec2(
+            device,
+            "delta",
+            delta_cells,
+            cfg.readback_usage(FluidField::Delta),
+        );
+        // only pressure_a is reset between solves; `dump_streamfunction` solves into the pair
+        // at readback time and copies out of whichever its last sweep wrote, and
+        // `dump_pressure_every` copies out of it mid-solve
+        let mut streamfunction_usage = cfg.readback_usage(FluidField::Streamfunction)
+            | cfg.readback_usage(FluidField::Pressure);
+        if cfg.dump_pressure_every > 0 {
+            streamfunction_usage |= wgpu::BufferUsages::COPY_SRC;
+        }
+        let pressure_a = mk_storage_f32(
+            device,
+            "pressure-a",
+            cells,
+            wgpu::BufferUsages::COPY_DST | streamfunction_usage,
+        );
+        let pressure_b = mk_storage_f32(device, "pressure-b", cells, streamfunction_usage);
+
+        // one u32 per step of a submission batch; copied into `Params.step_index` ahead of each
+        // step so kernels see the advancing step while still batching many steps per submit.
+        let step_table = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("step-table"),
+            size: (STEPS_PER_SUBMIT_MAX as usize * std::mem::size_of::<u32>()) as u64,
+            usage: wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // GPU-side stability counters, accumulated over the whole run (see `COUNTER_*`)
+        let counters = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("counters"),
+            size: COUNTERS_BYTES,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let counters_read = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("counters-read"),
+            size: COUNTERS_BYTES,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let vel_read = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("vel-read"),
+            size: (cells * std::mem::size_of::<[f32; 2]>()) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let dye_read = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("dye-read"),
+            size: (dye_cells * std::mem::size_of::<f32>()) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        // `inject_color` for the seed, then the `dye_color_schedule` entries, each copied into
+        // `Params.inject_r..inject_b` ahead of what injects it (None without a schedule)
+        let dye_colors = (!cfg.dye_color_schedule.is_empty()).then(|| {
+            use wgpu::util::DeviceExt as _;
+            let mut colors = vec![cfg.inject_color];
+            colors.extend(&cfg.dye_color_schedule);
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("dye-colors"),
+                contents: bytemuck::cast_slice(&colors),
+                usage: wgpu::BufferUsages::COPY_SRC,
+            })
+        });
+
+        // `init_layers`: the all-zero seed block, then one `InitLayerParams` per layer, copied
+        // into the params ahead of each init dispatch
+        let mut layer_table = vec![InitLayerParams::default()];
+        layer_table.extend(cfg.init_layer_params());
+        let init_layers = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("init-layers"),
+            size: std::mem::size_of_val(layer_table.as_slice()) as u64,
+            usage: wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(&init_layers, 0, bytemuck::cast_slice(&layer_table));
+
+        // `residual_history`: one max-residual slot per recorded iteration, plus a slot-index
+        // table copied into `Params.residual_slot` the same way as `step_table`.
+        let residual_points = cfg.residual_points();
+        let residual_bytes = (residual_points.len().max(1) * std::mem::size_of::<u32>()) as u64;
+        let residuals = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("residuals"),
+            size: residual_bytes,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let residual_slots = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("residual-slots"),
+            size: residual_bytes,
+            usage: wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let slot_indices: Vec<u32> = (0..residual_points.len().max(1) as u32).collect();
+        queue.write_buffer(&residual_slots, 0, bytemuck::cast_slice(&slot_indices));
+        let rb_phases = {
+            use wgpu::util::DeviceExt as _;
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("rb-phases"),
+                contents: bytemuck::cast_slice(&[0u32, 1]),
+                usage: wgpu::BufferUsages::COPY_SRC,
+            })
+        };
+        let residual_read = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("residual-read"),
+            size: residual_bytes,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        // max |lap(psi) + omega| of the last streamfunction solve, as f32 bits
+        let streamfunction_residual = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("streamfunction-residual"),
+            size: std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let streamfunction_residual_read = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("streamfunction-residual-read"),
+            size: std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let max_speed = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("max-speed"),
+            size: std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let max_speed_read = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("max-speed-read"),
+            size: std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let dt_peaks = mk_storage_f32(device, "dt-peaks", 2, wgpu::BufferUsages::COPY_DST);
+        let dt_stats = mk_storage_f32(
+            device,
+            "dt-stats",
+            DT_STATS_LEN,
+            wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+        );
+        let dt_stats_read = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("dt-stats-read"),
+            size: dt_stats.size(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let projection_gate = {
+            use wgpu::util::DeviceExt as _;
+            let mut words = [0u32; PROJECTION_GATE_LEN];
+            words[PROJECTION_GATE_LEN - 1] = cfg.target_divergence.to_bits();
+            let len = if cfg.target_divergence > 0.0 {
+                PROJECTION_GATE_LEN
+            } else {
+                1
+            };
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("projection-gate"),
+                contents: bytemuck::cast_slice(&words[..len]),
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::INDIRECT
+                    | wgpu::BufferUsages::COPY_SRC
+                    | wgpu::BufferUsages::COPY_DST,
+            })
+        };
+        let projection_gate_read = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("projection-gate-read"),
+            size: projection_gate.size(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let clamp_history = cfg.clamp_history.then(|| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("clamp-history"),
+                size: (cfg.steps.max(1) as usize * std::mem::size_of::<u32>()) as u64,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            })
+        });
+        let pressure_frame_points = cfg.pressure_frame_points();
+        let pressure_frames = (!pressure_frame_points.is_empty()).then(|| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("pressure-frames"),
+                size: (pressure_frame_points.len() * cells * std::mem::size_of::<f32>()) as u64,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            })
+        });
+
+        // `dye_budget`: (added, faded, advection loss) per cell, accumulated by advect_dye and
+        // fade. A placeholder triple keeps the bindings valid when the budget is off.
+        let dye_budget = mk_storage_f32(
+            device,
+            "dye-budget",
+            3 * if cfg.dye_budget { cells } else { 1 },
+            wgpu::BufferUsages::COPY_DST
+                | if cfg.dye_budget {
+                    wgpu::BufferUsages::COPY_SRC
+                } else {
+                    none
+                },
+        );
+
+        // `forcing_budget`: (power, work) per cell, written by advect_vel; one placeholder pair
+        // switches it off
+        let forcing = mk_storage_vec2(
+            device,
+            "forcing",
+            if cfg.forcing_budget { cells } else { 1 },
+            wgpu::BufferUsages::COPY_DST
+                | if cfg.forcing_budget {
+                    wgpu::BufferUsages::COPY_SRC
+                } else {
+                    none
+                },
+        );
+
+        // `time_average` running sums; a single placeholder element switches a sum off
+        let dye_sum = mk_storage_f32(
+            device,
+            "dye-sum",
+            if cfg.time_average { cells } else { 1 },
+            wgpu::BufferUsages::COPY_DST | cfg.readback_usage(FluidField::DyeMean),
+        );
+        let vel_sum = mk_storage_vec2(
+            device,
+            "vel-sum",
+            if cfg.time_average_vel { cells } else { 1 },
+            wgpu::BufferUsages::COPY_DST | cfg.readback_usage(FluidField::VelMean),
+        );
+
+        let dye = |wgsl| dye_wgsl(wgsl, cfg.channels);
+        let pipelines = FluidPipelines {
+            init: mk_pipeline(device, "fluid-init", &dye(FLUID_INIT_WGSL)),
+            advect_vel: mk_pipeline(device, "advect-vel", FLUID_ADVECT_VEL_WGSL),
+            predict_vel: mk_pipeline(device, "predict-vel", FLUID_PREDICT_VEL_WGSL),
+            predict_dye: mk_pipeline(device, "predict-dye", &dye(FLUID_PREDICT_DYE_WGSL)),
+            diffuse_vel: mk_pipeline(device, "diffuse-vel", FLUID_DIFFUSE_VEL_WGSL),
+            curl: mk_pipeline(device, "curl", FLUID_CURL_WGSL),
+            vorticity_force: mk_pipeline(device, "vorticity-force", FLUID_VORTICITY_FORCE_WGSL),
+            divergence: mk_pipeline(device, "divergence", FLUID_DIVERGENCE_WGSL),
+            jacobi: mk_pipeline(device, "jacobi", FLUID_JACOBI_WGSL),
+            red_black: mk_pipeline(device, "red-black", FLUID_RED_BLACK_WGSL),
+            project: mk_pipeline(device, "project", FLUID_PROJECT_WGSL),
+            advect_dye: mk_pipeline(device, "advect-dye", &dye(FLUID_ADVECT_DYE_WGSL)),
+            fade: mk_pipeline(device, "fade", &dye(FLUID_FADE_WGSL)),
+            residual: mk_pipeline(device, "residual", FLUID_RESIDUAL_WGSL),
+            okubo_weiss: mk_pipeline(device, "okubo-weiss", FLUID_OKUBO_WEISS_WGSL),
+            dye_gradient: mk_pipeline(device, "dye-gradient", FLUID_DYE_GRADIENT_WGSL),
+            delta: mk_pipeline(device, "delta", FLUID_DELTA_WGSL),
+            streamfunction_rhs: mk_pipeline(
+                device,
+                "streamfunction-rhs",
+                FLUID_STREAMFUNCTION_RHS_WGSL,
+            ),
+            fill: mk_pipeline(device, "fill", FLUID_FILL_WGSL),
+            accumulate: mk_pipeline(device, "accumulate", FLUID_ACCUMULATE_WGSL),
+            max_speed: mk_pipeline(device, "max-speed", FLUID_MAX_SPEED_WGSL),
+            dt_peaks: mk_pipeline(device, "dt-peaks", FLUID_DT_PEAKS_WGSL),
+            pick_dt: mk_pipeline(device, "pick-dt", FLUID_PICK_DT_WGSL),
+            max_divergence: mk_pipeline(device, "max-divergence", FLUID_MAX_DIVERGENCE_WGSL),
+            divergence_gate: mk_pipeline(device, "divergence-gate", FLUID_DIVERGENCE_GATE_WGSL),
+            move_obstacles: mk_pipeline(device, "move-obstacles", FLUID_MOVE_OBSTACLES_WGSL),
+        };
+
+        // pre-build bind groups so per-step work stays focused on GPU kernels (less CPU descriptor churn)
+        let p = &params_buf;
+        let groups = FluidBindGroups {
+            init: mk_bind_group(
+                device,
+                "bg-init",
+                &pipelines.init,
+                &[p, &vel_a, &dye_a, &obstacles],
+            ),
+            advect_vel: mk_bind_group(
+                device,
+                "bg-advect-vel",
+                &pipelines.advect_vel,
+                &[
+                    p,
+                    &vel_a,
+                    &vel_b,
+                    &counters,
+                    &ghost,
+                    &force_path,
+                    &forcing,
+                    &vel_pred,
+                    &obstacles,
+                ],
+            ),
+            predict_vel: mk_bind_group(
+                device,
+                "bg-predict-vel",
+                &pipelines.predict_vel,
+                &[p, &vel_a, &vel_pred],
+            ),
+            predict_dye_from_a: mk_bind_group(
+                device,
+                "bg-predict-dye-from-a",
+                &pipelines.predict_dye,
+                &[p, &vel_a, &dye_a, &dye_pred],
+            ),
+            predict_dye_from_b: mk_bind_group(
+                device,
+                "bg-predict-dye-from-b",
+                &pipelines.predict_dye,
+                &[p, &vel_b, &dye_a, &dye_pred],
+            ),
+            predict_dye_from_dye_vel: mk_bind_group(
+                device,
+                "bg-predict-dye-from-dye-vel",
+                &pipelines.predict_dye,
+                &[p, &dye_vel, &dye_a, &dye_pred],
+            ),
+            diffuse_rhs_a: mk_bind_group(
+                device,
+                "bg-diffuse-rhs-a",
+                &pipelines.diffuse_vel,
+                &[p, &diffuse_rhs, &vel_a, &ghost, &diffuse_rhs, &obstacles],
+            ),
+            diffuse_rhs_b: mk_bind_group(
+                device,
+                "bg-diffuse-rhs-b",
+                &pipelines.diffuse_vel,
+                &[p, &diffuse_rhs, &vel_b, &ghost, &diffuse_rhs, &obstacles],
+            ),
+            diffuse_ba: mk_bind_group(
+                device,
+                "bg-diffuse-ba",
+                &pipelines.diffuse_vel,
+                &[p, &vel_b, &vel_a, &ghost, &diffuse_rhs, &obstacles],
+            ),
+            diffuse_ab: mk_bind_group(
+                device,
+                "bg-diffuse-ab",
+                &pipelines.diffuse_vel,
+                &[p, &vel_a, &vel_b, &ghost, &diffuse_rhs, &obstacles],
+            ),
+            curl: mk_bind_group(
+                device,
+                "bg-curl",
+                &pipelines.curl,
+                &[p, &vel_b, &curl, &obstacles],
+            ),
+            vorticity_force: mk_bind_group(
+                device,
+                "bg-vorticity-force",
+                &pipelines.vorticity_force,
+                &[p, &curl, &vel_b, &obstacles],
+            ),
+            div: mk_bind_group(
+                device,
+                "bg-div",
+                &pipelines.divergence,
+                &[p, &vel_b, &div, &div_target_buf, &obstacles],
+            ),
+            div_from_a: mk_bind_group(
+                device,
+                "bg-div-from-a",
+                &pipelines.divergence,
+                &[p, &vel_a, &div, &div_target_buf, &obstacles],
+            ),
+            jacobi_ab: mk_bind_group(
+                device,
+                "bg-jacobi-ab",
+                &pipelines.jacobi,
+                &[p, &pressure_a, &div, &pressure_b, &obstacles],
+            ),
+            jacobi_ba: mk_bind_group(
+                device,
+                "bg-jacobi-ba",
+                &pipelines.jacobi,
+                &[p, &pressure_b, &div, &pressure_a, &obstacles],
+            ),
+            red_black_ab: mk_bind_group(
+                device,
+                "bg-red-black-ab",
+                &pipelines.red_black,
+                &[p, &pressure_a, &div, &pressure_b, &obstacles],
+            ),
+            red_black_ba: mk_bind_group(
+                device,
+                "bg-red-black-ba",
+                &pipelines.red_black,
+                &[p, &pressure_b, &div, &pressure_a, &obstacles],
+            ),
+            project_from_a: mk_bind_group(
+                device,
+                "bg-project-from-a",
+                &pipelines.project,
+                &[
+                    p,
+                    &vel_b,
+                    &pressure_a,
+                    &vel_a,
+                    &ghost,
+                    &counters,
+                    &projection_gate,
+                    &obstacles,
+                ],
+            ),
+            project_from_b: mk_bind_group(
+                device,
+                "bg-project-from-b",
+                &pipelines.project,
+                &[
+                    p,
+                    &vel_b,
+                    &pressure_b,
+                    &vel_a,
+                    &ghost,
+                    &counters,
+                    &projection_gate,
+                    &obstacles,
+                ],
+            ),
+            project_cleanup_from_a: mk_bind_group(
+                device,
+                "bg-project-cleanup-from-a",
+                &pipelines.project,
+                &[
+                    p,
+                    &vel_a,
+                    &pressure_a,
+                    &vel_b,
+                    &ghost,
+                    &counters,
+                    &projection_gate,
+                    &obstacles,
+                ],
+            ),
+            project_cleanup_from_b: mk_bind_group(
+                device,
+                "bg-project-cleanup-from-b",
+                &pipelines.project,
+                &[
+                    p,
+                    &vel_a,
+                    &pressure_b,
+                    &vel_b,
+                    &ghost,
+                    &counters,
+                    &projection_gate,
+                    &obstacles,
+                ],
+            ),
+            advect_dye_from_a: mk_bind_group(
+                device,
+                "bg-advect-dye-from-a",
+                &pipelines.advect_dye,
+                &[p, &vel_a, &dye_a, &dye_b, &counters, &dye_budget, &dye_pred],
+            ),
+            advect_dye_from_b: mk_bind_group(
+                device,
+                "bg-advect-dye-from-b",
+                &pipelines.advect_dye,
+                &[p, &vel_b, &dye_a, &dye_b, &counters, &dye_budget, &dye_pred],
+            ),
+            advect_dye_from_dye_vel: mk_bind_group(
+                device,
+                "bg-advect-dye-from-dye-vel",
+                &pipelines.advect_dye,
+                &[
+                    p,
+                    &dye_vel,
+                    &dye_a,
+                    &dye_b,
+                    &counters,
+                    &dye_budget,
+                    &dye_pred,
+                ],
+            ),
+            fade: mk_bind_group(
+                device,
+                "bg-fade",
+                &pipelines.fade,
+                &[p, &dye_b, &dye_a, &dye_budget],
+            ),
+            residual_a: mk_bind_group(
+                device,
+                "bg-residual-a",
+                &pipelines.residual,
+                &[p, &pressure_a, &div, &residuals, &obstacles],
+            ),
+            residual_b: mk_bind_group(
+                device,
+                "bg-residual-b",
+                &pipelines.residual,
+                &[p, &pressure_b, &div, &residuals, &obstacles],
+            ),
+            okubo_weiss_from_a: mk_bind_group(
+                device,
+                "bg-okubo-weiss-from-a",
+                &pipelines.okubo_weiss,
+                &[p, &vel_a, &diag],
+            ),
+            okubo_weiss_from_b: mk_bind_group(
+                device,
+                "bg-okubo-weiss-from-b",
+                &pipelines.okubo_weiss,
+                &[p, &vel_b, &diag],
+            ),
+            dye_gradient_from_a: mk_bind_group(
+                device,
+                "bg-dye-gradient-from-a",
+                &pipelines.dye_gradient,
+                &[p, &dye_a, &dye_grad],
+            ),
+            dye_gradient_from_b: mk_bind_group(
+                device,
+                "bg-dye-gradient-from-b",
+                &pipelines.dye_gradient,
+                &[p, &dye_b, &dye_grad],
+            ),
+            delta_from: [&vel_a, &vel_b].map(|vel| {
+                [&dye_a, &dye_b].map(|dye| {
+                    mk_bind_group(
+                        device,
+                        "bg-delta",
+                        &pipelines.delta,
+                        &[p, vel, dye, &vel_prev, &dye_prev, &delta],
+                    )
+                })
+            }),
+            streamfunction_rhs_from_a: mk_bind_group(
+                device,
+                "bg-streamfunction-rhs-from-a",
+                &pipelines.streamfunction_rhs,
+                &[p, &vel_a, &div],
+            ),
+            streamfunction_rhs_from_b: mk_bind_group(
+                device,
+                "bg-streamfunction-rhs-from-b",
+                &pipelines.streamfunction_rhs,
+                &[p, &vel_b, &div],
+            ),
+            streamfunction_residual_a: mk_bind_group(
+                device,
+                "bg-streamfunction-residual-a",
+                &pipelines.residual,
+                &[p, &pressure_a, &div, &streamfunction_residual, &obstacles],
+            ),
+            streamfunction_residual_b: mk_bind_group(
+                device,
+                "bg-streamfunction-residual-b",
+                &pipelines.residual,
+                &[p, &pressure_b, &div, &streamfunction_residual, &obstacles],
+            ),
+            pressure_fill: mk_bind_group(
+                device,
+                "bg-pressure-fill",
+                &pipelines.fill,
+                &[p, &pressure_a],
+            ),
+            accumulate_from_a: mk_bind_group(
+                device,
+                "bg-accumulate-from-a",
+                &pipelines.accumulate,
+                &[p, &vel_a, &dye_a, &vel_sum, &dye_sum],
+            ),
+            accumulate_from_b: mk_bind_group(
+                device,
+                "bg-accumulate-from-b",
+                &pipelines.accumulate,
+                &[p, &vel_b, &dye_a, &vel_sum, &dye_sum],
+            ),
+            max_speed_from_a: mk_bind_group(
+                device,
+                "bg-max-speed-from-a",
+                &pipelines.max_speed,
+                &[p, &vel_a, &max_speed],
+            ),
+            max_speed_from_b: mk_bind_group(
+                device,
+                "bg-max-speed-from-b",
+                &pipelines.max_speed,
+                &[p, &vel_b, &max_speed],
+            ),
+            // steps always advect out of vel_a, so that is the velocity a step's dt must suit
+            dt_peaks: mk_bind_group(
+                device,
+                "bg-dt-peaks",
+                &pipelines.dt_peaks,
+                &[p, &vel_a, &dt_peaks],
+            ),
+            pick_dt: mk_bind_group(
+                device,
+                "bg-pick-dt",
+                &pipelines.pick_dt,
+                &[p, &dt_peaks, &dt_stats],
+            ),
+            max_divergence: mk_bind_group(
+                device,
+                "bg-max-divergence",
+                &pipelines.max_divergence,
+                &[p, &div, &projection_gate],
+            ),
+            divergence_gate: mk_bind_group(
+                device,
+                "bg-divergence-gate",
+                &pipelines.divergence_gate,
+                &[p, &projection_gate],
+            ),
+            move_obstacles: mk_bind_group(
+                device,
+                "bg-move-obstacles",
+                &pipelines.move_obstacles,
+                &[p, &obstacle_start, &obstacles],
+            ),
+        };
+
+        let field_tex = field_texture.map(|format| {
+            FieldTexture::new(
+                device,
+                format,
+                cfg,
+                p,
+                &vel_a,
+                &vel_b,
+                &dye_a,
+                &dye_b,
+                &counters,
+                &dye_budget,
+                &ghost,
+                &force_path,
+                &forcing,
+                &dye_vel,
+                (&vel_pred, &dye_pred),
+                &obstacles,
+            )
+        });
+
+        let grid = if cfg.indirect_dispatch {
+            GridDispatch::indirect(device, queue, &params_buf)
+        } else {
+            let (x, y) = workgroups(cfg.width, cfg.height, WORKGROUP_SIDE);
+            GridDispatch::Direct(x, y)
+        };
+        let gated_grid =
+            (cfg.target_divergence > 0.0).then(|| GridDispatch::Indirect(projection_gate.clone()));
+
+        let passes_per_step = 8u32
+            + cfg
+                .jacobi_iters
+                .saturating_mul(cfg.projection_passes.max(1));
+        Self {
+            device: device.clone(),
+            queue: queue.clone(),
+            cfg: cfg.clone(),
+            params_buf,
+            vel_a,
+            vel_b,
+            dye_a,
+            dye_b,
+            dye_vel,
+            diffuse_rhs,
+            pressure_a,
+            pressure_b,
+            diag,
+            dye_grad,
+            vel_prev,
+            dye_prev,
+            delta,
+            step_table,
+            dye_colors,
+            counters,
+            counters_read,
+            vel_read,
+            dye_read,
+            residuals,
+            residual_slots,
+            rb_phases,
+            init_layers,
+            init_vel,
+            init_dye,
+            streamfunction_residual,
+            streamfunction_residual_read,
+            residual_read,
+            residual_points,
+            max_speed,
+            max_speed_read,
+            dt_peaks,
+            dt_stats,
+            dt_stats_read,
+            projection_gate,
+            projection_gate_read,
+            gated_grid,
+            projection_steps: 0,
+            dispatch_trace: None,
+            clamp_history,
+            pressure_frames,
+            pressure_frame_points,
+            dye_budget,
+            forcing,
+            dye_sum,
+            vel_sum,
+            mean_steps: 0,
+            pipelines,
+            groups,
+            field_tex,
+            grid,
+            steps_per_submit: (2048u32 / passes_per_step.max(1)).clamp(4, STEPS_PER_SUBMIT_MAX),
+            vel_slot: Slot::A,
+            dye_slot: Slot::A,
+            steps_done: 0,
+            pending_seed: false,
+            pipeline_cache_hit: compiled_before.is_some()
+                && gpu_cache::compiled(device) == compiled_before,
+            backend,
+        }
+    }
+
+    /// (Re)seed initial velocity + dye. The dispatch rides along with the next `step` or
+    /// `read_fields` submission.
+    fn seed(&mut self) {
+        self.pending_seed = true;
+        self.vel_slot = Slot::A;
+        self.dye_slot = Slot::A;
+        self.mean_steps = 0;
+        self.projection_steps = 0;
+    }
+
+    fn encode_pending_seed(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.pending_seed {
+            return;
+        }
+        encoder.clear_buffer(&self.dye_budget, 0, None);
+        encoder.clear_buffer(&self.forcing, 0, None);
+        encoder.clear_buffer(&self.dye_sum, 0, None);
+        encoder.clear_buffer(&self.vel_sum, 0, None);
+        encoder.clear_buffer(&self.dt_stats, 0, None);
+        if self.gated_grid.is_some() {
+            // everything but the target
+            let tally = ((PROJECTION_GATE_LEN - 1) * std::mem::size_of::<u32>()) as u64;
+            encoder.clear_buffer(&self.projection_gate, 0, Some(tally));
+        }
+        // the seed, then each `init_layers` entry on top with its block copied into the
+        // params; the zero seed block goes back afterwards. `skip_init` has no layers.
+        self.copy_dye_color(encoder, 0);
+        let block = std::mem::size_of::<InitLayerParams>() as u64;
+        let layers = self.cfg.init_layers.len() as u64;
+        let seeds = if self.cfg.skip_init { 0 } else { layers + 1 };
+        for layer in 0..seeds {
+            if layer > 0 {
+                encoder.copy_buffer_to_buffer(
+                    &self.init_layers,
+                    layer * block,
+                    &self.params_buf,
+                    std::mem::offset_of!(Params, init_layer) as u64,
+                    block,
+                );
+            }
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&self.pipelines.init);
+            pass.set_bind_group(0, &self.groups.init, &[]);
+            self.grid.dispatch(&mut pass);
+        }
+        if layers > 0 {
+            encoder.copy_buffer_to_buffer(
+                &self.init_layers,
+                0,
+                &self.params_buf,
+                std::mem::offset_of!(Params, init_layer) as u64,
+                block,
+            );
+        }
+        // uploads replace whatever the seed wrote
+        if let Some(vel) = &self.init_vel {
+            encoder.copy_buffer_to_buffer(vel, 0, &self.vel_a, 0, None);
+        }
+        if let Some(dye) = &self.init_dye {
+            encoder.copy_buffer_to_buffer(dye, 0, &self.dye_a, 0, None);
+        }
+        self.pending_seed = false;
+    }
+
+    /// Copy entry `entry` of `dye_colors` into the params (nothing without a schedule).
+    fn copy_dye_color(&self, encoder: &mut wgpu::CommandEncoder, entry: u64) {
+        if let Some(colors) = &self.dye_colors {
+            let color = std::mem::size_of::<[f32; 3]>() as u64;
+            let at = std::mem::offset_of!(Params, inject_r) as u64;
+            encoder.copy_buffer_to_buffer(colors, entry * color, &self.params_buf, at, color);
+        }
+    }
+
+    /// Advance `steps` steps, batching several steps per submission.
+    fn step(&mut self, steps: u32) {
+        let mut remaining = steps;
+        while remaining > 0 {
+            let batch_steps = remaining.min(self.steps_per_submit);
+            let first_step = self.steps_done;
+            let indices: Vec<u32> = (first_step..first_step + batch_steps).collect();
+            self.queue
+                .write_buffer(&self.step_table, 0, bytemuck::cast_slice(&indices));
+            let mut encoder = self.device.create_command_encoder(&Default::default());
+            self.encode_pending_seed(&mut encoder);
+            for i in 0..batch_steps {
+                if self.cfg.delta_snapshot_step(first_step + i) {
+                    let (vel, dye) = (
+                        self.field_buffer(FluidField::Vel),
+                        self.field_buffer(FluidField::Dye),
+                    );
+                    encoder.copy_buffer_to_buffer(vel, 0, &self.vel_prev, 0, vel.size());
+                    encoder.copy_buffer_to_buffer(dye, 0, &self.dye_prev, 0, dye.size());
+                }
+                let schedule_len = self.cfg.dye_color_schedule.len();
+                if let Some(slot) =
+                    dye_color_slot(schedule_len, self.cfg.dye_color_every, first_step + i)
+                {
+                    self.copy_dye_color(&mut encoder, 1 + slot as u64);
+                }
+                let record_residuals = self.cfg.residual_step() == Some(first_step + i);
+                let record_pressure = self.cfg.pressure_frames_step() == Some(first_step + i);
+                let mut trace = if self.cfg.trace_dispatches && first_step + i == 0 {
+                    DispatchTrace::recording()
+                } else {
+                    DispatchTrace::default()
+                };
+                self.encode_step(
+                    &mut encoder,
+                    i,
+                    record_residuals,
+                    record_pressure,
+                    &mut trace,
+                );
+                if self.cfg.averages_step(first_step + i) {
+                    self.encode_accumulate(&mut encoder, &mut trace);
+                }
+                if let DispatchTrace(Some(groups)) = trace {
+                    self.dispatch_trace = Some(groups);
+                }
+                let step = first_step + i;
+                if let Some(history) = &self.clamp_history
+                    && step < self.cfg.steps
+                {
+                    encoder.copy_buffer_to_buffer(
+                        &self.counters,
+                        (COUNTER_CLAMP_EVENTS * std::mem::size_of::<u32>()) as u64,
+                        history,
+                        step as u64 * 4,
+                        4,
+                    );
+                }
+            }
+            self.queue.submit(Some(encoder.finish()));
+            remaining -= batch_steps;
+            self.steps_done += batch_steps;
+        }
+    }
+
+    /// Encode one full step; `batch_slot` selects this step's entry in `step_table`.
+    fn encode_step(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        batch_slot: u32,
+        record_residuals: bool,
+        record_pressure: bool,
+        trace: &mut DispatchTrace,
+    ) {
+        let FluidStepRequest {
+            jacobi_iters,
+            projection_passes,
+            viscosity,
+            diffuse_iters,
+            vorticity,
+            ..
+        } = self.cfg;
+        let grid = &self.grid;
+        let pl = &self.pipelines;
+        let bg = &self.groups;
+
+        encoder.copy_buffer_to_buffer(
+            &self.step_table,
+            (batch_slot as usize * std::mem::size_of::<u32>()) as u64,
+            &self.params_buf,
+            std::mem::offset_of!(Params, step_index) as u64,
+            std::mem::size_of::<u32>() as u64,
+        );
+
+        // place the moving obstacles for this step before anything reads the walls
+        if !self.cfg.obstacle_velocity.is_empty() {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&pl.move_obstacles);
+            pass.set_bind_group(0, &bg.move_obstacles, &[]);
+            grid.dispatch(&mut pass);
+            trace.push("move_obstacles");
+        }
+
+        if self.gated_grid.is_some() {
+            // reopen the gate: the step's passes run until it closes
+            let words = std::mem::size_of::<u32>() as u64;
+            encoder.clear_buffer(&self.projection_gate, 3 * words, Some(2 * words));
+        }
+        if self.cfg.adaptive_dt {
+            self.encode_pick_dt(encoder, trace);
+        }
+
+        // reset pressure source before solve so each projection starts from a clean slate.
+        // pressure_b is fully overwritten on the first Jacobi pass, so clearing it is wasted work.
+        self.reset_pressure(encoder, trace);
+
+        // velocity advection, vel_a -> vel_b
+        let maccormack = self.cfg.advection == AdvectionScheme::MacCormack;
+        if maccormack {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&pl.predict_vel);
+            pass.set_bind_group(0, &bg.predict_vel, &[]);
+            grid.dispatch(&mut pass);
+            trace.push("predict_vel");
+        }
+        let mut vel = Slot::B;
+        if let Some(tex) = &self.field_tex {
+            // dye_a is untouched until dye advection, so one pack serves both advections
+            {
+                let mut pass = encoder.begin_compute_pass(&Default::default());
+                pass.set_pipeline(&tex.pack);
+                pass.set_bind_group(0, &tex.pack_group, &[]);
+                grid.dispatch(&mut pass);
+                trace.push("tex.pack_group");
+            }
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&tex.advect_vel);
+            pass.set_bind_group(0, &tex.advect_vel_group, &[]);
+            grid.dispatch(&mut pass);
+            trace.push("tex.advect_vel_group");
+        } else {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&pl.advect_vel);
+            pass.set_bind_group(0, &bg.advect_vel, &[]);
+            grid.dispatch(&mut pass);
+            trace.push("advect_vel");
+        }
+
+        // viscosity diffusion solve: Jacobi on (1 - viscosity dt lap) v = v_advected, starting
+        // from the advected velocity and ping-ponging so that the last iteration writes vel_b
+        if viscosity > 0.0 {
+            encoder.copy_buffer_to_buffer(&self.vel_b, 0, &self.diffuse_rhs, 0, None);
+            for i in 0..diffuse_iters {
+                let into_b = (diffuse_iters - i) % 2 == 1;
+                let (name, group) = match (i, into_b) {
+                    (0, true) => ("diffuse_rhs_b", &bg.diffuse_rhs_b),
+                    (0, false) => ("diffuse_rhs_a", &bg.diffuse_rhs_a),
+                    (_, true) => ("diffuse_ab", &bg.diffuse_ab),
+                    (_, false) => ("diffuse_ba", &bg.diffuse_ba),
+                };
+                let mut pass = encoder.begin_compute_pass(&Default::default());
+                pass.set_pipeline(&pl.diffuse_vel);
+                pass.set_bind_group(0, group, &[]);
+                grid.dispatch(&mut pass);
+                trace.push(name);
+                vel = if into_b { Slot::B } else { Slot::A };
+            }
+        }
+        debug_assert_eq!(vel, Slot::B, "divergence and projection read vel_b");
+
+        // vorticity confinement, in place on vel_b
+        if vorticity > 0.0 {
+            for (name, pipeline, group) in [
+                ("curl", &pl.curl, &bg.curl),
+                ("vorticity_force", &pl.vorticity_force, &bg.vorticity_force),
+            ] {
+                let mut pass = encoder.begin_compute_pass(&Default::default());
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(0, group, &[]);
+                grid.dispatch(&mut pass);
+                trace.push(name);
+            }
+        }
+        if self.cfg.dye_advect_velocity == DyeAdvectVelocity::Raw {
+            encoder.copy_buffer_to_buffer(&self.vel_b, 0, &self.dye_vel, 0, None);
+        }
+
+        // divergence
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&pl.divergence);
+            pass.set_bind_group(0, &bg.div, &[]);
+            grid.dispatch(&mut pass);
+            trace.push("div");
+        }
+
+        if record_residuals {
+            encoder.clear_buffer(&self.residuals, 0, None);
+        }
+        for i in 0..jacobi_iters {
+            self.encode_pressure_iteration(encoder, i, grid, trace);
+
+            let slot = record_residuals
+                .then(|| self.residual_points.iter().position(|&r| r == i))
+                .flatten();
+            if let Some(slot) = slot {
+                encoder.copy_buffer_to_buffer(
+                    &self.residual_slots,
+                    (slot * std::mem::size_of::<u32>()) as u64,
+                    &self.params_buf,
+                    std::mem::offset_of!(Params, residual_slot) as u64,
+                    std::mem::size_of::<u32>() as u64,
+                );
+                // iteration i wrote pressure_b when even, pressure_a when odd
+                let mut pass = encoder.begin_compute_pass(&Default::default());
+                pass.set_pipeline(&pl.residual);
+                let (name, group) = if i % 2 == 0 {
+                    ("residual_b", &bg.residual_b)
+                } else {
+                    ("residual_a", &bg.residual_a)
+                };
+                pass.set_bind_group(0, group, &[]);
+                grid.dispatch(&mut pass);
+                trace.push(name);
+            }
+            let frame = record_pressure
+                .then(|| self.pressure_frame_points.iter().position(|&f| f == i))
+                .flatten();
+            if let (Some(frame), Some(frames)) = (frame, &self.pressure_frames) {
+                // the same parity as the residual: iteration i wrote pressure_b when even
+                let latest = if i % 2 == 0 {
+                    &self.pressure_b
+                } else {
+                    &self.pressure_a
+                };
+                encoder.copy_buffer_to_buffer(
+                    latest,
+                    0,
+                    frames,
+                    frame as u64 * latest.size(),
+                    latest.size(),
+                );
+            }
+        }
+
+        // projection
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&pl.project);
+            let (name, group) = if jacobi_iters.is_multiple_of(2) {
+                ("project_from_a", &bg.project_from_a)
+            } else {
+                ("project_from_b", &bg.project_from_b)
+            };
+            pass.set_bind_group(0, group, &[]);
+            grid.dispatch(&mut pass);
+            trace.push(name);
+        }
+        vel = Slot::A;
+
+        // optional additional projection passes to tighten incompressibility.
+        // Keep velocity ping-ponging between A/B without explicit buffer copies.
+        // With `target_divergence` each pass first asks the gate whether it is still needed;
+        // a closed gate skips the solve and the project kernel only copies.
+        let solve_grid = self.gated_grid.as_ref().unwrap_or(grid);
+        for _ in 1..projection_passes {
+            let vel_in_a = vel == Slot::A;
+            {
+                let mut pass = encoder.begin_compute_pass(&Default::default());
+                pass.set_pipeline(&pl.divergence);
+                let (name, group) = if vel_in_a {
+                    ("div_from_a", &bg.div_from_a)
+                } else {
+                    ("div", &bg.div)
+                };
+                pass.set_bind_group(0, group, &[]);
+                grid.dispatch(&mut pass);
+                trace.push(name);
+            }
+            if self.gated_grid.is_some() {
+                self.encode_max_divergence(encoder, trace);
+                let mut pass = encoder.begin_compute_pass(&Default::default());
+                pass.set_pipeline(&pl.divergence_gate);
+                pass.set_bind_group(0, &bg.divergence_gate, &[]);
+                pass.dispatch_workgroups(1, 1, 1);
+                trace.push("divergence_gate");
+            }
+
+            self.reset_pressure(encoder, trace);
+            for i in 0..jacobi_iters {
+                self.encode_pressure_iteration(encoder, i, solve_grid, trace);
+            }
+
+            {
+                let mut pass = encoder.begin_compute_pass(&Default::default());
+                pass.set_pipeline(&pl.project);
+                let (name, group) = match (vel_in_a, jacobi_iters.is_multiple_of(2)) {
+                    (true, true) => ("project_cleanup_from_a", &bg.project_cleanup_from_a),
+                    (true, false) => ("project_cleanup_from_b", &bg.project_cleanup_from_b),
+                    (false, true) => ("project_from_a", &bg.project_from_a),
+                    (false, false) => ("project_from_b", &bg.project_from_b),
+                };
+                pass.set_bind_group(0, group, &[]);
+                grid.dispatch(&mut pass);
+                trace.push(name);
+            }
+
+            vel = vel.flipped();
+        }
+        if self.gated_grid.is_some() {
+            // the divergence the last pass left, for `projection_divergence`
+            {
+                let mut pass = encoder.begin_compute_pass(&Default::default());
+                pass.set_pipeline(&pl.divergence);
+                let (name, group) = if vel == Slot::A {
+                    ("div_from_a", &bg.div_from_a)
+                } else {
+                    ("div", &bg.div)
+                };
+                pass.set_bind_group(0, group, &[]);
+                grid.dispatch(&mut pass);
+                trace.push(name);
+            }
+            self.encode_max_divergence(encoder, trace);
+            self.projection_steps += 1;
+        }
+        self.vel_slot = vel;
+
+        // dye advection
+        {
+            let (name, group) = match (self.cfg.dye_advect_velocity, vel) {
+                (DyeAdvectVelocity::Projected, Slot::A) => {
+                    ("predict_dye_from_a", &bg.predict_dye_from_a)
+                }
+                (DyeAdvectVelocity::Projected, Slot::B) => {
+                    ("predict_dye_from_b", &bg.predict_dye_from_b)
+                }
+                _ => ("predict_dye_from_dye_vel", &bg.predict_dye_from_dye_vel),
+            };
+            if maccormack {
+                let mut pass = encoder.begin_compute_pass(&Default::default());
+                pass.set_pipeline(&pl.predict_dye);
+                pass.set_bind_group(0, group, &[]);
+                grid.dispatch(&mut pass);
+                trace.push(name);
+            }
+            let (pipeline, from_a, from_b, from_dye_vel) = match &self.field_tex {
+                Some(tex) => (
+                    &tex.advect_dye,
+                    ("tex.advect_dye_from_a", &tex.advect_dye_from_a),
+                    ("tex.advect_dye_from_b", &tex.advect_dye_from_b),
+                    ("tex.advect_dye_from_dye_vel", &tex.advect_dye_from_dye_vel),
+                ),
+                None => (
+                    &pl.advect_dye,
+                    ("advect_dye_from_a", &bg.advect_dye_from_a),
+                    ("advect_dye_from_b", &bg.advect_dye_from_b),
+                    ("advect_dye_from_dye_vel", &bg.advect_dye_from_dye_vel),
+                ),
+            };
+            let (name, group) = match (self.cfg.dye_advect_velocity, vel) {
+                (DyeAdvectVelocity::Projected, Slot::A) => from_a,
+                (DyeAdvectVelocity::Projected, Slot::B) => from_b,
+                _ => from_dye_vel,
+            };
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, group, &[]);
+            grid.dispatch(&mut pass);
+            trace.push(name);
+        }
+        let mut dye = Slot::B;
+
+        // dye fade and re-seed source slightly
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&pl.fade);
+            pass.set_bind_group(0, &bg.fade, &[]);
+            grid.dispatch(&mut pass);
+            trace.push("fade");
+        }
+        dye = dye.flipped();
+        self.dye_slot = dye;
+    }
+
+    /// Pressure iteration `i` of a projection solve: pressure_a -> pressure_b when even, back
+    /// when odd, with either solver, dispatched over `grid` (the gate's args in a
+    /// `target_divergence` pass, else `self.grid`).
+    fn encode_pressure_iteration(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        i: u32,
+        grid: &GridDispatch,
+        trace: &mut DispatchTrace,
+    ) {
+        let (pl, bg) = (&self.pipelines, &self.groups);
+        let even = i.is_multiple_of(2);
+        match self.cfg.pressure_solver {
+            PressureSolver::Jacobi => {
+                let mut pass = encoder.begin_compute_pass(&Default::default());
+                pass.set_pipeline(&pl.jacobi);
+                let (name, group) = if even {
+                    ("jacobi_ab", &bg.jacobi_ab)
+                } else {
+                    ("jacobi_ba", &bg.jacobi_ba)
+                };
+                pass.set_bind_group(0, group, &[]);
+                grid.dispatch(&mut pass);
+                trace.push(name);
+            }
+            PressureSolver::RedBlack => {
+                for phase in 0..2u64 {
+                    encoder.copy_buffer_to_buffer(
+                        &self.rb_phases,
+                        phase * std::mem::size_of::<u32>() as u64,
+                        &self.params_buf,
+                        std::mem::offset_of!(Params, rb_phase) as u64,
+                        std::mem::size_of::<u32>() as u64,
+                    );
+                    let mut pass = encoder.begin_compute_pass(&Default::default());
+                    pass.set_pipeline(&pl.red_black);
+                    let (name, group) = if even {
+                        ("red_black_ab", &bg.red_black_ab)
+                    } else {
+                        ("red_black_ba", &bg.red_black_ba)
+                    };
+                    pass.set_bind_group(0, group, &[]);
+                    grid.dispatch(&mut pass);
+                    trace.push(name);
+                }
+            }
+        }
+    }
+
+    /// `target_divergence`: reduce the divergence the last pass left to its max, into the
+    /// gate's `f32` slot.
+    fn encode_max_divergence(&self, encoder: &mut wgpu::CommandEncoder, trace: &mut DispatchTrace) {
+        let words = std::mem::size_of::<u32>() as u64;
+        encoder.clear_buffer(&self.projection_gate, 5 * words, Some(words));
+        let mut pass = encoder.begin_compute_pass(&Default::default());
+        pass.set_pipeline(&self.pipelines.max_divergence);
+        pass.set_bind_group(0, &self.groups.max_divergence, &[]);
+        self.grid.dispatch(&mut pass);
+        trace.push("max_divergence");
+    }
+
+    /// `adaptive_dt`: reduce the velocity about to be advected to its peaks and copy the `dt`
+    /// they allow into the params, for every kernel of this step.
+    fn encode_pick_dt(&self, encoder: &mut wgpu::CommandEncoder, trace: &mut DispatchTrace) {
+        encoder.clear_buffer(&self.dt_peaks, 0, None);
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&self.pipelines.dt_peaks);
+            pass.set_bind_group(0, &self.groups.dt_peaks, &[]);
+            self.grid.dispatch(&mut pass);
+            trace.push("dt_peaks");
+        }
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&self.pipelines.pick_dt);
+            pass.set_bind_group(0, &self.groups.pick_dt, &[]);
+            pass.dispatch_workgroups(1, 1, 1);
+            trace.push("pick_dt");
+        }
+        encoder.copy_buffer_to_buffer(
+            &self.dt_stats,
+            0,
+            &self.params_buf,
+            std::mem::offset_of!(Params, dt) as u64,
+            std::mem::size_of::<f32>() as u64,
+        );
+    }
+
+    /// Add the step's final velocity and dye to the `time_average` sums.
+    fn encode_accumulate(&mut self, encoder: &mut wgpu::CommandEncoder, trace: &mut DispatchTrace) {
+        let mut pass = encoder.begin_compute_pass(&Default::default());
+        pass.set_pipeline(&self.pipelines.accumulate);
+        let (name, group) = if self.vel_slot == Slot::A {
+            ("accumulate_from_a", &self.groups.accumulate_from_a)
+        } else {
+            ("accumulate_from_b", &self.groups.accumulate_from_b)
+        };
+        pass.set_bind_group(0, group, &[]);
+        self.grid.dispatch(&mut pass);
+        trace.push(name);
+        self.mean_steps += 1;
+    }
+
+    /// Start the next pressure solve from `pressure_init`: a buffer clear for the default 0,
+    /// else the fill kernel (`clear_buffer` can only write zero bytes).
+    fn reset_pressure(&self, encoder: &mut wgpu::CommandEncoder, trace: &mut DispatchTrace) {
+        if self.cfg.pressure_init == 0.0 {
+            encoder.clear_buffer(&self.pressure_a, 0, None);
+            return;
+        }
+        let mut pass = encoder.begin_compute_pass(&Default::default());
+        pass.set_pipeline(&self.pipelines.fill);
+        pass.set_bind_group(0, &self.groups.pressure_fill, &[]);
+        self.grid.dispatch(&mut pass);
+        trace.push("pressure_fill");
+    }
+
+    /// Buffer currently holding the latest values of `field`.
+    fn field_buffer(&self, field: FluidField) -> &wgpu::Buffer {
+        match field {
+            FluidField::Vel => match self.vel_slot {
+                Slot::A => &self.vel_a,
+                Slot::B => &self.vel_b,
             },
-        ],
-    });
+            FluidField::Dye | FluidField::DyeRgb => match self.dye_slot {
+                Slot::A => &self.dye_a,
+                Slot::B => &self.dye_b,
+            },
+            FluidField::OkuboWeiss => &self.diag,
+            FluidField::DyeGradient => &self.dye_grad,
+            FluidField::Delta => &self.delta,
+            // sweep i writes pressure_b when even (see `encode_streamfunction`)
+            FluidField::Streamfunction if self.cfg.streamfunction_iters % 2 == 1 => {
+                &self.pressure_b
+            }
+            FluidField::Streamfunction => &self.pressure_a,
+            // an odd Jacobi count ends each solve in pressure_b, as `project_from_b` reads it
+            FluidField::Pressure if self.cfg.jacobi_iters % 2 == 1 => &self.pressure_b,
+            FluidField::Pressure => &self.pressure_a,
+            FluidField::DyeMean => &self.dye_sum,
+            FluidField::VelMean => &self.vel_sum,
+        }
+    }
+
+    /// Read back only `roi` of each of `fields`: one copy per row into a window-sized staging
+    /// buffer (a single copy when the window spans full rows), all in one submission so the
+    /// fields share a snapshot, then mapped together.
+    fn read_regions(&mut self, fields: &[FluidField], roi: Roi) -> Result<Vec<Vec<f32>>> {
+        let width = self.cfg.width as u64;
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        self.encode_pending_seed(&mut encoder);
+        if fields.contains(&FluidField::OkuboWeiss) {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&self.pipelines.okubo_weiss);
+            pass.set_bind_group(
+                0,
+                if self.vel_slot == Slot::A {
+                    &self.groups.okubo_weiss_from_a
+                } else {
+                    &self.groups.okubo_weiss_from_b
+                },
+                &[],
+            );
+            self.grid.dispatch(&mut pass);
+        }
+        if fields.contains(&FluidField::DyeGradient) {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&self.pipelines.dye_gradient);
+            pass.set_bind_group(
+                0,
+                if self.dye_slot == Slot::A {
+                    &self.groups.dye_gradient_from_a
+                } else {
+                    &self.groups.dye_gradient_from_b
+                },
+                &[],
+            );
+            self.grid.dispatch(&mut pass);
+        }
+        if fields.contains(&FluidField::Delta) {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&self.pipelines.delta);
+            let slot = |slot| (slot == Slot::B) as usize;
+            pass.set_bind_group(
+                0,
+                &self.groups.delta_from[slot(self.vel_slot)][slot(self.dye_slot)],
+                &[],
+            );
+            self.grid.dispatch(&mut pass);
+        }
+        if fields.contains(&FluidField::Streamfunction) {
+            self.encode_streamfunction(&mut encoder);
+        }
+        let mut staging = Vec::with_capacity(fields.len());
+        for &field in fields {
+            let src = self.field_buffer(field);
+            check_readable(field, src.usage())?;
+            let cell_bytes = (self.buffer_stride(field) * std::mem::size_of::<f32>()) as u64;
+            let row_bytes = roi.w as u64 * cell_bytes;
+            let dst = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("roi-read"),
+                size: row_bytes * roi.h as u64,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            });
+            let origin = (roi.y as u64 * width + roi.x as u64) * cell_bytes;
+            if roi.x == 0 && roi.w as u64 == width {
+                encoder.copy_buffer_to_buffer(src, origin, &dst, 0, row_bytes * roi.h as u64);
+            } else {
+                for row in 0..roi.h as u64 {
+                    encoder.copy_buffer_to_buffer(
+                        src,
+                        origin + row * width * cell_bytes,
+                        &dst,
+                        row * row_bytes,
+                        row_bytes,
+                    );
+                }
+            }
+            staging.push(dst);
+        }
+        self.queue.submit(Some(encoder.finish()));
+
+        let slices: Vec<_> = staging.iter().map(|b| b.slice(..)).collect();
+        map_wait_all(&self.device, &slices)?;
+        let values = slices
+            .iter()
+            .zip(fields)
+            .map(|(slice, &field)| {
+                self.unpad(field, bytemuck::cast_slice(&slice.get_mapped_range()))
+            })
+            .collect();
+        staging.iter().for_each(|b| b.unmap());
+        Ok(values)
+    }
+
+    /// `f32`s a cell of `field` takes in its buffer: `components`, except for RGB dye.
+    fn buffer_stride(&self, field: FluidField) -> usize {
+        match field {
+            FluidField::Dye | FluidField::DyeRgb => self.cfg.dye_stride(),
+            _ => field.components(),
+        }
+    }
+
+    /// `field`'s `components` per cell out of `buffer_stride` ones read back.
+    fn unpad(&self, field: FluidField, values: &[f32]) -> Vec<f32> {
+        match field {
+            FluidField::Dye => self.dye_scalars(values),
+            FluidField::DyeRgb => values
+                .chunks(self.buffer_stride(field))
+                .flat_map(|cell| &cell[..3])
+                .copied()
+                .collect(),
+            _ => values.to_vec(),
+        }
+    }
+
+    /// The scalar of each dye cell: the dye itself, or the luminance of RGB dye.
+    fn dye_scalars(&self, dye: &[f32]) -> Vec<f32> {
+        match self.cfg.dye_stride() {
+            1 => dye.to_vec(),
+            stride => dye
+                .chunks(stride)
+                .map(|cell| luminance([cell[0], cell[1], cell[2]]))
+                .collect(),
+        }
+    }
+
+    /// Solve `lap(psi) = -omega` for the current velocity into the pressure pair, from zero and
+    /// with the pressure solve's Jacobi kernel, then record the solve's residual. Leaves `div`
+    /// and the pressure pair scratch, which the next step recomputes anyway.
+    fn encode_streamfunction(&self, encoder: &mut wgpu::CommandEncoder) {
+        let (pl, bg) = (&self.pipelines, &self.groups);
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&pl.streamfunction_rhs);
+            pass.set_bind_group(
+                0,
+                if self.vel_slot == Slot::A {
+                    &bg.streamfunction_rhs_from_a
+                } else {
+                    &bg.streamfunction_rhs_from_b
+                },
+                &[],
+            );
+            self.grid.dispatch(&mut pass);
+        }
+        encoder.clear_buffer(&self.pressure_a, 0, None);
+        let iters = self.cfg.streamfunction_iters;
+        for i in 0..iters {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&pl.jacobi);
+            pass.set_bind_group(
+                0,
+                if i % 2 == 0 {
+                    &bg.jacobi_ab
+                } else {
+                    &bg.jacobi_ba
+                },
+                &[],
+            );
+            self.grid.dispatch(&mut pass);
+        }
+        // the residual kernel writes slot `Params.residual_slot`; slot table entry 0 is 0
+        encoder.copy_buffer_to_buffer(
+            &self.residual_slots,
+            0,
+            &self.params_buf,
+            std::mem::offset_of!(Params, residual_slot) as u64,
+            std::mem::size_of::<u32>() as u64,
+        );
+        encoder.clear_buffer(&self.streamfunction_residual, 0, None);
+        let mut pass = encoder.begin_compute_pass(&Default::default());
+        pass.set_pipeline(&pl.residual);
+        pass.set_bind_group(
+            0,
+            if iters % 2 == 1 {
+                &bg.streamfunction_residual_b
+            } else {
+                &bg.streamfunction_residual_a
+            },
+            &[],
+        );
+        self.grid.dispatch(&mut pass);
+    }
+
+    fn read_streamfunction_residual(&mut self) -> Result<f32> {
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(
+            &self.streamfunction_residual,
+            0,
+            &self.streamfunction_residual_read,
+            0,
+            self.streamfunction_residual.size(),
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = self.streamfunction_residual_read.slice(..);
+        map_wait(&self.device, &slice)?;
+        let bits: u32 = bytemuck::pod_read_unaligned(&slice.get_mapped_range());
+        self.streamfunction_residual_read.unmap();
+        Ok(f32::from_bits(bits))
+    }
+
+    /// Map the per-cell `dye_budget` triples back to the host.
+    fn read_dye_budget(&mut self) -> Result<Vec<f32>> {
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("dye-budget-read"),
+            size: self.dye_budget.size(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(&self.dye_budget, 0, &staging, 0, staging.size());
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = staging.slice(..);
+        map_wait(&self.device, &slice)?;
+        let values = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        staging.unmap();
+        Ok(values)
+    }
+
+    /// Map the per-cell `forcing_budget` pairs back to the host.
+    fn read_forcing_budget(&mut self) -> Result<Vec<[f32; 2]>> {
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("forcing-read"),
+            size: self.forcing.size(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        self.encode_pending_seed(&mut encoder);
+        encoder.copy_buffer_to_buffer(&self.forcing, 0, &staging, 0, staging.size());
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = staging.slice(..);
+        map_wait(&self.device, &slice)?;
+        let values = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        staging.unmap();
+        Ok(values)
+    }
+
+    /// Map the `residual_history` slots (f32 bit patterns) back to the host.
+    fn read_residuals(&mut self) -> Result<Vec<f32>> {
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(
+            &self.residuals,
+            0,
+            &self.residual_read,
+            0,
+            self.residuals.size(),
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = self.residual_read.slice(..);
+        map_wait(&self.device, &slice)?;
+        let bits: Vec<u32> = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        self.residual_read.unmap();
+        Ok(bits
+            .into_iter()
+            .take(self.residual_points.len())
+            .map(f32::from_bits)
+            .collect())
+    }
+
+    /// Reduce `|v|` to its max on the GPU and map back just that one value.
+    fn read_max_speed(&mut self) -> Result<f32> {
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        self.encode_pending_seed(&mut encoder);
+        encoder.clear_buffer(&self.max_speed, 0, None);
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&self.pipelines.max_speed);
+            pass.set_bind_group(
+                0,
+                if self.vel_slot == Slot::A {
+                    &self.groups.max_speed_from_a
+                } else {
+                    &self.groups.max_speed_from_b
+                },
+                &[],
+            );
+            self.grid.dispatch(&mut pass);
+        }
+        encoder.copy_buffer_to_buffer(
+            &self.max_speed,
+            0,
+            &self.max_speed_read,
+            0,
+            self.max_speed.size(),
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = self.max_speed_read.slice(..);
+        map_wait(&self.device, &slice)?;
+        let bits: u32 = bytemuck::pod_read_unaligned(&slice.get_mapped_range());
+        self.max_speed_read.unmap();
+        Ok(f32::from_bits(bits))
+    }
+
+    /// `adaptive_dt` statistics (see `DT_STATS_LEN`).
+    fn read_dt_stats(&mut self) -> Result<[f32; DT_STATS_LEN]> {
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        self.encode_pending_seed(&mut encoder);
+        encoder.copy_buffer_to_buffer(
+            &self.dt_stats,
+            0,
+            &self.dt_stats_read,
+            0,
+            self.dt_stats.size(),
+        );
+        self.queue.submit(Some(encoder.finish()));
 
-    let mut seeded = false;
-    let mut remaining = steps;
-    let wg_x = width.div_ceil(8);
-    let wg_y = height.div_ceil(8);
-    let passes_per_step = 8u32 + jacobi_iters.saturating_mul(projection_passes.max(1));
-    let steps_per_submit = (2048u32 / passes_per_step.max(1)).clamp(4, 24);
-    let mut final_vel_is_a = true;
+        let slice = self.dt_stats_read.slice(..);
+        map_wait(&self.device, &slice)?;
+        let stats = bytemuck::pod_read_unaligned(&slice.get_mapped_range());
+        self.dt_stats_read.unmap();
+        Ok(stats)
+    }
 
-    while remaining > 0 {
-        let batch_steps = remaining.min(steps_per_submit);
-        let mut encoder = device.create_command_encoder(&Default::default());
+    /// `target_divergence` bookkeeping since the last (re)seed (`None` when off).
+    fn read_projection_tally(&mut self) -> Result<Option<ProjectionTally>> {
+        if self.gated_grid.is_none() {
+            return Ok(None);
+        }
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        self.encode_pending_seed(&mut encoder);
+        encoder.copy_buffer_to_buffer(
+            &self.projection_gate,
+            0,
+            &self.projection_gate_read,
+            0,
+            self.projection_gate.size(),
+        );
+        self.queue.submit(Some(encoder.finish()));
 
-        if !seeded {
-            // seed initial velocity + dye
-            let mut pass = encoder.begin_compute_pass(&Default::default());
-            pass.set_pipeline(&init_pipeline);
-            pass.set_bind_group(0, &bg_init, &[]);
-            pass.dispatch_workgroups(wg_x, wg_y, 1);
-            seeded = true;
+        let slice = self.projection_gate_read.slice(..);
+        map_wait(&self.device, &slice)?;
+        let words: [u32; PROJECTION_GATE_LEN] =
+            bytemuck::pod_read_unaligned(&slice.get_mapped_range());
+        self.projection_gate_read.unmap();
+        Ok(Some(ProjectionTally {
+            last_extra: words[4],
+            last_divergence: f32::from_bits(words[5]),
+            extra_sum: words[6],
+            extra_max: words[7],
+            steps: self.projection_steps,
+        }))
+    }
+
+    /// The `dump_pressure_every` frames, one full field each (empty when off).
+    fn read_pressure_frames(&mut self) -> Result<Vec<Vec<f32>>> {
+        let Some(frames) = &self.pressure_frames else {
+            return Ok(Vec::new());
+        };
+        let slice = frames.slice(..);
+        map_wait(&self.device, &slice)?;
+        let values: Vec<f32> = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        frames.unmap();
+        let cells = (self.cfg.width as usize) * (self.cfg.height as usize);
+        Ok(values.chunks(cells).map(<[f32]>::to_vec).collect())
+    }
+
+    /// The `clamp_history` entries of the steps run so far.
+    fn read_clamp_history(&mut self) -> Result<Vec<u32>> {
+        let Some(history) = &self.clamp_history else {
+            return Ok(Vec::new());
+        };
+        let steps = self.steps_done.min(self.cfg.steps) as u64 * 4;
+        if steps == 0 {
+            return Ok(Vec::new());
         }
+        let slice = history.slice(..steps);
+        map_wait(&self.device, &slice)?;
+        let totals = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        history.unmap();
+        Ok(totals)
+    }
 
-        for _ in 0..batch_steps {
-            // reset pressure source before solve so each projection starts from a clean slate.
-            // pressure_b is fully overwritten on the first Jacobi pass, so clearing it is wasted work.
-            encoder.clear_buffer(&pressure_a, 0, None);
+    /// Copy the current velocity, dye and counters back to the host.
+    fn read_fields(&mut self) -> Result<FluidFields> {
+        let cells = (self.cfg.width as usize) * (self.cfg.height as usize);
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        self.encode_pending_seed(&mut encoder);
+        encoder.copy_buffer_to_buffer(
+            self.field_buffer(FluidField::Vel),
+            0,
+            &self.vel_read,
+            0,
+            (cells * std::mem::size_of::<[f32; 2]>()) as u64,
+        );
+        encoder.copy_buffer_to_buffer(
+            self.field_buffer(FluidField::Dye),
+            0,
+            &self.dye_read,
+            0,
+            self.dye_read.size(),
+        );
+        encoder.copy_buffer_to_buffer(&self.counters, 0, &self.counters_read, 0, COUNTERS_BYTES);
+        self.queue.submit(Some(encoder.finish()));
 
-            // velocity advection
-            {
-                let mut pass = encoder.begin_compute_pass(&Default::default());
-                pass.set_pipeline(&advect_vel_pipeline);
-                pass.set_bind_group(0, &bg_advect_vel, &[]);
-                pass.dispatch_workgroups(wg_x, wg_y, 1);
-            }
+        let vel_slice = self.vel_read.slice(..);
+        let dye_slice = self.dye_read.slice(..);
+        let counters_slice = self.counters_read.slice(..);
+        map_wait_all(&self.device, &[vel_slice, dye_slice, counters_slice])?;
+        let fields = FluidFields {
+            vel: bytemuck::cast_slice(&vel_slice.get_mapped_range()).to_vec(),
+            dye: self.dye_scalars(bytemuck::cast_slice(&dye_slice.get_mapped_range())),
+            counters: bytemuck::pod_read_unaligned(&counters_slice.get_mapped_range()),
+            pressure: Vec::new(),
+        };
+        self.vel_read.unmap();
+        self.dye_read.unmap();
+        self.counters_read.unmap();
+        Ok(fields)
+    }
+}
 
-            // viscosity diffusion solve (small Jacobi ping-pong on velocity field)
-            if viscosity > 0.0 {
-                const DIFFUSE_ITERS: u32 = 4;
-                for i in 0..DIFFUSE_ITERS {
-                    let mut pass = encoder.begin_compute_pass(&Default::default());
-                    pass.set_pipeline(&diffuse_vel_pipeline);
-                    pass.set_bind_group(
-                        0,
-                        if i % 2 == 0 {
-                            &bg_diffuse_ba
-                        } else {
-                            &bg_diffuse_ab
-                        },
-                        &[],
-                    );
-                    pass.dispatch_workgroups(wg_x, wg_y, 1);
-                }
-            }
+fn fluid_params(cfg: &FluidStepRequest) -> Params {
+    Params {
+        width: cfg.width,
+        height: cfg.height,
+        jacobi_iters: cfg.jacobi_iters,
+        dt: cfg.dt,
+        viscosity: cfg.viscosity,
+        vorticity: cfg.vorticity,
+        fade: cfg.fade,
+        dye_radius: cfg.dye_radius,
+        impulse: cfg.impulse * cfg.rotation.sign(),
+        inv_dx: cfg.width as f32,
+        inv_dy: cfg.height as f32,
+        max_velocity: cfg.max_velocity,
+        impulse_ramp_steps: cfg.impulse_ramp_steps,
+        clamp_to_neighbors: cfg.clamp_to_neighbors as u32,
+        dye_budget: cfg.dye_budget as u32,
+        pressure_init: cfg.pressure_init,
+        source_period: cfg.source_period,
+        source_on_steps: cfg.source_on_steps(),
+        init_kind: cfg.init.as_u32(),
+        init_mode: cfg.mode,
+        init_dye: cfg.init_dye.as_u32(),
+        source_center_x: cfg.source_center[0],
+        source_center_y: cfg.source_center[1],
+        force_keys: cfg.force_path.len() as u32,
+        cfl_limit: cfg.cfl_limit,
+        vorticity_cfl_limit: cfg.vorticity_cfl_limit,
+        dt_max: cfg.dt,
+        advect_integrator: cfg.advect_integrator.as_u32(),
+        advection: cfg.advection.as_u32(),
+        inject_r: cfg.inject_color[0],
+        inject_g: cfg.inject_color[1],
+        inject_b: cfg.inject_color[2],
+        fade_gradient: cfg.fade_gradient.as_u32(),
+        fade_edge: cfg.fade_edge,
+        cfl_max: if cfg.cfl_clamp { cfg.cfl_max } else { 0.0 },
+        ..Params::zeroed()
+    }
+}
 
-            // divergence
-            {
-                let mut pass = encoder.begin_compute_pass(&Default::default());
-                pass.set_pipeline(&divergence_pipeline);
-                pass.set_bind_group(0, &bg_div, &[]);
-                pass.dispatch_workgroups(wg_x, wg_y, 1);
-            }
+/// CPU mirror of the advect kernel's forcing ramp for step `step` (0-based).
+fn impulse_ramp(step: u32, ramp_steps: u32) -> f32 {
+    if ramp_steps == 0 {
+        1.0
+    } else {
+        ((step + 1) as f32 / ramp_steps as f32).min(1.0)
+    }
+}
 
-            for i in 0..jacobi_iters {
-                let mut pass = encoder.begin_compute_pass(&Default::default());
-                pass.set_pipeline(&jacobi_pipeline);
-                pass.set_bind_group(
-                    0,
-                    if i % 2 == 0 {
-                        &bg_jacobi_ab
-                    } else {
-                        &bg_jacobi_ba
-                    },
-                    &[],
-                );
-                pass.dispatch_workgroups(wg_x, wg_y, 1);
-            }
+/// Encode velocity into an RGBA8 PNG: R/G hold vx/vy mapped from [-scale, scale] to [0, 255],
+/// B = 0, A = 255. Row 0 of the image is grid row y = 0.
+fn write_velocity_png(
+    path: &str,
+    width: u32,
+    height: u32,
+    vel: &[[f32; 2]],
+    scale: f32,
+) -> Result<()> {
+    let encode = |c: f32| (((c / scale).clamp(-1.0, 1.0) * 0.5 + 0.5) * 255.0).round() as u8;
+    let mut rgba = Vec::with_capacity(vel.len() * 4);
+    for v in vel {
+        rgba.extend_from_slice(&[encode(v[0]), encode(v[1]), 0, 255]);
+    }
+    write_png_rgba(path, width, height, &rgba)
+}
 
-            // projection
-            {
-                let mut pass = encoder.begin_compute_pass(&Default::default());
-                pass.set_pipeline(&project_pipeline);
-                pass.set_bind_group(
-                    0,
-                    if jacobi_iters % 2 == 0 {
-                        &bg_project_from_a
-                    } else {
-                        &bg_project_from_b
-                    },
-                    &[],
-                );
-                pass.dispatch_workgroups(wg_x, wg_y, 1);
-            }
+/// `values` as CSV rows of `width` comma-separated numbers (shortest round-trip form).
+fn write_csv(path: &str, width: u32, values: &[f32]) -> Result<()> {
+    use std::io::Write;
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("create {path}"))
+        .code(ErrorCode::IoError)?;
+    let mut out = io::BufWriter::new(file);
+    for row in values.chunks(width as usize) {
+        let line: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+        writeln!(out, "{}", line.join(","))
+            .with_context(|| format!("write {path}"))
+            .code(ErrorCode::IoError)?;
+    }
+    out.flush()
+        .with_context(|| format!("write {path}"))
+        .code(ErrorCode::IoError)
+}
 
-            // optional additional projection passes to tighten incompressibility.
-            // Keep velocity ping-ponging between A/B without explicit buffer copies.
-            let mut vel_in_a = true;
-            for _ in 1..projection_passes {
-                {
-                    let mut pass = encoder.begin_compute_pass(&Default::default());
-                    pass.set_pipeline(&divergence_pipeline);
-                    pass.set_bind_group(0, if vel_in_a { &bg_div_from_a } else { &bg_div }, &[]);
-                    pass.dispatch_workgroups(wg_x, wg_y, 1);
-                }
+fn write_png_rgba(path: &str, width: u32, height: u32, rgba: &[u8]) -> Result<()> {
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("create {path}"))
+        .code(ErrorCode::IoError)?;
+    png_rgba_to(io::BufWriter::new(file), width, height, rgba)
+}
 
-                encoder.clear_buffer(&pressure_a, 0, None);
-                for i in 0..jacobi_iters {
-                    let mut pass = encoder.begin_compute_pass(&Default::default());
-                    pass.set_pipeline(&jacobi_pipeline);
-                    pass.set_bind_group(
-                        0,
-                        if i % 2 == 0 {
-                            &bg_jacobi_ab
-                        } else {
-                            &bg_jacobi_ba
-                        },
-                        &[],
-                    );
-                    pass.dispatch_workgroups(wg_x, wg_y, 1);
-                }
+fn encode_png_rgba(width: u32, height: u32, rgba: &[u8]) -> Result<Vec<u8>> {
+    let mut png = Vec::new();
+    png_rgba_to(&mut png, width, height, rgba)?;
+    Ok(png)
+}
 
-                {
-                    let mut pass = encoder.begin_compute_pass(&Default::default());
-                    pass.set_pipeline(&project_pipeline);
-                    pass.set_bind_group(
-                        0,
-                        if vel_in_a {
-                            if jacobi_iters % 2 == 0 {
-                                &bg_project_cleanup_from_a
-                            } else {
-                                &bg_project_cleanup_from_b
-                            }
-                        } else if jacobi_iters % 2 == 0 {
-                            &bg_project_from_a
-                        } else {
-                            &bg_project_from_b
-                        },
-                        &[],
-                    );
-                    pass.dispatch_workgroups(wg_x, wg_y, 1);
-                }
+fn png_rgba_to(out: impl io::Write, width: u32, height: u32, rgba: &[u8]) -> Result<()> {
+    let mut encoder = png::Encoder::new(out, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder
+        .write_header()
+        .context("png header")
+        .code(ErrorCode::IoError)?;
+    writer
+        .write_image_data(rgba)
+        .context("png data")
+        .code(ErrorCode::IoError)?;
+    writer
+        .finish()
+        .context("png finish")
+        .code(ErrorCode::IoError)?;
+    Ok(())
+}
+
+/// Largest image `fluid_render` will inline as a `data_url` (1024x1024): the base64 payload
+/// lands in a single JSON response line.
+const DATA_URL_MAX_PIXELS: u32 = 1024 * 1024;
+
+const STEPS_PER_SUBMIT_MAX: u32 = 24;
+
+// Slots of the `counters` storage buffer (mirrored as `array<atomic<u32>, 4>` in WGSL).
+const COUNTER_SLOTS: usize = 4;
+const COUNTERS_BYTES: u64 = (COUNTER_SLOTS * std::mem::size_of::<u32>()) as u64;
+const COUNTER_NEIGHBOR_CLAMP_VEL: usize = 0;
+const COUNTER_NEIGHBOR_CLAMP_DYE: usize = 1;
+/// Interior cells whose projected velocity hit `max_velocity`, plus velocity backtraces that
+/// left the domain, over every step (`gpu_clamp_events`).
+const COUNTER_CLAMP_EVENTS: usize = 2;
+/// Interior cells whose projected velocity `cfl_clamp` capped, over every step
+/// (`cfl_clamped_cells`).
+const COUNTER_CFL_CLAMP: usize = 3;
 
-                vel_in_a = !vel_in_a;
+/// Fixed battery of small runs checked against built-in tolerances (CI entry point).
+async fn run_self_test(backend: WgpuBackend) -> SelfTestResponse {
+    let mut cases = Vec::new();
+    // the adapter's backend, once a case got one
+    let mut label = None;
+
+    cases.push(
+        match run_smoke(1024, default_smoke_workgroup(), backend).await {
+            Ok(r) => {
+                label = Some(r.backend);
+                SelfTestCase {
+                    name: "smoke_1024",
+                    passed: r.ok && r.sample == [1.0, 2.0, 11.0, 1024.0],
+                    detail: format!("sample={:?} mismatches={}", r.sample, r.mismatch_count),
+                }
             }
-            final_vel_is_a = vel_in_a;
+            Err(err) => self_test_error("smoke_1024", err),
+        },
+    );
 
-            // dye advection
-            {
-                let mut pass = encoder.begin_compute_pass(&Default::default());
-                pass.set_pipeline(&advect_dye_pipeline);
-                pass.set_bind_group(
-                    0,
-                    if vel_in_a {
-                        &bg_advect_dye_from_a
-                    } else {
-                        &bg_advect_dye_from_b
-                    },
-                    &[],
-                );
-                pass.dispatch_workgroups(wg_x, wg_y, 1);
+    // default forcing on a small grid: finite stats, bounded divergence, dye mass within
+    // a sane band around the seeded blob (the fade source adds some, fade removes some).
+    let cfg = small_fluid_config(serde_json::json!({"steps": 20, "backend": backend}));
+    let seeded = seeded_dye_total(cfg.width, cfg.height, cfg.dye_radius);
+    cases.push(match run_fluid_step(&cfg).await {
+        Ok(r) => {
+            label = label.or(Some(r.backend));
+            let ratio = r.dye_total / seeded;
+            let finite = [r.avg_speed, r.max_speed, r.avg_divergence, r.dye_total]
+                .iter()
+                .all(|v| v.is_finite());
+            SelfTestCase {
+                name: "fluid_default_64",
+                passed: finite && r.avg_divergence < 10.0 && (0.5..=2.0).contains(&ratio),
+                detail: format!(
+                    "avg_divergence={:.4} (< 10) dye_ratio={ratio:.4} (0.5..2.0)",
+                    r.avg_divergence
+                ),
             }
+        }
+        Err(err) => self_test_error("fluid_default_64", err),
+    });
 
-            // dye fade and re-seed source slightly
-            {
-                let mut pass = encoder.begin_compute_pass(&Default::default());
-                pass.set_pipeline(&fade_pipeline);
-                pass.set_bind_group(0, &bg_fade, &[]);
-                pass.dispatch_workgroups(wg_x, wg_y, 1);
+    // extra projection passes must tighten incompressibility
+    let single =
+        small_fluid_config(serde_json::json!({"projection_passes": 1, "backend": backend}));
+    let triple =
+        small_fluid_config(serde_json::json!({"projection_passes": 3, "backend": backend}));
+    cases.push(
+        match (run_fluid_step(&single).await, run_fluid_step(&triple).await) {
+            (Ok(a), Ok(b)) => SelfTestCase {
+                name: "projection_passes_tighten",
+                passed: b.avg_divergence < a.avg_divergence * 0.8,
+                detail: format!(
+                    "avg_divergence passes=1 {:.4} vs passes=3 {:.4} (< 0.8x)",
+                    a.avg_divergence, b.avg_divergence
+                ),
+            },
+            (Err(err), _) | (_, Err(err)) => self_test_error("projection_passes_tighten", err),
+        },
+    );
+
+    let clamped = small_fluid_config(serde_json::json!({
+        "impulse": 200.0, "max_velocity": 4.0, "backend": backend,
+    }));
+    cases.push(match run_fluid_step(&clamped).await {
+        Ok(r) => SelfTestCase {
+            name: "max_velocity_clamp",
+            passed: r.max_speed <= 4.0 * (1.0 + 1e-3) && r.velocity_clamped_cells > 0,
+            detail: format!(
+                "max_speed={:.4} (<= 4) clamped_cells={}",
+                r.max_speed, r.velocity_clamped_cells
+            ),
+        },
+        Err(err) => self_test_error("max_velocity_clamp", err),
+    });
+
+    SelfTestResponse {
+        ok: cases.iter().all(|c| c.passed),
+        backend: label,
+        cases,
+    }
+}
+
+/// 64x64, 20-step fluid config with `overrides` merged over the request defaults.
+fn small_fluid_config(overrides: serde_json::Value) -> FluidStepRequest {
+    let mut req = serde_json::json!({"width": 64, "height": 64, "steps": 20});
+    if let (Some(base), Some(extra)) = (req.as_object_mut(), overrides.as_object()) {
+        base.extend(extra.clone());
+    }
+    serde_json::from_value::<FluidStepRequest>(req)
+        .expect("self-test config matches FluidStepRequest")
+        .clamped()
+}
+
+fn self_test_error(name: &'static str, err: anyhow::Error) -> SelfTestCase {
+    SelfTestCase {
+        name,
+        passed: false,
+        detail: format!("{err:#}"),
+    }
+}
+
+/// CPU evaluation of the init kernel's dye blob, used as the conservation baseline.
+fn seeded_dye_total(width: u32, height: u32, dye_radius: f32) -> f32 {
+    let mut total = 0.0f32;
+    for y in 0..height {
+        for x in 0..width {
+            let cx = (x as f32 + 0.5) / width as f32 - 0.5;
+            let cy = (y as f32 + 0.5) / height as f32 - 0.5;
+            let r = (cx * cx + cy * cy).sqrt();
+            if r <= dye_radius {
+                total += 1.0 - r / dye_radius.max(0.01);
             }
         }
+    }
+    total
+}
 
-        queue.submit(Some(encoder.finish()));
-        remaining -= batch_steps;
+/// Storage bindings of a fluid kernel after the `Params` uniform at binding 0, in binding order.
+#[derive(Clone, Copy, PartialEq)]
+enum KernelBinding {
+    Vec2,
+    Scalar,
+    Counters,
+}
+
+struct FluidKernel {
+    name: &'static str,
+    wgsl: &'static str,
+    bindings: &'static [KernelBinding],
+}
+
+const FLUID_KERNELS: &[FluidKernel] = {
+    use KernelBinding::*;
+    &[
+        FluidKernel {
+            name: "init",
+            wgsl: FLUID_INIT_WGSL,
+            bindings: &[Vec2, Scalar, Scalar],
+        },
+        FluidKernel {
+            name: "advect_vel",
+            wgsl: FLUID_ADVECT_VEL_WGSL,
+            bindings: &[Vec2, Vec2, Counters, Vec2, Scalar, Vec2, Vec2, Scalar],
+        },
+        FluidKernel {
+            name: "predict_vel",
+            wgsl: FLUID_PREDICT_VEL_WGSL,
+            bindings: &[Vec2, Vec2],
+        },
+        FluidKernel {
+            name: "diffuse_vel",
+            wgsl: FLUID_DIFFUSE_VEL_WGSL,
+            bindings: &[Vec2, Vec2, Vec2, Vec2, Scalar],
+        },
+        FluidKernel {
+            name: "curl",
+            wgsl: FLUID_CURL_WGSL,
+            bindings: &[Vec2, Scalar, Scalar],
+        },
+        FluidKernel {
+            name: "vorticity_force",
+            wgsl: FLUID_VORTICITY_FORCE_WGSL,
+            bindings: &[Scalar, Vec2, Scalar],
+        },
+        FluidKernel {
+            name: "move_obstacles",
+            wgsl: FLUID_MOVE_OBSTACLES_WGSL,
+            bindings: &[Scalar, Scalar],
+        },
+        FluidKernel {
+            name: "divergence",
+            wgsl: FLUID_DIVERGENCE_WGSL,
+            bindings: &[Vec2, Scalar, Scalar, Scalar],
+        },
+        FluidKernel {
+            name: "jacobi",
+            wgsl: FLUID_JACOBI_WGSL,
+            bindings: &[Scalar, Scalar, Scalar, Scalar],
+        },
+        FluidKernel {
+            name: "project",
+            wgsl: FLUID_PROJECT_WGSL,
+            bindings: &[Vec2, Scalar, Vec2, Vec2, Counters, Scalar, Scalar],
+        },
+        FluidKernel {
+            name: "advect_dye",
+            wgsl: FLUID_ADVECT_DYE_WGSL,
+            bindings: &[Vec2, Scalar, Scalar, Counters, Scalar, Scalar],
+        },
+        FluidKernel {
+            name: "predict_dye",
+            wgsl: FLUID_PREDICT_DYE_WGSL,
+            bindings: &[Vec2, Scalar, Scalar],
+        },
+        FluidKernel {
+            name: "fade",
+            wgsl: FLUID_FADE_WGSL,
+            bindings: &[Scalar, Scalar, Scalar],
+        },
+        FluidKernel {
+            name: "okubo_weiss",
+            wgsl: FLUID_OKUBO_WEISS_WGSL,
+            bindings: &[Vec2, Scalar],
+        },
+        FluidKernel {
+            name: "dye_gradient",
+            wgsl: FLUID_DYE_GRADIENT_WGSL,
+            bindings: &[Scalar, Scalar],
+        },
+        FluidKernel {
+            name: "delta",
+            wgsl: FLUID_DELTA_WGSL,
+            bindings: &[Vec2, Scalar, Vec2, Scalar, Vec2],
+        },
+        FluidKernel {
+            name: "residual",
+            wgsl: FLUID_RESIDUAL_WGSL,
+            bindings: &[Scalar, Scalar, Counters, Scalar],
+        },
+        FluidKernel {
+            name: "max_speed",
+            wgsl: FLUID_MAX_SPEED_WGSL,
+            bindings: &[Vec2, Counters],
+        },
+        FluidKernel {
+            name: "dt_peaks",
+            wgsl: FLUID_DT_PEAKS_WGSL,
+            bindings: &[Vec2, Counters],
+        },
+    ]
+};
+
+/// Time `iters` downloads of a `bytes`-sized storage buffer: each one a copy into a fresh
+/// mapping of the staging buffer, a submit, the map wait and a host-side copy of the bytes.
+async fn run_readback_bench(
+    bytes: u64,
+    iters: u32,
+    backend: WgpuBackend,
+    choice: &AdapterChoice,
+) -> Result<ReadbackBenchResponse> {
+    let bytes = bytes.max(1).next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT);
+    let adapter = request_adapter(backend, choice).await?;
+    let (device, queue) = request_device(&adapter, wgpu::Features::empty()).await?;
+    let max_bytes = device.limits().max_buffer_size;
+    if bytes > max_bytes {
+        return Err(anyhow::anyhow!(
+            "readback of {bytes} bytes exceeds the device's {max_bytes}-byte buffer limit"
+        ))
+        .code(ErrorCode::GridTooLarge);
     }
 
-    {
+    let pattern: Vec<u32> = (0..(bytes / 4) as u32).collect();
+    let src = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("readback-src"),
+        size: bytes,
+        usage: wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_SRC
+            | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    queue.write_buffer(&src, 0, bytemuck::cast_slice(&pattern));
+    let staging = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("readback-staging"),
+        size: bytes,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut host = vec![0u8; bytes as usize];
+    let mut read_once = || -> Result<f64> {
+        let t0 = std::time::Instant::now();
         let mut encoder = device.create_command_encoder(&Default::default());
-        encoder.copy_buffer_to_buffer(
-            if final_vel_is_a { &vel_a } else { &vel_b },
-            0,
-            &vel_read,
-            0,
-            (cells * std::mem::size_of::<[f32; 2]>()) as u64,
-        );
-        encoder.copy_buffer_to_buffer(
-            &dye_a,
-            0,
-            &dye_read,
-            0,
-            (cells * std::mem::size_of::<f32>()) as u64,
-        );
+        encoder.copy_buffer_to_buffer(&src, 0, &staging, 0, bytes);
         queue.submit(Some(encoder.finish()));
+        let slice = staging.slice(..);
+        map_wait(&device, &slice)?;
+        host.copy_from_slice(&slice.get_mapped_range());
+        staging.unmap();
+        Ok(t0.elapsed().as_secs_f64())
+    };
+    // untimed first pass absorbs the upload and first-map costs
+    read_once()?;
+    let mut times = Vec::with_capacity(iters as usize);
+    for _ in 0..iters {
+        times.push(read_once()?);
     }
+    let verified = host == bytemuck::cast_slice::<u32, u8>(&pattern);
 
-    let vel_slice = vel_read.slice(..);
-    let dye_slice = dye_read.slice(..);
-    map_wait(&device, &vel_slice)?;
-    map_wait(&device, &dye_slice)?;
+    let mean = times.iter().sum::<f64>() / times.len() as f64;
+    let min = times.iter().copied().fold(f64::INFINITY, f64::min);
+    let gb_per_s = |secs: f64| bytes as f64 / secs.max(1e-9) / 1e9;
+    Ok(ReadbackBenchResponse {
+        ok: verified,
+        backend: backend_label(adapter.get_info().backend),
+        adapter: adapter.get_info().name,
+        bytes,
+        iters,
+        mean_ms: mean * 1000.0,
+        min_ms: min * 1000.0,
+        gb_per_s: gb_per_s(mean),
+        peak_gb_per_s: gb_per_s(min),
+        verified,
+    })
+}
 
-    let vel_mapped = vel_slice.get_mapped_range();
-    let dye_mapped = dye_slice.get_mapped_range();
-    let vel: &[[f32; 2]] = bytemuck::cast_slice(&vel_mapped);
-    let dye: &[f32] = bytemuck::cast_slice(&dye_mapped);
+/// Dispatch one fluid kernel `iters` times over dedicated buffers, timing the batch (and with
+/// in-pass timestamps each dispatch) with timestamp queries when the adapter supports them.
+async fn run_kernel_bench(
+    kernel: &str,
+    width: u32,
+    height: u32,
+    iters: u32,
+    backend: WgpuBackend,
+    choice: &AdapterChoice,
+) -> Result<KernelBenchResponse> {
+    let spec = FLUID_KERNELS
+        .iter()
+        .find(|k| k.name == kernel)
+        .with_context(|| {
+            let names: Vec<_> = FLUID_KERNELS.iter().map(|k| k.name).collect();
+            format!("unknown kernel {kernel:?} (expected one of {names:?})")
+        })?;
+    let adapter = request_adapter(backend, choice).await?;
+    let (device, queue) = request_device(
+        &adapter,
+        wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::TIMESTAMP_QUERY_INSIDE_PASSES,
+    )
+    .await?;
+    check_grid_limits(&device, width, height, 8)?;
+    let granularity = TimestampGranularity::new(device.features(), iters);
+    let queries = granularity.queries(iters);
+    let cells = (width as usize) * (height as usize);
+    let (wg_x, wg_y) = workgroups(width, height, WORKGROUP_SIDE);
 
-    let mut sum_speed = 0.0f32;
-    let mut max_speed = 0.0f32;
-    for v in vel {
-        let s = (v[0] * v[0] + v[1] * v[1]).sqrt();
-        sum_speed += s;
-        max_speed = max_speed.max(s);
-    }
+    let cfg = small_fluid_config(serde_json::json!({"width": width, "height": height}));
+    let params_buf = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("bench-params"),
+        size: std::mem::size_of::<Params>() as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    queue.write_buffer(&params_buf, 0, bytemuck::bytes_of(&fluid_params(&cfg)));
 
-    let w = width as usize;
-    let h = height as usize;
-    let mut sum_div = 0.0f32;
-    let mut max_div = 0.0f32;
-    for y in 0..h {
-        let ym = y.saturating_sub(1);
-        let yp = (y + 1).min(h - 1);
-        for x in 0..w {
-            let xm = x.saturating_sub(1);
-            let xp = (x + 1).min(w - 1);
-            let vl = vel[y * w + xm][0];
-            let vr = vel[y * w + xp][0];
-            let vb = vel[ym * w + x][1];
-            let vt = vel[yp * w + x][1];
-            let d = 0.5 * ((vr - vl) * (width as f32) + (vt - vb) * (height as f32));
-            let ad = d.abs();
-            sum_div += ad;
-            max_div = max_div.max(ad);
-        }
+    let buffers: Vec<wgpu::Buffer> = spec
+        .bindings
+        .iter()
+        .map(|b| match b {
+            KernelBinding::Vec2 => {
+                mk_storage_vec2(&device, "bench-vec2", cells, wgpu::BufferUsages::empty())
+            }
+            KernelBinding::Scalar => {
+                mk_storage_f32(&device, "bench-scalar", cells, wgpu::BufferUsages::empty())
+            }
+            KernelBinding::Counters => device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("bench-counters"),
+                size: COUNTERS_BYTES,
+                usage: wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            }),
+        })
+        .collect();
+    let pipeline = mk_pipeline(&device, spec.name, spec.wgsl);
+    let mut entries = vec![wgpu::BindGroupEntry {
+        binding: 0,
+        resource: params_buf.as_entire_binding(),
+    }];
+    entries.extend(
+        buffers
+            .iter()
+            .enumerate()
+            .map(|(i, buf)| wgpu::BindGroupEntry {
+                binding: i as u32 + 1,
+                resource: buf.as_entire_binding(),
+            }),
+    );
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("bench-bg"),
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &entries,
+    });
+
+    // seed the swirl into the first velocity/scalar inputs so advection-style kernels see
+    // realistic data, then run one untimed dispatch to absorb first-use costs.
+    let mut encoder = device.create_command_encoder(&Default::default());
+    let first = |kind| spec.bindings.iter().position(|b| *b == kind);
+    if let (Some(vi), Some(si)) = (first(KernelBinding::Vec2), first(KernelBinding::Scalar)) {
+        let init_pipeline = mk_pipeline(&device, "init", FLUID_INIT_WGSL);
+        let no_obstacles = mk_storage_f32(&device, "no-obstacles", 1, wgpu::BufferUsages::empty());
+        let init_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bench-init-bg"),
+            layout: &init_pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: buffers[vi].as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffers[si].as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: no_obstacles.as_entire_binding(),
+                },
+            ],
+        });
+        let mut pass = encoder.begin_compute_pass(&Default::default());
+        pass.set_pipeline(&init_pipeline);
+        pass.set_bind_group(0, &init_bg, &[]);
+        pass.dispatch_workgroups(wg_x, wg_y, 1);
+    }
+    {
+        let mut pass = encoder.begin_compute_pass(&Default::default());
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(wg_x, wg_y, 1);
     }
+    queue.submit(Some(encoder.finish()));
+    let _ = device.poll(wgpu::PollType::wait_indefinitely());
 
-    let mut dye_total = 0.0f32;
-    let mut nonzero = 0usize;
-    for &d in dye {
-        dye_total += d;
-        if d > 0.01 {
-            nonzero += 1;
+    let query_set = (queries > 0).then(|| {
+        device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("bench-timestamps"),
+            ty: wgpu::QueryType::Timestamp,
+            count: queries,
+        })
+    });
+    let ts_bytes = (queries.max(1) as usize * std::mem::size_of::<u64>()) as u64;
+    let ts_resolve = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("bench-ts-resolve"),
+        size: ts_bytes,
+        usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let ts_read = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("bench-ts-read"),
+        size: ts_bytes,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let t0 = std::time::Instant::now();
+    let mut encoder = device.create_command_encoder(&Default::default());
+    {
+        let in_pass = query_set
+            .as_ref()
+            .filter(|_| granularity == TimestampGranularity::Pass);
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("bench-pass"),
+            timestamp_writes: query_set
+                .as_ref()
+                .filter(|_| granularity == TimestampGranularity::Submission)
+                .map(|qs| wgpu::ComputePassTimestampWrites {
+                    query_set: qs,
+                    beginning_of_pass_write_index: Some(0),
+                    end_of_pass_write_index: Some(1),
+                }),
+        });
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        for i in 0..iters {
+            if let Some(qs) = in_pass {
+                pass.write_timestamp(qs, i);
+            }
+            pass.dispatch_workgroups(wg_x, wg_y, 1);
+        }
+        if let Some(qs) = in_pass {
+            pass.write_timestamp(qs, iters);
         }
     }
+    if let Some(qs) = &query_set {
+        encoder.resolve_query_set(qs, 0..queries, &ts_resolve, 0);
+        encoder.copy_buffer_to_buffer(&ts_resolve, 0, &ts_read, 0, ts_bytes);
+    }
+    queue.submit(Some(encoder.finish()));
+    let _ = device.poll(wgpu::PollType::wait_indefinitely());
+    let wall_ms = t0.elapsed().as_secs_f64() * 1000.0;
 
-    drop(vel_mapped);
-    drop(dye_mapped);
-    vel_read.unmap();
-    dye_read.unmap();
+    let ticks: Vec<u64> = if query_set.is_some() {
+        let slice = ts_read.slice(..);
+        map_wait(&device, &slice)?;
+        let ticks = bytemuck::pod_collect_to_vec(&slice.get_mapped_range());
+        ts_read.unmap();
+        ticks
+    } else {
+        Vec::new()
+    };
+    let period_ns = queue.get_timestamp_period() as f64;
+    let span_ms = |a: u64, b: u64| b.saturating_sub(a) as f64 * period_ns / 1e6;
+    let gpu_ms = (ticks.len() >= 2).then(|| span_ms(ticks[0], ticks[ticks.len() - 1]));
+    let dispatch_gpu_ms = (granularity == TimestampGranularity::Pass).then(|| {
+        let times: Vec<f64> = ticks.windows(2).map(|w| span_ms(w[0], w[1])).collect();
+        DispatchSpread {
+            min: times.iter().copied().fold(f64::INFINITY, f64::min),
+            mean: times.iter().sum::<f64>() / times.len() as f64,
+            max: times.iter().copied().fold(0.0, f64::max),
+        }
+    });
 
-    let elapsed = t0.elapsed().as_secs_f64();
-    Ok(FluidStepResponse {
+    let measured_ms = gpu_ms.filter(|ms| *ms > 0.0).unwrap_or(wall_ms);
+    Ok(KernelBenchResponse {
         ok: true,
-        backend: "metal/wgpu",
+        backend: backend_label(adapter.get_info().backend),
+        adapter: adapter.get_info().name,
+        kernel: spec.name,
         width,
         height,
-        steps,
-        elapsed_ms: elapsed * 1000.0,
-        sps: (steps as f64) / elapsed.max(1e-6),
-        avg_speed: sum_speed / (cells as f32),
-        max_speed,
-        avg_divergence: sum_div / (cells as f32),
-        max_divergence: max_div,
-        dye_footprint: (nonzero as f32) / (cells as f32),
-        dye_total,
+        iters,
+        wall_ms,
+        timestamp_granularity: granularity,
+        gpu_ms,
+        dispatch_gpu_ms,
+        cells_per_sec: (cells as f64) * (iters as f64) / (measured_ms.max(1e-6) / 1000.0),
     })
 }
 
 fn mk_pipeline(device: &wgpu::Device, label: &str, wgsl: &str) -> wgpu::ComputePipeline {
+    gpu_cache::pipeline(device, label, wgsl, compile_pipeline)
+}
+
+fn compile_pipeline(device: &wgpu::Device, label: &str, wgsl: &str) -> wgpu::ComputePipeline {
     let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
         label: Some(label),
         source: wgpu::ShaderSource::Wgsl(wgsl.into()),
@@ -992,42 +7270,160 @@ fn mk_pipeline(device: &wgpu::Device, label: &str, wgsl: &str) -> wgpu::ComputeP
     })
 }
 
-fn mk_storage_vec2(device: &wgpu::Device, label: &str, cells: usize) -> wgpu::Buffer {
+/// Bind `buffers` to bindings 0.. of the pipeline's (auto-derived) group 0.
+fn mk_bind_group(
+    device: &wgpu::Device,
+    label: &str,
+    pipeline: &wgpu::ComputePipeline,
+    buffers: &[&wgpu::Buffer],
+) -> wgpu::BindGroup {
+    let resources: Vec<_> = buffers.iter().map(|buf| buf.as_entire_binding()).collect();
+    mk_bind_group_with(device, label, pipeline, resources)
+}
+
+/// Like `mk_bind_group`, for groups that also hold textures or samplers.
+fn mk_bind_group_with(
+    device: &wgpu::Device,
+    label: &str,
+    pipeline: &wgpu::ComputePipeline,
+    resources: Vec<wgpu::BindingResource<'_>>,
+) -> wgpu::BindGroup {
+    let entries: Vec<wgpu::BindGroupEntry> = resources
+        .into_iter()
+        .enumerate()
+        .map(|(i, resource)| wgpu::BindGroupEntry {
+            binding: i as u32,
+            resource,
+        })
+        .collect();
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(label),
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &entries,
+    })
+}
+
+/// A storage buffer that is also `usage`: only the copies and clears it actually takes part in.
+fn mk_storage_vec2(
+    device: &wgpu::Device,
+    label: &str,
+    cells: usize,
+    usage: wgpu::BufferUsages,
+) -> wgpu::Buffer {
     device.create_buffer(&wgpu::BufferDescriptor {
         label: Some(label),
         size: (cells * std::mem::size_of::<[f32; 2]>()) as u64,
-        usage: wgpu::BufferUsages::STORAGE
-            | wgpu::BufferUsages::COPY_SRC
-            | wgpu::BufferUsages::COPY_DST,
+        usage: wgpu::BufferUsages::STORAGE | usage,
         mapped_at_creation: false,
     })
 }
 
-fn mk_storage_f32(device: &wgpu::Device, label: &str, cells: usize) -> wgpu::Buffer {
+fn mk_storage_f32(
+    device: &wgpu::Device,
+    label: &str,
+    cells: usize,
+    usage: wgpu::BufferUsages,
+) -> wgpu::Buffer {
     device.create_buffer(&wgpu::BufferDescriptor {
         label: Some(label),
         size: (cells * std::mem::size_of::<f32>()) as u64,
-        usage: wgpu::BufferUsages::STORAGE
-            | wgpu::BufferUsages::COPY_SRC
-            | wgpu::BufferUsages::COPY_DST,
+        usage: wgpu::BufferUsages::STORAGE | usage,
         mapped_at_creation: false,
     })
 }
 
+/// Longest a readback waits for the GPU to drain before failing with `gpu_timeout`.
+const GPU_WAIT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(120);
+
 fn map_wait(device: &wgpu::Device, slice: &wgpu::BufferSlice<'_>) -> Result<()> {
+    map_wait_all(device, std::slice::from_ref(slice))
+}
+
+/// `map_wait` for several slices behind one wait.
+fn map_wait_all(device: &wgpu::Device, slices: &[wgpu::BufferSlice<'_>]) -> Result<()> {
     let (tx, rx) = std::sync::mpsc::channel();
-    slice.map_async(wgpu::MapMode::Read, move |r| {
-        let _ = tx.send(r);
+    for slice in slices {
+        let tx = tx.clone();
+        slice.map_async(wgpu::MapMode::Read, move |r| {
+            let _ = tx.send(r);
+        });
+    }
+    let poll = device.poll(wgpu::PollType::Wait {
+        submission_index: None,
+        timeout: Some(GPU_WAIT_TIMEOUT),
     });
-    let _ = device.poll(wgpu::PollType::wait_indefinitely());
-    rx.recv().context("map_async channel closed")??;
-    Ok(())
+    if let Err(err) = poll {
+        let code = match err {
+            wgpu::PollError::Timeout => ErrorCode::GpuTimeout,
+            _ => {
+                gpu_cache::evict(|cached| cached.device == *device);
+                ErrorCode::DeviceError
+            }
+        };
+        return Err(err).context("waiting for the GPU").code(code);
+    }
+    check_device_error()?;
+    let mapped = slices.iter().try_for_each(|_| {
+        rx.recv()
+            .context("map_async channel closed")
+            .code(ErrorCode::DeviceError)?
+            .context("buffer map failed")
+            .code(ErrorCode::DeviceError)
+    });
+    if mapped.is_err() {
+        gpu_cache::evict(|cached| cached.device == *device);
+    }
+    mapped
+}
+
+/// The smoke kernel: add 1.0 to every element, `SMOKE_WORKGROUP` invocations per workgroup.
+const SMOKE_WGSL: &str = concat!(
+    wgsl_params!(),
+    "
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read_write> data: array<f32>;
+
+@compute @workgroup_size(SMOKE_WORKGROUP)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  let i = gid.x;
+  if (i < p.width) {
+    data[i] = data[i] + 1.0;
+  }
+}
+"
+);
+
+/// Reject a smoke `workgroup` the device can't run; returns the workgroups covering `n`.
+fn check_smoke_workgroup(device: &wgpu::Device, n: u32, workgroup: u32) -> Result<u32> {
+    let limits = device.limits();
+    let max = limits
+        .max_compute_invocations_per_workgroup
+        .min(limits.max_compute_workgroup_size_x);
+    if !(1..=max).contains(&workgroup) {
+        return Err(anyhow::anyhow!(
+            "workgroup must be 1..={max} (max_compute_invocations_per_workgroup {}, \
+             max_compute_workgroup_size_x {}), got {workgroup}",
+            limits.max_compute_invocations_per_workgroup,
+            limits.max_compute_workgroup_size_x
+        ))
+        .code(ErrorCode::InvalidRequest);
+    }
+    let groups = n.div_ceil(workgroup);
+    let max_groups = limits.max_compute_workgroups_per_dimension;
+    if groups > max_groups {
+        return Err(anyhow::anyhow!(
+            "n = {n} needs {groups} workgroups of {workgroup}; the device allows {max_groups}"
+        ))
+        .code(ErrorCode::GridTooLarge);
+    }
+    Ok(groups)
 }
 
-async fn run_smoke(n: u32) -> Result<SmokeResponse> {
+async fn run_smoke(n: u32, workgroup: u32, backend: WgpuBackend) -> Result<SmokeResponse> {
     let t0 = std::time::Instant::now();
 
-    let (device, queue) = create_device().await?;
+    let (device, queue, label) = create_device(backend).await?;
+    let groups = check_smoke_workgroup(&device, n, workgroup)?;
 
     let len = n as usize;
     let bytes = (len * std::mem::size_of::<f32>()) as wgpu::BufferAddress;
@@ -1061,49 +7457,18 @@ async fn run_smoke(n: u32) -> Result<SmokeResponse> {
         bytemuck::bytes_of(&Params {
             width: n,
             height: 1,
-            jacobi_iters: 0,
-            _pad0: 0,
-            dt: 0.0,
-            viscosity: 0.0,
-            fade: 0.0,
-            dye_radius: 0.0,
-            impulse: 0.0,
             inv_dx: n as f32,
             inv_dy: 1.0,
-            _pad3: 0.0,
+            ..Params::zeroed()
         }),
     );
 
     let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
         label: Some("smoke"),
         source: wgpu::ShaderSource::Wgsl(
-            "
-struct Params {
-  width: u32,
-  height: u32,
-  jacobi_iters: u32,
-  _pad0: u32,
-  dt: f32,
-  viscosity: f32,
-  fade: f32,
-  dye_radius: f32,
-  impulse: f32,
-  inv_dx: f32,
-  inv_dy: f32,
-  _pad3: f32,
-};
-@group(0) @binding(0) var<uniform> p: Params;
-@group(0) @binding(1) var<storage, read_write> data: array<f32>;
-
-@compute @workgroup_size(64)
-fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
-  let i = gid.x;
-  if (i < p.width) {
-    data[i] = data[i] + 1.0;
-  }
-}
-"
-            .into(),
+            SMOKE_WGSL
+                .replace("SMOKE_WORKGROUP", &workgroup.to_string())
+                .into(),
         ),
     });
 
@@ -1131,14 +7496,21 @@ fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
         ],
     });
 
+    // the upload and pipeline creation finish first, so the timed submit is the dispatch alone
+    let _ = device.poll(wgpu::PollType::wait_indefinitely());
+    let kernel_start = std::time::Instant::now();
     let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
     {
         let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
         pass.set_pipeline(&pipeline);
         pass.set_bind_group(0, &bind_group, &[]);
-        let groups = n.div_ceil(64);
         pass.dispatch_workgroups(groups, 1, 1);
     }
+    queue.submit(Some(encoder.finish()));
+    let _ = device.poll(wgpu::PollType::wait_indefinitely());
+    let kernel_ms = kernel_start.elapsed().as_secs_f64() * 1000.0;
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
     encoder.copy_buffer_to_buffer(&storage, 0, &readback, 0, bytes);
     queue.submit(Some(encoder.finish()));
 
@@ -1163,384 +7535,2487 @@ fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
     drop(mapped);
     readback.unmap();
 
-    let ok = mismatch_count == 0 && max_abs_error <= 1e-5;
+    let ok = mismatch_count == 0 && max_abs_error <= 1e-5;
+
+    Ok(SmokeResponse {
+        ok,
+        backend: label,
+        n,
+        workgroup,
+        elapsed_ms: t0.elapsed().as_secs_f64() * 1000.0,
+        kernel_ms,
+        elements_per_sec: n as f64 / (kernel_ms.max(1e-6) / 1000.0),
+        sample,
+        mismatch_count,
+        max_abs_error,
+    })
+}
+
+/// `Dye`, one cell of the dye buffers, for `channels: 1`: `tint` turns an injected amount
+/// into dye and `luminance` turns dye back into the scalar the `dye_budget` terms sum.
+/// `dye_wgsl` swaps it for `DYE_RGB_WGSL`.
+macro_rules! wgsl_dye {
+    () => {
+        r#"
+alias Dye = f32;
+fn tint(a: f32) -> Dye { return a; }
+fn luminance(d: Dye) -> f32 { return d; }
+"#
+    };
+}
+
+/// `wgsl_dye!` for `channels: 3`: `inject_color` tints, weighted back by `DYE_LUMINANCE`.
+const DYE_RGB_WGSL: &str = r#"
+alias Dye = vec3<f32>;
+fn tint(a: f32) -> Dye { return a * vec3<f32>(p.inject_r, p.inject_g, p.inject_b); }
+fn luminance(d: Dye) -> f32 { return dot(d, vec3<f32>(0.2126, 0.7152, 0.0722)); }
+"#;
+
+/// `wgsl`, a kernel built on `wgsl_dye!`, for `channels` dye channels.
+fn dye_wgsl(wgsl: &str, channels: u32) -> std::borrow::Cow<'_, str> {
+    if channels == 3 {
+        wgsl.replace(wgsl_dye!(), DYE_RGB_WGSL).into()
+    } else {
+        wgsl.into()
+    }
+}
+
+/// `solid(i)`: whether cell `i` is an `obstacles` wall, `solid_vel`, the velocity a wall cell
+/// holds (its `obstacle_velocity`, zero for static walls), and `wall_p`, the pressure a fluid
+/// cell sees across a solid neighbor: its own, so the wall holds no gradient (dp/dn = 0).
+/// Kernels using them bind `obstacles: array<u32>`, laid out as `obstacle_table` (a single
+/// element without a mask).
+macro_rules! wgsl_solid {
+    () => {
+        r#"
+fn solid(i: u32) -> bool {
+  return arrayLength(&obstacles) >= p.width * p.height && obstacles[i] != 0u;
+}
+fn solid_vel(i: u32) -> vec2<f32> {
+  if (!solid(i)) { return vec2<f32>(0.0, 0.0); }
+  let at = p.width * p.height + 2u * (obstacles[i] - 1u);
+  if (arrayLength(&obstacles) < at + 2u) { return vec2<f32>(0.0, 0.0); }
+  return vec2<f32>(bitcast<f32>(obstacles[at]), bitcast<f32>(obstacles[at + 1u]));
+}
+fn wall_p(i: u32, v: f32, center: f32) -> f32 { return select(v, center, solid(i)); }
+"#
+    };
+}
+
+const FLUID_INIT_WGSL: &str = concat!(
+    wgsl_params!(),
+    wgsl_dye!(),
+    wgsl_solid!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read_write> vel: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read_write> dye: array<Dye>;
+@group(0) @binding(3) var<storage, read> obstacles: array<u32>;
+
+fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
+
+@compute @workgroup_size(WG, WG, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  if (gid.x >= p.width || gid.y >= p.height) { return; }
+  let uv = (vec2<f32>(f32(gid.x), f32(gid.y)) + vec2<f32>(0.5, 0.5)) / vec2<f32>(f32(p.width), f32(p.height));
+  let c = uv - vec2<f32>(0.5, 0.5);
+  let r = length(c);
+  let id = idx(gid.x, gid.y);
+  if (p.init_layer != 0u) {
+    // an `init_layers` entry: add to the seed instead of replacing it
+    let lc = uv - vec2<f32>(p.layer_center_x, p.layer_center_y);
+    let lr = length(lc);
+    vel[id] += vec2<f32>(-lc.y, lc.x) * p.layer_impulse * exp(-p.layer_tightness * lr * lr);
+    dye[id] += tint(select(0.0, 1.0 - lr / max(p.layer_radius, 0.01), lr <= p.layer_radius));
+  } else {
+    if (p.init_kind == 1u) {
+      let k = 6.283185307179586 * f32(p.init_mode);
+      vel[id] = vec2<f32>(sin(k * uv.x) * cos(k * uv.y), -cos(k * uv.x) * sin(k * uv.y));
+    } else {
+      vel[id] = vec2<f32>(-c.y, c.x) * p.impulse * exp(-30.0 * r * r);
+    }
+    switch p.init_dye {
+      case 1u: { dye[id] = tint(1.0); }
+      case 2u: { dye[id] = tint(uv.x); }
+      case 3u: {
+        let tile = vec2<u32>(floor(uv * 8.0));
+        dye[id] = tint(f32((tile.x + tile.y) % 2u));
+      }
+      default: { dye[id] = tint(select(0.0, 1.0 - r / max(p.dye_radius, 0.01), r <= p.dye_radius)); }
+    }
+  }
+  // solid cells start at the wall velocity every step holds them at
+  if (solid(id)) { vel[id] = solid_vel(id); }
+}
+"#
+);
+
+/// `edge_vel(x, y)`: the velocity an edge-ring cell is held at, its `ghost_b64` entry or the
+/// no-slip 0. Kernels using it bind `ghost: array<vec2<f32>>`.
+macro_rules! wgsl_edge_vel {
+    () => {
+        r#"
+fn edge_vel(x: u32, y: u32) -> vec2<f32> {
+  let ring = 2u * (p.width + p.height) - 4u;
+  if (arrayLength(&ghost) != ring) { return vec2<f32>(0.0, 0.0); }
+  // row-major ring order: bottom row, a left/right pair per inner row, top row
+  var i = x;
+  if (y == p.height - 1u) {
+    i = ring - p.width + x;
+  } else if (y > 0u) {
+    i = p.width + 2u * (y - 1u) + select(0u, 1u, x > 0u);
+  }
+  return ghost[i];
+}
+"#
+    };
+}
+
+/// `backtrace(pos, v, dt)`: where the fluid at cell `pos` (velocity `v`) came from `dt` ago,
+/// per `Params.advect_integrator` (a negative `dt` traces forward). The extra RK stages read
+/// the velocity through the kernel's `vel_at` with a plain bilinear filter, so
+/// `clamp_to_neighbors` only ever counts the final sample.
+macro_rules! wgsl_backtrace {
+    () => {
+        r#"
+// The bilinear taps around `pos`: the lower-left cell `i`, the offsets of the cells to its
+// right and above, and the weights.
+struct Footprint { i: u32, dx: u32, dy: u32, f: vec2<f32> };
+fn footprint(pos: vec2<f32>) -> Footprint {
+  let x = clamp(pos.x, 0.0, max(f32(p.width) - 1.001, 0.0));
+  let y = clamp(pos.y, 0.0, max(f32(p.height) - 1.001, 0.0));
+  let x0 = u32(floor(x));
+  let y0 = u32(floor(y));
+  // the far taps fall back onto the near ones along a 1-cell axis (where fract is 0)
+  let dx = min(x0 + 1u, p.width - 1u) - x0;
+  let dy = (min(y0 + 1u, p.height - 1u) - y0) * p.width;
+  return Footprint(y0 * p.width + x0, dx, dy, vec2<f32>(fract(x), fract(y)));
+}
+fn trace_vel(pos: vec2<f32>) -> vec2<f32> {
+  let fp = footprint(pos);
+  let a = vel_at(fp.i);
+  let b = vel_at(fp.i + fp.dx);
+  let c = vel_at(fp.i + fp.dy);
+  let d = vel_at(fp.i + fp.dy + fp.dx);
+  return mix(mix(a, b, fp.f.x), mix(c, d, fp.f.x), fp.f.y);
+}
+fn backtrace(pos: vec2<f32>, v: vec2<f32>, dt: f32) -> vec2<f32> {
+  switch p.advect_integrator {
+    case 1u: {
+      return pos - dt * trace_vel(pos - 0.5 * dt * v);
+    }
+    case 2u: {
+      let k2 = trace_vel(pos - 0.5 * dt * v);
+      let k3 = trace_vel(pos - 0.5 * dt * k2);
+      let k4 = trace_vel(pos - dt * k3);
+      return pos - (dt / 6.0) * (v + 2.0 * k2 + 2.0 * k3 + k4);
+    }
+    default: {
+      return pos - dt * v;
+    }
+  }
+}
+"#
+    };
+}
+
+/// Shared body of the buffer- and texture-sampling velocity advection kernels (each defines
+/// `sample_vel`).
+macro_rules! wgsl_advect_vel_main {
+    () => {
+        concat!(
+            r#"
+fn vel_at(i: u32) -> vec2<f32> { return src[i]; }
+
+// `ForceKey`; `force_path` holds `p.force_keys` of them (a placeholder element when 0).
+struct ForceKey { step: u32, x: f32, y: f32 };
+
+// Unit-domain forcing center this step: `source_center`, or along `force_path`.
+fn force_center() -> vec2<f32> {
+  if (p.force_keys == 0u) { return vec2<f32>(p.source_center_x, p.source_center_y); }
+  let first = force_path[0];
+  let last = force_path[p.force_keys - 1u];
+  if (p.step_index <= first.step) { return vec2<f32>(first.x, first.y); }
+  if (p.step_index >= last.step) { return vec2<f32>(last.x, last.y); }
+  var a = first;
+  for (var i = 1u; i < p.force_keys; i++) {
+    let b = force_path[i];
+    if (p.step_index < b.step) {
+      let t = f32(p.step_index - a.step) / f32(b.step - a.step);
+      return mix(vec2<f32>(a.x, a.y), vec2<f32>(b.x, b.y), t);
+    }
+    a = b;
+  }
+  return vec2<f32>(last.x, last.y);
+}
+"#,
+            wgsl_backtrace!(),
+            r#"
+// `advection: "maccormack"`: corrects the semi-Lagrangian sample `v_hat` at `back` by half
+// of what tracing the predictor's field `pred` forward from the cell gets wrong, clamped to
+// the source cells around `back` so the correction cannot overshoot.
+fn maccormack_vel(id: u32, pos: vec2<f32>, v: vec2<f32>, dt: f32, back: vec2<f32>,
+                  v_hat: vec2<f32>) -> vec2<f32> {
+  let fwd = footprint(backtrace(pos, v, -dt));
+  let v_bar = mix(mix(pred[fwd.i], pred[fwd.i + fwd.dx], fwd.f.x),
+                  mix(pred[fwd.i + fwd.dy], pred[fwd.i + fwd.dy + fwd.dx], fwd.f.x), fwd.f.y);
+  let fp = footprint(back);
+  let a = src[fp.i];
+  let b = src[fp.i + fp.dx];
+  let c = src[fp.i + fp.dy];
+  let d = src[fp.i + fp.dy + fp.dx];
+  return clamp(v_hat + 0.5 * (src[id] - v_bar), min(min(a, b), min(c, d)), max(max(a, b), max(c, d)));
+}
+
+@compute @workgroup_size(WG, WG, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  if (gid.x >= p.width || gid.y >= p.height) { return; }
+  let id = idx(gid.x, gid.y);
+  let edge = gid.x == 0u || gid.y == 0u || gid.x == (p.width - 1u) || gid.y == (p.height - 1u);
+  if (edge) {
+    dst[id] = edge_vel(gid.x, gid.y);
+    return;
+  }
+  if (solid(id)) {
+    dst[id] = solid_vel(id);
+    return;
+  }
+
+  let pos = vec2<f32>(f32(gid.x), f32(gid.y));
+  let v = src[id];
+  let max_travel = 1.25;
+  let dt_eff = p.dt * min(1.0, max_travel / (length(v) * p.dt + 1e-5));
+  let back = backtrace(pos, v, dt_eff);
+  let domain_max = vec2<f32>(f32(p.width) - 1.0, f32(p.height) - 1.0);
+  if (any(back != clamp(back, vec2<f32>(0.0, 0.0), domain_max))) {
+    atomicAdd(&counters[2], 1u);
+  }
+
+  // Semi-Lagrangian (or MacCormack) advection + simple viscosity damping.
+  let visc_decay = 1.0 / (1.0 + 4.0 * p.viscosity * dt_eff);
+  var v_hat = sample_vel(back);
+  if (p.advection == 1u) { v_hat = maccormack_vel(id, pos, v, dt_eff, back, v_hat); }
+  var v_next = v_hat * visc_decay;
+  let center = force_center() * vec2<f32>(f32(p.width), f32(p.height));
+  let rel = pos - center;
+  let r = length(rel) / max(f32(min(p.width, p.height)), 1.0);
+  var power = 0.0;
+  if (r <= p.dye_radius) {
+    let tangential = normalize(vec2<f32>(-rel.y, rel.x) + vec2<f32>(1e-4, 0.0));
+    let falloff = 1.0 - r / max(p.dye_radius, 1e-3);
+    // linear startup ramp: step k applies (k + 1) / impulse_ramp_steps of the full impulse
+    var ramp = 1.0;
+    if (p.impulse_ramp_steps > 0u) {
+      ramp = min(f32(p.step_index + 1u) / f32(p.impulse_ramp_steps), 1.0);
+    }
+    let kick = tangential * (p.impulse * ramp * dt_eff * falloff);
+    // f . v at the kick's midpoint, so f . v dt is exactly the |v|^2 / 2 it adds
+    power = dot(tangential * (p.impulse * ramp * falloff), v_next + 0.5 * kick);
+    v_next = v_next + kick;
+  }
+  if (arrayLength(&forcing) == p.width * p.height) {
+    forcing[id] = vec2<f32>(power, forcing[id].y + power * dt_eff);
+  }
+
+  dst[id] = v_next;
+}
+"#
+        )
+    };
+}
+
+const FLUID_ADVECT_VEL_WGSL: &str = concat!(
+    wgsl_params!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read> src: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read_write> dst: array<vec2<f32>>;
+@group(0) @binding(3) var<storage, read_write> counters: array<atomic<u32>, 4>;
+@group(0) @binding(4) var<storage, read> ghost: array<vec2<f32>>;
+@group(0) @binding(5) var<storage, read> force_path: array<ForceKey>;
+// `forcing_budget` (power, work) per cell; a single element when off
+@group(0) @binding(6) var<storage, read_write> forcing: array<vec2<f32>>;
+// `FLUID_PREDICT_VEL_WGSL`'s output; a single element unless `advection` is MacCormack
+@group(0) @binding(7) var<storage, read> pred: array<vec2<f32>>;
+@group(0) @binding(8) var<storage, read> obstacles: array<u32>;
+
+fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
+fn clamp_xy(x: i32, y: i32) -> vec2<u32> {
+  let cx = u32(clamp(x, 0, i32(p.width) - 1));
+  let cy = u32(clamp(y, 0, i32(p.height) - 1));
+  return vec2<u32>(cx, cy);
+}
+fn sample_vel(pos: vec2<f32>) -> vec2<f32> {
+  let x = clamp(pos.x, 0.0, max(f32(p.width) - 1.001, 0.0));
+  let y = clamp(pos.y, 0.0, max(f32(p.height) - 1.001, 0.0));
+  let x0 = i32(floor(x));
+  let y0 = i32(floor(y));
+  let x1 = x0 + 1;
+  let y1 = y0 + 1;
+  let fx = fract(x);
+  let fy = fract(y);
+  let a = src[idx(clamp_xy(x0, y0).x, clamp_xy(x0, y0).y)];
+  let b = src[idx(clamp_xy(x1, y0).x, clamp_xy(x1, y0).y)];
+  let c = src[idx(clamp_xy(x0, y1).x, clamp_xy(x0, y1).y)];
+  let d = src[idx(clamp_xy(x1, y1).x, clamp_xy(x1, y1).y)];
+  let v = mix(mix(a, b, fx), mix(c, d, fx), fy);
+  if (p.clamp_to_neighbors != 0u) {
+    let vc = clamp(v, min(min(a, b), min(c, d)), max(max(a, b), max(c, d)));
+    if (any(vc != v)) { atomicAdd(&counters[0], 1u); }
+    return vc;
+  }
+  return v;
+}
+"#,
+    wgsl_edge_vel!(),
+    wgsl_solid!(),
+    wgsl_advect_vel_main!()
+);
+
+const FLUID_DIFFUSE_VEL_WGSL: &str = concat!(
+    wgsl_params!(),
+    wgsl_edge_vel!(),
+    wgsl_solid!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read> src: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read_write> dst: array<vec2<f32>>;
+@group(0) @binding(3) var<storage, read> ghost: array<vec2<f32>>;
+// the advected velocity, the right-hand side every iteration is held to
+@group(0) @binding(4) var<storage, read> rhs: array<vec2<f32>>;
+@group(0) @binding(5) var<storage, read> obstacles: array<u32>;
+
+fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
+fn c(x: i32, maxv: u32) -> u32 { return u32(clamp(x, 0, i32(maxv) - 1)); }
+// a solid neighbor is a no-slip wall moving with its obstacle
+fn wall_vel(i: u32) -> vec2<f32> { return select(src[i], solid_vel(i), solid(i)); }
+
+@compute @workgroup_size(WG, WG, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  if (gid.x >= p.width || gid.y >= p.height) { return; }
+  let id = idx(gid.x, gid.y);
+  let edge = gid.x == 0u || gid.y == 0u || gid.x == (p.width - 1u) || gid.y == (p.height - 1u);
+  if (edge) {
+    dst[id] = edge_vel(gid.x, gid.y);
+    return;
+  }
+  if (solid(id)) {
+    dst[id] = solid_vel(id);
+    return;
+  }
+
+  let x = i32(gid.x);
+  let y = i32(gid.y);
+  let center = rhs[id];
+  let vl = wall_vel(idx(c(x - 1, p.width), c(y, p.height)));
+  let vr = wall_vel(idx(c(x + 1, p.width), c(y, p.height)));
+  let vb = wall_vel(idx(c(x, p.width), c(y - 1, p.height)));
+  let vt = wall_vel(idx(c(x, p.width), c(y + 1, p.height)));
+
+  let a = max(p.viscosity * p.dt, 0.0);
+  dst[id] = (center + a * (vl + vr + vb + vt)) / (1.0 + 4.0 * a);
+}
+"#
+);
+
+const FLUID_CURL_WGSL: &str = concat!(
+    wgsl_params!(),
+    wgsl_solid!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read> vel: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read_write> curl: array<f32>;
+@group(0) @binding(3) var<storage, read> obstacles: array<u32>;
+
+fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
+fn wall_vel(i: u32) -> vec2<f32> { return select(vel[i], solid_vel(i), solid(i)); }
+
+// Vorticity dvy/dx - dvx/dy in cell units (central differences), 0 on the edge ring and in
+// solid cells, whose neighbors read their wall velocity.
+@compute @workgroup_size(WG, WG, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  if (gid.x >= p.width || gid.y >= p.height) { return; }
+  let id = idx(gid.x, gid.y);
+  let edge = gid.x == 0u || gid.y == 0u || gid.x == (p.width - 1u) || gid.y == (p.height - 1u);
+  if (edge || solid(id)) {
+    curl[id] = 0.0;
+    return;
+  }
+
+  let vl = wall_vel(idx(gid.x - 1u, gid.y));
+  let vr = wall_vel(idx(gid.x + 1u, gid.y));
+  let vb = wall_vel(idx(gid.x, gid.y - 1u));
+  let vt = wall_vel(idx(gid.x, gid.y + 1u));
+  curl[id] = 0.5 * ((vr.y - vl.y) - (vt.x - vb.x));
+}
+"#
+);
+
+const FLUID_VORTICITY_FORCE_WGSL: &str = concat!(
+    wgsl_params!(),
+    wgsl_solid!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read> curl: array<f32>;
+@group(0) @binding(2) var<storage, read_write> vel: array<vec2<f32>>;
+@group(0) @binding(3) var<storage, read> obstacles: array<u32>;
+
+fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
+
+// Vorticity confinement: v += dt * vorticity * (N x curl), N the unit gradient of |curl|, so
+// the force turns with the local rotation around its peaks. Edge and solid cells keep their
+// boundary velocity; each cell only writes its own velocity, so the update can run in place.
+@compute @workgroup_size(WG, WG, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  if (gid.x >= p.width || gid.y >= p.height) { return; }
+  let edge = gid.x == 0u || gid.y == 0u || gid.x == (p.width - 1u) || gid.y == (p.height - 1u);
+  let id = idx(gid.x, gid.y);
+  if (edge || solid(id)) { return; }
+
+  let grad = 0.5 * vec2<f32>(
+    abs(curl[idx(gid.x + 1u, gid.y)]) - abs(curl[idx(gid.x - 1u, gid.y)]),
+    abs(curl[idx(gid.x, gid.y + 1u)]) - abs(curl[idx(gid.x, gid.y - 1u)]),
+  );
+  let n = grad / (length(grad) + 1e-5);
+  let w = curl[id];
+  vel[id] = vel[id] + (p.dt * p.vorticity) * vec2<f32>(n.y * w, -n.x * w);
+}
+"#
+);
+
+const FLUID_MOVE_OBSTACLES_WGSL: &str = concat!(
+    wgsl_params!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+// `obstacle_labels` of the request's mask: where every obstacle starts
+@group(0) @binding(1) var<storage, read> start: array<u32>;
+// `obstacle_table`: the cell labels rewritten here, then the velocities as f32 bits
+@group(0) @binding(2) var<storage, read_write> obstacles: array<u32>;
+
+// Re-rasterize the moving obstacles at start + velocity * step * dt, rounded to whole cells: a
+// cell takes the first obstacle whose shifted start covers it, and is open otherwise.
+@compute @workgroup_size(WG, WG, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  if (gid.x >= p.width || gid.y >= p.height) { return; }
+  let cells = p.width * p.height;
+  let count = select(0u, (arrayLength(&obstacles) - cells) / 2u, arrayLength(&obstacles) > cells);
+  let size = vec2<i32>(i32(p.width), i32(p.height));
+  var label = 0u;
+  for (var k = 0u; k < count; k++) {
+    let v = vec2<f32>(bitcast<f32>(obstacles[cells + 2u * k]), bitcast<f32>(obstacles[cells + 2u * k + 1u]));
+    let shift = clamp(floor(v * f32(p.step_index) * p.dt + 0.5), vec2<f32>(-1e6), vec2<f32>(1e6));
+    let origin = vec2<i32>(gid.xy) - vec2<i32>(shift);
+    if (all(origin >= vec2<i32>(0)) && all(origin < size) && start[u32(origin.y) * p.width + u32(origin.x)] == k + 1u) {
+      label = k + 1u;
+      break;
+    }
+  }
+  obstacles[gid.y * p.width + gid.x] = label;
+}
+"#
+);
+
+const FLUID_DIVERGENCE_WGSL: &str = concat!(
+    wgsl_params!(),
+    wgsl_solid!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read> vel: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read_write> div: array<f32>;
+// `div_sources` target; a single element when there are none
+@group(0) @binding(3) var<storage, read> div_target: array<f32>;
+@group(0) @binding(4) var<storage, read> obstacles: array<u32>;
+
+fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
+fn c(x: i32, maxv: u32) -> u32 { return u32(clamp(x, 0, i32(maxv) - 1)); }
+// a solid neighbor is a wall: its own velocity is the no-penetration flux
+fn wall_vel(i: u32) -> vec2<f32> { return select(vel[i], solid_vel(i), solid(i)); }
+
+@compute @workgroup_size(WG, WG, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  if (gid.x >= p.width || gid.y >= p.height) { return; }
+
+  let edge = gid.x == 0u || gid.y == 0u || gid.x == (p.width - 1u) || gid.y == (p.height - 1u);
+  if (edge || solid(idx(gid.x, gid.y))) {
+    div[idx(gid.x, gid.y)] = 0.0;
+    return;
+  }
+
+  let x = i32(gid.x);
+  let y = i32(gid.y);
+  let vl = wall_vel(idx(c(x - 1, p.width), c(y, p.height))).x;
+  let vr = wall_vel(idx(c(x + 1, p.width), c(y, p.height))).x;
+  let vb = wall_vel(idx(c(x, p.width), c(y - 1, p.height))).y;
+  let vt = wall_vel(idx(c(x, p.width), c(y + 1, p.height))).y;
+  var d = 0.5 * ((vr - vl) * p.inv_dx + (vt - vb) * p.inv_dy);
+  if (arrayLength(&div_target) == p.width * p.height) { d -= div_target[idx(gid.x, gid.y)]; }
+  div[idx(gid.x, gid.y)] = d;
+}
+"#
+);
+
+const FLUID_JACOBI_WGSL: &str = concat!(
+    wgsl_params!(),
+    wgsl_solid!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read> p_in: array<f32>;
+@group(0) @binding(2) var<storage, read> div: array<f32>;
+@group(0) @binding(3) var<storage, read_write> p_out: array<f32>;
+@group(0) @binding(4) var<storage, read> obstacles: array<u32>;
+
+fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
+fn c(x: i32, maxv: u32) -> u32 { return u32(clamp(x, 0, i32(maxv) - 1)); }
+fn p_at(i: u32, center: f32) -> f32 { return wall_p(i, p_in[i], center); }
+
+@compute @workgroup_size(WG, WG, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  if (gid.x >= p.width || gid.y >= p.height) { return; }
+
+  let edge = gid.x == 0u || gid.y == 0u || gid.x == (p.width - 1u) || gid.y == (p.height - 1u);
+  if (edge || solid(idx(gid.x, gid.y))) {
+    p_out[idx(gid.x, gid.y)] = 0.0;
+    return;
+  }
+
+  let x = i32(gid.x);
+  let y = i32(gid.y);
+  let pc = p_in[idx(gid.x, gid.y)];
+  let pl = p_at(idx(c(x - 1, p.width), c(y, p.height)), pc);
+  let pr = p_at(idx(c(x + 1, p.width), c(y, p.height)), pc);
+  let pb = p_at(idx(c(x, p.width), c(y - 1, p.height)), pc);
+  let pt = p_at(idx(c(x, p.width), c(y + 1, p.height)), pc);
+  let d = div[idx(gid.x, gid.y)];
+  let idx2 = p.inv_dx * p.inv_dx;
+  let idy2 = p.inv_dy * p.inv_dy;
+  let denom = 2.0 * (idx2 + idy2);
+  p_out[idx(gid.x, gid.y)] = ((pl + pr) * idx2 + (pb + pt) * idy2 - d) / max(denom, 1e-6);
+}
+"#
+);
+
+/// One half of a red-black Gauss-Seidel iteration over the interior, colored by `(x + y) % 2`
+/// from the grid origin so the checkerboard is the same on every grid parity. Phase 0 updates
+/// the red cells from `p_in`, carries the black ones over and zeroes the edge ring, so `p_out`
+/// is complete; phase 1 then updates the black cells in place from their (fresh) red
+/// neighbors. Both halves write only `p_out`, leaving the iteration's buffer ping-pong the same
+/// as Jacobi's. Solid cells are zeroed with the ring.
+const FLUID_RED_BLACK_WGSL: &str = concat!(
+    wgsl_params!(),
+    wgsl_solid!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read> p_in: array<f32>;
+@group(0) @binding(2) var<storage, read> div: array<f32>;
+@group(0) @binding(3) var<storage, read_write> p_out: array<f32>;
+@group(0) @binding(4) var<storage, read> obstacles: array<u32>;
+
+fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
+
+@compute @workgroup_size(WG, WG, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  if (gid.x >= p.width || gid.y >= p.height) { return; }
+  let i = idx(gid.x, gid.y);
+  let edge = gid.x == 0u || gid.y == 0u || gid.x == (p.width - 1u) || gid.y == (p.height - 1u)
+    || solid(i);
+  let red = ((gid.x + gid.y) & 1u) == 0u;
 
-    Ok(SmokeResponse {
-        ok,
-        backend: "metal/wgpu",
-        n,
-        elapsed_ms: t0.elapsed().as_secs_f64() * 1000.0,
-        sample,
-        mismatch_count,
-        max_abs_error,
-    })
+  var pl: f32;
+  var pr: f32;
+  var pb: f32;
+  var pt: f32;
+  if (p.rb_phase == 0u) {
+    if (edge) {
+      p_out[i] = 0.0;
+      return;
+    }
+    if (!red) {
+      p_out[i] = p_in[i];
+      return;
+    }
+    pl = wall_p(i - 1u, p_in[i - 1u], p_in[i]);
+    pr = wall_p(i + 1u, p_in[i + 1u], p_in[i]);
+    pb = wall_p(i - p.width, p_in[i - p.width], p_in[i]);
+    pt = wall_p(i + p.width, p_in[i + p.width], p_in[i]);
+  } else {
+    if (edge || red) { return; }
+    pl = wall_p(i - 1u, p_out[i - 1u], p_out[i]);
+    pr = wall_p(i + 1u, p_out[i + 1u], p_out[i]);
+    pb = wall_p(i - p.width, p_out[i - p.width], p_out[i]);
+    pt = wall_p(i + p.width, p_out[i + p.width], p_out[i]);
+  }
+  let idx2 = p.inv_dx * p.inv_dx;
+  let idy2 = p.inv_dy * p.inv_dy;
+  let denom = 2.0 * (idx2 + idy2);
+  p_out[i] = ((pl + pr) * idx2 + (pb + pt) * idy2 - div[i]) / max(denom, 1e-6);
 }
+"#
+);
 
-const FLUID_INIT_WGSL: &str = r#"
-struct Params {
-  width: u32,
-  height: u32,
-  jacobi_iters: u32,
-  _pad0: u32,
-  dt: f32,
-  viscosity: f32,
-  fade: f32,
-  dye_radius: f32,
-  impulse: f32,
-  inv_dx: f32,
-  inv_dy: f32,
-  _pad3: f32,
-};
+const FLUID_PROJECT_WGSL: &str = concat!(
+    wgsl_params!(),
+    wgsl_edge_vel!(),
+    wgsl_solid!(),
+    r#"
 @group(0) @binding(0) var<uniform> p: Params;
-@group(0) @binding(1) var<storage, read_write> vel: array<vec2<f32>>;
-@group(0) @binding(2) var<storage, read_write> dye: array<f32>;
+@group(0) @binding(1) var<storage, read> vel: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read> pressure: array<f32>;
+@group(0) @binding(3) var<storage, read_write> out_vel: array<vec2<f32>>;
+@group(0) @binding(4) var<storage, read> ghost: array<vec2<f32>>;
+@group(0) @binding(5) var<storage, read_write> counters: array<atomic<u32>, 4>;
+// `target_divergence` gate (see FLUID_DIVERGENCE_GATE_WGSL); a single element when off
+@group(0) @binding(6) var<storage, read> gate: array<u32>;
+@group(0) @binding(7) var<storage, read> obstacles: array<u32>;
 
 fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
+fn c(x: i32, maxv: u32) -> u32 { return u32(clamp(x, 0, i32(maxv) - 1)); }
+fn p_at(i: u32, center: f32) -> f32 { return wall_p(i, pressure[i], center); }
 
-@compute @workgroup_size(8, 8, 1)
+@compute @workgroup_size(WG, WG, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  if (gid.x >= p.width || gid.y >= p.height) { return; }
+  // a pass the gate closed solved nothing: carry the velocity over as it is
+  if (arrayLength(&gate) > 3u && gate[3] != 0u) {
+    out_vel[idx(gid.x, gid.y)] = vel[idx(gid.x, gid.y)];
+    return;
+  }
+  let edge = gid.x == 0u || gid.y == 0u || gid.x == (p.width - 1u) || gid.y == (p.height - 1u);
+  if (!edge && solid(idx(gid.x, gid.y))) {
+    out_vel[idx(gid.x, gid.y)] = solid_vel(idx(gid.x, gid.y));
+    return;
+  }
+  let x = i32(gid.x);
+  let y = i32(gid.y);
+  let pc = pressure[idx(gid.x, gid.y)];
+  let pl = p_at(idx(c(x - 1, p.width), c(y, p.height)), pc);
+  let pr = p_at(idx(c(x + 1, p.width), c(y, p.height)), pc);
+  let pb = p_at(idx(c(x, p.width), c(y - 1, p.height)), pc);
+  let pt = p_at(idx(c(x, p.width), c(y + 1, p.height)), pc);
+  let grad = vec2<f32>((pr - pl) * 0.5 * p.inv_dx, (pt - pb) * 0.5 * p.inv_dy);
+
+  var v = vel[idx(gid.x, gid.y)] - grad;
+  // optional magnitude cap so runaway forcing saturates instead of blowing up to Inf/NaN
+  let speed = length(v);
+  if (p.max_velocity > 0.0 && speed > p.max_velocity) {
+    v = v * (p.max_velocity / speed);
+    if (!edge) { atomicAdd(&counters[2], 1u); }
+  }
+  // `cfl_clamp`: the next backtrace moves at most `cfl_max` cells
+  if (p.cfl_max > 0.0) {
+    let cap = p.cfl_max / p.dt;
+    let s = length(v);
+    if (s > cap) {
+      v = v * (cap / s);
+      if (!edge) { atomicAdd(&counters[3], 1u); }
+    }
+  }
+
+  out_vel[idx(gid.x, gid.y)] = select(v, edge_vel(gid.x, gid.y), edge);
+}
+"#
+);
+
+/// Shared body of the buffer- and texture-sampling dye advection kernels (each defines
+/// `sample_dye`).
+macro_rules! wgsl_advect_dye_main {
+    () => {
+        concat!(
+            r#"
+fn vel_at(i: u32) -> vec2<f32> { return vel[i]; }
+"#,
+            wgsl_backtrace!(),
+            r#"
+// The dye counterpart of `maccormack_vel`, over `FLUID_PREDICT_DYE_WGSL`'s `pred`.
+fn maccormack_dye(id: u32, pos: vec2<f32>, v: vec2<f32>, dt: f32, back: vec2<f32>,
+                  d_hat: Dye) -> Dye {
+  let fwd = footprint(backtrace(pos, v, -dt));
+  let d_bar = mix(mix(pred[fwd.i], pred[fwd.i + fwd.dx], fwd.f.x),
+                  mix(pred[fwd.i + fwd.dy], pred[fwd.i + fwd.dy + fwd.dx], fwd.f.x), fwd.f.y);
+  let fp = footprint(back);
+  let a = dye_src[fp.i];
+  let b = dye_src[fp.i + fp.dx];
+  let c = dye_src[fp.i + fp.dy];
+  let d = dye_src[fp.i + fp.dy + fp.dx];
+  return clamp(d_hat + 0.5 * (dye_src[id] - d_bar), min(min(a, b), min(c, d)), max(max(a, b), max(c, d)));
+}
+
+@compute @workgroup_size(WG, WG, 1)
 fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
   if (gid.x >= p.width || gid.y >= p.height) { return; }
-  let uv = (vec2<f32>(f32(gid.x), f32(gid.y)) + vec2<f32>(0.5, 0.5)) / vec2<f32>(f32(p.width), f32(p.height));
-  let c = uv - vec2<f32>(0.5, 0.5);
-  let r = length(c);
   let id = idx(gid.x, gid.y);
-  let swirl = vec2<f32>(-c.y, c.x) * p.impulse * exp(-30.0 * r * r);
-  vel[id] = swirl;
-  dye[id] = select(0.0, 1.0 - r / max(p.dye_radius, 0.01), r <= p.dye_radius);
+  let pos = vec2<f32>(f32(gid.x), f32(gid.y));
+  let v = vel[id];
+  let max_travel = 1.25;
+  let dt_eff = p.dt * min(1.0, max_travel / (length(v) * p.dt + 1e-5));
+  let back = backtrace(pos, v, dt_eff);
+  var d = sample_dye(back);
+  if (p.advection == 1u) { d = maccormack_dye(id, pos, v, dt_eff, back, d); }
+  dye_dst[id] = d;
+  if (p.dye_budget != 0u) {
+    budget[3u * id + 2u] += luminance(source_dye(gid.xy) - d);
+  }
+}
+"#
+        )
+    };
 }
-"#;
 
-const FLUID_ADVECT_VEL_WGSL: &str = r#"
-struct Params {
-  width: u32,
-  height: u32,
-  jacobi_iters: u32,
-  _pad0: u32,
-  dt: f32,
-  viscosity: f32,
-  fade: f32,
-  dye_radius: f32,
-  impulse: f32,
-  inv_dx: f32,
-  inv_dy: f32,
-  _pad3: f32,
-};
+const FLUID_ADVECT_DYE_WGSL: &str = concat!(
+    wgsl_params!(),
+    wgsl_dye!(),
+    r#"
 @group(0) @binding(0) var<uniform> p: Params;
-@group(0) @binding(1) var<storage, read> src: array<vec2<f32>>;
-@group(0) @binding(2) var<storage, read_write> dst: array<vec2<f32>>;
+@group(0) @binding(1) var<storage, read> vel: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read> dye_src: array<Dye>;
+@group(0) @binding(3) var<storage, read_write> dye_dst: array<Dye>;
+@group(0) @binding(4) var<storage, read_write> counters: array<atomic<u32>, 4>;
+@group(0) @binding(5) var<storage, read_write> budget: array<f32>;
+// `FLUID_PREDICT_DYE_WGSL`'s output; a single element unless `advection` is MacCormack
+@group(0) @binding(6) var<storage, read> pred: array<Dye>;
 
 fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
+fn source_dye(cell: vec2<u32>) -> Dye { return dye_src[idx(cell.x, cell.y)]; }
 fn clamp_xy(x: i32, y: i32) -> vec2<u32> {
   let cx = u32(clamp(x, 0, i32(p.width) - 1));
   let cy = u32(clamp(y, 0, i32(p.height) - 1));
   return vec2<u32>(cx, cy);
 }
-fn sample_vel(pos: vec2<f32>) -> vec2<f32> {
-  let x = clamp(pos.x, 0.0, f32(p.width) - 1.001);
-  let y = clamp(pos.y, 0.0, f32(p.height) - 1.001);
+
+fn sample_dye(pos: vec2<f32>) -> Dye {
+  let x = clamp(pos.x, 0.0, max(f32(p.width) - 1.001, 0.0));
+  let y = clamp(pos.y, 0.0, max(f32(p.height) - 1.001, 0.0));
   let x0 = i32(floor(x));
   let y0 = i32(floor(y));
   let x1 = x0 + 1;
   let y1 = y0 + 1;
   let fx = fract(x);
   let fy = fract(y);
-  let a = src[idx(clamp_xy(x0, y0).x, clamp_xy(x0, y0).y)];
-  let b = src[idx(clamp_xy(x1, y0).x, clamp_xy(x1, y0).y)];
-  let c = src[idx(clamp_xy(x0, y1).x, clamp_xy(x0, y1).y)];
-  let d = src[idx(clamp_xy(x1, y1).x, clamp_xy(x1, y1).y)];
-  return mix(mix(a, b, fx), mix(c, d, fx), fy);
+  let a = dye_src[idx(clamp_xy(x0, y0).x, clamp_xy(x0, y0).y)];
+  let b = dye_src[idx(clamp_xy(x1, y0).x, clamp_xy(x1, y0).y)];
+  let c = dye_src[idx(clamp_xy(x0, y1).x, clamp_xy(x0, y1).y)];
+  let d = dye_src[idx(clamp_xy(x1, y1).x, clamp_xy(x1, y1).y)];
+  let v = mix(mix(a, b, fx), mix(c, d, fx), fy);
+  if (p.clamp_to_neighbors != 0u) {
+    let vc = clamp(v, min(min(a, b), min(c, d)), max(max(a, b), max(c, d)));
+    if (any(vc != v)) { atomicAdd(&counters[1], 1u); }
+    return vc;
+  }
+  return v;
 }
+"#,
+    wgsl_advect_dye_main!()
+);
+
+/// MacCormack predictor of the velocity advection: every cell's plain semi-Lagrangian sample
+/// (edges included), traced as `FLUID_ADVECT_VEL_WGSL` traces it, for its correction to trace
+/// forward again.
+const FLUID_PREDICT_VEL_WGSL: &str = concat!(
+    wgsl_params!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read> src: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read_write> pred: array<vec2<f32>>;
 
-@compute @workgroup_size(8, 8, 1)
+fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
+fn vel_at(i: u32) -> vec2<f32> { return src[i]; }
+"#,
+    wgsl_backtrace!(),
+    r#"
+@compute @workgroup_size(WG, WG, 1)
 fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
   if (gid.x >= p.width || gid.y >= p.height) { return; }
   let id = idx(gid.x, gid.y);
-  let edge = gid.x == 0u || gid.y == 0u || gid.x == (p.width - 1u) || gid.y == (p.height - 1u);
-  if (edge) {
-    dst[id] = vec2<f32>(0.0, 0.0);
-    return;
-  }
-
-  let pos = vec2<f32>(f32(gid.x), f32(gid.y));
   let v = src[id];
   let max_travel = 1.25;
   let dt_eff = p.dt * min(1.0, max_travel / (length(v) * p.dt + 1e-5));
-  let back = pos - dt_eff * v;
+  pred[id] = trace_vel(backtrace(vec2<f32>(f32(gid.x), f32(gid.y)), v, dt_eff));
+}
+"#
+);
 
-  // Semi-Lagrangian advection + simple viscosity damping.
-  let visc_decay = 1.0 / (1.0 + 4.0 * p.viscosity * dt_eff);
-  var v_next = sample_vel(back) * visc_decay;
-  let center = vec2<f32>(f32(p.width) * 0.5, f32(p.height) * 0.5);
-  let rel = pos - center;
-  let r = length(rel) / max(f32(min(p.width, p.height)), 1.0);
-  if (r <= p.dye_radius) {
-    let tangential = normalize(vec2<f32>(-rel.y, rel.x) + vec2<f32>(1e-4, 0.0));
-    let falloff = 1.0 - r / max(p.dye_radius, 1e-3);
-    v_next = v_next + tangential * (p.impulse * dt_eff * falloff);
-  }
+/// The dye counterpart of `FLUID_PREDICT_VEL_WGSL`, through the velocity dye advection reads.
+const FLUID_PREDICT_DYE_WGSL: &str = concat!(
+    wgsl_params!(),
+    wgsl_dye!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read> vel: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read> dye_src: array<Dye>;
+@group(0) @binding(3) var<storage, read_write> pred: array<Dye>;
 
-  dst[id] = v_next;
+fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
+fn vel_at(i: u32) -> vec2<f32> { return vel[i]; }
+"#,
+    wgsl_backtrace!(),
+    r#"
+@compute @workgroup_size(WG, WG, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  if (gid.x >= p.width || gid.y >= p.height) { return; }
+  let id = idx(gid.x, gid.y);
+  let v = vel[id];
+  let max_travel = 1.25;
+  let dt_eff = p.dt * min(1.0, max_travel / (length(v) * p.dt + 1e-5));
+  let fp = footprint(backtrace(vec2<f32>(f32(gid.x), f32(gid.y)), v, dt_eff));
+  pred[id] = mix(mix(dye_src[fp.i], dye_src[fp.i + fp.dx], fp.f.x),
+                 mix(dye_src[fp.i + fp.dy], dye_src[fp.i + fp.dy + fp.dx], fp.f.x), fp.f.y);
 }
-"#;
+"#
+);
 
-const FLUID_DIFFUSE_VEL_WGSL: &str = r#"
-struct Params {
-  width: u32,
-  height: u32,
-  jacobi_iters: u32,
-  _pad0: u32,
-  dt: f32,
-  viscosity: f32,
-  fade: f32,
-  dye_radius: f32,
-  impulse: f32,
-  inv_dx: f32,
-  inv_dy: f32,
-  _pad3: f32,
-};
+const FLUID_FILL_WGSL: &str = concat!(
+    wgsl_params!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read_write> dst: array<f32>;
+
+@compute @workgroup_size(WG, WG, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  if (gid.x >= p.width || gid.y >= p.height) { return; }
+  dst[gid.y * p.width + gid.x] = p.pressure_init;
+}
+"#
+);
+
+/// Writes the `WG`-square workgroup grid for `Params.width`/`height` as indirect dispatch args.
+const FLUID_DISPATCH_ARGS_WGSL: &str = concat!(
+    wgsl_params!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read_write> args: array<u32, 3>;
+
+@compute @workgroup_size(1, 1, 1)
+fn main() {
+  args[0] = (p.width + WG - 1u) / WG;
+  args[1] = (p.height + WG - 1u) / WG;
+  args[2] = 1u;
+}
+"#
+);
+
+/// Packs (vel.x, vel.y, dye) into the `storage: "texture"` field texture; `FIELD_TEX_FORMAT` is
+/// substituted with the format picked by `field_texture_format`.
+const FLUID_PACK_TEX_WGSL: &str = concat!(
+    wgsl_params!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read> vel: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read> dye: array<f32>;
+@group(0) @binding(3) var fields: texture_storage_2d<FIELD_TEX_FORMAT, write>;
+
+@compute @workgroup_size(WG, WG, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  if (gid.x >= p.width || gid.y >= p.height) { return; }
+  let id = gid.y * p.width + gid.x;
+  textureStore(fields, vec2<i32>(gid.xy), vec4<f32>(vel[id], dye[id], 0.0));
+}
+"#
+);
+
+// Texture-sampling variants of the advection kernels: same backtrace clamp as the buffer
+// kernels, with cell centers at texel centers. `textureGather` fetches the same 2x2 footprint
+// the filter used, for `clamp_to_neighbors`.
+const FLUID_ADVECT_VEL_TEX_WGSL: &str = concat!(
+    wgsl_params!(),
+    r#"
 @group(0) @binding(0) var<uniform> p: Params;
 @group(0) @binding(1) var<storage, read> src: array<vec2<f32>>;
 @group(0) @binding(2) var<storage, read_write> dst: array<vec2<f32>>;
+@group(0) @binding(3) var<storage, read_write> counters: array<atomic<u32>, 4>;
+@group(0) @binding(4) var<storage, read> ghost: array<vec2<f32>>;
+@group(0) @binding(5) var<storage, read> force_path: array<ForceKey>;
+@group(0) @binding(6) var<storage, read_write> forcing: array<vec2<f32>>;
+@group(0) @binding(7) var<storage, read> pred: array<vec2<f32>>;
+@group(0) @binding(8) var<storage, read> obstacles: array<u32>;
+@group(0) @binding(9) var fields: texture_2d<f32>;
+@group(0) @binding(10) var fields_sampler: sampler;
 
 fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
-fn c(x: i32, maxv: u32) -> u32 { return u32(clamp(x, 0, i32(maxv) - 1)); }
+fn field_uv(pos: vec2<f32>) -> vec2<f32> {
+  let x = clamp(pos.x, 0.0, max(f32(p.width) - 1.001, 0.0));
+  let y = clamp(pos.y, 0.0, max(f32(p.height) - 1.001, 0.0));
+  return (vec2<f32>(x, y) + vec2<f32>(0.5, 0.5)) / vec2<f32>(f32(p.width), f32(p.height));
+}
+fn sample_vel(pos: vec2<f32>) -> vec2<f32> {
+  let uv = field_uv(pos);
+  let v = textureSampleLevel(fields, fields_sampler, uv, 0.0).xy;
+  if (p.clamp_to_neighbors != 0u) {
+    let gx = textureGather(0, fields, fields_sampler, uv);
+    let gy = textureGather(1, fields, fields_sampler, uv);
+    let lo = vec2<f32>(min(min(gx.x, gx.y), min(gx.z, gx.w)), min(min(gy.x, gy.y), min(gy.z, gy.w)));
+    let hi = vec2<f32>(max(max(gx.x, gx.y), max(gx.z, gx.w)), max(max(gy.x, gy.y), max(gy.z, gy.w)));
+    let vc = clamp(v, lo, hi);
+    if (any(vc != v)) { atomicAdd(&counters[0], 1u); }
+    return vc;
+  }
+  return v;
+}
+"#,
+    wgsl_edge_vel!(),
+    wgsl_solid!(),
+    wgsl_advect_vel_main!()
+);
+
+// scalar dye only: the texture mirror holds one dye channel
+const FLUID_ADVECT_DYE_TEX_WGSL: &str = concat!(
+    wgsl_params!(),
+    wgsl_dye!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read> vel: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read> dye_src: array<f32>;
+@group(0) @binding(3) var<storage, read_write> dye_dst: array<f32>;
+@group(0) @binding(4) var<storage, read_write> counters: array<atomic<u32>, 4>;
+@group(0) @binding(5) var<storage, read_write> budget: array<f32>;
+@group(0) @binding(6) var<storage, read> pred: array<f32>;
+@group(0) @binding(7) var fields: texture_2d<f32>;
+@group(0) @binding(8) var fields_sampler: sampler;
+
+fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
+// full-precision source even when the texture is half precision, so the budget closes
+fn source_dye(cell: vec2<u32>) -> f32 { return dye_src[idx(cell.x, cell.y)]; }
+fn field_uv(pos: vec2<f32>) -> vec2<f32> {
+  let x = clamp(pos.x, 0.0, max(f32(p.width) - 1.001, 0.0));
+  let y = clamp(pos.y, 0.0, max(f32(p.height) - 1.001, 0.0));
+  return (vec2<f32>(x, y) + vec2<f32>(0.5, 0.5)) / vec2<f32>(f32(p.width), f32(p.height));
+}
+fn sample_dye(pos: vec2<f32>) -> f32 {
+  let uv = field_uv(pos);
+  let v = textureSampleLevel(fields, fields_sampler, uv, 0.0).z;
+  if (p.clamp_to_neighbors != 0u) {
+    let g = textureGather(2, fields, fields_sampler, uv);
+    let vc = clamp(v, min(min(g.x, g.y), min(g.z, g.w)), max(max(g.x, g.y), max(g.z, g.w)));
+    if (vc != v) { atomicAdd(&counters[1], 1u); }
+    return vc;
+  }
+  return v;
+}
+"#,
+    wgsl_advect_dye_main!()
+);
+
+const FLUID_FADE_WGSL: &str = concat!(
+    wgsl_params!(),
+    wgsl_dye!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read> src: array<Dye>;
+@group(0) @binding(2) var<storage, read_write> dst: array<Dye>;
+@group(0) @binding(3) var<storage, read_write> budget: array<f32>;
+
+fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
+
+// Fade factor at unit position `uv`, `r` away from the source (see `FadeGradient`).
+fn local_fade(uv: vec2<f32>, r: f32) -> f32 {
+  switch p.fade_gradient {
+    case 1u: { return mix(p.fade, p.fade_edge, min(2.0 * r, 1.0)); }
+    case 2u: { return mix(p.fade, p.fade_edge, uv.y); }
+    default: { return p.fade; }
+  }
+}
 
-@compute @workgroup_size(8, 8, 1)
+@compute @workgroup_size(WG, WG, 1)
 fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
   if (gid.x >= p.width || gid.y >= p.height) { return; }
+  let uv = (vec2<f32>(f32(gid.x), f32(gid.y)) + vec2<f32>(0.5, 0.5)) / vec2<f32>(f32(p.width), f32(p.height));
+  let r = length(uv - vec2<f32>(p.source_center_x, p.source_center_y));
   let id = idx(gid.x, gid.y);
-  let edge = gid.x == 0u || gid.y == 0u || gid.x == (p.width - 1u) || gid.y == (p.height - 1u);
-  if (edge) {
-    dst[id] = vec2<f32>(0.0, 0.0);
-    return;
+  let pulse_on = p.source_period == 0u || p.step_index % p.source_period < p.source_on_steps;
+  let source = select(0.0, 0.02, pulse_on && r <= p.dye_radius * 0.4);
+  let fade = local_fade(uv, r);
+  dst[id] = src[id] * fade + tint(source);
+  if (p.dye_budget != 0u) {
+    budget[3u * id] += luminance(tint(source));
+    budget[3u * id + 1u] += luminance(src[id]) * (1.0 - fade);
   }
+}
+"#
+);
 
-  let x = i32(gid.x);
-  let y = i32(gid.y);
-  let center = src[id];
-  let vl = src[idx(c(x - 1, p.width), c(y, p.height))];
-  let vr = src[idx(c(x + 1, p.width), c(y, p.height))];
-  let vb = src[idx(c(x, p.width), c(y - 1, p.height))];
-  let vt = src[idx(c(x, p.width), c(y + 1, p.height))];
+// Running `time_average` sums. Sums sized to a single element are switched off.
+const FLUID_ACCUMULATE_WGSL: &str = concat!(
+    wgsl_params!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read> vel: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read> dye: array<f32>;
+@group(0) @binding(3) var<storage, read_write> vel_sum: array<vec2<f32>>;
+@group(0) @binding(4) var<storage, read_write> dye_sum: array<f32>;
 
-  let a = max(p.viscosity * p.dt, 0.0);
-  dst[id] = (center + a * (vl + vr + vb + vt)) / (1.0 + 4.0 * a);
+@compute @workgroup_size(WG, WG, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  if (gid.x >= p.width || gid.y >= p.height) { return; }
+  let cells = p.width * p.height;
+  let id = gid.y * p.width + gid.x;
+  if (arrayLength(&vel_sum) == cells) { vel_sum[id] += vel[id]; }
+  if (arrayLength(&dye_sum) == cells) { dye_sum[id] += dye[id]; }
 }
-"#;
+"#
+);
 
-const FLUID_DIVERGENCE_WGSL: &str = r#"
-struct Params {
-  width: u32,
-  height: u32,
-  jacobi_iters: u32,
-  _pad0: u32,
-  dt: f32,
-  viscosity: f32,
-  fade: f32,
-  dye_radius: f32,
-  impulse: f32,
-  inv_dx: f32,
-  inv_dy: f32,
-  _pad3: f32,
-};
+const FLUID_MAX_SPEED_WGSL: &str = concat!(
+    wgsl_params!(),
+    r#"
 @group(0) @binding(0) var<uniform> p: Params;
 @group(0) @binding(1) var<storage, read> vel: array<vec2<f32>>;
-@group(0) @binding(2) var<storage, read_write> div: array<f32>;
+@group(0) @binding(2) var<storage, read_write> max_speed: array<atomic<u32>>;
 
-fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
-fn c(x: i32, maxv: u32) -> u32 { return u32(clamp(x, 0, i32(maxv) - 1)); }
+var<workgroup> wg_max: atomic<u32>;
 
-@compute @workgroup_size(8, 8, 1)
-fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
-  if (gid.x >= p.width || gid.y >= p.height) { return; }
+// Max |v| over the grid into max_speed[0], as f32 bits (see FLUID_RESIDUAL_WGSL).
+@compute @workgroup_size(WG, WG, 1)
+fn main(
+  @builtin(global_invocation_id) gid: vec3<u32>,
+  @builtin(local_invocation_index) lid: u32,
+) {
+  var s = 0.0;
+  if (gid.x < p.width && gid.y < p.height) {
+    s = length(vel[gid.y * p.width + gid.x]);
+  }
+  atomicMax(&wg_max, bitcast<u32>(s));
+  workgroupBarrier();
+  if (lid == 0u) {
+    atomicMax(&max_speed[0], atomicLoad(&wg_max));
+  }
+}
+"#
+);
 
-  let edge = gid.x == 0u || gid.y == 0u || gid.x == (p.width - 1u) || gid.y == (p.height - 1u);
-  if (edge) {
-    div[idx(gid.x, gid.y)] = 0.0;
-    return;
+const FLUID_DT_PEAKS_WGSL: &str = concat!(
+    wgsl_params!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read> vel: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read_write> peaks: array<atomic<u32>>;
+
+var<workgroup> wg_speed: atomic<u32>;
+var<workgroup> wg_curl: atomic<u32>;
+
+// Max |v| over the grid and max |curl v| over the interior (central differences, per cell)
+// into peaks[0] / peaks[1] as f32 bits, for FLUID_PICK_DT_WGSL.
+@compute @workgroup_size(WG, WG, 1)
+fn main(
+  @builtin(global_invocation_id) gid: vec3<u32>,
+  @builtin(local_invocation_index) lid: u32,
+) {
+  var s = 0.0;
+  var w = 0.0;
+  if (gid.x < p.width && gid.y < p.height) {
+    let id = gid.y * p.width + gid.x;
+    s = length(vel[id]);
+    if (gid.x > 0u && gid.y > 0u && gid.x + 1u < p.width && gid.y + 1u < p.height) {
+      let dvy_dx = vel[id + 1u].y - vel[id - 1u].y;
+      let dvx_dy = vel[id + p.width].x - vel[id - p.width].x;
+      w = abs(0.5 * (dvy_dx - dvx_dy));
+    }
+  }
+  atomicMax(&wg_speed, bitcast<u32>(s));
+  atomicMax(&wg_curl, bitcast<u32>(w));
+  workgroupBarrier();
+  if (lid == 0u) {
+    atomicMax(&peaks[0], atomicLoad(&wg_speed));
+    atomicMax(&peaks[1], atomicLoad(&wg_curl));
   }
+}
+"#
+);
 
-  let x = i32(gid.x);
-  let y = i32(gid.y);
-  let vl = vel[idx(c(x - 1, p.width), c(y, p.height))].x;
-  let vr = vel[idx(c(x + 1, p.width), c(y, p.height))].x;
-  let vb = vel[idx(c(x, p.width), c(y - 1, p.height))].y;
-  let vt = vel[idx(c(x, p.width), c(y + 1, p.height))].y;
-  div[idx(gid.x, gid.y)] = 0.5 * ((vr - vl) * p.inv_dx + (vt - vb) * p.inv_dy);
+const FLUID_PICK_DT_WGSL: &str = concat!(
+    wgsl_params!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read> peaks: array<u32>;
+@group(0) @binding(2) var<storage, read_write> dt_stats: array<f32>;
+
+// Largest dt up to dt_max that keeps both CFL numbers under their limits, into dt_stats[0]
+// (copied into Params.dt next), folded into the running (min, max, sum, steps) after it.
+@compute @workgroup_size(1)
+fn main() {
+  let speed = bitcast<f32>(peaks[0]);
+  let curl = bitcast<f32>(peaks[1]);
+  var dt = p.dt_max;
+  if (speed > 0.0) { dt = min(dt, p.cfl_limit / speed); }
+  if (curl > 0.0) { dt = min(dt, p.vorticity_cfl_limit / curl); }
+  dt_stats[0] = dt;
+  if (dt_stats[4] == 0.0 || dt < dt_stats[1]) { dt_stats[1] = dt; }
+  dt_stats[2] = max(dt_stats[2], dt);
+  dt_stats[3] += dt;
+  dt_stats[4] += 1.0;
 }
-"#;
+"#
+);
 
-const FLUID_JACOBI_WGSL: &str = r#"
-struct Params {
-  width: u32,
-  height: u32,
-  jacobi_iters: u32,
-  _pad0: u32,
-  dt: f32,
-  viscosity: f32,
-  fade: f32,
-  dye_radius: f32,
-  impulse: f32,
-  inv_dx: f32,
-  inv_dy: f32,
-  _pad3: f32,
-};
+const FLUID_MAX_DIVERGENCE_WGSL: &str = concat!(
+    wgsl_params!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read> div: array<f32>;
+@group(0) @binding(2) var<storage, read_write> gate: array<atomic<u32>>;
+
+var<workgroup> wg_max: atomic<u32>;
+
+// Max |div| over the grid (the divergence kernel zeroes the edge ring) into gate[5], as f32
+// bits (see FLUID_RESIDUAL_WGSL).
+@compute @workgroup_size(WG, WG, 1)
+fn main(
+  @builtin(global_invocation_id) gid: vec3<u32>,
+  @builtin(local_invocation_index) lid: u32,
+) {
+  var d = 0.0;
+  if (gid.x < p.width && gid.y < p.height) {
+    d = abs(div[gid.y * p.width + gid.x]);
+  }
+  atomicMax(&wg_max, bitcast<u32>(d));
+  workgroupBarrier();
+  if (lid == 0u) {
+    atomicMax(&gate[5], atomicLoad(&wg_max));
+  }
+}
+"#
+);
+
+const FLUID_DIVERGENCE_GATE_WGSL: &str = concat!(
+    wgsl_params!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read_write> gate: array<u32>;
+
+// Before each extra projection pass: while the step's passes still run and the reduced
+// max |div| (gate[5]) is above the target (gate[8]), give the pass's pressure solve the full
+// grid in the indirect args and count it. Otherwise zero the args and close the gate for the
+// rest of the step, so the project kernel only copies.
+@compute @workgroup_size(1)
+fn main() {
+  if (gate[3] == 0u && bitcast<f32>(gate[5]) > bitcast<f32>(gate[8])) {
+    gate[0] = (p.width + WG - 1u) / WG;
+    gate[1] = (p.height + WG - 1u) / WG;
+    gate[2] = 1u;
+    gate[4] += 1u;
+    gate[6] += 1u;
+    gate[7] = max(gate[7], gate[4]);
+  } else {
+    gate[0] = 0u;
+    gate[1] = 0u;
+    gate[2] = 0u;
+    gate[3] = 1u;
+  }
+}
+"#
+);
+
+const FLUID_RESIDUAL_WGSL: &str = concat!(
+    wgsl_params!(),
+    wgsl_solid!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read> pressure: array<f32>;
+@group(0) @binding(2) var<storage, read> div: array<f32>;
+@group(0) @binding(3) var<storage, read_write> residuals: array<atomic<u32>>;
+@group(0) @binding(4) var<storage, read> obstacles: array<u32>;
+
+fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
+
+var<workgroup> wg_max: atomic<u32>;
+
+// Max |Lp - div| over interior fluid cells (the solve's stencil) into
+// residuals[p.residual_slot]. Non-negative f32 bit patterns order the same as u32, so atomicMax
+// on the bits is a float max.
+@compute @workgroup_size(WG, WG, 1)
+fn main(
+  @builtin(global_invocation_id) gid: vec3<u32>,
+  @builtin(local_invocation_index) lid: u32,
+) {
+  var r = 0.0;
+  let interior = gid.x > 0u && gid.y > 0u && gid.x + 1u < p.width && gid.y + 1u < p.height;
+  if (interior && !solid(idx(gid.x, gid.y))) {
+    let i = idx(gid.x, gid.y);
+    let pc = pressure[i];
+    let pl = wall_p(i - 1u, pressure[i - 1u], pc);
+    let pr = wall_p(i + 1u, pressure[i + 1u], pc);
+    let pb = wall_p(i - p.width, pressure[i - p.width], pc);
+    let pt = wall_p(i + p.width, pressure[i + p.width], pc);
+    let idx2 = p.inv_dx * p.inv_dx;
+    let idy2 = p.inv_dy * p.inv_dy;
+    let lap = (pl + pr - 2.0 * pc) * idx2 + (pb + pt - 2.0 * pc) * idy2;
+    r = abs(lap - div[idx(gid.x, gid.y)]);
+  }
+  atomicMax(&wg_max, bitcast<u32>(r));
+  workgroupBarrier();
+  if (lid == 0u) {
+    atomicMax(&residuals[p.residual_slot], atomicLoad(&wg_max));
+  }
+}
+"#
+);
+
+const FLUID_STREAMFUNCTION_RHS_WGSL: &str = concat!(
+    wgsl_params!(),
+    r#"
 @group(0) @binding(0) var<uniform> p: Params;
-@group(0) @binding(1) var<storage, read> p_in: array<f32>;
-@group(0) @binding(2) var<storage, read> div: array<f32>;
-@group(0) @binding(3) var<storage, read_write> p_out: array<f32>;
+@group(0) @binding(1) var<storage, read> vel: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read_write> rhs: array<f32>;
 
 fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
-fn c(x: i32, maxv: u32) -> u32 { return u32(clamp(x, 0, i32(maxv) - 1)); }
 
-@compute @workgroup_size(8, 8, 1)
+// -omega from central differences (same stencil as divergence), the right-hand side of
+// lap(psi) = -omega for the Jacobi kernel.
+@compute @workgroup_size(WG, WG, 1)
 fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
   if (gid.x >= p.width || gid.y >= p.height) { return; }
 
   let edge = gid.x == 0u || gid.y == 0u || gid.x == (p.width - 1u) || gid.y == (p.height - 1u);
   if (edge) {
-    p_out[idx(gid.x, gid.y)] = 0.0;
+    rhs[idx(gid.x, gid.y)] = 0.0;
     return;
   }
 
-  let x = i32(gid.x);
-  let y = i32(gid.y);
-  let pl = p_in[idx(c(x - 1, p.width), c(y, p.height))];
-  let pr = p_in[idx(c(x + 1, p.width), c(y, p.height))];
-  let pb = p_in[idx(c(x, p.width), c(y - 1, p.height))];
-  let pt = p_in[idx(c(x, p.width), c(y + 1, p.height))];
-  let d = div[idx(gid.x, gid.y)];
-  let idx2 = p.inv_dx * p.inv_dx;
-  let idy2 = p.inv_dy * p.inv_dy;
-  let denom = 2.0 * (idx2 + idy2);
-  p_out[idx(gid.x, gid.y)] = ((pl + pr) * idx2 + (pb + pt) * idy2 - d) / max(denom, 1e-6);
+  let vl = vel[idx(gid.x - 1u, gid.y)];
+  let vr = vel[idx(gid.x + 1u, gid.y)];
+  let vb = vel[idx(gid.x, gid.y - 1u)];
+  let vt = vel[idx(gid.x, gid.y + 1u)];
+  let vorticity = (vr.y - vl.y) * 0.5 * p.inv_dx - (vt.x - vb.x) * 0.5 * p.inv_dy;
+  rhs[idx(gid.x, gid.y)] = -vorticity;
 }
-"#;
+"#
+);
 
-const FLUID_PROJECT_WGSL: &str = r#"
-struct Params {
-  width: u32,
-  height: u32,
-  jacobi_iters: u32,
-  _pad0: u32,
-  dt: f32,
-  viscosity: f32,
-  fade: f32,
-  dye_radius: f32,
-  impulse: f32,
-  inv_dx: f32,
-  inv_dy: f32,
-  _pad3: f32,
-};
+const FLUID_OKUBO_WEISS_WGSL: &str = concat!(
+    wgsl_params!(),
+    r#"
 @group(0) @binding(0) var<uniform> p: Params;
 @group(0) @binding(1) var<storage, read> vel: array<vec2<f32>>;
-@group(0) @binding(2) var<storage, read> pressure: array<f32>;
-@group(0) @binding(3) var<storage, read_write> out_vel: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read_write> ow: array<f32>;
 
 fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
-fn c(x: i32, maxv: u32) -> u32 { return u32(clamp(x, 0, i32(maxv) - 1)); }
 
-@compute @workgroup_size(8, 8, 1)
+// W = s_n^2 + s_s^2 - omega^2 from central differences (same stencil as divergence).
+@compute @workgroup_size(WG, WG, 1)
 fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
   if (gid.x >= p.width || gid.y >= p.height) { return; }
-  let x = i32(gid.x);
-  let y = i32(gid.y);
-  let pl = pressure[idx(c(x - 1, p.width), c(y, p.height))];
-  let pr = pressure[idx(c(x + 1, p.width), c(y, p.height))];
-  let pb = pressure[idx(c(x, p.width), c(y - 1, p.height))];
-  let pt = pressure[idx(c(x, p.width), c(y + 1, p.height))];
-  let grad = vec2<f32>((pr - pl) * 0.5 * p.inv_dx, (pt - pb) * 0.5 * p.inv_dy);
 
   let edge = gid.x == 0u || gid.y == 0u || gid.x == (p.width - 1u) || gid.y == (p.height - 1u);
-  out_vel[idx(gid.x, gid.y)] = select(vel[idx(gid.x, gid.y)] - grad, vec2<f32>(0.0, 0.0), edge);
+  if (edge) {
+    ow[idx(gid.x, gid.y)] = 0.0;
+    return;
+  }
+
+  let vl = vel[idx(gid.x - 1u, gid.y)];
+  let vr = vel[idx(gid.x + 1u, gid.y)];
+  let vb = vel[idx(gid.x, gid.y - 1u)];
+  let vt = vel[idx(gid.x, gid.y + 1u)];
+  let ddx = (vr - vl) * 0.5 * p.inv_dx;
+  let ddy = (vt - vb) * 0.5 * p.inv_dy;
+  let normal_strain = ddx.x - ddy.y;
+  let shear_strain = ddx.y + ddy.x;
+  let vorticity = ddx.y - ddy.x;
+  ow[idx(gid.x, gid.y)] =
+    normal_strain * normal_strain + shear_strain * shear_strain - vorticity * vorticity;
 }
-"#;
+"#
+);
 
-const FLUID_ADVECT_DYE_WGSL: &str = r#"
-struct Params {
-  width: u32,
-  height: u32,
-  jacobi_iters: u32,
-  _pad0: u32,
-  dt: f32,
-  viscosity: f32,
-  fade: f32,
-  dye_radius: f32,
-  impulse: f32,
-  inv_dx: f32,
-  inv_dy: f32,
-  _pad3: f32,
-};
+const FLUID_DYE_GRADIENT_WGSL: &str = concat!(
+    wgsl_params!(),
+    r#"
 @group(0) @binding(0) var<uniform> p: Params;
-@group(0) @binding(1) var<storage, read> vel: array<vec2<f32>>;
-@group(0) @binding(2) var<storage, read> dye_src: array<f32>;
-@group(0) @binding(3) var<storage, read_write> dye_dst: array<f32>;
+@group(0) @binding(1) var<storage, read> dye: array<f32>;
+@group(0) @binding(2) var<storage, read_write> grad: array<f32>;
 
 fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
-fn clamp_xy(x: i32, y: i32) -> vec2<u32> {
-  let cx = u32(clamp(x, 0, i32(p.width) - 1));
-  let cy = u32(clamp(y, 0, i32(p.height) - 1));
-  return vec2<u32>(cx, cy);
-}
 
-fn sample_dye(pos: vec2<f32>) -> f32 {
-  let x = clamp(pos.x, 0.0, f32(p.width) - 1.001);
-  let y = clamp(pos.y, 0.0, f32(p.height) - 1.001);
-  let x0 = i32(floor(x));
-  let y0 = i32(floor(y));
-  let x1 = x0 + 1;
-  let y1 = y0 + 1;
-  let fx = fract(x);
-  let fy = fract(y);
-  let a = dye_src[idx(clamp_xy(x0, y0).x, clamp_xy(x0, y0).y)];
-  let b = dye_src[idx(clamp_xy(x1, y0).x, clamp_xy(x1, y0).y)];
-  let c = dye_src[idx(clamp_xy(x0, y1).x, clamp_xy(x0, y1).y)];
-  let d = dye_src[idx(clamp_xy(x1, y1).x, clamp_xy(x1, y1).y)];
-  return mix(mix(a, b, fx), mix(c, d, fx), fy);
+// |grad dye| from central differences, one-sided at the grid edge (clamped neighbours, as the
+// stats' `dye_gradient`).
+@compute @workgroup_size(WG, WG, 1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  if (gid.x >= p.width || gid.y >= p.height) { return; }
+
+  let xm = max(gid.x, 1u) - 1u;
+  let xp = min(gid.x + 1u, p.width - 1u);
+  let ym = max(gid.y, 1u) - 1u;
+  let yp = min(gid.y + 1u, p.height - 1u);
+  let ddx = (dye[idx(xp, gid.y)] - dye[idx(xm, gid.y)]) * 0.5 * p.inv_dx;
+  let ddy = (dye[idx(gid.x, yp)] - dye[idx(gid.x, ym)]) * 0.5 * p.inv_dy;
+  grad[idx(gid.x, gid.y)] = sqrt(ddx * ddx + ddy * ddy);
 }
+"#
+);
+
+// `delta_every`: how far each cell moved from the snapshot, `[|v - v_prev|, |dye - dye_prev|]`.
+const FLUID_DELTA_WGSL: &str = concat!(
+    wgsl_params!(),
+    r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read> vel: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read> dye: array<f32>;
+@group(0) @binding(3) var<storage, read> vel_prev: array<vec2<f32>>;
+@group(0) @binding(4) var<storage, read> dye_prev: array<f32>;
+@group(0) @binding(5) var<storage, read_write> delta: array<vec2<f32>>;
 
-@compute @workgroup_size(8, 8, 1)
+@compute @workgroup_size(WG, WG, 1)
 fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
   if (gid.x >= p.width || gid.y >= p.height) { return; }
-  let id = idx(gid.x, gid.y);
-  let pos = vec2<f32>(f32(gid.x), f32(gid.y));
-  let v = vel[id];
-  let max_travel = 1.25;
-  let dt_eff = p.dt * min(1.0, max_travel / (length(v) * p.dt + 1e-5));
-  let back = pos - dt_eff * v;
-  dye_dst[id] = sample_dye(back);
+  let id = gid.y * p.width + gid.x;
+  delta[id] = vec2<f32>(length(vel[id] - vel_prev[id]), abs(dye[id] - dye_prev[id]));
 }
-"#;
+"#
+);
 
-const FLUID_FADE_WGSL: &str = r#"
-struct Params {
-  width: u32,
-  height: u32,
-  jacobi_iters: u32,
-  _pad0: u32,
-  dt: f32,
-  viscosity: f32,
-  fade: f32,
-  dye_radius: f32,
-  impulse: f32,
-  inv_dx: f32,
-  inv_dy: f32,
-  _pad3: f32,
-};
-@group(0) @binding(0) var<uniform> p: Params;
-@group(0) @binding(1) var<storage, read> src: array<f32>;
-@group(0) @binding(2) var<storage, read_write> dst: array<f32>;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn idx(x: u32, y: u32) -> u32 { return y * p.width + x; }
+    fn request(json: serde_json::Value) -> FluidStepRequest {
+        serde_json::from_value(json).unwrap()
+    }
+
+    /// The smoke kernel adds 1.0 to `0..n`; the sample reads indices 0, 1, 10 and n - 1.
+    #[test]
+    fn smoke_sample_matches_kernel() {
+        let resp = match pollster::block_on(run_smoke(
+            1024,
+            default_smoke_workgroup(),
+            WgpuBackend::Auto,
+        )) {
+            Ok(resp) => resp,
+            // no GPU adapter on this machine
+            Err(err) if error::error_code(&err) == ErrorCode::NoAdapter => return,
+            Err(err) => panic!("smoke run failed: {err:#}"),
+        };
+        assert_eq!(resp.sample, [1.0, 2.0, 11.0, 1024.0]);
+        assert_eq!(resp.mismatch_count, 0);
+    }
+
+    #[test]
+    fn smoke_workgroup_sizes_cover_every_element() {
+        for workgroup in [1, 7, 64, 256] {
+            let resp = match pollster::block_on(run_smoke(1000, workgroup, WgpuBackend::Auto)) {
+                Ok(resp) => resp,
+                Err(err) if error::error_code(&err) == ErrorCode::NoAdapter => return,
+                Err(err) => panic!("workgroup {workgroup}: {err:#}"),
+            };
+            assert_eq!(resp.mismatch_count, 0, "workgroup {workgroup}");
+            assert!(resp.elements_per_sec > 0.0, "workgroup {workgroup}");
+        }
+        for workgroup in [0, u32::MAX] {
+            let err =
+                pollster::block_on(run_smoke(1000, workgroup, WgpuBackend::Auto)).unwrap_err();
+            assert_eq!(error::error_code(&err), ErrorCode::InvalidRequest);
+        }
+    }
+
+    #[test]
+    fn smoke_reports_the_requested_backend() {
+        for (backend, label) in [
+            (WgpuBackend::Metal, "metal/wgpu"),
+            (WgpuBackend::Vulkan, "vulkan/wgpu"),
+            (WgpuBackend::Dx12, "dx12/wgpu"),
+            (WgpuBackend::Gl, "gl/wgpu"),
+        ] {
+            match pollster::block_on(run_smoke(64, 64, backend)) {
+                Ok(resp) => assert_eq!(resp.backend, label),
+                Err(err) if error::error_code(&err) == ErrorCode::NoAdapter => {
+                    assert_eq!(format!("{err}"), format!("no {backend:?} adapter"));
+                }
+                Err(err) => panic!("{backend:?}: {err:#}"),
+            }
+        }
+    }
+
+    #[test]
+    fn dumped_fields_get_copy_src() {
+        let cfg = request(serde_json::json!({
+            "width": 16, "height": 16,
+            "dump_okubo_weiss": true, "dump_dye_gradient": true,
+            "time_average": true, "time_average_vel": true, "delta_every": 4,
+        }));
+        for field in [
+            FluidField::Vel,
+            FluidField::Dye,
+            FluidField::OkuboWeiss,
+            FluidField::DyeGradient,
+            FluidField::Delta,
+            FluidField::DyeMean,
+            FluidField::VelMean,
+        ] {
+            assert!(check_readable(field, cfg.readback_usage(field)).is_ok());
+        }
+    }
+
+    #[test]
+    fn reading_an_unrequested_field_is_rejected() {
+        let cfg = request(serde_json::json!({"width": 16, "height": 16}));
+        assert!(check_readable(FluidField::Vel, cfg.readback_usage(FluidField::Vel)).is_ok());
+        for field in [
+            FluidField::OkuboWeiss,
+            FluidField::DyeGradient,
+            FluidField::Delta,
+            FluidField::DyeMean,
+            FluidField::VelMean,
+        ] {
+            let usage = cfg.readback_usage(field);
+            assert!(!usage.contains(wgpu::BufferUsages::COPY_SRC));
+            let err = check_readable(field, usage).unwrap_err();
+            assert_eq!(error::error_code(&err), ErrorCode::UnsupportedFeature);
+        }
+    }
+    /// `dispatch` on `json`, parsed back; `None` when the request needs a GPU this machine
+    /// lacks.
+    fn round_trip(json: serde_json::Value) -> Option<serde_json::Value> {
+        match dispatch(&json.to_string()) {
+            Ok(reply) => {
+                assert!(!reply.failed);
+                Some(serde_json::from_str(&reply.json).expect("response is JSON"))
+            }
+            Err(err) if error::error_code(&err) == ErrorCode::NoAdapter => None,
+            Err(err) => panic!("{json} failed: {err:#}"),
+        }
+    }
+
+    #[test]
+    fn fluid_commands_round_trip() {
+        let step = round_trip(serde_json::json!({
+            "cmd": "fluid_step", "width": 24, "height": 20, "steps": 4, "backend": "cpu",
+        }))
+        .expect("cpu runs anywhere");
+        assert_eq!(step["ok"], true);
+        assert_eq!(step["backend"], "cpu");
+        assert_eq!(
+            (step["width"].as_u64(), step["height"].as_u64()),
+            (Some(24), Some(20))
+        );
+        let num = |v: &serde_json::Value, key: &str| v[key].as_f64().expect(key);
+        assert!(num(&step, "avg_divergence") >= 0.0);
+        assert!(num(&step, "max_divergence") >= num(&step, "avg_divergence"));
+        assert!((0.0..=1.0).contains(&num(&step, "dye_footprint")));
+        assert!(num(&step, "max_speed") >= num(&step, "avg_speed"));
+        assert!(num(&step, "avg_speed") >= 0.0);
+        assert!(num(&step, "dye_total") > 0.0);
+
+        let advanced = round_trip(serde_json::json!({
+            "cmd": "fluid_step", "width": 16, "height": 16, "steps": 3, "backend": "cpu",
+            "readback": false,
+        }))
+        .expect("cpu runs anywhere");
+        let keys: Vec<&str> = advanced
+            .as_object()
+            .expect("object")
+            .keys()
+            .map(String::as_str)
+            .collect();
+        assert_eq!(keys, ["compute_ms", "ok", "steps"]);
+        assert_eq!(advanced["steps"], 3);
+
+        let render = round_trip(serde_json::json!({
+            "cmd": "fluid_render", "width": 16, "height": 16, "steps": 2, "backend": "cpu",
+            "data_url": true, "overlay": "pressure",
+        }))
+        .expect("cpu runs anywhere");
+        assert_eq!(render["ok"], true);
+        assert_eq!(render["image_width"], 16);
+        assert_eq!(render["overlay"], "pressure");
+        let range = &render["overlay_range"];
+        assert!(range[1].as_f64().is_some_and(|m| m > 0.0));
+        assert_eq!(range[0].as_f64(), range[1].as_f64().map(|m| -m));
+        assert!(
+            render["data_url"]
+                .as_str()
+                .is_some_and(|url| url.starts_with("data:image/png;base64,"))
+        );
+        let range = render["value_range"].as_array().expect("value_range");
+        assert!(range[0].as_f64() <= range[1].as_f64());
+
+        let explained = round_trip(serde_json::json!({
+            "cmd": "fluid_step", "width": 16, "height": 16, "explain": true,
+        }))
+        .expect("explain runs nothing");
+        assert_eq!(explained["explain"], true);
+        assert!(num(&explained, "dispatches_total") > 0.0);
+        let out_dir = std::env::temp_dir().join(format!("live-render-{}", std::process::id()));
+        let live = round_trip(serde_json::json!({
+            "cmd": "live_render", "width": 16, "height": 16, "backend": "cpu", "fps": 200.0,
+            "out_dir": out_dir, "max_steps": 3,
+        }))
+        .expect("cpu runs anywhere");
+        assert_eq!(live["frames_written"], 3);
+        assert!(out_dir.join("frame_000002.png").exists());
+        let _ = std::fs::remove_dir_all(&out_dir);
+    }
+
+    #[test]
+    fn step_only_replies_reject_readback_options() {
+        for option in [
+            serde_json::json!({ "on_blowup": "reset" }),
+            serde_json::json!({ "progress_every": 10 }),
+            serde_json::json!({ "dump_vel": true }),
+            serde_json::json!({ "dump_dye": true }),
+            serde_json::json!({ "dump_okubo_weiss": true }),
+            serde_json::json!({ "dump_dye_gradient": true }),
+            serde_json::json!({ "delta_every": 2 }),
+            serde_json::json!({ "dump_streamfunction": true }),
+            serde_json::json!({ "dump_pressure_every": 10 }),
+            serde_json::json!({ "dump_json": true }),
+            serde_json::json!({ "time_average": true }),
+            serde_json::json!({ "velocity_texture_path": "vel.png" }),
+            serde_json::json!({ "csv_out_path": "vel.csv" }),
+            serde_json::json!({ "vtk_out_path": "fields.vtk" }),
+            serde_json::json!({ "residual_history": true }),
+            serde_json::json!({ "clamp_history": true }),
+            serde_json::json!({ "trace_dispatches": true }),
+            serde_json::json!({ "dye_budget": true }),
+            serde_json::json!({ "forcing_budget": true }),
+            serde_json::json!({ "helmholtz_check": true }),
+            serde_json::json!({ "report_reynolds": true }),
+            serde_json::json!({ "report_param_sources": true }),
+            serde_json::json!({ "integral_length": true }),
+        ] {
+            let mut json = serde_json::json!({
+                "width": 16, "height": 16, "steps": 3, "backend": "cpu", "readback": false,
+            });
+            json.as_object_mut()
+                .unwrap()
+                .extend(option.as_object().unwrap().clone());
+            let name = option.as_object().unwrap().keys().next().unwrap().clone();
+            let err = request(json).prepare().expect_err(&name);
+            assert!(
+                format!("{err:#}").contains(&format!("so {name} need readback")),
+                "{err:#}"
+            );
+        }
+    }
 
-@compute @workgroup_size(8, 8, 1)
+    #[test]
+    fn delta_compares_against_the_last_snapshot() {
+        let cfg = request(serde_json::json!({
+            "width": 16, "height": 16, "backend": "cpu", "delta_every": 3, "dump_delta": true,
+        }))
+        .prepare()
+        .expect("valid config")
+        .0;
+        assert_eq!(cfg.delta_steps(0), None);
+        assert_eq!(cfg.delta_steps(5), Some(2));
+        assert_eq!(cfg.delta_steps(6), Some(3));
+        let mut sim = cpu_ref::CpuFluid::new(&cfg).unwrap();
+        sim.seed();
+        // snapshots before steps 0 and 3; after 5 steps the delta covers steps 3 and 4
+        sim.step(3);
+        let snapshot = sim.read_fields();
+        sim.step(2);
+        let now = sim.read_fields();
+        let delta = sim.read_region(FluidField::Delta, Roi::full(16, 16));
+        for (id, cell) in delta.chunks_exact(2).enumerate() {
+            let dv = [
+                now.vel[id][0] - snapshot.vel[id][0],
+                now.vel[id][1] - snapshot.vel[id][1],
+            ];
+            assert!((cell[0] - (dv[0] * dv[0] + dv[1] * dv[1]).sqrt()).abs() < 1e-6);
+            assert!((cell[1] - (now.dye[id] - snapshot.dye[id]).abs()).abs() < 1e-6);
+        }
+        assert!(delta.iter().any(|&d| d > 0.0), "the flow moved");
+    }
+
+    #[test]
+    fn force_path_interpolates_between_keys() {
+        let key = |step, x, y| ForceKey { step, x, y };
+        let path = [key(4, 0.2, 0.2), key(8, 0.6, 0.2), key(12, 0.6, 1.0)];
+        assert_eq!(force_path_center(&[], 5), None);
+        for (step, expected) in [
+            (0, [0.2, 0.2]),
+            (4, [0.2, 0.2]),
+            (6, [0.4, 0.2]),
+            (8, [0.6, 0.2]),
+            (11, [0.6, 0.8]),
+            (40, [0.6, 1.0]),
+        ] {
+            let [x, y] = force_path_center(&path, step).unwrap();
+            assert!(
+                (x - expected[0]).abs() < 1e-6 && (y - expected[1]).abs() < 1e-6,
+                "step {step}: [{x}, {y}]"
+            );
+        }
+        // a one-key path at `source_center` forces exactly where the default does
+        let run = |json: serde_json::Value| {
+            let cfg = request(json).prepare().expect("valid config").0;
+            let mut sim = cpu_ref::CpuFluid::new(&cfg).unwrap();
+            sim.seed();
+            sim.step(cfg.steps);
+            sim.read_fields().vel
+        };
+        let base = serde_json::json!({"width": 16, "height": 16, "steps": 4, "backend": "cpu"});
+        let mut pinned = base.clone();
+        pinned["force_path"] = serde_json::json!([{"step": 0, "x": 0.5, "y": 0.5}]);
+        assert_eq!(run(base), run(pinned));
+    }
+
+    #[test]
+    fn timestamp_granularity_degrades_with_the_features() {
+        use wgpu::Features as F;
+        let both = F::TIMESTAMP_QUERY | F::TIMESTAMP_QUERY_INSIDE_PASSES;
+        let cases = [
+            (both, 200, TimestampGranularity::Pass, 201),
+            (F::TIMESTAMP_QUERY, 200, TimestampGranularity::Submission, 2),
+            (F::empty(), 200, TimestampGranularity::None, 0),
+            // one query per dispatch boundary would overflow the query set
+            (
+                both,
+                wgpu::QUERY_SET_MAX_QUERIES,
+                TimestampGranularity::Submission,
+                2,
+            ),
+        ];
+        for (features, dispatches, granularity, queries) in cases {
+            let got = TimestampGranularity::new(features, dispatches);
+            assert_eq!(got, granularity, "{features:?} x{dispatches}");
+            assert_eq!(got.queries(dispatches), queries);
+            assert!(queries <= wgpu::QUERY_SET_MAX_QUERIES);
+        }
+    }
+
+    #[test]
+    fn sps_stats_smooth_in_run_order() {
+        let stats = SpsStats::new(&[100.0, 200.0, 50.0, 150.0], 0.5);
+        assert_eq!(
+            (stats.sps_min, stats.sps_median, stats.sps_max),
+            (50.0, 125.0, 200.0)
+        );
+        // 100 -> 150 -> 100 -> 125
+        assert_eq!(stats.sps_ema, 125.0);
+        assert_eq!(SpsStats::new(&[80.0], 0.3).sps_ema, 80.0);
+    }
+
+    #[test]
+    fn gpu_commands_round_trip() {
+        let requests = [
+            serde_json::json!({"cmd": "smoke", "n": 256}),
+            serde_json::json!({"cmd": "fluid_init", "width": 16, "height": 16}),
+            serde_json::json!({"cmd": "fluid_step", "width": 16, "height": 16, "steps": 2}),
+            serde_json::json!({"cmd": "formats"}),
+            serde_json::json!({"cmd": "device_info"}),
+            serde_json::json!({"cmd": "kernel_bench", "kernel": "jacobi", "width": 16, "height": 16, "iters": 2}),
+            serde_json::json!({"cmd": "readback_bench", "bytes": 4096, "iters": 2}),
+            serde_json::json!({"cmd": "shader_check"}),
+        ];
+        for json in requests {
+            let Some(resp) = round_trip(json.clone()) else {
+                return;
+            };
+            assert_eq!(resp["ok"], true, "{json}");
+            assert!(resp["backend"].is_string(), "{json}");
+        }
+    }
+
+    #[test]
+    fn dye_moments_locate_two_blobs() {
+        let cfg = request(serde_json::json!({"width": 16, "height": 16}));
+        let vel = vec![[0.0; 2]; 256];
+        assert_eq!(summarize(&cfg, 1, &vel, &[0.0; 256]).dye_centroid, None);
+        // equal dye in cells (3, 8) and (11, 8): centered between them in x, spread 4 cells
+        let mut dye = vec![0.0; 256];
+        dye[8 * 16 + 3] = 1.0;
+        dye[8 * 16 + 11] = 1.0;
+        let stats = summarize(&cfg, 1, &vel, &dye);
+        let [cx, cy] = stats.dye_centroid.unwrap();
+        let [sx, sy] = stats.dye_spread.unwrap();
+        assert!((cx - 7.5 / 16.0).abs() < 1e-6 && (cy - 8.5 / 16.0).abs() < 1e-6);
+        assert!((sx - 4.0 / 16.0).abs() < 1e-6 && sy.abs() < 1e-6);
+    }
+
+    /// Run one 2D kernel over the `params` grid once: `params` at binding 0 and `inputs` uploaded
+    /// to storage bindings 1.. in order. Returns every storage binding's contents afterwards, or
+    /// `None` without an adapter.
+    fn dispatch_once(wgsl: &str, params: &Params, inputs: &[&[f32]]) -> Option<Vec<Vec<f32>>> {
+        use wgpu::util::DeviceExt as _;
+
+        let (device, queue, _) = match pollster::block_on(create_device(WgpuBackend::Auto)) {
+            Ok(gpu) => gpu,
+            Err(err) if error::error_code(&err) == ErrorCode::NoAdapter => return None,
+            Err(err) => panic!("{err:#}"),
+        };
+        let pipeline = compile_pipeline(&device, "dispatch-once", wgsl);
+        let params_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("once-params"),
+            contents: bytemuck::bytes_of(params),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let storage: Vec<wgpu::Buffer> = inputs
+            .iter()
+            .map(|data| {
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("once-storage"),
+                    contents: bytemuck::cast_slice(data),
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                })
+            })
+            .collect();
+        let reads: Vec<wgpu::Buffer> = storage
+            .iter()
+            .map(|buf| {
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("once-read"),
+                    size: buf.size(),
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect();
+        let buffers: Vec<&wgpu::Buffer> = std::iter::once(&params_buf).chain(&storage).collect();
+        let group = mk_bind_group(&device, "bg-once", &pipeline, &buffers);
+        let (wg_x, wg_y) = workgroups(params.width, params.height, WORKGROUP_SIDE);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &group, &[]);
+            pass.dispatch_workgroups(wg_x, wg_y, 1);
+        }
+        for (buf, read) in storage.iter().zip(&reads) {
+            encoder.copy_buffer_to_buffer(buf, 0, read, 0, buf.size());
+        }
+        queue.submit(Some(encoder.finish()));
+        let slices: Vec<_> = reads.iter().map(|read| read.slice(..)).collect();
+        Some(
+            slices
+                .iter()
+                .map(|slice| {
+                    map_wait(&device, slice).unwrap();
+                    bytemuck::cast_slice(&slice.get_mapped_range()).to_vec()
+                })
+                .collect(),
+        )
+    }
+
+    /// One fade dispatch over uniform dye: every cell decays by `fade`, and the cells within
+    /// `0.4 * dye_radius` of the source center also gain the fixed 0.02 injection.
+    #[test]
+    fn fade_decays_everywhere_and_injects_at_the_source() {
+        let cfg = request(serde_json::json!({
+            "width": 32, "height": 32, "fade": 0.9, "dye_radius": 0.25,
+        }))
+        .prepare()
+        .expect("valid config")
+        .0;
+        let params = fluid_params(&cfg);
+        let initial = 0.5f32;
+        let cells = 32 * 32;
+        let src = vec![initial; cells];
+        let Some(out) = dispatch_once(FLUID_FADE_WGSL, &params, &[&src, &vec![0.0; cells], &[0.0]])
+        else {
+            return;
+        };
+        let mut sources = 0;
+        for (id, &dye) in out[1].iter().enumerate() {
+            let (x, y) = (id % 32, id / 32);
+            let dx = (x as f32 + 0.5) / 32.0 - 0.5;
+            let dy = (y as f32 + 0.5) / 32.0 - 0.5;
+            let source = (dx * dx + dy * dy).sqrt() <= 0.25 * 0.4;
+            sources += source as usize;
+            let expected = initial * 0.9 + if source { 0.02 } else { 0.0 };
+            assert!(
+                (dye - expected).abs() < 1e-6,
+                "cell ({x}, {y}): {dye} vs {expected}"
+            );
+        }
+        // the source disc is a few cells across, not empty or the whole grid
+        assert!((4..cells / 2).contains(&sources), "{sources} source cells");
+    }
+
+    /// Counts the invocations that reach each cell past the usual bounds guard.
+    const COVERAGE_WGSL: &str = concat!(
+        wgsl_params!(),
+        r#"
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read_write> hits: array<atomic<u32>>;
+
+@compute @workgroup_size(WG, WG, 1)
 fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
   if (gid.x >= p.width || gid.y >= p.height) { return; }
-  let uv = (vec2<f32>(f32(gid.x), f32(gid.y)) + vec2<f32>(0.5, 0.5)) / vec2<f32>(f32(p.width), f32(p.height));
-  let c = uv - vec2<f32>(0.5, 0.5);
-  let r = length(c);
-  let id = idx(gid.x, gid.y);
-  let source = select(0.0, 0.02, r <= p.dye_radius * 0.4);
-  dst[id] = src[id] * p.fade + source;
+  atomicAdd(&hits[gid.y * p.width + gid.x], 1u);
+}
+"#
+    );
+
+    /// With `wgsl_params!()`'s `WG` set to `side`, direct dispatch of `workgroups` and the
+    /// indirect args kernel both run every cell of the grid exactly once.
+    #[test]
+    fn every_cell_is_dispatched_exactly_once() {
+        use wgpu::util::DeviceExt as _;
+
+        let (device, queue, _) = match pollster::block_on(create_device(WgpuBackend::Auto)) {
+            Ok(gpu) => gpu,
+            Err(err) if error::error_code(&err) == ErrorCode::NoAdapter => return,
+            Err(err) => panic!("{err:#}"),
+        };
+        let prelude = format!("const WG: u32 = {WORKGROUP_SIDE}u;");
+        for side in [1, 4, WORKGROUP_SIDE, 16] {
+            let resize = |wgsl: &str| wgsl.replace(&prelude, &format!("const WG: u32 = {side}u;"));
+            let coverage = compile_pipeline(&device, "coverage", &resize(COVERAGE_WGSL));
+            let args_pipeline =
+                compile_pipeline(&device, "dispatch-args", &resize(FLUID_DISPATCH_ARGS_WGSL));
+            for (width, height) in [(1, 1), (7, 5), (8, 8), (9, 17), (64, 3), (33, 100)] {
+                let params = Params {
+                    width,
+                    height,
+                    ..Params::zeroed()
+                };
+                let params_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("coverage-params"),
+                    contents: bytemuck::bytes_of(&params),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+                let args = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("coverage-args"),
+                    size: 12,
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT,
+                    mapped_at_creation: false,
+                });
+                let cells = (width * height) as usize;
+                let hits = mk_storage_f32(&device, "hits", cells, wgpu::BufferUsages::COPY_SRC);
+                let read = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("hits-read"),
+                    size: hits.size(),
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                });
+                let group = mk_bind_group(&device, "bg-coverage", &coverage, &[&params_buf, &hits]);
+                let args_group =
+                    mk_bind_group(&device, "bg-args", &args_pipeline, &[&params_buf, &args]);
+                let (wg_x, wg_y) = workgroups(width, height, side);
+                let mut encoder = device.create_command_encoder(&Default::default());
+                {
+                    let mut pass = encoder.begin_compute_pass(&Default::default());
+                    pass.set_pipeline(&args_pipeline);
+                    pass.set_bind_group(0, &args_group, &[]);
+                    pass.dispatch_workgroups(1, 1, 1);
+                }
+                {
+                    let mut pass = encoder.begin_compute_pass(&Default::default());
+                    pass.set_pipeline(&coverage);
+                    pass.set_bind_group(0, &group, &[]);
+                    pass.dispatch_workgroups(wg_x, wg_y, 1);
+                    pass.dispatch_workgroups_indirect(&args, 0);
+                }
+                encoder.copy_buffer_to_buffer(&hits, 0, &read, 0, hits.size());
+                queue.submit(Some(encoder.finish()));
+                let slice = read.slice(..);
+                map_wait(&device, &slice).unwrap();
+                let counts: Vec<u32> = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+                let wrong = counts.iter().filter(|&&n| n != 2).count();
+                assert_eq!(
+                    wrong, 0,
+                    "{width}x{height}, {side}x{side} workgroups: {wrong} cells not hit once per dispatch"
+                );
+            }
+        }
+    }
+
+    /// Whichever buffer each pass structure leaves the fields in, the GPU reads back the same
+    /// state as the CPU mirror.
+    #[test]
+    fn gpu_readback_follows_the_pass_structure() {
+        // a 4x4 block right of the swirl's center, for the rows with walls; the moving rows
+        // shift it a cell down and to the left on each step after the first
+        let block = serde_json::json!({ "runs": [105, 4, 12, 4, 12, 4, 12, 4, 99] });
+        for (projection_passes, jacobi_iters, viscosity, vorticity, advection, walls, channels) in [
+            (1, 6, 0.0, 0.0, "semi_lagrangian", "none", 1),
+            (2, 6, 0.0, 0.0, "semi_lagrangian", "none", 1),
+            (2, 5, 0.05, 0.0, "semi_lagrangian", "none", 1),
+            (3, 5, 0.0, 0.0, "semi_lagrangian", "none", 1),
+            (4, 6, 0.05, 0.0, "semi_lagrangian", "none", 1),
+            (1, 6, 0.0, 1.0, "semi_lagrangian", "none", 1),
+            (2, 5, 0.05, 1.0, "semi_lagrangian", "none", 1),
+            (1, 6, 0.0, 0.0, "maccormack", "none", 1),
+            (2, 5, 0.05, 1.0, "maccormack", "none", 1),
+            (1, 6, 0.0, 0.0, "semi_lagrangian", "static", 1),
+            (2, 5, 0.05, 1.0, "semi_lagrangian", "static", 1),
+            (2, 5, 0.05, 1.0, "maccormack", "static", 1),
+            (1, 6, 0.0, 0.0, "semi_lagrangian", "moving", 1),
+            (2, 5, 0.05, 1.0, "maccormack", "moving", 1),
+            (1, 6, 0.0, 0.0, "semi_lagrangian", "none", 3),
+            (2, 5, 0.05, 1.0, "maccormack", "static", 3),
+        ] {
+            let mut json = serde_json::json!({
+                "width": 16, "height": 16, "steps": 3, "projection_passes": projection_passes,
+                "jacobi_iters": jacobi_iters, "viscosity": viscosity, "vorticity": vorticity,
+                "advection": advection, "channels": channels, "inject_color": [1.0, 0.4, 0.1],
+            });
+            if walls != "none" {
+                json["obstacles"] = block.clone();
+            }
+            if walls == "moving" {
+                json["obstacle_velocity"] = serde_json::json!([[-10.0, -10.0]]);
+            }
+            if channels == 3 {
+                json["dye_color_schedule"] = serde_json::json!([[0.0, 1.0, 0.0], [0.2, 0.0, 1.0]]);
+            }
+            let fields = |backend: &str| {
+                let mut json = json.clone();
+                json["backend"] = backend.into();
+                let cfg = request(json).prepare().expect("valid config").0;
+                let (mut sim, _) = pollster::block_on(FluidRun::new(&cfg))?;
+                sim.seed();
+                sim.step(cfg.steps);
+                sim.read_fields()
+            };
+            let gpu = match fields("gpu") {
+                Ok(gpu) => gpu,
+                Err(err) if error::error_code(&err) == ErrorCode::NoAdapter => return,
+                Err(err) => panic!("{json}: {err:#}"),
+            };
+            let cpu = fields("cpu").expect("cpu runs anywhere");
+            let vel_diff = gpu
+                .vel
+                .iter()
+                .zip(&cpu.vel)
+                .map(|(g, c)| (g[0] - c[0]).abs().max((g[1] - c[1]).abs()));
+            let dye_diff = gpu.dye.iter().zip(&cpu.dye).map(|(g, c)| (g - c).abs());
+            assert!(vel_diff.fold(0.0, f32::max) < 1e-4, "{json}");
+            assert!(dye_diff.fold(0.0, f32::max) < 1e-4, "{json}");
+        }
+    }
+
+    #[test]
+    fn maccormack_advection_keeps_dye_fronts_sharper() {
+        // a checkerboard carried through the Taylor-Green cells without any source: every
+        // cell the smeared edges push over the footprint threshold counts
+        let footprint = |advection: &str| {
+            let step = round_trip(serde_json::json!({
+                "cmd": "fluid_step", "width": 64, "height": 64, "steps": 60, "backend": "cpu",
+                "init": "taylor_green", "init_dye": "checker", "impulse": 0.0,
+                "advection": advection,
+            }))
+            .expect("cpu runs anywhere");
+            assert_eq!(step["advection"], advection);
+            step["dye_footprint"].as_f64().expect("dye_footprint")
+        };
+        let (semi_lagrangian, maccormack) = (footprint("semi_lagrangian"), footprint("maccormack"));
+        assert!(
+            maccormack < 0.9 * semi_lagrangian,
+            "{maccormack} vs {semi_lagrangian}"
+        );
+    }
+
+    #[test]
+    fn obstacles_hold_still_and_keep_the_divergence_down() {
+        // a 6x12 block inside the Taylor-Green cells, given both ways
+        let (w, h) = (64usize, 64usize);
+        let solid = |i: usize| (36..42).contains(&(i % w)) && (24..36).contains(&(i / w));
+        let flat: Vec<u8> = (0..w * h).map(|i| solid(i) as u8).collect();
+        let mut runs = vec![0u32];
+        for i in 0..w * h {
+            if solid(i) != (runs.len() % 2 == 0) {
+                runs.push(0);
+            }
+            *runs.last_mut().unwrap() += 1;
+        }
+        let run = |obstacles: Option<serde_json::Value>| {
+            let mut json = serde_json::json!({
+                "width": w, "height": h, "steps": 40, "backend": "cpu",
+                "init": "taylor_green", "impulse": 0.0, "jacobi_iters": 100,
+            });
+            if let Some(obstacles) = obstacles {
+                json["obstacles"] = obstacles;
+            }
+            let cfg = request(json).prepare().expect("valid config").0;
+            let (mut sim, _) = pollster::block_on(FluidRun::new(&cfg)).expect("cpu runs anywhere");
+            sim.seed();
+            sim.step(cfg.steps);
+            let fields = sim.read_fields().expect("cpu readback");
+            let stats = summarize(&cfg, cfg.steps, &fields.vel, &fields.dye);
+            (fields.vel, stats)
+        };
+        let (_, open) = run(None);
+        let (vel, walled) = run(Some(serde_json::json!({ "flat": flat })));
+        assert_eq!(vel, run(Some(serde_json::json!({ "runs": runs }))).0);
+        assert_eq!(
+            (open.obstacle_cells, walled.obstacle_cells),
+            (None, Some(72))
+        );
+        assert!(
+            (0..w * h)
+                .filter(|&i| solid(i))
+                .all(|i| vel[i] == [0.0, 0.0])
+        );
+        // the walls leave about as much divergence behind as the edge ring does
+        assert!(
+            walled.avg_divergence < 1.25 * open.avg_divergence,
+            "{} vs {}",
+            walled.avg_divergence,
+            open.avg_divergence
+        );
+
+        // the seed already holds the walls, before any step does
+        for backend in ["cpu", "gpu"] {
+            let cfg = request(serde_json::json!({
+                "width": w, "height": h, "steps": 0, "backend": backend,
+                "init": "taylor_green", "obstacles": { "flat": flat },
+            }))
+            .prepare()
+            .expect("valid config")
+            .0;
+            let mut sim = match pollster::block_on(FluidRun::new(&cfg)) {
+                Ok((sim, _)) => sim,
+                Err(err) if error::error_code(&err) == ErrorCode::NoAdapter => continue,
+                Err(err) => panic!("{err:#}"),
+            };
+            sim.seed();
+            let seeded = sim.read_fields().expect("readback").vel;
+            for (i, v) in seeded.iter().enumerate() {
+                assert_eq!(solid(i), *v == [0.0, 0.0], "{backend} cell {i}: {v:?}");
+            }
+        }
+
+        let err = request(serde_json::json!({
+            "width": 16, "height": 16, "obstacles": { "runs": [200, 100] },
+        }))
+        .prepare()
+        .expect_err("runs past the grid");
+        assert!(format!("{err:#}").contains("256 cells"), "{err:#}");
+    }
+
+    #[test]
+    fn moving_obstacles_carry_their_velocity() {
+        // a 4x4 block moving a cell per step and a 2x2 one half a cell per step
+        let (w, h) = (32usize, 32usize);
+        let block = |x0: usize, y0: usize, size: usize, x: usize, y: usize| {
+            (x0..x0 + size).contains(&x) && (y0..y0 + size).contains(&y)
+        };
+        let start = |x, y| block(8, 6, 4, x, y) || block(20, 20, 2, x, y);
+        let flat: Vec<u8> = (0..w * h).map(|i| start(i % w, i / w) as u8).collect();
+        let json = serde_json::json!({
+            "width": w, "height": h, "steps": 6, "backend": "cpu",
+            "obstacles": { "flat": flat }, "obstacle_velocity": [[10.0, 0.0], [0.0, -5.0]],
+        });
+        let cfg = request(json.clone()).prepare().expect("valid config").0;
+        let (mut sim, _) = pollster::block_on(FluidRun::new(&cfg)).expect("cpu runs anywhere");
+        sim.seed();
+        sim.step(cfg.steps);
+        let vel = sim.read_fields().expect("cpu readback").vel;
+        // the last step (5) shifts them 5 cells right and round(-2.5) = 2 cells down
+        for (i, v) in vel.iter().enumerate() {
+            let (x, y) = (i % w, i / w);
+            let expected = if block(13, 6, 4, x, y) {
+                [10.0, 0.0]
+            } else if block(20, 18, 2, x, y) {
+                [0.0, -5.0]
+            } else {
+                continue;
+            };
+            assert_eq!(*v, expected, "cell ({x}, {y})");
+        }
+        assert_eq!(
+            summarize(&cfg, cfg.steps, &vel, &[0.0; 1024]).obstacle_cells,
+            Some(20)
+        );
+        assert_eq!(
+            cfg.obstacle_positions_at(5),
+            Some(vec![[15.0 / 32.0, 8.0 / 32.0], [21.0 / 32.0, 18.5 / 32.0]])
+        );
+
+        for (patch, message) in [
+            (
+                serde_json::json!({ "obstacle_velocity": [[1.0, 0.0]] }),
+                "2 separate",
+            ),
+            (serde_json::json!({ "obstacles": null }), "needs obstacles"),
+            (serde_json::json!({ "adaptive_dt": true }), "adaptive_dt"),
+        ] {
+            let mut json = json.clone();
+            json.as_object_mut()
+                .unwrap()
+                .extend(patch.as_object().unwrap().clone());
+            let err = request(json).prepare().expect_err(message);
+            assert!(format!("{err:#}").contains(message), "{err:#}");
+        }
+    }
+
+    #[test]
+    fn rgb_dye_stats_follow_the_luminance() {
+        use base64::Engine as _;
+        let num = |v: &serde_json::Value, key: &str| v[key].as_f64().expect(key);
+        let step = |extra: serde_json::Value| {
+            let mut json = serde_json::json!({
+                "cmd": "fluid_step", "width": 32, "height": 32, "steps": 10, "backend": "cpu",
+                "dye_budget": true, "dump_dye": true,
+            });
+            json.as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            round_trip(json).expect("cpu runs anywhere")
+        };
+        let scalar = step(serde_json::json!({}));
+        // white dye: the luma weights sum to 1, so every channel carries the scalar dye
+        let white = step(serde_json::json!({ "channels": 3 }));
+        for key in ["dye_total", "dye_footprint", "dye_added", "dye_faded"] {
+            let (s, w) = (num(&scalar, key), num(&white, key));
+            assert!(
+                (s - w).abs() <= 1e-4 * s.abs().max(1.0),
+                "{key}: {s} vs {w}"
+            );
+        }
+        assert_eq!(scalar["dumps"][0]["field"], "dye");
+
+        let green = step(serde_json::json!({ "channels": 3, "inject_color": [0.0, 1.0, 0.0] }));
+        let dump = &green["dumps"][0];
+        assert_eq!(dump["field"], "dye_rgb");
+        assert_eq!(dump["shape"], serde_json::json!([32, 32, 3]));
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(dump["data_b64"].as_str().unwrap())
+            .unwrap();
+        let rgb: Vec<f32> = bytemuck::pod_collect_to_vec(&bytes);
+        assert!(rgb.chunks(3).all(|c| c[0] == 0.0 && c[2] == 0.0));
+        let g: f64 = rgb.chunks(3).map(|c| c[1] as f64).sum();
+        let total = num(&green, "dye_total");
+        assert!(
+            (g * DYE_LUMINANCE[1] as f64 - total).abs() < 1e-3 * total,
+            "{g} vs {total}"
+        );
+
+        let err = request(serde_json::json!({
+            "width": 16, "height": 16, "channels": 3, "storage": "texture",
+        }))
+        .prepare()
+        .expect_err("texture storage holds one dye value per cell");
+        assert!(format!("{err:#}").contains("needs scalar dye"), "{err:#}");
+    }
+
+    #[test]
+    fn dye_color_schedule_cycles_the_injected_color() {
+        use base64::Engine as _;
+        // a black seed, so all the dye comes from the source
+        let step = |steps: u32| {
+            round_trip(serde_json::json!({
+                "cmd": "fluid_step", "width": 16, "height": 16, "steps": steps, "backend": "cpu",
+                "channels": 3, "inject_color": [0.0, 0.0, 0.0], "dump_dye": true,
+                "dye_color_schedule": [[1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 0.0, 1.0]],
+                "dye_color_every": 2,
+            }))
+            .expect("cpu runs anywhere")
+        };
+        let colors = |step: &serde_json::Value| {
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(step["dumps"][0]["data_b64"].as_str().unwrap())
+                .unwrap();
+            let rgb: Vec<f32> = bytemuck::pod_collect_to_vec(&bytes);
+            [0, 1, 2].map(|k| rgb.chunks(3).map(|c| c[k] as f64).sum::<f64>())
+        };
+        // steps 0-1 red, 2-5 blue
+        let [r, g, b] = colors(&step(2));
+        assert!(r > 0.0 && g == 0.0 && b == 0.0, "{r} {g} {b}");
+        let blue = step(6);
+        assert_eq!(blue["dye_color_final"], serde_json::json!([0.0, 0.0, 1.0]));
+        let [r, g, b] = colors(&blue);
+        assert!(g == 0.0 && b > r, "{r} {g} {b}");
+        // and round again
+        assert_eq!(
+            step(7)["dye_color_final"],
+            serde_json::json!([1.0, 0.0, 0.0])
+        );
+
+        let err = request(serde_json::json!({
+            "width": 16, "height": 16, "dye_color_schedule": [[1.0, 0.0, 0.0]],
+        }))
+        .prepare()
+        .expect_err("scalar dye has no color");
+        assert!(format!("{err:#}").contains("channels: 3"), "{err:#}");
+    }
+
+    #[test]
+    fn pressure_frames_track_the_latest_buffer() {
+        // odd and even iteration counts end the solve in opposite buffers; a frame copied out
+        // of the stale one would trail the CPU's by an iteration
+        for (solver, jacobi_iters, every) in [
+            ("jacobi", 12u32, 5),
+            ("jacobi", 13, 4),
+            ("red_black", 9, 2),
+            ("red_black", 10, 3),
+        ] {
+            let json = serde_json::json!({
+                "width": 16, "height": 16, "steps": 2, "pressure_solver": solver,
+                "jacobi_iters": jacobi_iters, "dump_pressure_every": every,
+            });
+            let frames = |backend: &str| {
+                let mut json = json.clone();
+                json["backend"] = backend.into();
+                let cfg = request(json).prepare().expect("valid config").0;
+                let (mut sim, _) = pollster::block_on(FluidRun::new(&cfg))?;
+                sim.seed();
+                sim.step(cfg.steps);
+                sim.pressure_frames()
+            };
+            let gpu = match frames("gpu") {
+                Ok(gpu) => gpu,
+                Err(err) if error::error_code(&err) == ErrorCode::NoAdapter => return,
+                Err(err) => panic!("{json}: {err:#}"),
+            };
+            let cpu = frames("cpu").expect("cpu runs anywhere");
+            assert_eq!(gpu.len(), jacobi_iters.div_ceil(every) as usize, "{json}");
+            assert_eq!(gpu.len(), cpu.len(), "{json}");
+            for (g, c) in gpu.iter().zip(&cpu) {
+                let diff = g.iter().zip(c).map(|(g, c)| (g - c).abs());
+                assert!(diff.fold(0.0, f32::max) < 1e-6, "{json}");
+            }
+        }
+    }
+
+    #[test]
+    fn target_divergence_stops_the_extra_passes() {
+        let run = |json: serde_json::Value| {
+            let cfg = request(json).prepare().expect("valid config").0;
+            let (mut sim, _) = pollster::block_on(FluidRun::new(&cfg))?;
+            sim.seed();
+            sim.step(cfg.steps);
+            Ok::<_, anyhow::Error>((sim.read_fields()?.vel, sim.projection_tally()?))
+        };
+        // a target no divergence reaches closes the gate before the first extra pass, and the
+        // passes it skips leave the velocity exactly as one pass does
+        let base = serde_json::json!({"width": 16, "height": 16, "steps": 3, "backend": "cpu"});
+        let mut single = base.clone();
+        single["projection_passes"] = 1.into();
+        let mut skipped = base.clone();
+        skipped["projection_passes"] = 4.into();
+        skipped["target_divergence"] = 1e9.into();
+        let (vel, tally) = run(skipped).unwrap();
+        assert_eq!(vel, run(single).unwrap().0);
+        let tally = tally.expect("gated");
+        assert_eq!((tally.extra_sum, tally.steps), (0, 3));
+
+        // in between, some steps stop early; the GPU's gate must agree pass for pass
+        let json = serde_json::json!({
+            "width": 32, "height": 32, "steps": 12, "projection_passes": 4,
+            "target_divergence": 40.0,
+        });
+        let with = |backend: &str| {
+            let mut json = json.clone();
+            json["backend"] = backend.into();
+            run(json)
+        };
+        let (cpu_vel, cpu) = with("cpu").unwrap();
+        let cpu = cpu.expect("gated");
+        assert!(
+            0 < cpu.extra_sum && cpu.extra_sum < 3 * cpu.steps,
+            "{cpu:?}"
+        );
+        if cpu.last_extra < 3 {
+            assert!(cpu.last_divergence <= 40.0, "{cpu:?}");
+        }
+        let (gpu_vel, gpu) = match with("gpu") {
+            Ok(gpu) => gpu,
+            Err(err) if error::error_code(&err) == ErrorCode::NoAdapter => return,
+            Err(err) => panic!("{err:#}"),
+        };
+        let gpu = gpu.expect("gated");
+        assert_eq!(
+            (gpu.last_extra, gpu.extra_sum, gpu.extra_max, gpu.steps),
+            (cpu.last_extra, cpu.extra_sum, cpu.extra_max, cpu.steps)
+        );
+        assert!((gpu.last_divergence - cpu.last_divergence).abs() < 1e-3 * cpu.last_divergence);
+        let diff = gpu_vel
+            .iter()
+            .zip(&cpu_vel)
+            .map(|(g, c)| (g[0] - c[0]).abs().max((g[1] - c[1]).abs()));
+        assert!(diff.fold(0.0, f32::max) < 1e-3);
+    }
+
+    #[test]
+    fn dispatch_trace_follows_the_ping_pong() {
+        let cfg = request(serde_json::json!({
+            "width": 16, "height": 16, "steps": 2, "jacobi_iters": 5, "projection_passes": 2,
+            "diffuse_iters": 3, "trace_dispatches": true,
+        }))
+        .prepare()
+        .expect("valid config")
+        .0;
+        let mut sim = match pollster::block_on(FluidRun::new(&cfg)) {
+            Ok((sim, _)) => sim,
+            Err(err) if error::error_code(&err) == ErrorCode::NoAdapter => return,
+            Err(err) => panic!("{err:#}"),
+        };
+        sim.seed();
+        sim.step(cfg.steps);
+        // an odd solve ends in pressure_b; the cleanup pass reads the velocity the first left
+        // in vel_a, and dye follows the final velocity in vel_b
+        let jacobi = [
+            "jacobi_ab",
+            "jacobi_ba",
+            "jacobi_ab",
+            "jacobi_ba",
+            "jacobi_ab",
+        ];
+        // an odd viscosity solve starts straight into vel_b
+        let diffuse = ["diffuse_rhs_b", "diffuse_ba", "diffuse_ab"];
+        let mut expected = vec!["advect_vel"];
+        expected.extend(diffuse);
+        expected.push("div");
+        expected.extend(jacobi);
+        expected.extend(["project_from_b", "div_from_a"]);
+        expected.extend(jacobi);
+        expected.extend(["project_cleanup_from_b", "advect_dye_from_b", "fade"]);
+        assert_eq!(sim.dispatch_trace().expect("traced"), expected);
+    }
+
+    #[test]
+    fn zero_viscosity_skips_the_diffusion_solve() {
+        use base64::Engine as _;
+        let dumps = |backend: &str, viscosity: f32, diffuse_iters: u32| {
+            let step = round_trip(serde_json::json!({
+                "cmd": "fluid_step", "width": 32, "height": 32, "steps": 8, "backend": backend,
+                "viscosity": viscosity, "diffuse_iters": diffuse_iters,
+                "dump_vel": true, "dump_dye": true,
+            }))?;
+            let fields: Vec<Vec<f32>> = ["vel", "dye"]
+                .iter()
+                .zip(step["dumps"].as_array().expect("dumps"))
+                .map(|(field, dump)| {
+                    assert_eq!(dump["field"], *field);
+                    let bytes = base64::engine::general_purpose::STANDARD
+                        .decode(dump["data_b64"].as_str().unwrap())
+                        .unwrap();
+                    bytemuck::pod_collect_to_vec(&bytes)
+                })
+                .collect();
+            Some(fields)
+        };
+        let cpu = dumps("cpu", 0.0, 1).expect("cpu runs anywhere");
+        // with the solve skipped, diffuse_iters changes nothing down to the bit
+        assert_eq!(dumps("cpu", 0.0, 200), Some(cpu.clone()));
+        assert_ne!(dumps("cpu", 0.001, 20), Some(cpu.clone()));
+        let Some(gpu) = dumps("gpu", 0.0, 1) else {
+            return;
+        };
+        assert_eq!(dumps("gpu", 0.0, 200), Some(gpu.clone()));
+        assert_ne!(dumps("gpu", 0.001, 20), Some(gpu.clone()));
+        for (g, c) in gpu.iter().zip(&cpu) {
+            let diff = g.iter().zip(c).map(|(g, c)| (g - c).abs());
+            assert!(diff.fold(0.0, f32::max) < 1e-4);
+        }
+
+        let cfg = request(serde_json::json!({
+            "width": 16, "height": 16, "steps": 1, "viscosity": 0.0, "diffuse_iters": 200,
+            "trace_dispatches": true,
+        }))
+        .prepare()
+        .expect("valid config")
+        .0;
+        let (mut sim, _) = pollster::block_on(FluidRun::new(&cfg)).expect("gpu ran above");
+        sim.seed();
+        sim.step(cfg.steps);
+        let trace = sim.dispatch_trace().expect("traced");
+        assert!(
+            trace.iter().all(|name| !name.starts_with("diffuse")),
+            "{trace:?}"
+        );
+    }
+
+    #[test]
+    fn the_same_request_hits_the_pipeline_cache_the_second_time() {
+        // texture storage compiles kernels none of the other tests run on the cached device
+        let json = serde_json::json!({
+            "cmd": "fluid_step", "width": 16, "height": 16, "steps": 1, "storage": "texture",
+        });
+        let Some(first) = round_trip(json.clone()) else {
+            return;
+        };
+        let second = round_trip(json).expect("ran once");
+        assert_eq!(
+            (&first["pipeline_cache_hit"], &second["pipeline_cache_hit"]),
+            (&false.into(), &true.into()),
+            "{first}"
+        );
+    }
+
+    #[test]
+    fn uncaptured_wgpu_errors_fail_the_request() {
+        let device = pollster::block_on(async {
+            let adapter =
+                request_adapter(WgpuBackend::default(), &AdapterChoice::default()).await?;
+            request_device(&adapter, wgpu::Features::empty()).await
+        });
+        let (device, _queue) = match device {
+            Ok(device) => device,
+            Err(err) if error::error_code(&err) == ErrorCode::NoAdapter => return,
+            Err(err) => panic!("{err:#}"),
+        };
+        // mapping for both reading and writing is a validation error
+        let _ = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("invalid"),
+            size: 4,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::MAP_WRITE,
+            mapped_at_creation: false,
+        });
+        let err = check_device_error().expect_err("recorded");
+        assert_eq!(error::error_code(&err), ErrorCode::DeviceError);
+        assert!(format!("{err:#}").contains("wgpu error"), "{err:#}");
+        check_device_error().expect("checking clears the error");
+    }
+
+    #[test]
+    fn serve_answers_every_line() {
+        let input = concat!(
+            "{\"cmd\": \"fluid_step\", \"width\": 16, \"height\": 16, \"steps\": 2, ",
+            "\"backend\": \"cpu\", \"readback\": false}\n",
+            "\n",
+            "{not json\n",
+            "{\"cmd\": \"fluid_step\", \"width\": 16, \"height\": 16, \"steps\": 1, ",
+            "\"backend\": \"cpu\", \"readback\": false}\n",
+        );
+        let mut out = Vec::new();
+        serve_lines(input.as_bytes(), &mut out).unwrap();
+        let replies: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("one JSON reply per line"))
+            .collect();
+        assert_eq!(replies.len(), 3, "{replies:?}");
+        assert_eq!(
+            (&replies[0]["ok"], &replies[0]["steps"]),
+            (&true.into(), &2.into())
+        );
+        assert_eq!(replies[1]["error_code"], "invalid_json");
+        assert_eq!(
+            (&replies[2]["ok"], &replies[2]["steps"]),
+            (&true.into(), &1.into())
+        );
+    }
+
+    #[test]
+    fn serve_recovers_from_device_errors() {
+        let (device, _queue, _) = match pollster::block_on(create_device(WgpuBackend::default())) {
+            Ok(device) => device,
+            Err(err) if error::error_code(&err) == ErrorCode::NoAdapter => return,
+            Err(err) => panic!("{err:#}"),
+        };
+        // a validation error on the cached device the first request will run on
+        let _ = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("invalid"),
+            size: 4,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::MAP_WRITE,
+            mapped_at_creation: false,
+        });
+        let request = "{\"cmd\": \"fluid_step\", \"width\": 16, \"height\": 16, \"steps\": 1}\n";
+        let mut out = Vec::new();
+        serve_lines(request.repeat(2).as_bytes(), &mut out).unwrap();
+        let replies: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("one JSON reply per line"))
+            .collect();
+        assert_eq!(replies.len(), 2, "{replies:?}");
+        assert_eq!(replies[0]["error_code"], "device_error", "{replies:?}");
+        assert_eq!(replies[1]["ok"], true, "{replies:?}");
+        let (fresh, _, _) = pollster::block_on(create_device(WgpuBackend::default())).unwrap();
+        assert!(fresh != device, "the failed device was evicted");
+    }
+
+    #[test]
+    fn bad_requests_carry_error_codes() {
+        for input in [
+            "{",
+            r#"{"cmd": "warp_drive"}"#,
+            r#"{"cmd": "fluid_step", "width": "wide"}"#,
+        ] {
+            let err = dispatch(input).err().expect(input);
+            assert_eq!(error::error_code(&err), ErrorCode::InvalidJson, "{input}");
+            let json = error::error_json(&err);
+            assert_eq!(json["ok"], false);
+            assert_eq!(json["error_code"], "invalid_json");
+        }
+    }
 }
-"#;
@@ -0,0 +1,262 @@
+//! Named parameter presets and environment defaults for the fluid commands.
+//!
+//! `{"cmd":"fluid_step","preset":"tight_vortex"}` loads `<dir>/tight_vortex.toml` and fills in
+//! every key the request leaves out; keys present in the JSON always win. `<dir>` is
+//! `$GPU_SIDECAR_PRESETS` when set, else the `presets/` directory shipped with the crate.
+//!
+//! Keys still missing after that are taken from `FLUID_<KEY>` environment variables
+//! (`FLUID_JACOBI_ITERS=60`), so a fleet of containers can share defaults without every
+//! payload repeating them. Only then do the built-in defaults apply.
+
+use anyhow::{Context, Result};
+
+use crate::error::{ErrorCode, ResultExt};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Environment variable overriding the preset directory.
+const PRESETS_ENV: &str = "GPU_SIDECAR_PRESETS";
+
+/// Prefix of the environment variables that default absent parameters.
+const ENV_PREFIX: &str = "FLUID_";
+
+/// Commands that accept `preset` and read `FLUID_*` defaults.
+const PRESET_COMMANDS: [&str; 2] = ["fluid_step", "fluid_render"];
+
+/// Where each resolved parameter came from (`report_param_sources`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ParamSource {
+    Json,
+    Preset,
+    Env,
+    Default,
+}
+
+/// The keys `apply` and `apply_env` filled into a request.
+#[derive(Debug, Default)]
+pub(crate) struct Filled {
+    preset: Vec<String>,
+    env: Vec<String>,
+}
+
+impl Filled {
+    /// The keys taken from the environment.
+    pub(crate) fn env_keys(&self) -> &[String] {
+        &self.env
+    }
+
+    /// The source of every field of `resolved` (the parsed parameters), given the merged
+    /// request JSON `raw` it was parsed from.
+    pub(crate) fn sources(
+        &self,
+        raw: &Value,
+        resolved: &impl Serialize,
+    ) -> BTreeMap<String, ParamSource> {
+        let Ok(Value::Object(resolved)) = serde_json::to_value(resolved) else {
+            return BTreeMap::new();
+        };
+        resolved
+            .keys()
+            .map(|key| {
+                let source = if self.preset.contains(key) {
+                    ParamSource::Preset
+                } else if self.env.contains(key) {
+                    ParamSource::Env
+                } else if raw.get(key).is_some() {
+                    ParamSource::Json
+                } else {
+                    ParamSource::Default
+                };
+                (key.clone(), source)
+            })
+            .collect()
+    }
+}
+
+/// Fill `request` in place from its `preset` and then the environment (see the module docs).
+pub(crate) fn fill(request: &mut Value) -> Result<Filled> {
+    let preset = apply(request)?;
+    let env = apply_env(request, std::env::vars());
+    Ok(Filled { preset, env })
+}
+
+/// Merge the request's `preset` (if any) under its explicit fields, in place; returns the keys it
+/// filled.
+fn apply(request: &mut Value) -> Result<Vec<String>> {
+    let Some(obj) = request.as_object_mut() else {
+        return Ok(Vec::new());
+    };
+    let Some(name) = obj.get("preset").and_then(Value::as_str) else {
+        return Ok(Vec::new());
+    };
+    let cmd = obj.get("cmd").and_then(Value::as_str).unwrap_or_default();
+    if !PRESET_COMMANDS.contains(&cmd) {
+        return Err(anyhow::anyhow!(
+            "preset is only supported by {}",
+            PRESET_COMMANDS.join(", ")
+        ))
+        .code(ErrorCode::UnsupportedFeature);
+    }
+    let name = name.to_owned();
+    let preset = load(&name)?;
+    let mut filled = Vec::new();
+    for (key, value) in preset {
+        anyhow::ensure!(
+            key != "cmd" && key != "preset",
+            "preset {name} may not set `{key}`"
+        );
+        if !obj.contains_key(&key) {
+            filled.push(key.clone());
+            obj.insert(key, value);
+        }
+    }
+    Ok(filled)
+}
+
+/// Fill every key a fluid request still leaves out from `FLUID_<KEY>` in `vars`; returns the
+/// keys filled. A value is read as JSON when it parses (`60`, `true`, `[0.5, 0.5]`) and as a
+/// string otherwise (`taylor_green`).
+fn apply_env(request: &mut Value, vars: impl Iterator<Item = (String, String)>) -> Vec<String> {
+    let Some(obj) = request.as_object_mut() else {
+        return Vec::new();
+    };
+    let cmd = obj.get("cmd").and_then(Value::as_str).unwrap_or_default();
+    if !PRESET_COMMANDS.contains(&cmd) {
+        return Vec::new();
+    }
+    let mut filled = Vec::new();
+    for (var, text) in vars {
+        let Some(key) = var.strip_prefix(ENV_PREFIX).map(str::to_ascii_lowercase) else {
+            continue;
+        };
+        if key.is_empty() || key == "cmd" || key == "preset" || obj.contains_key(&key) {
+            continue;
+        }
+        let value = serde_json::from_str(&text).unwrap_or(Value::String(text));
+        filled.push(key.clone());
+        obj.insert(key, value);
+    }
+    filled
+}
+
+fn load(name: &str) -> Result<serde_json::Map<String, Value>> {
+    anyhow::ensure!(
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'),
+        "preset name {name:?} may only contain letters, digits, '_' and '-'"
+    );
+    let dir = std::env::var_os(PRESETS_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/presets")));
+    let path = dir.join(format!("{name}.toml"));
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read preset {}", path.display()))?;
+    toml::from_str(&text).with_context(|| format!("invalid preset {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        pairs
+            .iter()
+            .map(|&(var, text)| (var.to_owned(), text.to_owned()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn the_request_beats_the_environment_which_beats_the_preset() {
+        let mut request = json!({"cmd": "fluid_step", "preset": "tight_vortex", "width": 64});
+        let preset = apply(&mut request).expect("shipped preset");
+        let env = apply_env(
+            &mut request,
+            vars(&[
+                ("FLUID_WIDTH", "32"),
+                ("FLUID_JACOBI_ITERS", "60"),
+                ("FLUID_SEED", "8"),
+                ("FLUID_CMD", "smoke"),
+                ("FLUID_PRESET", "gentle_plume"),
+                ("GPU_WIDTH", "8"),
+            ]),
+        );
+        assert_eq!(
+            (
+                &request["width"],
+                &request["height"],
+                &request["jacobi_iters"]
+            ),
+            (&json!(64), &json!(256), &json!(40))
+        );
+        assert_eq!(env, ["seed"]);
+        assert_eq!(request["seed"], 8);
+        assert_eq!(
+            (&request["cmd"], &request["preset"]),
+            (&json!("fluid_step"), &json!("tight_vortex"))
+        );
+        assert!(preset.contains(&"height".to_owned()) && !preset.contains(&"width".to_owned()));
+    }
+
+    #[test]
+    fn environment_values_parse_as_json_or_stay_strings() {
+        let mut request = json!({"cmd": "fluid_render"});
+        apply_env(
+            &mut request,
+            vars(&[
+                ("FLUID_DT", "0.05"),
+                ("FLUID_CLAMP_HISTORY", "true"),
+                ("FLUID_SOURCE_CENTER", "[0.5, 0.25]"),
+                ("FLUID_INIT", "taylor_green"),
+                ("FLUID_PRESSURE_SOLVER", "\"jacobi\""),
+            ]),
+        );
+        assert_eq!(
+            request,
+            json!({
+                "cmd": "fluid_render", "dt": 0.05, "clamp_history": true,
+                "source_center": [0.5, 0.25], "init": "taylor_green",
+                "pressure_solver": "jacobi",
+            })
+        );
+        let mut smoke = json!({"cmd": "smoke"});
+        assert!(apply_env(&mut smoke, vars(&[("FLUID_N", "64")])).is_empty());
+        assert_eq!(smoke, json!({"cmd": "smoke"}));
+    }
+
+    #[test]
+    fn param_sources_name_where_each_key_came_from() {
+        let raw = json!({"cmd": "fluid_step", "width": 64, "height": 256, "dt": 0.05});
+        let filled = Filled {
+            preset: vec!["height".to_owned()],
+            env: vec!["dt".to_owned()],
+        };
+        let resolved = json!({"width": 64, "height": 256, "dt": 0.05, "fade": 0.99});
+        let sources = filled.sources(&raw, &resolved);
+        assert_eq!(
+            sources.into_iter().collect::<Vec<_>>(),
+            [
+                ("dt".to_owned(), ParamSource::Env),
+                ("fade".to_owned(), ParamSource::Default),
+                ("height".to_owned(), ParamSource::Preset),
+                ("width".to_owned(), ParamSource::Json),
+            ]
+        );
+    }
+
+    #[test]
+    fn an_invalid_environment_value_fails_the_request() {
+        let mut request = json!({"cmd": "fluid_step", "width": 16, "height": 16});
+        apply_env(&mut request, vars(&[("FLUID_CHANNELS", "2")]));
+        let cfg: crate::FluidStepRequest = serde_json::from_value(request).expect("parses");
+        let err = cfg.prepare().expect_err("channels 2");
+        assert_eq!(crate::error::error_code(&err), ErrorCode::InvalidRequest);
+        assert!(format!("{err:#}").contains("channels 2"), "{err:#}");
+    }
+}
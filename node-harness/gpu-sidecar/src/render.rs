@@ -0,0 +1,500 @@
+//! Field-to-PNG rendering for `fluid_render`: field selection, output sizing, resampling,
+//! colormaps and diagnostic overlays.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{FluidFields, FluidRenderRequest, divergence, vorticity};
+
+/// Largest rendered image edge, in pixels.
+const MAX_IMAGE_EDGE: u32 = 8192;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RenderField {
+    #[default]
+    Dye,
+    Speed,
+}
+
+impl RenderField {
+    pub(crate) fn values(self, fields: &FluidFields) -> Vec<f32> {
+        match self {
+            Self::Dye => fields.dye.clone(),
+            Self::Speed => fields
+                .vel
+                .iter()
+                .map(|v| (v[0] * v[0] + v[1] * v[1]).sqrt())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Colormap {
+    #[default]
+    Viridis,
+    Magma,
+    Gray,
+    /// `colormap_stops` from the request.
+    Custom,
+    /// Velocity direction as hue and speed as value (the optical-flow color wheel); renders
+    /// the velocity whatever the `field`.
+    FlowHsv,
+}
+
+impl Colormap {
+    /// Evenly spaced sRGB stops, interpolated linearly (none for `Custom`).
+    fn stops(self) -> &'static [[u8; 3]] {
+        match self {
+            Self::Viridis => &[
+                [68, 1, 84],
+                [71, 44, 122],
+                [59, 81, 139],
+                [44, 113, 142],
+                [33, 144, 141],
+                [39, 173, 129],
+                [92, 200, 99],
+                [170, 220, 50],
+                [253, 231, 37],
+            ],
+            Self::Magma => &[
+                [0, 0, 4],
+                [28, 16, 68],
+                [79, 18, 123],
+                [129, 37, 129],
+                [181, 54, 122],
+                [229, 80, 100],
+                [251, 135, 97],
+                [254, 194, 135],
+                [252, 253, 191],
+            ],
+            Self::Gray => &[[0, 0, 0], [255, 255, 255]],
+            Self::Custom | Self::FlowHsv => &[],
+        }
+    }
+}
+
+/// One `colormap_stops` entry: the color at position `t` in [0, 1].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct ColormapStop {
+    pub(crate) t: f32,
+    pub(crate) rgb: [u8; 3],
+}
+
+/// A colormap ready for lookup: a built-in one, validated custom stops, or the flow wheel.
+pub(crate) enum Palette {
+    Builtin(&'static [[u8; 3]]),
+    Custom(Vec<ColormapStop>),
+    FlowHsv,
+}
+
+impl Palette {
+    /// `stops` are only allowed (and required) with `Colormap::Custom`: at least two, with `t`
+    /// non-decreasing within [0, 1].
+    pub(crate) fn new(colormap: Colormap, stops: &[ColormapStop]) -> Result<Self> {
+        if !matches!(colormap, Colormap::Custom) {
+            anyhow::ensure!(
+                stops.is_empty(),
+                "colormap_stops needs colormap: \"custom\""
+            );
+            return Ok(match colormap {
+                Colormap::FlowHsv => Self::FlowHsv,
+                _ => Self::Builtin(colormap.stops()),
+            });
+        }
+        anyhow::ensure!(
+            stops.len() >= 2,
+            "colormap \"custom\" needs at least two colormap_stops"
+        );
+        for (i, stop) in stops.iter().enumerate() {
+            anyhow::ensure!(
+                (0.0..=1.0).contains(&stop.t),
+                "colormap_stops[{i}].t = {} is outside [0, 1]",
+                stop.t
+            );
+            anyhow::ensure!(
+                i == 0 || stops[i - 1].t <= stop.t,
+                "colormap_stops must be sorted by t (stop {i} has t = {} after {})",
+                stop.t,
+                stops[i - 1].t
+            );
+        }
+        Ok(Self::Custom(stops.to_vec()))
+    }
+
+    /// Color for `t` in [0, 1] (clamped; NaN maps to the low end). Custom maps hold their end
+    /// colors outside the first/last stop; the flow wheel takes `t` once around its hues.
+    pub(crate) fn map(&self, t: f32) -> [u8; 3] {
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        let (a, b, f) = match self {
+            Self::FlowHsv => return hsv_to_rgb(t * 360.0, 1.0, 1.0),
+            Self::Builtin(stops) => {
+                let pos = t * (stops.len() - 1) as f32;
+                let i = (pos.floor() as usize).min(stops.len() - 2);
+                (stops[i], stops[i + 1], pos - i as f32)
+            }
+            Self::Custom(stops) => {
+                // last segment starting at or below t (the first one when t is below every stop)
+                let i = stops[1..stops.len() - 1]
+                    .iter()
+                    .take_while(|s| s.t <= t)
+                    .count();
+                let (a, b) = (stops[i], stops[i + 1]);
+                let span = b.t - a.t;
+                let f = if span > 0.0 {
+                    ((t - a.t) / span).clamp(0.0, 1.0)
+                } else {
+                    1.0
+                };
+                (a.rgb, b.rgb, f)
+            }
+        };
+        std::array::from_fn(|k| (a[k] as f32 + (b[k] as f32 - a[k] as f32) * f).round() as u8)
+    }
+}
+
+/// sRGB bytes of hue `h` (degrees), saturation `s` and value `v` (both in [0, 1]).
+fn hsv_to_rgb(h: f32, s: f32, v: f32) -> [u8; 3] {
+    let h = h.rem_euclid(360.0) / 60.0;
+    let c = v * s;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let [r, g, b] = match h as u32 {
+        0 => [c, x, 0.0],
+        1 => [x, c, 0.0],
+        2 => [0.0, c, x],
+        3 => [0.0, x, c],
+        4 => [x, 0.0, c],
+        _ => [c, 0.0, x],
+    };
+    let m = v - c;
+    [r, g, b].map(|k| ((k + m) * 255.0).round().clamp(0.0, 255.0) as u8)
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Resample {
+    #[default]
+    Nearest,
+    Bilinear,
+}
+
+/// Output image size. With `square_pixels` the image keeps the grid's aspect ratio: at most one
+/// of `out_width`/`out_height` may be given and the other follows from it (grid size if
+/// neither). Without it both are free and default to the grid size.
+pub(crate) fn output_size(
+    grid_w: u32,
+    grid_h: u32,
+    square_pixels: bool,
+    out_width: Option<u32>,
+    out_height: Option<u32>,
+) -> Result<(u32, u32)> {
+    let scaled = |len: u32, num: u32, den: u32| {
+        ((len as f64 * num as f64 / den as f64).round() as u32).max(1)
+    };
+    let (w, h) = match (square_pixels, out_width, out_height) {
+        (true, Some(_), Some(_)) => anyhow::bail!(
+            "square_pixels derives one image dimension from the other; \
+             set square_pixels: false to give both out_width and out_height"
+        ),
+        (true, Some(w), None) => (w, scaled(w, grid_h, grid_w)),
+        (true, None, Some(h)) => (scaled(h, grid_w, grid_h), h),
+        (_, w, h) => (w.unwrap_or(grid_w), h.unwrap_or(grid_h)),
+    };
+    anyhow::ensure!(
+        (1..=MAX_IMAGE_EDGE).contains(&w) && (1..=MAX_IMAGE_EDGE).contains(&h),
+        "image size {w}x{h} must be within 1..={MAX_IMAGE_EDGE} per edge"
+    );
+    Ok((w, h))
+}
+
+/// Resample a row-major `w`x`h` scalar field to `out_w`x`out_h`, aligning cell centers.
+pub(crate) fn resample(
+    values: &[f32],
+    w: u32,
+    h: u32,
+    out_w: u32,
+    out_h: u32,
+    mode: Resample,
+) -> Vec<f32> {
+    if (w, h) == (out_w, out_h) {
+        return values.to_vec();
+    }
+    let at = |x: usize, y: usize| values[y * w as usize + x];
+    let src = |dst: u32, out: u32, len: u32| {
+        ((dst as f32 + 0.5) * len as f32 / out as f32 - 0.5).clamp(0.0, (len - 1) as f32)
+    };
+    let mut out = Vec::with_capacity(out_w as usize * out_h as usize);
+    for oy in 0..out_h {
+        let sy = src(oy, out_h, h);
+        for ox in 0..out_w {
+            let sx = src(ox, out_w, w);
+            out.push(match mode {
+                Resample::Nearest => at(sx.round() as usize, sy.round() as usize),
+                Resample::Bilinear => {
+                    let (x0, y0) = (sx.floor() as usize, sy.floor() as usize);
+                    let (x1, y1) = ((x0 + 1).min(w as usize - 1), (y0 + 1).min(h as usize - 1));
+                    let (fx, fy) = (sx - x0 as f32, sy - y0 as f32);
+                    let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * fx;
+                    let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * fx;
+                    top + (bottom - top) * fy
+                }
+            });
+        }
+    }
+    out
+}
+
+/// Finite min/max of `values` (`[0, 0]` when none are finite).
+pub(crate) fn value_range(values: &[f32]) -> [f32; 2] {
+    let mut range = [f32::INFINITY, f32::NEG_INFINITY];
+    for &v in values.iter().filter(|v| v.is_finite()) {
+        range = [range[0].min(v), range[1].max(v)];
+    }
+    if range[0] > range[1] {
+        [0.0, 0.0]
+    } else {
+        range
+    }
+}
+
+/// RGBA8 pixels for `values`, mapping `range` onto the ends of `palette`.
+pub(crate) fn colorize(values: &[f32], range: [f32; 2], palette: &Palette) -> Vec<u8> {
+    let span = (range[1] - range[0]).max(1e-12);
+    let mut rgba = Vec::with_capacity(values.len() * 4);
+    for &v in values {
+        let [r, g, b] = palette.map((v - range[0]) / span);
+        rgba.extend_from_slice(&[r, g, b, 255]);
+    }
+    rgba
+}
+
+/// RGBA8 pixels for the velocity components `vx`/`vy`: hue from the direction (0 = +x, counter-
+/// clockwise), full saturation, and value `|v| / max_speed`. Non-finite cells render black.
+pub(crate) fn flow_rgba(vx: &[f32], vy: &[f32], max_speed: f32) -> Vec<u8> {
+    let scale = 1.0 / max_speed.max(1e-12);
+    let mut rgba = Vec::with_capacity(vx.len() * 4);
+    for (&x, &y) in vx.iter().zip(vy) {
+        let [r, g, b] = if x.is_finite() && y.is_finite() {
+            let value = ((x * x + y * y).sqrt() * scale).min(1.0);
+            hsv_to_rgb(y.atan2(x).to_degrees(), 1.0, value)
+        } else {
+            [0, 0, 0]
+        };
+        rgba.extend_from_slice(&[r, g, b, 255]);
+    }
+    rgba
+}
+
+/// RGBA8 image of `req.field` resampled to `width`x`height`, plus the value range mapped onto
+/// `palette` (built from `req.colormap`). The flow wheel renders the velocity instead, with
+/// speeds `[0, max |v|]` as the range.
+pub(crate) fn render_rgba(
+    req: &FluidRenderRequest,
+    palette: &Palette,
+    fields: &FluidFields,
+    width: u32,
+    height: u32,
+) -> (Vec<u8>, [f32; 2]) {
+    if let Palette::FlowHsv = palette {
+        let max_speed = value_range(&RenderField::Speed.values(fields))[1];
+        let [vx, vy] = [0, 1].map(|k| {
+            let component: Vec<f32> = fields.vel.iter().map(|v| v[k]).collect();
+            resample(
+                &component,
+                req.sim.width,
+                req.sim.height,
+                width,
+                height,
+                req.resample,
+            )
+        });
+        return (flow_rgba(&vx, &vy, max_speed), [0.0, max_speed]);
+    }
+    let values = req.field.values(fields);
+    let range = value_range(&values);
+    let pixels = resample(
+        &values,
+        req.sim.width,
+        req.sim.height,
+        width,
+        height,
+        req.resample,
+    );
+    (colorize(&pixels, range, palette), range)
+}
+
+/// A signed diagnostic drawn over the render by `overlay`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Overlay {
+    Divergence,
+    /// The last pressure solve (needs `FluidFields::pressure`).
+    Pressure,
+    Curl,
+}
+
+impl Overlay {
+    pub(crate) fn values(self, w: u32, h: u32, fields: &FluidFields) -> Vec<f32> {
+        let (w, h) = (w as usize, h as usize);
+        let cells = (0..h).flat_map(|y| (0..w).map(move |x| (x, y)));
+        match self {
+            Self::Divergence => cells
+                .map(|(x, y)| divergence(&fields.vel, w, h, x, y))
+                .collect(),
+            Self::Pressure => fields.pressure.clone(),
+            Self::Curl => cells
+                .map(|(x, y)| vorticity(&fields.vel, w, h, x, y) as f32)
+                .collect(),
+        }
+    }
+}
+
+/// Blend `req.overlay` over `rgba` (`width`x`height`) as a diverging heatmap: red for positive,
+/// blue for negative, normalized to `[-m, m]` with `m` the largest finite `|value|`. Each
+/// pixel's opacity is `overlay_alpha * |value| / m`, so zero leaves the render untouched.
+/// Returns the `[-m, m]` range used, or `None` without an overlay.
+pub(crate) fn apply_overlay(
+    req: &FluidRenderRequest,
+    fields: &FluidFields,
+    rgba: &mut [u8],
+    width: u32,
+    height: u32,
+) -> Option<[f32; 2]> {
+    let overlay = req.overlay?;
+    let values = overlay.values(req.sim.width, req.sim.height, fields);
+    let [lo, hi] = value_range(&values);
+    let m = lo.abs().max(hi.abs());
+    let pixels = resample(
+        &values,
+        req.sim.width,
+        req.sim.height,
+        width,
+        height,
+        req.resample,
+    );
+    let scale = 1.0 / m.max(1e-12);
+    for (px, &v) in rgba.chunks_exact_mut(4).zip(&pixels) {
+        if !v.is_finite() {
+            continue;
+        }
+        let t = (v * scale).clamp(-1.0, 1.0);
+        let tint = if t >= 0.0 {
+            [255.0, 0.0, 0.0]
+        } else {
+            [0.0, 0.0, 255.0]
+        };
+        let alpha = req.overlay_alpha * t.abs();
+        for (c, tint) in px.iter_mut().zip(tint) {
+            *c = (*c as f32 + (tint - *c as f32) * alpha).round() as u8;
+        }
+    }
+    Some([-m, m])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{encode_png_rgba, run_fluid};
+    use std::path::PathBuf;
+
+    /// Largest per-channel difference tolerated against the golden image (absorbs float
+    /// differences across platforms).
+    const CHANNEL_TOLERANCE: u8 = 2;
+
+    fn decode_png(bytes: &[u8]) -> (u32, u32, Vec<u8>) {
+        let mut reader = png::Decoder::new(std::io::Cursor::new(bytes))
+            .read_info()
+            .expect("png header");
+        let mut buf = vec![0; reader.output_buffer_size().expect("png size")];
+        let info = reader.next_frame(&mut buf).expect("png frame");
+        buf.truncate(info.buffer_size());
+        (info.width, info.height, buf)
+    }
+
+    #[test]
+    fn custom_palette_interpolates_between_stops() {
+        let stop = |t, rgb| ColormapStop { t, rgb };
+        let stops = [
+            stop(0.25, [0, 0, 0]),
+            stop(0.5, [200, 100, 0]),
+            stop(0.5, [0, 0, 255]),
+            stop(1.0, [0, 0, 55]),
+        ];
+        let palette = Palette::new(Colormap::Custom, &stops).expect("valid stops");
+        assert_eq!(palette.map(0.0), [0, 0, 0]);
+        assert_eq!(palette.map(0.375), [100, 50, 0]);
+        assert_eq!(palette.map(0.5), [0, 0, 255]);
+        assert_eq!(palette.map(0.75), [0, 0, 155]);
+        assert_eq!(palette.map(f32::NAN), [0, 0, 0]);
+
+        let unsorted = [stop(0.5, [0, 0, 0]), stop(0.25, [0, 0, 0])];
+        assert!(Palette::new(Colormap::Custom, &unsorted).is_err());
+        assert!(
+            Palette::new(Colormap::Custom, &[stop(1.5, [0, 0, 0]), stop(1.0, [0; 3])]).is_err()
+        );
+        assert!(Palette::new(Colormap::Viridis, &stops).is_err());
+    }
+
+    #[test]
+    fn flow_wheel_colors_by_direction() {
+        // +x red, +y yellow-green, -x cyan; half the max speed at half value; at rest black
+        let vx = [1.0, 0.0, -1.0, 0.5, 0.0, f32::NAN];
+        let vy = [0.0, 1.0, 0.0, 0.0, 0.0, 0.0];
+        let rgba = flow_rgba(&vx, &vy, 1.0);
+        let px = |i: usize| [rgba[4 * i], rgba[4 * i + 1], rgba[4 * i + 2]];
+        assert_eq!(px(0), [255, 0, 0]);
+        assert_eq!(px(1), [128, 255, 0]);
+        assert_eq!(px(2), [0, 255, 255]);
+        assert_eq!(px(3), [128, 0, 0]);
+        assert_eq!(px(4), [0, 0, 0]);
+        assert_eq!(px(5), [0, 0, 0]);
+    }
+
+    /// Renders a fixed CPU-backend run and compares it with `tests/golden/render_dye_viridis.png`.
+    /// Set `UPDATE_GOLDEN=1` to rewrite the golden image after an intended rendering change.
+    #[test]
+    fn render_matches_golden_image() {
+        let mut req: FluidRenderRequest = serde_json::from_value(serde_json::json!({
+            "width": 48, "height": 32, "steps": 30, "backend": "cpu",
+            "colormap": "viridis", "out_width": 96,
+        }))
+        .expect("render config");
+        req.sim = req.sim.prepare().expect("valid config").0;
+        let (w, h) = output_size(48, 32, req.square_pixels, req.out_width, req.out_height)
+            .expect("output size");
+        let (_, fields) = pollster::block_on(run_fluid(&req.sim)).expect("cpu run");
+        let palette = Palette::new(req.colormap, &req.colormap_stops).expect("palette");
+        let (rgba, _) = render_rgba(&req, &palette, &fields, w, h);
+        let actual = encode_png_rgba(w, h, &rgba).expect("encode png");
+
+        let golden_path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden/render_dye_viridis.png");
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::create_dir_all(golden_path.parent().unwrap()).unwrap();
+            std::fs::write(&golden_path, &actual).unwrap();
+        }
+        let golden = std::fs::read(&golden_path)
+            .unwrap_or_else(|e| panic!("read {}: {e}", golden_path.display()));
+
+        let (gw, gh, expected) = decode_png(&golden);
+        let (aw, ah, got) = decode_png(&actual);
+        let worst = got
+            .iter()
+            .zip(&expected)
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap_or(0);
+        if (aw, ah) != (gw, gh) || worst > CHANNEL_TOLERANCE {
+            let out = std::env::temp_dir().join("render_dye_viridis.actual.png");
+            std::fs::write(&out, &actual).unwrap();
+            panic!(
+                "render differs from {} ({aw}x{ah} vs {gw}x{gh}, worst channel diff {worst} > \
+                 {CHANNEL_TOLERANCE}); actual image written to {}",
+                golden_path.display(),
+                out.display()
+            );
+        }
+    }
+}
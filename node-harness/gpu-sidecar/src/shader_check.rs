@@ -0,0 +1,119 @@
+//! `shader_check`: compile every fluid kernel on a chosen backend without running anything.
+//!
+//! Each shader module and its pipeline are created inside validation and internal error scopes,
+//! so a kernel that one backend's shader translation rejects is reported by name with the wgpu
+//! message instead of failing the first run that uses it. The device is created for the check
+//! alone (not through `gpu_cache`), so nothing compiled here is reused.
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    AdapterChoice, FLUID_ACCUMULATE_WGSL, FLUID_ADVECT_DYE_TEX_WGSL, FLUID_ADVECT_DYE_WGSL,
+    FLUID_ADVECT_VEL_TEX_WGSL, FLUID_ADVECT_VEL_WGSL, FLUID_CURL_WGSL, FLUID_DELTA_WGSL,
+    FLUID_DIFFUSE_VEL_WGSL, FLUID_DISPATCH_ARGS_WGSL, FLUID_DIVERGENCE_GATE_WGSL,
+    FLUID_DIVERGENCE_WGSL, FLUID_DT_PEAKS_WGSL, FLUID_DYE_GRADIENT_WGSL, FLUID_FADE_WGSL,
+    FLUID_FILL_WGSL, FLUID_INIT_WGSL, FLUID_JACOBI_WGSL, FLUID_MAX_DIVERGENCE_WGSL,
+    FLUID_MAX_SPEED_WGSL, FLUID_MOVE_OBSTACLES_WGSL, FLUID_OKUBO_WEISS_WGSL, FLUID_PACK_TEX_WGSL,
+    FLUID_PICK_DT_WGSL, FLUID_PREDICT_DYE_WGSL, FLUID_PREDICT_VEL_WGSL, FLUID_PROJECT_WGSL,
+    FLUID_RED_BLACK_WGSL, FLUID_RESIDUAL_WGSL, FLUID_STREAMFUNCTION_RHS_WGSL,
+    FLUID_VORTICITY_FORCE_WGSL, WgpuBackend, backend_label, compile_pipeline, dye_wgsl,
+    request_adapter, request_device,
+};
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ShaderCheckResponse {
+    /// Every kernel compiled.
+    ok: bool,
+    backend: &'static str,
+    adapter: String,
+    per_kernel: Vec<KernelCheck>,
+}
+
+#[derive(Debug, Serialize)]
+struct KernelCheck {
+    name: String,
+    compiled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Every kernel `FluidSim` can build, by the names `explain` uses; the `channels: 3` dye
+/// kernels again as `*_rgb`, and the field texture pack once per texture format.
+fn kernels() -> Vec<(String, String)> {
+    let mut kernels: Vec<(String, String)> = [
+        ("init", FLUID_INIT_WGSL),
+        ("advect_vel", FLUID_ADVECT_VEL_WGSL),
+        ("predict_vel", FLUID_PREDICT_VEL_WGSL),
+        ("diffuse_vel", FLUID_DIFFUSE_VEL_WGSL),
+        ("curl", FLUID_CURL_WGSL),
+        ("vorticity_force", FLUID_VORTICITY_FORCE_WGSL),
+        ("move_obstacles", FLUID_MOVE_OBSTACLES_WGSL),
+        ("divergence", FLUID_DIVERGENCE_WGSL),
+        ("jacobi", FLUID_JACOBI_WGSL),
+        ("red_black", FLUID_RED_BLACK_WGSL),
+        ("project", FLUID_PROJECT_WGSL),
+        ("advect_dye", FLUID_ADVECT_DYE_WGSL),
+        ("predict_dye", FLUID_PREDICT_DYE_WGSL),
+        ("fill", FLUID_FILL_WGSL),
+        ("dispatch_args", FLUID_DISPATCH_ARGS_WGSL),
+        ("advect_vel_tex", FLUID_ADVECT_VEL_TEX_WGSL),
+        ("advect_dye_tex", FLUID_ADVECT_DYE_TEX_WGSL),
+        ("fade", FLUID_FADE_WGSL),
+        ("accumulate", FLUID_ACCUMULATE_WGSL),
+        ("max_speed", FLUID_MAX_SPEED_WGSL),
+        ("dt_peaks", FLUID_DT_PEAKS_WGSL),
+        ("pick_dt", FLUID_PICK_DT_WGSL),
+        ("residual", FLUID_RESIDUAL_WGSL),
+        ("streamfunction_rhs", FLUID_STREAMFUNCTION_RHS_WGSL),
+        ("okubo_weiss", FLUID_OKUBO_WEISS_WGSL),
+        ("dye_gradient", FLUID_DYE_GRADIENT_WGSL),
+        ("delta", FLUID_DELTA_WGSL),
+        ("max_divergence", FLUID_MAX_DIVERGENCE_WGSL),
+        ("divergence_gate", FLUID_DIVERGENCE_GATE_WGSL),
+    ]
+    .into_iter()
+    .map(|(name, wgsl)| (name.to_owned(), wgsl.to_owned()))
+    .collect();
+    for (name, wgsl) in [
+        ("init", FLUID_INIT_WGSL),
+        ("advect_dye", FLUID_ADVECT_DYE_WGSL),
+        ("predict_dye", FLUID_PREDICT_DYE_WGSL),
+        ("fade", FLUID_FADE_WGSL),
+    ] {
+        kernels.push((format!("{name}_rgb"), dye_wgsl(wgsl, 3).into_owned()));
+    }
+    for format in ["rgba32float", "rgba16float"] {
+        kernels.push((
+            format!("pack_tex_{format}"),
+            FLUID_PACK_TEX_WGSL.replace("FIELD_TEX_FORMAT", format),
+        ));
+    }
+    kernels
+}
+
+pub(crate) async fn run(backend: WgpuBackend) -> Result<ShaderCheckResponse> {
+    let adapter = request_adapter(backend, &AdapterChoice::default()).await?;
+    let (device, _queue) = request_device(&adapter, wgpu::Features::empty()).await?;
+
+    let mut per_kernel = Vec::new();
+    for (name, wgsl) in kernels() {
+        device.push_error_scope(wgpu::ErrorFilter::Internal);
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        compile_pipeline(&device, &name, &wgsl);
+        let validation = device.pop_error_scope().await;
+        let internal = device.pop_error_scope().await;
+        let error = validation.or(internal).map(|err| err.to_string());
+        per_kernel.push(KernelCheck {
+            name,
+            compiled: error.is_none(),
+            error,
+        });
+    }
+    Ok(ShaderCheckResponse {
+        ok: per_kernel.iter().all(|k| k.compiled),
+        backend: backend_label(adapter.get_info().backend),
+        adapter: adapter.get_info().name,
+        per_kernel,
+    })
+}
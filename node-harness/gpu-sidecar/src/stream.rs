@@ -0,0 +1,171 @@
+//! `stream_tcp`: step one simulation and push each rendered frame over a TCP connection, as a
+//! frame source for a remote renderer.
+//!
+//! The sidecar connects to `addr` (it is the client; the renderer listens) and sends `frames`
+//! frames paced like `live_render`. Each frame is a 12-byte header of big-endian `u32`s,
+//! `payload length, image width, image height`, followed by the payload: a PNG, or with
+//! `format: "raw"` the RGBA8 pixels row by row from the top. A connection that fails to open is
+//! an `io_error`; one that drops mid-stream ends the stream with the frames sent so far and the
+//! write error in `connection_error`.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufWriter, Write};
+use std::net::TcpStream;
+
+use crate::error::{ErrorCode, ResultExt};
+use crate::live::{Frame, Pacer};
+use crate::{FluidRenderRequest, FluidRun, encode_png_rgba};
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct StreamTcpRequest {
+    /// The simulation and how each frame is drawn (`out_path` / `data_url` are unused).
+    #[serde(flatten)]
+    pub(crate) render: FluidRenderRequest,
+    /// `host:port` of the listening renderer.
+    pub(crate) addr: String,
+    pub(crate) fps: f64,
+    pub(crate) frames: u32,
+    #[serde(default = "default_steps_per_frame")]
+    pub(crate) steps_per_frame: u32,
+    #[serde(default)]
+    pub(crate) format: FrameFormat,
+}
+
+fn default_steps_per_frame() -> u32 {
+    1
+}
+
+/// The payload of each streamed frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum FrameFormat {
+    #[default]
+    Png,
+    /// Unencoded RGBA8, `4 * width * height` bytes.
+    Raw,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct StreamTcpResponse {
+    ok: bool,
+    addr: String,
+    format: FrameFormat,
+    frames_sent: u32,
+    frames_requested: u32,
+    /// Frames that finished after their deadline.
+    late_frames: u32,
+    target_fps: f64,
+    achieved_fps: f64,
+    steps_done: u32,
+    image_width: u32,
+    image_height: u32,
+    /// Why the stream stopped early: the write error once the receiver went away.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    connection_error: Option<String>,
+}
+
+/// Set up and seed the request's simulation, then stream from it.
+pub(crate) async fn run(req: &StreamTcpRequest) -> Result<StreamTcpResponse> {
+    let (mut sim, _adapter) = FluidRun::new(&req.render.sim).await?;
+    sim.seed();
+    run_loop(&mut sim, req)
+}
+
+/// The stream on an existing simulation (also `--interactive`'s `stream`): connect, then per
+/// frame step `steps_per_frame`, render, send and sleep to the next deadline.
+pub(crate) fn run_loop(sim: &mut FluidRun, req: &StreamTcpRequest) -> Result<StreamTcpResponse> {
+    let frame = Frame::new(&req.render, "stream_tcp")?;
+    let stream = TcpStream::connect(&req.addr)
+        .with_context(|| format!("connect to {}", req.addr))
+        .code(ErrorCode::IoError)?;
+    // one frame per write burst: don't hold the header back waiting for the payload's ack
+    stream
+        .set_nodelay(true)
+        .context("set TCP_NODELAY")
+        .code(ErrorCode::IoError)?;
+    let mut out = BufWriter::new(stream);
+    let steps_per_frame = req.steps_per_frame.max(1);
+    let mut pacer = Pacer::new(req.fps)?;
+
+    let (mut sent, mut connection_error) = (0u32, None);
+    while sent < req.frames {
+        let rgba = frame.render(sim, steps_per_frame)?;
+        let payload = match req.format {
+            FrameFormat::Png => encode_png_rgba(frame.width, frame.height, &rgba)?,
+            FrameFormat::Raw => rgba,
+        };
+        if let Err(err) = send_frame(&mut out, frame.width, frame.height, &payload) {
+            connection_error = Some(err.to_string());
+            break;
+        }
+        sent += 1;
+        pacer.wait();
+    }
+    Ok(StreamTcpResponse {
+        ok: true,
+        addr: req.addr.clone(),
+        format: req.format,
+        frames_sent: sent,
+        frames_requested: req.frames,
+        late_frames: pacer.late,
+        target_fps: req.fps,
+        achieved_fps: pacer.achieved_fps(sent),
+        steps_done: sim.steps_done(),
+        image_width: frame.width,
+        image_height: frame.height,
+        connection_error,
+    })
+}
+
+fn send_frame(out: &mut impl Write, width: u32, height: u32, payload: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(payload.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "frame over 4 GiB"))?;
+    for word in [len, width, height] {
+        out.write_all(&word.to_be_bytes())?;
+    }
+    out.write_all(payload)?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    fn read_frame(conn: &mut TcpStream) -> (u32, u32, Vec<u8>) {
+        let mut header = [0u8; 12];
+        conn.read_exact(&mut header).unwrap();
+        let word = |i: usize| u32::from_be_bytes(header[4 * i..4 * i + 4].try_into().unwrap());
+        let mut payload = vec![0u8; word(0) as usize];
+        conn.read_exact(&mut payload).unwrap();
+        (word(1), word(2), payload)
+    }
+
+    #[test]
+    fn stream_stops_when_the_receiver_hangs_up() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        // take two frames, then drop the connection
+        let receiver = std::thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            [read_frame(&mut conn), read_frame(&mut conn)]
+        });
+        let mut req: StreamTcpRequest = serde_json::from_value(serde_json::json!({
+            "width": 16, "height": 16, "backend": "cpu",
+            "addr": addr, "fps": 1000.0, "frames": 1000, "format": "raw",
+        }))
+        .unwrap();
+        req.render.sim = req.render.sim.prepare().unwrap().0;
+        let resp = pollster::block_on(run(&req)).unwrap();
+
+        for (width, height, payload) in receiver.join().unwrap() {
+            assert_eq!((width, height), (resp.image_width, resp.image_height));
+            assert_eq!(payload.len(), 4 * (width * height) as usize);
+        }
+        assert!(resp.connection_error.is_some(), "{resp:?}");
+        assert!((2..1000).contains(&resp.frames_sent), "{resp:?}");
+        assert_eq!(resp.steps_done, resp.frames_sent + 1);
+    }
+}
@@ -0,0 +1,141 @@
+//! `vtk_out_path`: the final velocity and dye as a legacy VTK file (ParaView, VisIt).
+//!
+//! The file is a `STRUCTURED_POINTS` dataset of `width x height x 1` points, one per cell, at
+//! the cell centers in cell units (`ORIGIN 0.5 0.5 0`, `SPACING 1 1 1`), so `x` runs along a row
+//! and `y` down the rows, as in every other dump. `POINT_DATA` holds `velocity` as `VECTORS`
+//! (`vx vy 0`, in cells per step) and `dye` as `SCALARS`. `vtk_format: "binary"` (the default)
+//! writes the values as big-endian `f32`s, which is what the legacy reader expects; `"ascii"`
+//! writes them as text.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufWriter, Write};
+
+use crate::error::{ErrorCode, ResultExt};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum VtkFormat {
+    #[default]
+    Binary,
+    Ascii,
+}
+
+/// Write `vel` and `dye` (`width * height` cells, row 0 first) to `path`.
+pub(crate) fn write(
+    path: &str,
+    format: VtkFormat,
+    width: u32,
+    height: u32,
+    vel: &[[f32; 2]],
+    dye: &[f32],
+) -> Result<()> {
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("create {path}"))
+        .code(ErrorCode::IoError)?;
+    write_to(BufWriter::new(file), format, width, height, vel, dye)
+        .with_context(|| format!("write {path}"))
+        .code(ErrorCode::IoError)
+}
+
+fn write_to(
+    mut out: impl Write,
+    format: VtkFormat,
+    width: u32,
+    height: u32,
+    vel: &[[f32; 2]],
+    dye: &[f32],
+) -> io::Result<()> {
+    let kind = match format {
+        VtkFormat::Binary => "BINARY",
+        VtkFormat::Ascii => "ASCII",
+    };
+    write!(
+        out,
+        "# vtk DataFile Version 3.0\n\
+         gpu-sidecar fluid {width}x{height}\n\
+         {kind}\n\
+         DATASET STRUCTURED_POINTS\n\
+         DIMENSIONS {width} {height} 1\n\
+         ORIGIN 0.5 0.5 0\n\
+         SPACING 1 1 1\n\
+         POINT_DATA {}\n\
+         VECTORS velocity float\n",
+        width * height
+    )?;
+    let vectors = vel.iter().flat_map(|v| [v[0], v[1], 0.0]);
+    write_values(&mut out, format, vectors, 3)?;
+    write!(out, "SCALARS dye float 1\nLOOKUP_TABLE default\n")?;
+    write_values(&mut out, format, dye.iter().copied(), 1)?;
+    out.flush()
+}
+
+/// `values` as big-endian `f32`s, or `per_line` to a text line.
+fn write_values(
+    out: &mut impl Write,
+    format: VtkFormat,
+    values: impl Iterator<Item = f32>,
+    per_line: usize,
+) -> io::Result<()> {
+    match format {
+        VtkFormat::Binary => {
+            for v in values {
+                out.write_all(&v.to_be_bytes())?;
+            }
+            // the next keyword starts on its own line
+            out.write_all(b"\n")
+        }
+        VtkFormat::Ascii => {
+            let values: Vec<f32> = values.collect();
+            for line in values.chunks(per_line) {
+                let line: Vec<String> = line.iter().map(|v| v.to_string()).collect();
+                writeln!(out, "{}", line.join(" "))?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_vtk_holds_big_endian_vectors_then_scalars() {
+        let (w, h) = (3u32, 2u32);
+        let vel: Vec<[f32; 2]> = (0..6).map(|i| [i as f32, -(i as f32)]).collect();
+        let dye: Vec<f32> = (0..6).map(|i| i as f32 * 0.25).collect();
+        let mut bytes = Vec::new();
+        write_to(&mut bytes, VtkFormat::Binary, w, h, &vel, &dye).unwrap();
+
+        let find = |needle: &[u8]| {
+            let at = bytes.windows(needle.len()).position(|s| s == needle);
+            at.unwrap_or_else(|| panic!("no {:?}", String::from_utf8_lossy(needle))) + needle.len()
+        };
+        let header = String::from_utf8_lossy(&bytes[..find(b"POINT_DATA 6\n")]).into_owned();
+        assert!(
+            header.starts_with("# vtk DataFile Version 3.0\n"),
+            "{header}"
+        );
+        assert!(header.contains("\nBINARY\nDATASET STRUCTURED_POINTS\nDIMENSIONS 3 2 1\n"));
+        let f32_at = |at: usize| f32::from_be_bytes(bytes[at..at + 4].try_into().unwrap());
+
+        let vectors = find(b"VECTORS velocity float\n");
+        for (i, v) in vel.iter().enumerate() {
+            let at = vectors + 12 * i;
+            assert_eq!(
+                [f32_at(at), f32_at(at + 4), f32_at(at + 8)],
+                [v[0], v[1], 0.0]
+            );
+        }
+        let scalars = find(b"LOOKUP_TABLE default\n");
+        assert_eq!(
+            scalars,
+            vectors + 12 * 6 + 1 + "SCALARS dye float 1\n".len() + 21
+        );
+        for (i, d) in dye.iter().enumerate() {
+            assert_eq!(f32_at(scalars + 4 * i), *d);
+        }
+        assert_eq!(bytes.len(), scalars + 4 * 6 + 1);
+    }
+}